@@ -0,0 +1,27 @@
+//! Lexes arbitrary bytes in both initial modes, and checks that the reader-based and slice-based lexers agree.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use watson_rs::language::Mode;
+use watson_rs::lexer;
+use watson_rs::vm::ReadToken;
+
+fuzz_target!(|data: &[u8]| {
+    for mode in [Mode::A, Mode::S] {
+        let config = lexer::Config {
+            initial_mode: mode,
+            ..Default::default()
+        };
+        let mut reader = config.clone().build(data);
+        let mut slice = config.build_slice(data);
+        loop {
+            let a = reader.read().unwrap();
+            let b = slice.read().unwrap();
+            assert_eq!(a, b);
+            if a.is_none() {
+                break;
+            }
+        }
+        assert_eq!(reader.stats(), slice.stats());
+    }
+});
@@ -0,0 +1,75 @@
+//! Serializes arbitrary values, executes the instructions (directly and through WATSON text) and compares the results
+//! with the original values.
+#![no_main]
+
+use arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use watson_rs::serializer::Serializer;
+use watson_rs::{Insn, Map, Value, VM};
+
+// Deeply nested values are covered by the unit tests of `Value`; this keeps the inputs small.
+const MAX_DEPTH: usize = 16;
+const MAX_LEN: usize = 8;
+
+#[derive(Debug)]
+struct Input(Value);
+
+impl<'a> Arbitrary<'a> for Input {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        arbitrary_value(u, 0).map(Input)
+    }
+}
+
+fn arbitrary_value(u: &mut Unstructured<'_>, depth: usize) -> arbitrary::Result<Value> {
+    let max_kind = if depth < MAX_DEPTH { 7 } else { 5 };
+    Ok(match u.int_in_range(0..=max_kind)? {
+        0 => Value::Int(u.arbitrary()?),
+        1 => Value::Uint(u.arbitrary()?),
+        2 => Value::Float(u.arbitrary()?),
+        3 => Value::String(u.arbitrary()?),
+        4 => Value::Bool(u.arbitrary()?),
+        5 => Value::Nil,
+        6 => Value::Array(
+            (0..u.int_in_range(0..=MAX_LEN)?)
+                .map(|_| arbitrary_value(u, depth + 1))
+                .collect::<arbitrary::Result<_>>()?,
+        ),
+        _ => {
+            let mut map = Map::new();
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                map.insert(u.arbitrary()?, arbitrary_value(u, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+    })
+}
+
+// Same as `==` except that NaN equals any NaN (NaN is encoded as `Fnan`, which drops its payload).
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(x), Value::Float(y)) => x == y || (x.is_nan() && y.is_nan()),
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| same(x, y))
+        }
+        (Value::Object(xs), Value::Object(ys)) => {
+            xs.len() == ys.len()
+                && xs
+                    .iter()
+                    .all(|(k, x)| ys.get(k).is_some_and(|y| same(x, y)))
+        }
+        _ => a == b,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let mut insns = Vec::<Insn>::new();
+    Serializer::new(&mut insns).serialize(&input.0).unwrap();
+    let mut vm = VM::new();
+    vm.execute_all_insns(insns).unwrap();
+    let executed = vm.into_top().unwrap();
+    assert!(same(&executed, &input.0));
+
+    let text = watson_rs::to_vec(&input.0).unwrap();
+    let decoded = watson_rs::from_reader(&text[..]).unwrap();
+    assert!(same(&decoded, &input.0));
+});
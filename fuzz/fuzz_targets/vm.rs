@@ -0,0 +1,49 @@
+//! Executes arbitrary sequences of instructions. The VM must report errors instead of panicking or overflowing.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use watson_rs::language::{Insn, Location, Mode, Token};
+use watson_rs::vm;
+
+// `Gdup` copies a value deeply, so each `Gdup` followed by `Aadd` can double the memory in use regardless of the
+// limits below. The number of `Gdup`s is capped so that the fuzzer doesn't report such inputs as out-of-memory.
+const MAX_GDUPS: usize = 10;
+const MAX_LEN: usize = 1 << 12;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    tokens: Vec<(u8, bool)>,
+    max_stack_depth: Option<u16>,
+}
+
+fuzz_target!(|input: Input| {
+    let insns = Insn::all().collect::<Vec<_>>();
+    let mut vm = vm::Config {
+        max_stack_depth: input.max_stack_depth.map(usize::from),
+        max_string_bytes: Some(MAX_LEN),
+        max_array_len: Some(MAX_LEN),
+        max_object_len: Some(MAX_LEN),
+        ..Default::default()
+    }
+    .build();
+    let mut gdups = 0;
+    for (index, s_mode) in input.tokens {
+        let insn = insns[index as usize % insns.len()];
+        if insn == Insn::Gdup {
+            gdups += 1;
+            if MAX_GDUPS < gdups {
+                break;
+            }
+        }
+        let token = Token {
+            insn,
+            mode: if s_mode { Mode::S } else { Mode::A },
+            location: Location::unknown(),
+        };
+        if vm.execute(token).is_err() {
+            break;
+        }
+    }
+    drop(vm.take_values());
+});
@@ -0,0 +1,135 @@
+//! Asynchronous counterparts of `to_writer` and `from_reader`.
+//!
+//! They don't stream: the whole text is buffered in memory, and only the I/O is asynchronous. Encoding and decoding
+//! themselves don't block, so they can be called inside async tasks without `spawn_blocking`.
+//! Use `from_reader_async_with_options` to bound the memory used for untrusted input.
+
+use serde::ser;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::Result;
+
+/// Serializes the given value as WATSON text and writes it to the given writer asynchronously.
+/// The whole text is serialized into memory before it is written.
+pub async fn to_writer_async<W, T>(mut writer: W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: ?Sized + ser::Serialize,
+{
+    let buf = crate::to_vec(value)?;
+    writer
+        .write_all(&buf)
+        .await
+        .map_err(watson_rs::Error::from)?;
+    writer.flush().await.map_err(watson_rs::Error::from)?;
+    Ok(())
+}
+
+/// Reads a WATSON value from the given reader asynchronously.
+/// The reader is read to the end into memory before the value is decoded.
+pub async fn from_reader_async<R>(reader: R) -> Result<watson_rs::Value>
+where
+    R: AsyncRead + Unpin,
+{
+    let buf = read_to_end(reader, None).await?;
+    crate::from_reader(buf.as_slice())
+}
+
+/// Reads a WATSON value from the given reader asynchronously, enforcing the given limits and policies.
+/// See `from_str_with_options` for details.
+///
+/// The reader is read into memory before the value is decoded, but reading stops with
+/// `ErrorKind::InputLimitExceeded` as soon as the input exceeds `DecodeOptions::max_input_bytes`.
+pub async fn from_reader_async_with_options<R>(
+    reader: R,
+    opts: &watson_rs::DecodeOptions,
+) -> Result<watson_rs::Value>
+where
+    R: AsyncRead + Unpin,
+{
+    let buf = read_to_end(reader, opts.max_input_bytes).await?;
+    crate::from_reader_with_options(buf.as_slice(), opts)
+}
+
+/// Reads the reader to the end, failing if it has more than `limit` bytes.
+async fn read_to_end<R>(reader: R, limit: Option<usize>) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    // One more byte than the limit is read to tell whether the input exceeds it.
+    let max = limit.map_or(u64::MAX, |n| (n as u64).saturating_add(1));
+    reader
+        .take(max)
+        .read_to_end(&mut buf)
+        .await
+        .map_err(watson_rs::Error::from)?;
+    check_input_limit(buf.len(), limit)?;
+    Ok(buf)
+}
+
+/// Fails with `ErrorKind::InputLimitExceeded` if `len` bytes exceed `limit`.
+pub(crate) fn check_input_limit(len: usize, limit: Option<usize>) -> Result<()> {
+    if limit.is_some_and(|limit| len > limit) {
+        return Err(watson_rs::Error {
+            kind: watson_rs::ErrorKind::InputLimitExceeded,
+            location: watson_rs::Location::unknown(),
+            source: None,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use watson_rs::Value::*;
+
+    use super::*;
+    use crate::ErrorKind;
+
+    #[tokio::test]
+    async fn async_roundtrip() -> Result<()> {
+        let value = BTreeMap::from([("a", vec![1, 2]), ("b", vec![])]);
+        let mut buf = Vec::new();
+        to_writer_async(&mut buf, &value).await?;
+        assert_eq!(buf, crate::to_vec(&value)?);
+
+        let v = from_reader_async(buf.as_slice()).await?;
+        assert_eq!(
+            v,
+            watson_rs::object![a: watson_rs::array![Int(1), Int(2)], b: watson_rs::array![]]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn async_reader_errors() {
+        let err = from_reader_async(&b""[..]).await.unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ExecutionError(_)));
+    }
+
+    #[tokio::test]
+    async fn async_reader_limits_input_bytes() -> Result<()> {
+        let text = crate::to_vec(&vec![1, 2, 3])?;
+        let opts = |max_input_bytes| watson_rs::DecodeOptions {
+            max_input_bytes,
+            ..Default::default()
+        };
+        let v = from_reader_async_with_options(text.as_slice(), &opts(Some(text.len()))).await?;
+        assert_eq!(v, watson_rs::array![Int(1), Int(2), Int(3)]);
+
+        // The reader is not read beyond the limit.
+        let mut reader = tokio::io::repeat(b' ');
+        let err = from_reader_async_with_options(&mut reader, &opts(Some(text.len())))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ExecutionError(watson_rs::ErrorKind::InputLimitExceeded)
+        );
+        Ok(())
+    }
+}
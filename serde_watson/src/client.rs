@@ -0,0 +1,196 @@
+//! Helpers to send and receive WATSON bodies with `reqwest`.
+
+use std::io;
+
+use futures_util::stream;
+use reqwest::header::CONTENT_TYPE as CONTENT_TYPE_HEADER;
+use reqwest::{Body, RequestBuilder, Response};
+use serde::{de, ser};
+use tokio::sync::mpsc;
+
+use crate::async_io::check_input_limit;
+use crate::de::Deserializer;
+use crate::error::Result;
+
+/// The media type of WATSON bodies.
+pub const CONTENT_TYPE: &str = "application/x-watson";
+
+// The number of chunks that `to_body_iter` serializes ahead of the receiver.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Serializes the given value into a request body.
+pub fn to_body<T>(value: &T) -> Result<Body>
+where
+    T: ?Sized + ser::Serialize,
+{
+    Ok(Body::from(crate::to_vec(value)?))
+}
+
+/// Returns a request body that streams the items of the given iterator as a WATSON array.
+///
+/// The items are serialized on a blocking thread of the Tokio runtime as the body is sent, so it must be called
+/// within the runtime. If an item fails to serialize, the body ends with the error.
+pub fn to_body_iter<I>(iter: I) -> Body
+where
+    I: IntoIterator + Send + 'static,
+    I::Item: ser::Serialize,
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = crate::to_writer_iter(ChannelWriter(tx.clone()), iter) {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    Body::wrap_stream(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+/// Reads the body of a response and deserializes it.
+/// The status of the response is not checked; use `Response::error_for_status` beforehand if necessary.
+///
+/// The whole body is buffered in memory before it is decoded. Use `from_response_with_options` to bound its size.
+pub async fn from_response<T>(response: Response) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    let body = read_body(response, None).await?;
+    let v = crate::from_reader(body.as_slice())?;
+    T::deserialize(Deserializer::from_owned(v))
+}
+
+/// Reads the body of a response and deserializes it, enforcing the given limits and policies.
+/// See `from_response` and `from_str_with_options` for details.
+///
+/// Reading stops with `ErrorKind::InputLimitExceeded` as soon as the body exceeds `DecodeOptions::max_input_bytes`.
+pub async fn from_response_with_options<T>(
+    response: Response,
+    opts: &watson_rs::DecodeOptions,
+) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    let body = read_body(response, opts.max_input_bytes).await?;
+    let v = crate::from_reader_with_options(body.as_slice(), opts)?;
+    T::deserialize(Deserializer::from_owned(v))
+}
+
+/// Reads the body of a response, failing if it has more than `limit` bytes.
+async fn read_body(mut response: Response, limit: Option<usize>) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        watson_rs::Error::from_io_error(io::Error::other(e), watson_rs::Location::unknown())
+    })? {
+        body.extend_from_slice(&chunk);
+        check_input_limit(body.len(), limit)?;
+    }
+    Ok(body)
+}
+
+/// Extends `RequestBuilder` with WATSON bodies.
+pub trait RequestBuilderExt: Sized {
+    /// Sets the body to the given value serialized as WATSON, and sets the `Content-Type` header to `CONTENT_TYPE`.
+    fn watson<T>(self, value: &T) -> Result<Self>
+    where
+        T: ?Sized + ser::Serialize;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn watson<T>(self, value: &T) -> Result<Self>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(self
+            .header(CONTENT_TYPE_HEADER, CONTENT_TYPE)
+            .body(to_body(value)?))
+    }
+}
+
+struct ChannelWriter(mpsc::Sender<Result<Vec<u8>>>);
+
+impl io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    #[test]
+    fn request_builder_sets_body_and_content_type() -> Result<()> {
+        let point = Point { x: 1, y: -2 };
+        let req = reqwest::Client::new()
+            .post("http://localhost/")
+            .watson(&point)?
+            .build()
+            .unwrap();
+        assert_eq!(req.headers()[CONTENT_TYPE_HEADER], CONTENT_TYPE);
+        assert_eq!(
+            req.body().and_then(Body::as_bytes),
+            Some(&crate::to_vec(&point)?[..])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn from_response_deserializes_body() -> Result<()> {
+        let point = Point { x: 3, y: 4 };
+        let response = http::Response::new(crate::to_vec(&point)?);
+        assert_eq!(from_response::<Point>(response.into()).await?, point);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn from_response_limits_body_size() -> Result<()> {
+        let point = Point { x: 3, y: 4 };
+        let body = crate::to_vec(&point)?;
+        let opts = |max_input_bytes| watson_rs::DecodeOptions {
+            max_input_bytes,
+            ..Default::default()
+        };
+        let response = || http::Response::new(body.clone()).into();
+        let decoded: Point =
+            from_response_with_options(response(), &opts(Some(body.len()))).await?;
+        assert_eq!(decoded, point);
+        let err = from_response_with_options::<Point>(response(), &opts(Some(body.len() - 1)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &crate::ErrorKind::ExecutionError(watson_rs::ErrorKind::InputLimitExceeded)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn to_body_iter_streams_array() -> Result<()> {
+        let body = to_body_iter((0..3).map(|i| Point { x: i, y: i * i }));
+        let points: Vec<Point> = from_response(http::Response::new(body).into()).await?;
+        assert_eq!(
+            points,
+            [
+                Point { x: 0, y: 0 },
+                Point { x: 1, y: 1 },
+                Point { x: 2, y: 4 }
+            ]
+        );
+        Ok(())
+    }
+}
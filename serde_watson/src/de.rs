@@ -1,3 +1,7 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::io;
 
 use serde::de;
@@ -5,18 +9,48 @@ use watson_rs::lexer;
 use watson_rs::vm;
 
 use crate::error::{Error, ErrorKind, Result};
+use crate::key;
+use crate::repr::{EnumRepr, NumericKeyRepr, StructRepr};
 
 /// Deserializes an `str` into a WATSON value.
 pub fn from_str(s: &str) -> Result<watson_rs::Value> {
-    from_reader(s.as_bytes())
+    execute(lexer::SliceLexer::new(s.as_bytes()))
 }
 
 /// Reads a WATSON value from the given reader.
+#[cfg(feature = "std")]
 pub fn from_reader<R>(reader: R) -> Result<watson_rs::Value>
 where
     R: io::Read,
 {
-    let lx = lexer::Lexer::new(reader);
+    execute(lexer::Lexer::new(reader))
+}
+
+/// Deserializes an `str` into a WATSON value, enforcing the given limits and policies
+/// (e.g. `DecodeOptions::duplicate_keys`).
+///
+/// Unlike `from_str`, it fails unless the input represents exactly one value.
+pub fn from_str_with_options(s: &str, opts: &watson_rs::DecodeOptions) -> Result<watson_rs::Value> {
+    Ok(opts.from_str(s)?)
+}
+
+/// Reads a WATSON value from the given reader, enforcing the given limits and policies.
+/// See `from_str_with_options` for details.
+#[cfg(feature = "std")]
+pub fn from_reader_with_options<R>(
+    reader: R,
+    opts: &watson_rs::DecodeOptions,
+) -> Result<watson_rs::Value>
+where
+    R: io::Read,
+{
+    Ok(opts.from_reader(reader)?)
+}
+
+fn execute<R>(lx: R) -> Result<watson_rs::Value>
+where
+    R: vm::ReadToken,
+{
     let mut vm = vm::VM::new();
     vm.execute_all(lx)?;
     let top = vm.into_top().map(Ok).unwrap_or_else(|| {
@@ -29,52 +63,339 @@ where
     Ok(top)
 }
 
+/// Config configures a `Deserializer`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Config {
+    /// The representation of enum variants (defaults to `EnumRepr::External`).
+    pub enum_repr: EnumRepr,
+
+    /// If set, structs are accepted only in the given representation (defaults to `None`, which accepts both
+    /// objects and arrays).
+    pub strict_struct_repr: Option<StructRepr>,
+
+    /// How strings that are not valid UTF-8 are deserialized into `str`, `String`, `char` and identifiers such as
+    /// names of variants (defaults to `InvalidUtf8::Error`).
+    /// Keys of fields are always passed in the escaped form of `key::escape` instead.
+    pub invalid_utf8: InvalidUtf8,
+
+    /// If set to true, integers, unsigned integers and floats are converted into each other when the requested
+    /// type differs from the actual one (defaults to false). Conversions that would lose precision or overflow fail.
+    pub coerce_numbers: bool,
+
+    /// The value that `is_human_readable` returns (defaults to true).
+    /// It must match `ser::Config::human_readable` of the serializer that produced the input.
+    pub human_readable: bool,
+
+    /// The representation of numbers in keys of maps (defaults to `NumericKeyRepr::Binary`).
+    /// It must match `ser::Config::numeric_keys` of the serializer that produced the input.
+    pub numeric_keys: NumericKeyRepr,
+
+    /// If set to true, NaN and infinities fail to deserialize with `ErrorKind::NonFiniteFloat` (defaults to false).
+    pub reject_non_finite_floats: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            enum_repr: EnumRepr::default(),
+            strict_struct_repr: None,
+            invalid_utf8: InvalidUtf8::default(),
+            coerce_numbers: false,
+            human_readable: true,
+            numeric_keys: NumericKeyRepr::default(),
+            reject_non_finite_floats: false,
+        }
+    }
+}
+
+/// `InvalidUtf8` specifies how strings that are not valid UTF-8 are converted into Rust strings.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum InvalidUtf8 {
+    /// Fails to deserialize them.
+    #[default]
+    Error,
+
+    /// Replaces invalid sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    Replace,
+
+    /// Encodes the whole string in the standard Base64 alphabet with padding.
+    Base64,
+}
+
+impl Config {
+    /// Returns a new `Deserializer` that reads from `value`.
+    pub fn build(self, value: &watson_rs::Value) -> Deserializer<&watson_rs::Value> {
+        self.build_input(value)
+    }
+
+    /// Returns a new `Deserializer` that takes `value`.
+    pub fn build_owned(self, value: watson_rs::Value) -> OwnedDeserializer {
+        self.build_input(value)
+    }
+
+    /// Returns a new `Deserializer` that reads `input`, which is used for nested values.
+    fn build_input<I>(self, input: I) -> Deserializer<I> {
+        Deserializer {
+            input,
+            config: self,
+        }
+    }
+}
+
 /// Deserializer implements serde::de::Deserializer for WATSON encoding.
 ///
 /// Since WATSON format can't be deserialized incrementally, we do not provide deserializers that
 /// borrows an `str` or `io::Read`. Use this deserializer in combination with `from_str` or `from_reader`
 /// if you want to deserialize WATSON values directly from these sources.
-pub struct Deserializer<'de> {
-    value: &'de watson_rs::Value,
+///
+/// A `Deserializer` either borrows a `Value` (`Deserializer::new`) or takes ownership of it (`Deserializer::from_owned`).
+/// A borrowing one lends strings and keys to the visitor wherever it accepts borrowed data, so types like
+/// `&'de str`, `&'de [u8]` and `#[serde(borrow)] Cow<'de, str>` don't allocate, while owned strings are copied.
+/// An owning one moves strings, byte arrays and keys out of the `Value` instead, so it is cheaper when the `Value`
+/// is no longer needed, but it can't deserialize types that borrow from the input, such as `&str`.
+#[derive(Clone, Copy)]
+pub struct Deserializer<I> {
+    input: I,
+    config: Config,
 }
 
-impl<'de> Deserializer<'de> {
+/// OwnedDeserializer is a `Deserializer` that takes ownership of a `Value`.
+pub type OwnedDeserializer = Deserializer<watson_rs::Value>;
+
+impl<'de> Deserializer<&'de watson_rs::Value> {
     /// Returns a new `Deserializer` that reads from `value`.
     pub fn new(value: &'de watson_rs::Value) -> Self {
-        Deserializer { value }
+        Config::default().build(value)
+    }
+}
+
+impl Deserializer<watson_rs::Value> {
+    /// Returns a new `Deserializer` that takes `value`.
+    pub fn from_owned(value: watson_rs::Value) -> Self {
+        Config::default().build_owned(value)
     }
+}
 
-    /// Borrows an `str` from `Value::String`.
-    fn borrow_str<V>(&self, visitor: &V) -> Result<&'de str>
-    where
-        V: de::Visitor<'de>,
-    {
-        match self.value {
-            watson_rs::Value::String(bytes) => try_borrow_str(bytes, visitor),
-            _ => Err(self.invalid_type(visitor)),
+impl<I> Deserializer<I> {
+    /// Sets the representation of enum variants.
+    pub fn with_enum_repr(mut self, repr: EnumRepr) -> Self {
+        self.config.enum_repr = repr;
+        self
+    }
+
+    /// Accepts only structs in the given representation.
+    /// By default, structs can be deserialized from both objects and arrays.
+    pub fn with_strict_struct_repr(mut self, repr: StructRepr) -> Self {
+        self.config.strict_struct_repr = Some(repr);
+        self
+    }
+
+    /// Returns the configuration of this `Deserializer`.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+/// Input is a `Value` that a `Deserializer` reads, which is either borrowed (`&'de Value`) or owned (`Value`).
+///
+/// They differ only in how strings, arrays and objects are taken out of the `Value`.
+pub trait Input<'de>: Sized {
+    /// The iterator over the elements of an array.
+    type Elements: ExactSizeIterator<Item = Self>;
+
+    /// The iterator over the entries of an object.
+    type Entries: ExactSizeIterator<Item = (Cow<'de, [u8]>, Self)>;
+
+    /// Returns the `Value`.
+    fn value(&self) -> &watson_rs::Value;
+
+    /// Returns `Value::Nil`.
+    fn nil() -> Self;
+
+    /// Takes out the bytes of `Value::String`, or returns `self` as is if it is not a string.
+    fn into_bytes(self) -> core::result::Result<Cow<'de, [u8]>, Self>;
+
+    /// Takes out the elements of `Value::Array`, or returns `self` as is if it is not an array.
+    fn into_elements(self) -> core::result::Result<Self::Elements, Self>;
+
+    /// Takes out the entries of `Value::Object`, or returns `self` as is if it is not an object.
+    fn into_entries(self) -> core::result::Result<Self::Entries, Self>;
+}
+
+type BorrowedEntry<'de> = (Cow<'de, [u8]>, &'de watson_rs::Value);
+
+impl<'de> Input<'de> for &'de watson_rs::Value {
+    type Elements = core::slice::Iter<'de, watson_rs::Value>;
+    type Entries = core::iter::Map<
+        <&'de watson_rs::Map as IntoIterator>::IntoIter,
+        fn((&'de watson_rs::Bytes, &'de watson_rs::Value)) -> BorrowedEntry<'de>,
+    >;
+
+    fn value(&self) -> &watson_rs::Value {
+        self
+    }
+
+    fn nil() -> Self {
+        static NIL: watson_rs::Value = watson_rs::Value::Nil;
+        &NIL
+    }
+
+    fn into_bytes(self) -> core::result::Result<Cow<'de, [u8]>, Self> {
+        match self {
+            watson_rs::Value::String(bytes) => Ok(Cow::Borrowed(bytes)),
+            _ => Err(self),
+        }
+    }
+
+    fn into_elements(self) -> core::result::Result<Self::Elements, Self> {
+        match self {
+            watson_rs::Value::Array(arr) => Ok(arr.iter()),
+            _ => Err(self),
+        }
+    }
+
+    fn into_entries(self) -> core::result::Result<Self::Entries, Self> {
+        match self {
+            watson_rs::Value::Object(map) => Ok(map.iter().map(|(k, v)| (Cow::Borrowed(k), v))),
+            _ => Err(self),
+        }
+    }
+}
+
+type OwnedEntry<'de> = (Cow<'de, [u8]>, watson_rs::Value);
+
+impl<'de> Input<'de> for watson_rs::Value {
+    type Elements = alloc::vec::IntoIter<watson_rs::Value>;
+    type Entries = core::iter::Map<
+        <watson_rs::Map as IntoIterator>::IntoIter,
+        fn((watson_rs::Bytes, watson_rs::Value)) -> OwnedEntry<'de>,
+    >;
+
+    fn value(&self) -> &watson_rs::Value {
+        self
+    }
+
+    fn nil() -> Self {
+        watson_rs::Value::Nil
+    }
+
+    fn into_bytes(self) -> core::result::Result<Cow<'de, [u8]>, Self> {
+        match self {
+            watson_rs::Value::String(bytes) => Ok(Cow::Owned(bytes)),
+            _ => Err(self),
+        }
+    }
+
+    fn into_elements(self) -> core::result::Result<Self::Elements, Self> {
+        match self {
+            watson_rs::Value::Array(arr) => Ok(arr.into_iter()),
+            _ => Err(self),
+        }
+    }
+
+    fn into_entries(self) -> core::result::Result<Self::Entries, Self> {
+        match self {
+            watson_rs::Value::Object(map) => Ok(map.into_iter().map(|(k, v)| (Cow::Owned(k), v))),
+            _ => Err(self),
+        }
+    }
+}
+
+impl<'de, I: Input<'de>> Deserializer<I> {
+    /// Takes out the bytes of `Value::String`.
+    fn bytes(self, exp: &dyn de::Expected) -> Result<Cow<'de, [u8]>> {
+        self.input
+            .into_bytes()
+            .map_err(|input| invalid_type(unexpected(input.value()), exp))
+    }
+
+    /// Converts `Value::String` into an `str`, borrowing it if possible.
+    fn str(self, exp: &dyn de::Expected) -> Result<Cow<'de, str>> {
+        let policy = self.config.invalid_utf8;
+        match self.bytes(exp)? {
+            Cow::Borrowed(bytes) => to_str(bytes, policy, exp),
+            Cow::Owned(bytes) => into_string(bytes, policy, exp).map(Cow::Owned),
         }
     }
 
+    fn elements(self, exp: &dyn de::Expected) -> Result<SeqAccess<I::Elements>> {
+        let config = self.config;
+        match self.input.into_elements() {
+            Ok(it) => Ok(SeqAccess { config, it }),
+            Err(input) => Err(invalid_type(unexpected(input.value()), exp)),
+        }
+    }
+
+    fn entries(self, exp: &dyn de::Expected) -> Result<I::Entries> {
+        self.input
+            .into_entries()
+            .map_err(|input| invalid_type(unexpected(input.value()), exp))
+    }
+
+    fn fields(self, exp: &dyn de::Expected) -> Result<MapAccess<I, I::Entries>> {
+        let config = self.config;
+        Ok(MapAccess::new(config, self.entries(exp)?))
+    }
+
+    /// Reads `Value::Int`, or converts other numbers into `i64` if `coerce_numbers` is set.
+    fn int(&self, exp: &dyn de::Expected) -> Result<i64> {
+        use watson_rs::Value::*;
+        let n = match *self.input.value() {
+            Int(n) => return Ok(n),
+            Uint(n) if self.config.coerce_numbers => i64::try_from(n).ok(),
+            Float(f) if self.config.coerce_numbers => {
+                // The upper bound is 2^63, which is out of the range of `i64`.
+                exact_integer(f, -(2f64.powi(63))..2f64.powi(63)).map(|f| f as i64)
+            }
+            _ => return Err(self.invalid_type(exp)),
+        };
+        n.ok_or_else(|| de::Error::invalid_value(self.ty(), exp))
+    }
+
+    /// Reads `Value::Uint`, or converts other numbers into `u64` if `coerce_numbers` is set.
+    fn uint(&self, exp: &dyn de::Expected) -> Result<u64> {
+        use watson_rs::Value::*;
+        let n = match *self.input.value() {
+            Uint(n) => return Ok(n),
+            Int(n) if self.config.coerce_numbers => u64::try_from(n).ok(),
+            Float(f) if self.config.coerce_numbers => {
+                exact_integer(f, 0.0..2f64.powi(64)).map(|f| f as u64)
+            }
+            _ => return Err(self.invalid_type(exp)),
+        };
+        n.ok_or_else(|| de::Error::invalid_value(self.ty(), exp))
+    }
+
+    /// Reads `Value::Float`, or converts integers into `f64` if `coerce_numbers` is set and they are exactly
+    /// representable. NaN and infinities are rejected if `reject_non_finite_floats` is set.
+    fn float(&self, exp: &dyn de::Expected) -> Result<f64> {
+        use watson_rs::Value::*;
+        let f = match *self.input.value() {
+            Float(f) if !f.is_finite() && self.config.reject_non_finite_floats => {
+                return Err(Error::non_finite_float())
+            }
+            Float(f) => return Ok(f),
+            Int(n) if self.config.coerce_numbers => {
+                Some(n as f64).filter(|&f| f as i128 == n as i128)
+            }
+            Uint(n) if self.config.coerce_numbers => {
+                Some(n as f64).filter(|&f| f as u128 == n as u128)
+            }
+            _ => return Err(self.invalid_type(exp)),
+        };
+        f.ok_or_else(|| de::Error::invalid_value(self.ty(), exp))
+    }
+
     fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
         invalid_type(self.ty(), exp)
     }
 
     fn ty(&self) -> de::Unexpected<'_> {
-        use watson_rs::Value::*;
-        match *self.value {
-            Int(n) => de::Unexpected::Signed(n),
-            Uint(n) => de::Unexpected::Unsigned(n),
-            Float(f) => de::Unexpected::Float(f),
-            String(ref bs) => de::Unexpected::Bytes(bs.as_slice()),
-            Object(_) => de::Unexpected::Map,
-            Array(_) => de::Unexpected::Seq,
-            Bool(b) => de::Unexpected::Bool(b),
-            Nil => de::Unexpected::Unit,
-        }
+        unexpected(self.input.value())
     }
 }
 
-impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
+impl<'de, I: Input<'de>> de::Deserializer<'de> for Deserializer<I> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -82,11 +403,22 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
         V: de::Visitor<'de>,
     {
         use watson_rs::Value::*;
-        match *self.value {
+        match *self.input.value() {
             Int(_) => self.deserialize_i64(visitor),
             Uint(_) => self.deserialize_u64(visitor),
             Float(_) => self.deserialize_f64(visitor),
-            String(_) => self.deserialize_bytes(visitor),
+            // Strings are reported as `str` if possible so that buffering deserializers (e.g. for
+            // `#[serde(flatten)]`) can use them as enum variants and identifiers.
+            String(_) => match self.bytes(&visitor)? {
+                Cow::Borrowed(bytes) => match core::str::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                },
+                Cow::Owned(bytes) => match alloc::string::String::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_string(s),
+                    Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+                },
+            },
             Object(_) => self.deserialize_map(visitor),
             Array(_) => self.deserialize_seq(visitor),
             Bool(_) => self.deserialize_bool(visitor),
@@ -98,8 +430,8 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Bool(b) => visitor.visit_bool(b),
+        match *self.input.value() {
+            watson_rs::Value::Bool(b) => visitor.visit_bool(b),
             _ => Err(self.invalid_type(&visitor)),
         }
     }
@@ -108,107 +440,88 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Int(n) => visitor.visit_i64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.int(&visitor)?;
+        visitor.visit_i64(n)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Int(n) => visitor.visit_i64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.int(&visitor)?;
+        visitor.visit_i64(n)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Int(n) => visitor.visit_i64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.int(&visitor)?;
+        visitor.visit_i64(n)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Int(n) => visitor.visit_i64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.int(&visitor)?;
+        visitor.visit_i64(n)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Uint(n) => visitor.visit_u64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.uint(&visitor)?;
+        visitor.visit_u64(n)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Uint(n) => visitor.visit_u64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.uint(&visitor)?;
+        visitor.visit_u64(n)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Uint(n) => visitor.visit_u64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.uint(&visitor)?;
+        visitor.visit_u64(n)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Uint(n) => visitor.visit_u64(n),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.uint(&visitor)?;
+        visitor.visit_u64(n)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Float(f) => visitor.visit_f64(f),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.float(&visitor)?;
+        visitor.visit_f64(n)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Float(f) => visitor.visit_f64(f),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let n = self.float(&visitor)?;
+        visitor.visit_f64(n)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let mut chars = self.borrow_str(&visitor)?.chars();
+        let s = self.str(&visitor)?;
+        let mut chars = s.chars();
         match chars.next() {
             None => Err(invalid_value("empty byte sequence", &visitor)),
             Some(c) => {
@@ -228,25 +541,25 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let s = self.borrow_str(&visitor)?;
-        visitor.visit_borrowed_str(s)
+        let s = self.str(&visitor)?;
+        visit_str(s, visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let s = self.borrow_str(&visitor)?;
-        visitor.visit_string(s.to_owned())
+        let s = self.str(&visitor)?;
+        visitor.visit_string(s.into_owned())
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            watson_rs::Value::String(bytes) => visitor.visit_borrowed_bytes(bytes.as_slice()),
-            _ => Err(self.invalid_type(&visitor)),
+        match self.bytes(&visitor)? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
         }
     }
 
@@ -254,18 +567,16 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            watson_rs::Value::String(bytes) => visitor.visit_byte_buf(bytes.clone()),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let bytes = self.bytes(&visitor)?;
+        visitor.visit_byte_buf(bytes.into_owned())
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Nil => visitor.visit_none(),
+        match self.input.value() {
+            watson_rs::Value::Nil => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
@@ -274,8 +585,8 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            &watson_rs::Value::Nil => visitor.visit_unit(),
+        match self.input.value() {
+            watson_rs::Value::Nil => visitor.visit_unit(),
             _ => Err(self.invalid_type(&visitor)),
         }
     }
@@ -298,10 +609,8 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            watson_rs::Value::Array(vec) => visitor.visit_seq(SeqAccess::new(vec)),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let elements = self.elements(&visitor)?;
+        visitor.visit_seq(elements)
     }
 
     fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
@@ -327,10 +636,8 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            watson_rs::Value::Object(map) => visitor.visit_map(MapAccess::new(map)),
-            _ => Err(self.invalid_type(&visitor)),
-        }
+        let fields = self.fields(&visitor)?;
+        visitor.visit_map(fields)
     }
 
     fn deserialize_struct<V>(
@@ -342,9 +649,10 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match *self.value {
-            watson_rs::Value::Array(ref vec) => visitor.visit_seq(SeqAccess::new(vec)),
-            watson_rs::Value::Object(ref map) => visitor.visit_map(MapAccess::new(map)),
+        use StructRepr::*;
+        match (self.config.strict_struct_repr, self.input.value()) {
+            (None | Some(Array), watson_rs::Value::Array(_)) => self.deserialize_seq(visitor),
+            (None | Some(Object), watson_rs::Value::Object(_)) => self.deserialize_map(visitor),
             _ => Err(self.invalid_type(&visitor)),
         }
     }
@@ -358,9 +666,51 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        match *self.value {
-            watson_rs::Value::String(ref name) => visitor.visit_enum(UnitVariantAccess::new(name)),
-            watson_rs::Value::Object(ref map) => visitor.visit_enum(NonUnitVariantAccess::new(map)),
+        let config = self.config;
+        match (config.enum_repr, self.input.value()) {
+            (EnumRepr::External, watson_rs::Value::String(_)) => {
+                let name = self.bytes(&visitor)?;
+                visitor.visit_enum(UnitVariantAccess::new(name))
+            }
+            (EnumRepr::External, watson_rs::Value::Object(map)) => {
+                if map.len() != 1 {
+                    return Err(error(ErrorKind::UnexpectedMap));
+                }
+                let (k, v) = self.entries(&visitor)?.next().unwrap();
+                visitor.visit_enum(EnumAccess {
+                    ctor: EnumCtorDeserializer::new(k),
+                    content: config.build_input(v),
+                })
+            }
+            (EnumRepr::Internal { tag }, watson_rs::Value::Object(_)) => {
+                let mut fields: Vec<_> = self.entries(&visitor)?.collect();
+                let i = fields
+                    .iter()
+                    .position(|(k, _)| k.as_ref() == tag.as_bytes())
+                    .ok_or_else(|| <Error as de::Error>::missing_field(tag))?;
+                let (_, name) = fields.remove(i);
+                visitor.visit_enum(EnumAccess {
+                    ctor: config.build_input(name),
+                    content: FieldsDeserializer(MapAccess::new(config, fields.into_iter())),
+                })
+            }
+            (EnumRepr::Adjacent { tag, content }, watson_rs::Value::Object(_)) => {
+                let (mut name, mut value) = (None, None);
+                for (k, v) in self.entries(&visitor)? {
+                    if k.as_ref() == tag.as_bytes() {
+                        name = Some(v);
+                    } else if k.as_ref() == content.as_bytes() {
+                        value = Some(v);
+                    }
+                }
+                let name = name.ok_or_else(|| <Error as de::Error>::missing_field(tag))?;
+                visitor.visit_enum(EnumAccess {
+                    ctor: config.build_input(name),
+                    // Unit variants have no content.
+                    content: config.build_input(value.unwrap_or_else(I::nil)),
+                })
+            }
+            (EnumRepr::Untagged, _) => Err(error(ErrorKind::UnrepresentableVariant)),
             _ => Err(self.invalid_type(&visitor)),
         }
     }
@@ -378,52 +728,114 @@ impl<'a, 'de> de::Deserializer<'de> for &'a Deserializer<'de> {
     {
         visitor.visit_unit()
     }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
+    }
 }
 
-struct SeqAccess<'de> {
-    arr: &'de Vec<watson_rs::Value>,
-    next: usize,
+/// Forwards methods of `de::Deserializer` to a copy of `*self`.
+macro_rules! forward_to_copy {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                de::Deserializer::$method(*self, $($arg,)* visitor)
+            }
+        )*
+    };
 }
 
-impl<'de> SeqAccess<'de> {
-    fn new(arr: &'de Vec<watson_rs::Value>) -> Self {
-        SeqAccess { arr, next: 0 }
+impl<'de> de::Deserializer<'de> for &Deserializer<&'de watson_rs::Value> {
+    type Error = Error;
+
+    forward_to_copy! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
     }
 }
 
-impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+struct SeqAccess<It> {
+    config: Config,
+    it: It,
+}
+
+impl<'de, I, It> de::SeqAccess<'de> for SeqAccess<It>
+where
+    I: Input<'de>,
+    It: ExactSizeIterator<Item = I>,
+{
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: de::DeserializeSeed<'de>,
     {
-        if self.arr.len() <= self.next {
-            Ok(None)
-        } else {
-            let i = self.next;
-            self.next += 1;
-            let next_elem = seed.deserialize(&Deserializer::new(&self.arr[i]))?;
-            Ok(Some(next_elem))
+        match self.it.next() {
+            None => Ok(None),
+            Some(v) => seed.deserialize(self.config.build_input(v)).map(Some),
         }
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.it.len())
+    }
 }
 
-struct MapAccess<'de> {
-    it: std::collections::hash_map::Iter<'de, watson_rs::Bytes, watson_rs::Value>,
-    next_value: Option<&'de watson_rs::Value>,
+struct MapAccess<I, It> {
+    config: Config,
+    it: It,
+    next_value: Option<I>,
 }
 
-impl<'de> MapAccess<'de> {
-    fn new(map: &'de watson_rs::Map) -> Self {
+impl<I, It> MapAccess<I, It> {
+    fn new(config: Config, it: It) -> Self {
         MapAccess {
-            it: map.iter(),
+            config,
+            it,
             next_value: None,
         }
     }
 }
 
-impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+impl<'de, I, It> de::MapAccess<'de> for MapAccess<I, It>
+where
+    I: Input<'de>,
+    It: ExactSizeIterator<Item = (Cow<'de, [u8]>, I)>,
+{
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -437,7 +849,7 @@ impl<'de> de::MapAccess<'de> for MapAccess<'de> {
             None => Ok(None),
             Some((k, v)) => {
                 self.next_value = Some(v);
-                let next_key = seed.deserialize(MapKeyDeserializer::new(k))?;
+                let next_key = seed.deserialize(MapKeyDeserializer::new(k, self.config))?;
                 Ok(Some(next_key))
             }
         }
@@ -449,32 +861,62 @@ impl<'de> de::MapAccess<'de> for MapAccess<'de> {
     {
         match self.next_value.take() {
             None => Err(error(ErrorKind::UnexpectedMapKey)),
-            Some(v) => seed.deserialize(&Deserializer::new(v)),
+            Some(v) => seed.deserialize(self.config.build_input(v)),
         }
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.it.len())
+    }
 }
 
+/// Deserializes a key of an object, which is either borrowed from a `Value` or owned.
 struct MapKeyDeserializer<'de> {
-    key: &'de watson_rs::Bytes,
+    key: Cow<'de, [u8]>,
+    config: Config,
 }
 
 impl<'de> MapKeyDeserializer<'de> {
-    fn new(k: &'de watson_rs::Bytes) -> Self {
-        MapKeyDeserializer { key: k }
+    fn new(key: impl Into<Cow<'de, [u8]>>, config: Config) -> Self {
+        MapKeyDeserializer {
+            key: key.into(),
+            config,
+        }
     }
 }
 
 impl<'de> MapKeyDeserializer<'de> {
     fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
-        invalid_type(de::Unexpected::Bytes(self.key.as_slice()), exp)
+        invalid_type(de::Unexpected::Bytes(&self.key), exp)
     }
 
     fn to_array<const N: usize>(&self, exp: &dyn de::Expected) -> Result<[u8; N]> {
         self.key
-            .as_slice()
+            .as_ref()
             .try_into()
             .map_err(|_| self.invalid_type(exp))
     }
+
+    fn number<T: core::str::FromStr, const N: usize>(
+        &self,
+        from_be_bytes: fn([u8; N]) -> T,
+        exp: &dyn de::Expected,
+    ) -> Result<T> {
+        match self.config.numeric_keys {
+            NumericKeyRepr::Binary => self.to_array(exp).map(from_be_bytes),
+            NumericKeyRepr::Decimal => core::str::from_utf8(&self.key)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| self.invalid_type(exp)),
+        }
+    }
+
+    fn str(self, exp: &dyn de::Expected) -> Result<Cow<'de, str>> {
+        match self.key {
+            Cow::Borrowed(k) => to_str(k, self.config.invalid_utf8, exp),
+            Cow::Owned(k) => into_string(k, self.config.invalid_utf8, exp).map(Cow::Owned),
+        }
+    }
 }
 
 impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
@@ -502,7 +944,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = i8::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(i8::from_be_bytes, &visitor)?;
         visitor.visit_i8(n)
     }
 
@@ -510,7 +952,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = i16::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(i16::from_be_bytes, &visitor)?;
         visitor.visit_i16(n)
     }
 
@@ -518,7 +960,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = i32::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(i32::from_be_bytes, &visitor)?;
         visitor.visit_i32(n)
     }
 
@@ -526,7 +968,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = i64::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(i64::from_be_bytes, &visitor)?;
         visitor.visit_i64(n)
     }
 
@@ -534,7 +976,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = u8::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(u8::from_be_bytes, &visitor)?;
         visitor.visit_u8(n)
     }
 
@@ -542,7 +984,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = u16::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(u16::from_be_bytes, &visitor)?;
         visitor.visit_u16(n)
     }
 
@@ -550,7 +992,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = u32::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(u32::from_be_bytes, &visitor)?;
         visitor.visit_u32(n)
     }
 
@@ -558,7 +1000,7 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let n = u64::from_be_bytes(self.to_array(&visitor)?);
+        let n = self.number(u64::from_be_bytes, &visitor)?;
         visitor.visit_u64(n)
     }
 
@@ -566,21 +1008,23 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = self.number(|b| f64::from_be_bytes(b) as f32, &visitor)?;
+        visitor.visit_f32(n)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = self.number(f64::from_be_bytes, &visitor)?;
+        visitor.visit_f64(n)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let s = try_borrow_str(self.key, &visitor)?;
+        let s = to_str(&self.key, self.config.invalid_utf8, &visitor)?;
         let mut chars = s.chars();
         let c = chars
             .next()
@@ -596,30 +1040,33 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let s = try_borrow_str(self.key, &visitor)?;
-        visitor.visit_borrowed_str(s)
+        let s = self.str(&visitor)?;
+        visit_str(s, visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let s = try_borrow_str(self.key, &visitor)?;
-        visitor.visit_string(s.to_owned())
+        let s = self.str(&visitor)?;
+        visitor.visit_string(s.into_owned())
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_borrowed_bytes(self.key.as_slice())
+        match self.key {
+            Cow::Borrowed(k) => visitor.visit_borrowed_bytes(k),
+            Cow::Owned(k) => visitor.visit_byte_buf(k),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_byte_buf(self.key.clone())
+        visitor.visit_byte_buf(self.key.into_owned())
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
@@ -633,14 +1080,18 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        if self.key.is_empty() {
+            visitor.visit_unit()
+        } else {
+            Err(self.invalid_type(&visitor))
+        }
     }
 
     fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        self.deserialize_unit(visitor)
     }
 
     fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
@@ -654,26 +1105,29 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(MapKeySeqAccess::new(self.key))
+        visitor.visit_seq(MapKeySeqAccess::new(&self.key))
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let mut access = MapKeySeqAccess::new(&self.key);
+        let v = visitor.visit_seq(&mut access)?;
+        access.end(len)?;
+        Ok(v)
     }
 
     fn deserialize_tuple_struct<V>(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
@@ -711,6 +1165,10 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
+        // Fields whose keys are not valid UTF-8 are named in the escaped form.
+        if core::str::from_utf8(&self.key).is_err() {
+            return visitor.visit_string(key::escape(&self.key));
+        }
         self.deserialize_str(visitor)
     }
 
@@ -722,50 +1180,66 @@ impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
     }
 }
 
-struct MapKeySeqAccess<'de> {
-    key: &'de watson_rs::Bytes,
-    next: usize,
+// Elements of a sequence are never borrowed, so the key may live shorter than `'de`.
+struct MapKeySeqAccess<'a> {
+    rest: &'a [u8],
 }
 
-impl<'de> MapKeySeqAccess<'de> {
-    fn new(key: &'de watson_rs::Bytes) -> Self {
-        MapKeySeqAccess { key, next: 0 }
+impl<'a> MapKeySeqAccess<'a> {
+    fn new(key: &'a [u8]) -> Self {
+        MapKeySeqAccess { rest: key }
+    }
+
+    /// Fails if there are bytes that are not consumed by the visitor.
+    fn end(&self, len: usize) -> Result<()> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(de::Error::invalid_length(len, &"fewer elements in map key"))
+        }
     }
 }
 
-impl<'de> de::SeqAccess<'de> for MapKeySeqAccess<'de> {
+impl<'de> de::SeqAccess<'de> for MapKeySeqAccess<'_> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: de::DeserializeSeed<'de>,
     {
-        if self.key.len() <= self.next {
+        if self.rest.is_empty() {
             Ok(None)
         } else {
-            let i = self.next;
-            self.next += 1;
-            let v = seed.deserialize(MapKeyByteDeserializer::new(self.key[i]))?;
+            let v = seed.deserialize(MapKeyElemDeserializer {
+                rest: &mut self.rest,
+            })?;
             Ok(Some(v))
         }
     }
 }
 
-struct MapKeyByteDeserializer {
-    b: u8,
+/// Deserializes an element of a sequence encoded in a map key.
+/// Each element takes as many bytes as `MapKeyDeserializer` uses for the same type.
+struct MapKeyElemDeserializer<'a, 'b> {
+    rest: &'a mut &'b [u8],
 }
 
-impl MapKeyByteDeserializer {
-    fn new(b: u8) -> Self {
-        MapKeyByteDeserializer { b }
+impl MapKeyElemDeserializer<'_, '_> {
+    fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
+        invalid_type(de::Unexpected::Bytes(self.rest), exp)
     }
 
-    fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
-        invalid_type(de::Unexpected::Unsigned(self.b as u64), exp)
+    fn take<const N: usize>(&mut self, exp: &dyn de::Expected) -> Result<[u8; N]> {
+        if self.rest.len() < N {
+            return Err(self.invalid_type(exp));
+        }
+        let (head, tail) = self.rest.split_at(N);
+        *self.rest = tail;
+        Ok(head.try_into().unwrap())
     }
 }
 
-impl<'de> de::Deserializer<'de> for MapKeyByteDeserializer {
+impl<'de> de::Deserializer<'de> for MapKeyElemDeserializer<'_, '_> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -775,88 +1249,109 @@ impl<'de> de::Deserializer<'de> for MapKeyByteDeserializer {
         Err(self.invalid_type(&visitor))
     }
 
-    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_bool<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        match self.take::<1>(&visitor)? {
+            [0] => visitor.visit_bool(false),
+            [1] => visitor.visit_bool(true),
+            _ => Err(self.invalid_type(&visitor)),
+        }
     }
 
-    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_i8<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = i8::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_i8(n)
     }
 
-    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_i16<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = i16::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_i16(n)
     }
 
-    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_i32<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = i32::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_i32(n)
     }
 
-    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_i64<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = i64::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_i64(n)
     }
 
-    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_u8<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_u8(self.b)
+        let n = u8::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_u8(n)
     }
 
-    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_u16<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = u16::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_u16(n)
     }
 
-    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_u32<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = u32::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_u32(n)
     }
 
-    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_u64<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = u64::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_u64(n)
     }
 
-    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_f32<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = f64::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_f32(n as f32)
     }
 
-    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_f64<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        let n = f64::from_be_bytes(self.take(&visitor)?);
+        visitor.visit_f64(n)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        // UTF-8 tells the length of a character by its first byte.
+        let len = (1..=4)
+            .find(|&n| n <= self.rest.len() && core::str::from_utf8(&self.rest[..n]).is_ok())
+            .ok_or_else(|| invalid_utf8(&visitor))?;
+        let (head, tail) = self.rest.split_at(len);
+        *self.rest = tail;
+        let c = core::str::from_utf8(head).unwrap().chars().next().unwrap();
+        visitor.visit_char(c)
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
@@ -912,7 +1407,7 @@ impl<'de> de::Deserializer<'de> for MapKeyByteDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
@@ -979,25 +1474,27 @@ impl<'de> de::Deserializer<'de> for MapKeyByteDeserializer {
         Err(self.invalid_type(&visitor))
     }
 
-    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(self.invalid_type(&visitor))
+        // The width of the element is unknown, so it is regarded as a single byte.
+        self.take::<1>(&visitor)?;
+        visitor.visit_unit()
     }
 }
 
 struct EnumCtorDeserializer<'de> {
-    name: &'de watson_rs::Bytes,
+    name: Cow<'de, [u8]>,
 }
 
 impl<'de> EnumCtorDeserializer<'de> {
-    fn new(name: &'de watson_rs::Bytes) -> Self {
-        EnumCtorDeserializer { name }
+    fn new(name: impl Into<Cow<'de, [u8]>>) -> Self {
+        EnumCtorDeserializer { name: name.into() }
     }
 
     fn invalid_type(&self, exp: &dyn de::Expected) -> Error {
-        invalid_type(de::Unexpected::Bytes(self.name.as_slice()), exp)
+        invalid_type(de::Unexpected::Bytes(&self.name), exp)
     }
 }
 
@@ -1212,8 +1709,16 @@ impl<'de> de::Deserializer<'de> for EnumCtorDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let s = try_borrow_str(self.name, &visitor)?;
-        visitor.visit_borrowed_str(s)
+        match self.name {
+            Cow::Borrowed(name) => {
+                let s = core::str::from_utf8(name).map_err(|_| invalid_utf8(&visitor))?;
+                visitor.visit_borrowed_str(s)
+            }
+            Cow::Owned(name) => {
+                let s = String::from_utf8(name).map_err(|_| invalid_utf8(&visitor))?;
+                visitor.visit_string(s)
+            }
+        }
     }
 
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
@@ -1225,12 +1730,12 @@ impl<'de> de::Deserializer<'de> for EnumCtorDeserializer<'de> {
 }
 
 struct UnitVariantAccess<'de> {
-    name: &'de watson_rs::Bytes,
+    name: Cow<'de, [u8]>,
 }
 
 impl<'de> UnitVariantAccess<'de> {
-    fn new(name: &'de watson_rs::Bytes) -> Self {
-        UnitVariantAccess { name }
+    fn new(name: impl Into<Cow<'de, [u8]>>) -> Self {
+        UnitVariantAccess { name: name.into() }
     }
 }
 
@@ -1238,11 +1743,11 @@ impl<'de> de::EnumAccess<'de> for UnitVariantAccess<'de> {
     type Error = Error;
     type Variant = Self;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: de::DeserializeSeed<'de>,
     {
-        let v = seed.deserialize(EnumCtorDeserializer::new(self.name))?;
+        let v = seed.deserialize(EnumCtorDeserializer::new(core::mem::take(&mut self.name)))?;
         Ok((v, self))
     }
 }
@@ -1285,70 +1790,111 @@ impl<'de> de::VariantAccess<'de> for UnitVariantAccess<'de> {
     }
 }
 
-struct NonUnitVariantAccess<'de> {
-    map: &'de watson_rs::Map,
-}
-
-impl<'de> NonUnitVariantAccess<'de> {
-    fn new(map: &'de watson_rs::Map) -> Self {
-        NonUnitVariantAccess { map }
-    }
+/// Reads a variant whose name is read by `C` and whose content is read by `A`.
+struct EnumAccess<C, A> {
+    ctor: C,
+    content: A,
 }
 
-impl<'de> de::EnumAccess<'de> for NonUnitVariantAccess<'de> {
+impl<'de, C, A> de::EnumAccess<'de> for EnumAccess<C, A>
+where
+    C: de::Deserializer<'de, Error = Error>,
+    A: de::VariantAccess<'de, Error = Error>,
+{
     type Error = Error;
-    type Variant = VariantFieldAccess<'de>;
+    type Variant = A;
 
     fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: de::DeserializeSeed<'de>,
     {
-        if self.map.len() != 1 {
-            Err(error(ErrorKind::UnexpectedMap))
-        } else {
-            let (k, v) = self.map.iter().next().unwrap();
-            let ctor = seed.deserialize(EnumCtorDeserializer::new(k))?;
-            Ok((ctor, VariantFieldAccess::new(v)))
-        }
+        let ctor = seed.deserialize(self.ctor)?;
+        Ok((ctor, self.content))
     }
 }
 
-struct VariantFieldAccess<'de> {
-    value: &'de watson_rs::Value,
-}
+/// Reads the content of an externally or adjacently tagged variant.
+impl<'de, I: Input<'de>> de::VariantAccess<'de> for Deserializer<I> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
 
-impl<'de> VariantFieldAccess<'de> {
-    fn new(v: &'de watson_rs::Value) -> Self {
-        VariantFieldAccess { value: v }
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
     }
 }
 
-impl<'de> de::VariantAccess<'de> for VariantFieldAccess<'de> {
+/// Reads the fields of an internally tagged variant, which are left in the object after the tag is taken out.
+struct FieldsDeserializer<M>(M);
+
+impl<'de, M> de::VariantAccess<'de> for FieldsDeserializer<M>
+where
+    M: de::MapAccess<'de, Error = Error>,
+{
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        de::Deserialize::deserialize(&Deserializer::new(self.value))
+        Ok(())
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
     where
         T: de::DeserializeSeed<'de>,
     {
-        seed.deserialize(&Deserializer::new(self.value))
+        seed.deserialize(self)
     }
 
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        de::Deserializer::deserialize_seq(&Deserializer::new(self.value), visitor)
+        Err(error(ErrorKind::UnrepresentableVariant))
     }
 
-    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_map(self.0)
+    }
+}
+
+impl<'de, M> de::Deserializer<'de> for FieldsDeserializer<M>
+where
+    M: de::MapAccess<'de, Error = Error>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        de::Deserializer::deserialize_struct(&Deserializer::new(self.value), "", fields, visitor)
+        visitor.visit_map(self.0)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
@@ -1356,11 +1902,77 @@ impl<'de> de::VariantAccess<'de> for VariantFieldAccess<'de> {
  * Helper functions
  */
 
-fn try_borrow_str<'de, V>(bytes: &'de watson_rs::Bytes, visitor: &V) -> Result<&'de str>
+/// Returns `f` if it is an integer within the range.
+fn exact_integer(f: f64, range: core::ops::Range<f64>) -> Option<f64> {
+    (f.fract() == 0.0 && range.contains(&f)).then_some(f)
+}
+
+/// Describes `value` for error messages.
+fn unexpected(value: &watson_rs::Value) -> de::Unexpected<'_> {
+    use watson_rs::Value::*;
+    match *value {
+        Int(n) => de::Unexpected::Signed(n),
+        Uint(n) => de::Unexpected::Unsigned(n),
+        Float(f) => de::Unexpected::Float(f),
+        String(ref bs) => de::Unexpected::Bytes(bs.as_slice()),
+        Object(_) => de::Unexpected::Map,
+        Array(_) => de::Unexpected::Seq,
+        Bool(b) => de::Unexpected::Bool(b),
+        Nil => de::Unexpected::Unit,
+    }
+}
+
+fn to_str<'de>(
+    bytes: &'de [u8],
+    policy: InvalidUtf8,
+    exp: &dyn de::Expected,
+) -> Result<Cow<'de, str>> {
+    match core::str::from_utf8(bytes) {
+        Ok(s) => Ok(Cow::Borrowed(s)),
+        Err(_) => match policy {
+            InvalidUtf8::Error => Err(invalid_utf8(exp)),
+            InvalidUtf8::Replace => Ok(String::from_utf8_lossy(bytes)),
+            InvalidUtf8::Base64 => Ok(Cow::Owned(base64(bytes))),
+        },
+    }
+}
+
+/// Converts `bytes` into a `String` like `to_str`, without copying them if they are valid UTF-8.
+fn into_string(
+    bytes: watson_rs::Bytes,
+    policy: InvalidUtf8,
+    exp: &dyn de::Expected,
+) -> Result<String> {
+    String::from_utf8(bytes).or_else(|e| to_str(e.as_bytes(), policy, exp).map(Cow::into_owned))
+}
+
+fn visit_str<'de, V>(s: Cow<'de, str>, visitor: V) -> Result<V::Value>
 where
     V: de::Visitor<'de>,
 {
-    std::str::from_utf8(bytes.as_slice()).map_err(|_| invalid_utf8(visitor))
+    match s {
+        Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+        Cow::Owned(s) => visitor.visit_string(s),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
 }
 
 fn invalid_type(ty: de::Unexpected, exp: &dyn de::Expected) -> Error {
@@ -1400,6 +2012,114 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_from_str_with_options() -> Result<()> {
+        // {"": nil, "": false}
+        let text = watson_rs::asm::assemble_text("Onew Snew Nnew Oadd Snew Bnew Oadd")?;
+        let entry = |v| Object([(vec![], v)].into_iter().collect());
+        let mut opts = watson_rs::DecodeOptions::default();
+        let text = std::str::from_utf8(&text).unwrap();
+        assert_eq!(from_str_with_options(text, &opts)?, entry(Bool(false)));
+        opts.duplicate_keys = watson_rs::vm::DuplicateKeys::FirstWins;
+        assert_eq!(from_str_with_options(text, &opts)?, entry(Nil));
+        #[cfg(feature = "std")]
+        assert_eq!(
+            from_reader_with_options(text.as_bytes(), &opts)?,
+            entry(Nil)
+        );
+
+        opts.duplicate_keys = watson_rs::vm::DuplicateKeys::Error;
+        let err = from_str_with_options(text, &opts).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ExecutionError(watson_rs::ErrorKind::DuplicateKey)
+        );
+        assert!(err.to_string().ends_with(r#": key "" is already defined"#));
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_borrowed() {
+        use std::borrow::Cow;
+        use std::collections::{BTreeMap, HashMap};
+
+        #[derive(Deserialize, Debug)]
+        struct S<'a> {
+            s: &'a str,
+            #[serde(borrow)]
+            cow: Cow<'a, str>,
+            #[serde(borrow)]
+            cow_bytes: Cow<'a, [u8]>,
+            bytes: &'a [u8],
+            opt: Option<&'a str>,
+            list: Vec<&'a str>,
+            str_keys: HashMap<&'a str, &'a str>,
+            byte_keys: BTreeMap<&'a [u8], &'a [u8]>,
+        }
+
+        let v = object![
+            s: String(b"a".to_vec()),
+            cow: String(b"b".to_vec()),
+            cow_bytes: String(b"c".to_vec()),
+            bytes: String(b"d".to_vec()),
+            opt: String(b"e".to_vec()),
+            list: array![String(b"f".to_vec())],
+            str_keys: object![g: String(b"h".to_vec())],
+            byte_keys: object![i: String(b"j".to_vec())],
+        ];
+        let x: S = deserialize(&v);
+        assert_eq!(x.s, "a");
+        assert!(matches!(x.cow, Cow::Borrowed("b")));
+        assert!(matches!(x.cow_bytes, Cow::Borrowed(b"c")));
+        assert_eq!(x.bytes, b"d");
+        assert_eq!(x.opt, Some("e"));
+        assert_eq!(x.list, vec!["f"]);
+        assert_eq!(x.str_keys.get("g"), Some(&"h"));
+        assert_eq!(x.byte_keys.get(&b"i"[..]), Some(&&b"j"[..]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn deserialize_serde_bytes() {
+        use std::collections::BTreeMap;
+
+        use serde_bytes::{ByteBuf, Bytes};
+
+        #[derive(serde::Serialize, Deserialize, Debug)]
+        struct S<'a> {
+            buf: ByteBuf,
+            #[serde(borrow)]
+            bytes: &'a Bytes,
+            keys: BTreeMap<ByteBuf, ByteBuf>,
+        }
+
+        let x = S {
+            buf: ByteBuf::from(b"\x00\xff".to_vec()),
+            bytes: Bytes::new(b"\x80"),
+            keys: BTreeMap::from([(ByteBuf::from(b"\xfe".to_vec()), ByteBuf::new())]),
+        };
+        let v = crate::from_str(&crate::to_string(&x).unwrap()).unwrap();
+        assert_eq!(
+            v,
+            object![
+                buf: String(b"\x00\xff".to_vec()),
+                bytes: String(b"\x80".to_vec()),
+                keys: Object([(b"\xfe".to_vec(), String(Vec::new()))].into_iter().collect()),
+            ]
+        );
+        let y: S = deserialize(&v);
+        assert_eq!(y.buf, x.buf);
+        assert_eq!(y.bytes, x.bytes);
+        assert_eq!(y.keys, x.keys);
+        match &v {
+            Object(map) => match map.get(&b"bytes"[..]) {
+                Some(String(b)) => assert_eq!(y.bytes.as_ptr(), b.as_ptr()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn deserialize_any() {
         assert_decodes(Value::new(Int(123)), &Int(123));
@@ -1602,8 +2322,11 @@ mod test {
     fn deserialize_map_key_any() {
         use crate::value::Value;
         use watson_rs::ToBytes;
-        let v = Value::deserialize(MapKeyDeserializer::new(&b"foo".to_bytes()))
-            .expect("deserialization error");
+        let v = Value::deserialize(MapKeyDeserializer::new(
+            b"foo".to_bytes(),
+            Config::default(),
+        ))
+        .expect("deserialization error");
         assert_eq!(v, Value::new(String(b"foo".to_vec())))
     }
 
@@ -1747,6 +2470,55 @@ mod test {
         );
     }
 
+    #[test]
+    fn deserialize_map_key_float() {
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct K<F>(F);
+        impl<F: PartialEq> Eq for K<F> {}
+        impl<F: Copy + Into<f64>> std::hash::Hash for K<F> {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.into().to_bits().hash(state)
+            }
+        }
+        type HM<F> = std::collections::HashMap<K<F>, i32>;
+
+        let v = object![
+            [b"\x3f\xf8\x00\x00\x00\x00\x00\x00"]: Int(1),
+            [b"\xff\xf0\x00\x00\x00\x00\x00\x00"]: Int(2),
+        ];
+        assert_decodes(
+            [(K(1.5_f64), 1), (K(f64::NEG_INFINITY), 2)]
+                .into_iter()
+                .collect::<HM<_>>(),
+            &v,
+        );
+        assert_decodes(
+            [(K(1.5_f32), 1), (K(f32::NEG_INFINITY), 2)]
+                .into_iter()
+                .collect::<HM<_>>(),
+            &v,
+        );
+        assert_decodes(
+            [((K(1.5_f64), 7_u8), 1)]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, i32>>(),
+            &object![[b"\x3f\xf8\x00\x00\x00\x00\x00\x00\x07"]: Int(1)],
+        );
+        assert!(HM::<f64>::deserialize(&Deserializer::new(&object![[b"\x3f"]: Int(1)])).is_err());
+
+        let config = Config {
+            numeric_keys: NumericKeyRepr::Decimal,
+            ..Default::default()
+        };
+        let v = object![[b"1.5"]: Int(1), [b"-inf"]: Int(2)];
+        assert_eq!(
+            HM::<f64>::deserialize(&config.build(&v)).unwrap(),
+            [(K(1.5), 1), (K(f64::NEG_INFINITY), 2)]
+                .into_iter()
+                .collect()
+        );
+    }
+
     #[test]
     fn deserialize_map_key_u64() {
         type HM<T> = std::collections::HashMap<u64, T>;
@@ -1909,6 +2681,115 @@ mod test {
         );
     }
 
+    #[test]
+    fn deserialize_map_key_tuple() {
+        #[derive(Eq, PartialEq, Hash, Deserialize, Debug)]
+        struct Key(u8, char);
+
+        type HM<K, T> = std::collections::HashMap<K, T>;
+
+        assert_decodes(
+            [((1_u8, -2_i16, true), 1), ((0xff, 0x100, false), 2)]
+                .into_iter()
+                .collect::<HM<_, i32>>(),
+            &object![
+                [b"\x01\xff\xfe\x01"]: Int(1),
+                [b"\xff\x01\x00\x00"]: Int(2),
+            ],
+        );
+        assert_decodes(
+            [(Key(b'a', '参'), 1)].into_iter().collect::<HM<_, i32>>(),
+            &object![[b"a\xe5\x8f\x82"]: Int(1)],
+        );
+        assert_decodes(
+            [(vec![0x1234_u16, 0x5678], 1)]
+                .into_iter()
+                .collect::<HM<_, i32>>(),
+            &object![[b"\x12\x34\x56\x78"]: Int(1)],
+        );
+
+        // The length of the key must match the tuple.
+        for key in [&b"\x01\x02"[..], b"\x01\x02\x03\x04"] {
+            let v = object![[key]: Int(1)];
+            assert!(HM::<(u8, u16), i32>::deserialize(&Deserializer::new(&v)).is_err());
+        }
+    }
+
+    #[test]
+    fn deserialize_map_key_unit() {
+        type HM<K, T> = std::collections::HashMap<K, T>;
+
+        assert_decodes(
+            [((), 1)].into_iter().collect::<HM<_, i32>>(),
+            &object![[b""]: Int(1)],
+        );
+        let v = object![[b"x"]: Int(1)];
+        assert!(HM::<(), i32>::deserialize(&Deserializer::new(&v)).is_err());
+    }
+
+    #[test]
+    fn deserialize_map_key_ignored_any() {
+        // Counts entries of an object while ignoring its keys.
+        #[derive(Eq, PartialEq, Debug)]
+        struct Count<K>(usize, std::marker::PhantomData<K>);
+
+        impl<'de, K: de::Deserialize<'de>> de::Deserialize<'de> for Count<K> {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                deserializer.deserialize_map(CountVisitor(std::marker::PhantomData))
+            }
+        }
+
+        struct CountVisitor<K>(std::marker::PhantomData<K>);
+
+        impl<'de, K: de::Deserialize<'de>> de::Visitor<'de> for CountVisitor<K> {
+            type Value = Count<K>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "map")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Count<K>, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                let mut n = 0;
+                while map.next_key::<K>()?.is_some() {
+                    map.next_value::<i32>()?;
+                    n += 1;
+                }
+                Ok(Count(n, std::marker::PhantomData))
+            }
+        }
+
+        let obj = object![foo: Int(1), bar: Int(2)];
+        assert_decodes(Count::<de::IgnoredAny>(2, std::marker::PhantomData), &obj);
+        assert_decodes(
+            Count::<Vec<de::IgnoredAny>>(2, std::marker::PhantomData),
+            &obj,
+        );
+    }
+
+    #[test]
+    fn deserialize_ignored_any() {
+        #[derive(Eq, PartialEq, Deserialize, Debug)]
+        struct S {
+            f1: i32,
+        }
+
+        assert_decodes(
+            S { f1: 123 },
+            &object![
+                f1: Int(123),
+                unknown: object![a: array![Nil, Float(1.5)]],
+                other: String(b"x".to_vec()),
+            ],
+        );
+        deserialize::<de::IgnoredAny>(&array![object![a: Nil], Bool(true)]);
+    }
+
     #[test]
     fn deserialize_struct() {
         #[derive(Eq, PartialEq, Deserialize, Debug)]
@@ -1927,6 +2808,358 @@ mod test {
         assert_decodes(S { f1: 456, f2: false }, &array![Int(456), Bool(false)]);
     }
 
+    #[test]
+    fn deserialize_human_readable() {
+        use std::net::Ipv4Addr;
+
+        let addr = Ipv4Addr::new(127, 0, 0, 1);
+        assert_decodes(addr, &String(b"127.0.0.1".to_vec()));
+
+        let config = Config {
+            human_readable: false,
+            ..Default::default()
+        };
+        let v = array![Uint(127), Uint(0), Uint(0), Uint(1)];
+        assert_eq!(Ipv4Addr::deserialize(&config.build(&v)).unwrap(), addr);
+        assert_eq!(Ipv4Addr::deserialize(config.build_owned(v)).unwrap(), addr);
+        assert!(Ipv4Addr::deserialize(&Deserializer::new(&String(vec![127, 0, 0, 1]))).is_err());
+    }
+
+    #[test]
+    fn deserialize_numeric_keys_decimal() {
+        type HM<K> = std::collections::HashMap<K, i64>;
+
+        let config = Config {
+            numeric_keys: NumericKeyRepr::Decimal,
+            ..Default::default()
+        };
+        let v = object![[b"258"]: Int(1), [b"-7"]: Int(2)];
+        let expected: HM<i16> = [(258, 1), (-7, 2)].into_iter().collect();
+        assert_eq!(HM::<i16>::deserialize(&config.build(&v)).unwrap(), expected);
+        assert_eq!(
+            HM::<i16>::deserialize(config.build_owned(v)).unwrap(),
+            expected
+        );
+
+        for invalid in [&b"\x01\x02"[..], b"x", b"70000", b""] {
+            let v = object![[invalid]: Int(1)];
+            assert!(HM::<i16>::deserialize(&config.build(&v)).is_err());
+        }
+        assert!(HM::<u8>::deserialize(&config.build(&object![[b"-1"]: Int(1)])).is_err());
+    }
+
+    #[test]
+    fn deserialize_reject_non_finite_floats() {
+        let config = Config {
+            reject_non_finite_floats: true,
+            ..Default::default()
+        };
+        for f in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let v = Float(f);
+            assert!(f64::deserialize(&Deserializer::new(&v)).is_ok());
+            let err = f64::deserialize(&config.build(&v)).unwrap_err();
+            assert_eq!(err.kind(), &ErrorKind::NonFiniteFloat);
+            assert!(f32::deserialize(config.build_owned(v)).is_err());
+        }
+        assert_eq!(f64::deserialize(&config.build(&Float(1.5))).unwrap(), 1.5);
+        let v = array![Float(f64::NAN)];
+        assert!(Value::deserialize(&config.build(&v)).is_err());
+    }
+
+    #[test]
+    fn deserialize_coerce_numbers() {
+        fn coerce<T: de::DeserializeOwned>(v: watson_rs::Value) -> Result<T> {
+            let config = Config {
+                coerce_numbers: true,
+                ..Default::default()
+            };
+            T::deserialize(&config.build(&v))
+        }
+
+        assert!(u32::deserialize(&Deserializer::new(&Int(5))).is_err());
+        assert_eq!(coerce::<u32>(Int(5)).unwrap(), 5);
+        assert_eq!(coerce::<u8>(Float(255.0)).unwrap(), 255);
+        assert_eq!(coerce::<i64>(Uint(7)).unwrap(), 7);
+        assert_eq!(coerce::<i16>(Float(-3.0)).unwrap(), -3);
+        assert_eq!(coerce::<f64>(Int(-2)).unwrap(), -2.0);
+        assert_eq!(coerce::<f32>(Uint(1 << 53)).unwrap(), 2f32.powi(53));
+
+        // Conversions that lose information fail.
+        assert!(coerce::<u64>(Int(-1)).is_err());
+        assert!(coerce::<u8>(Uint(256)).is_err());
+        assert!(coerce::<i64>(Uint(u64::MAX)).is_err());
+        assert!(coerce::<i32>(Float(1.5)).is_err());
+        assert!(coerce::<u64>(Float(f64::NAN)).is_err());
+        assert!(coerce::<i64>(Float(2f64.powi(63))).is_err());
+        assert!(coerce::<f64>(Int((1 << 53) + 1)).is_err());
+        assert!(coerce::<i32>(Bool(true)).is_err());
+    }
+
+    #[test]
+    fn deserialize_invalid_utf8() {
+        fn decode<T: de::DeserializeOwned>(policy: InvalidUtf8, v: watson_rs::Value) -> Result<T> {
+            let config = Config {
+                invalid_utf8: policy,
+                ..Default::default()
+            };
+            T::deserialize(&config.build(&v))
+        }
+
+        let invalid = || String(b"f\xff".to_vec());
+        assert!(decode::<std::string::String>(InvalidUtf8::Error, invalid()).is_err());
+        assert_eq!(
+            decode::<std::string::String>(InvalidUtf8::Replace, invalid()).unwrap(),
+            "f\u{fffd}"
+        );
+        assert_eq!(
+            decode::<std::string::String>(InvalidUtf8::Base64, invalid()).unwrap(),
+            "Zv8="
+        );
+        assert_eq!(
+            decode::<char>(InvalidUtf8::Replace, String(b"\xff".to_vec())).unwrap(),
+            '\u{fffd}'
+        );
+        // Valid strings are never converted.
+        assert_eq!(
+            decode::<std::string::String>(InvalidUtf8::Base64, String(b"foo".to_vec())).unwrap(),
+            "foo"
+        );
+
+        type HM = std::collections::HashMap<std::string::String, i64>;
+        let map = Object([(b"\xff".to_vec(), Int(2))].into_iter().collect());
+        let decoded = decode::<HM>(InvalidUtf8::Replace, map.clone()).unwrap();
+        assert_eq!(decoded.get("\u{fffd}"), Some(&2));
+        assert!(decode::<HM>(InvalidUtf8::Error, map).is_err());
+
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ] {
+            assert_eq!(base64(bytes), encoded);
+        }
+    }
+
+    #[test]
+    fn deserialize_strict_struct_repr() {
+        #[derive(Eq, PartialEq, Deserialize, Debug)]
+        struct S {
+            f1: i32,
+            f2: bool,
+        }
+
+        let obj = object![f1: Int(1), f2: Bool(true)];
+        let arr = array![Int(1), Bool(true)];
+        for (repr, accepted, rejected) in [
+            (StructRepr::Object, &obj, &arr),
+            (StructRepr::Array, &arr, &obj),
+        ] {
+            let de = Deserializer::new(accepted).with_strict_struct_repr(repr);
+            assert_eq!(S::deserialize(&de).unwrap(), S { f1: 1, f2: true });
+            let de = Deserializer::new(rejected).with_strict_struct_repr(repr);
+            assert!(S::deserialize(&de).is_err());
+            // The mode applies to nested values.
+            let nested = array![rejected.clone()];
+            let de = Deserializer::new(&nested).with_strict_struct_repr(repr);
+            assert!(Vec::<S>::deserialize(&de).is_err());
+        }
+    }
+
+    #[test]
+    fn deserialize_flatten() {
+        #[derive(PartialEq, Deserialize, Debug)]
+        enum E {
+            A,
+            B(i32),
+        }
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct Inner {
+            b: u32,
+            c: Option<std::string::String>,
+            e: E,
+        }
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct S {
+            a: std::string::String,
+            #[serde(flatten)]
+            inner: Inner,
+            #[serde(flatten)]
+            rest: std::collections::HashMap<std::string::String, Value>,
+        }
+
+        let rest: std::collections::HashMap<_, _> =
+            [("z".to_owned(), Value::new(Int(5)))].into_iter().collect();
+        assert_decodes(
+            S {
+                a: "x".to_owned(),
+                inner: Inner {
+                    b: 1,
+                    c: Some("y".to_owned()),
+                    e: E::A,
+                },
+                rest: rest.clone(),
+            },
+            &object![
+                a: String(b"x".to_vec()),
+                b: Uint(1),
+                c: String(b"y".to_vec()),
+                e: String(b"A".to_vec()),
+                z: Int(5),
+            ],
+        );
+        assert_decodes(
+            S {
+                a: "x".to_owned(),
+                inner: Inner {
+                    b: 2,
+                    c: None,
+                    e: E::B(3),
+                },
+                rest,
+            },
+            &object![
+                a: String(b"x".to_vec()),
+                b: Int(2),
+                c: Nil,
+                e: object![B: Int(3)],
+                z: Int(5),
+            ],
+        );
+    }
+
+    #[test]
+    fn deserialize_enum_repr() {
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct S {
+            x: i32,
+        }
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        enum E {
+            U,
+            N(S),
+            T(i32, i32),
+            St { x: i32 },
+        }
+
+        let name = |s: &str| String(s.as_bytes().to_vec());
+        let internal = EnumRepr::Internal { tag: "t" };
+        assert_eq!(
+            deserialize_with::<Vec<E>>(
+                internal,
+                &array![
+                    object![t: name("U")],
+                    object![t: name("N"), x: Int(1)],
+                    object![t: name("St"), x: Int(2)],
+                ]
+            )
+            .unwrap(),
+            vec![E::U, E::N(S { x: 1 }), E::St { x: 2 }],
+        );
+        assert!(deserialize_with::<E>(internal, &object![x: Int(1)]).is_err());
+        let err = deserialize_with::<E>(internal, &object![t: name("T")]).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnrepresentableVariant);
+
+        let adjacent = EnumRepr::Adjacent {
+            tag: "t",
+            content: "c",
+        };
+        assert_eq!(
+            deserialize_with::<Vec<E>>(
+                adjacent,
+                &array![
+                    object![t: name("U")],
+                    object![t: name("N"), c: object![x: Int(1)]],
+                    object![t: name("T"), c: array![Int(1), Int(2)]],
+                    object![t: name("St"), c: object![x: Int(2)]],
+                ]
+            )
+            .unwrap(),
+            vec![E::U, E::N(S { x: 1 }), E::T(1, 2), E::St { x: 2 }],
+        );
+
+        let err = deserialize_with::<E>(EnumRepr::Untagged, &Nil).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnrepresentableVariant);
+    }
+
+    #[test]
+    fn deserialize_owned() {
+        use std::collections::HashMap;
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        enum E {
+            U,
+            N(std::string::String),
+            T(i32, i32),
+            St { x: i32 },
+        }
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct S {
+            s: std::string::String,
+            buf: Buf,
+            opt: Option<u8>,
+            list: Vec<std::string::String>,
+            map: HashMap<std::string::String, i64>,
+            keys: HashMap<(u8, bool), ()>,
+            enums: Vec<E>,
+        }
+
+        let v = object![
+            s: String(b"hello".to_vec()),
+            buf: String(b"\xff".to_vec()),
+            opt: Uint(1),
+            list: array![String(b"a".to_vec())],
+            map: object![k: Int(-1)],
+            keys: Object([(b"\x02\x01".to_vec(), Nil)].into_iter().collect()),
+            enums: array![
+                String(b"U".to_vec()),
+                object![N: String(b"n".to_vec())],
+                object![T: array![Int(1), Int(2)]],
+                object![St: object![x: Int(3)]],
+            ],
+        ];
+        let expected: S = deserialize(&v);
+        let ptr = match &v {
+            Object(map) => match map.get(&b"s"[..]) {
+                Some(String(s)) => s.as_ptr(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let x = S::deserialize(Deserializer::from_owned(v)).unwrap();
+        assert_eq!(x, expected);
+        // The string is moved rather than copied.
+        assert_eq!(x.s.as_ptr(), ptr);
+
+        let name = |s: &str| String(s.as_bytes().to_vec());
+        let decode = |repr, v| E::deserialize(Deserializer::from_owned(v).with_enum_repr(repr));
+        let internal = EnumRepr::Internal { tag: "t" };
+        assert_eq!(decode(internal, object![t: name("U")]).unwrap(), E::U);
+        assert_eq!(
+            decode(internal, object![t: name("St"), x: Int(2)]).unwrap(),
+            E::St { x: 2 }
+        );
+        let err = decode(internal, object![t: name("T")]).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnrepresentableVariant);
+        let adjacent = EnumRepr::Adjacent {
+            tag: "t",
+            content: "c",
+        };
+        assert_eq!(decode(adjacent, object![t: name("U")]).unwrap(), E::U);
+        assert_eq!(
+            decode(adjacent, object![t: name("N"), c: name("n")]).unwrap(),
+            E::N("n".to_owned())
+        );
+
+        // Types that borrow from the input can't be deserialized.
+        assert!(<&str>::deserialize(Deserializer::from_owned(name("a"))).is_err());
+    }
+
     #[test]
     fn deserialize_enum() {
         #[derive(PartialEq, Deserialize, Debug)]
@@ -1976,6 +3209,13 @@ mod test {
         T::deserialize(&Deserializer::new(v)).expect("deserialization error")
     }
 
+    fn deserialize_with<'de, T>(repr: EnumRepr, v: &'de watson_rs::Value) -> Result<T>
+    where
+        T: fmt::Debug + de::Deserialize<'de>,
+    {
+        T::deserialize(&Deserializer::new(v).with_enum_repr(repr))
+    }
+
     // The standard `de::Deserialize` implementation for `Vec<u8>` does not use `deserialize_byte_buf`.
     #[derive(Eq, PartialEq, Hash, Debug)]
     struct Buf(Vec<u8>);
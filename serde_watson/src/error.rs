@@ -1,16 +1,19 @@
-use std::error::Error as StdError;
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::error::Error as StdError;
+use core::fmt;
 
 use serde::{de, ser};
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 /// Error represents an error when serializing to or deserializing from WATSON.
 #[derive(Debug)]
 pub struct Error {
     pub(crate) kind: ErrorKind,
     pub(crate) location: Option<watson_rs::Location>,
-    pub(crate) source: Option<Box<dyn StdError>>,
+    pub(crate) source: Option<Box<dyn StdError + Send + Sync>>,
 }
 
 impl Error {
@@ -28,15 +31,25 @@ impl Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.location() {
-            Some(loc) => write!(f, "{} at {}", self.kind, loc),
-            None => write!(f, "{} at unknown location", self.kind),
+            Some(loc) => write!(f, "{} at {}", self.kind, loc)?,
+            None => write!(f, "{} at unknown location", self.kind)?,
         }
+        let source = self
+            .source
+            .as_ref()
+            .and_then(|e| e.downcast_ref::<watson_rs::Error>());
+        if let Some(detail) = source.and_then(|e| e.duplicate_key()) {
+            write!(f, ": {detail}")?;
+        }
+        Ok(())
     }
 }
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
-        self.source.as_deref()
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn StdError + 'static))
     }
 }
 
@@ -63,9 +76,11 @@ impl de::Error for Error {
 
 impl From<watson_rs::Error> for Error {
     fn from(err: watson_rs::Error) -> Self {
+        // Errors that don't come from any source text (e.g. errors while serializing) have no location.
+        let location = Some(err.location.clone()).filter(|l| *l != watson_rs::Location::unknown());
         Error {
             kind: ErrorKind::ExecutionError(err.kind),
-            location: Some(err.location.clone()),
+            location,
             source: Some(Box::new(err)),
         }
     }
@@ -79,6 +94,30 @@ impl Error {
             source: None,
         }
     }
+
+    pub(crate) fn unrepresentable_variant() -> Self {
+        Error {
+            kind: ErrorKind::UnrepresentableVariant,
+            location: None,
+            source: None,
+        }
+    }
+
+    pub(crate) fn tag_collision() -> Self {
+        Error {
+            kind: ErrorKind::TagCollision,
+            location: None,
+            source: None,
+        }
+    }
+
+    pub(crate) fn non_finite_float() -> Self {
+        Error {
+            kind: ErrorKind::NonFiniteFloat,
+            location: None,
+            source: None,
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -95,6 +134,15 @@ pub enum ErrorKind {
     /// Unexpected map detected while deserializing.
     UnexpectedMap,
 
+    /// Enum variant can't be represented in the configured `EnumRepr`.
+    UnrepresentableVariant,
+
+    /// A field of an internally tagged variant has the same key as the tag.
+    TagCollision,
+
+    /// NaN or infinity is rejected by the configured policy.
+    NonFiniteFloat,
+
     /// An error occurred during VM execution.
     ExecutionError(watson_rs::error::ErrorKind),
 
@@ -109,8 +157,43 @@ impl fmt::Display for ErrorKind {
             ErrorKind::UnexpectedMapKey => write!(f, "Unexpected map key"),
             ErrorKind::UnexpectedMapValue => write!(f, "Unexpected map value"),
             ErrorKind::UnexpectedMap => write!(f, "Unexpected map"),
+            ErrorKind::UnrepresentableVariant => write!(f, "Unrepresentable variant"),
+            ErrorKind::TagCollision => write!(f, "Field collides with tag"),
+            ErrorKind::NonFiniteFloat => write!(f, "Non-finite float"),
             ErrorKind::ExecutionError(ref k) => k.fmt(f),
             ErrorKind::Custom(ref s) => write!(f, "{s}"),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_from_watson_error() {
+        let err = crate::from_str("?u").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ExecutionError(watson_rs::ErrorKind::TypeMismatch)
+        );
+        assert_eq!(err.location().map(|l| l.column), Some(2));
+        assert!(err.to_string().contains("line: 1, column: 2"));
+        assert!(err.source().is_some());
+
+        let err = Error::from(watson_rs::Error {
+            kind: watson_rs::ErrorKind::IOError,
+            location: watson_rs::Location::unknown(),
+            source: None,
+        });
+        assert_eq!(err.location(), None);
+        assert_eq!(err.to_string(), "I/O error at unknown location");
+    }
+
+    #[test]
+    fn custom_error() {
+        let err = <Error as ser::Error>::custom("oops");
+        assert_eq!(err.kind(), &ErrorKind::Custom("oops".to_owned()));
+        assert_eq!(err.to_string(), "oops at unknown location");
+    }
+}
@@ -0,0 +1,148 @@
+//! Keys of fields that are not valid UTF-8.
+//!
+//! Serde names fields with `&'static str`, so a field whose key is not valid UTF-8 is named with an escaped form,
+//! which is `U+FDD0` followed by the bytes of the key in lowercase hexadecimal.
+//! `Serializer` writes fields named in this form with the original bytes, and `Deserializer` passes keys that are
+//! not valid UTF-8 to identifiers in this form. With the `derive` feature, `#[watson_keys]` names fields this way
+//! from `#[watson(key = b"...")]`:
+//!
+//! ```ignore
+//! #[serde_watson::watson_keys]
+//! #[derive(Serialize, Deserialize)]
+//! struct S {
+//!     #[watson(key = b"\xff")]
+//!     raw: i32,
+//!     #[watson(rename = "other")]
+//!     renamed: i32,
+//! }
+//! ```
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde::ser;
+
+/// The first character of escaped names.
+pub const ESCAPE: char = '\u{fdd0}';
+
+/// Returns the name of a field whose key is `key`. Keys that are valid UTF-8 are left as they are.
+pub fn escape(key: &[u8]) -> String {
+    match core::str::from_utf8(key) {
+        Ok(s) => s.to_owned(),
+        Err(_) => {
+            let mut name = String::with_capacity(ESCAPE.len_utf8() + key.len() * 2);
+            name.push(ESCAPE);
+            for b in key {
+                name.push_str(&format!("{b:02x}"));
+            }
+            name
+        }
+    }
+}
+
+/// Returns the key of a field named by `escape`, or `None` if `name` is not escaped.
+pub fn unescape(name: &str) -> Option<Vec<u8>> {
+    let hex = name.strip_prefix(ESCAPE)?.as_bytes();
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            let pair = core::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// The key of a field, which is serialized into bytes if it is escaped.
+pub(crate) struct FieldKey(pub &'static str);
+
+impl ser::Serialize for FieldKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match unescape(self.0) {
+            Some(key) => serializer.serialize_bytes(&key),
+            None => serializer.serialize_str(self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escape_keys() {
+        assert_eq!(escape(b"foo"), "foo");
+        assert_eq!(escape(b"\x00\x01"), "\u{0}\u{1}");
+        assert_eq!(escape(b"f\xff"), "\u{fdd0}66ff");
+        for key in [&b"f\xff"[..], b"\xc3", b"\x80\x00"] {
+            assert_eq!(unescape(&escape(key)), Some(key.to_vec()));
+        }
+        assert_eq!(unescape("foo"), None);
+        assert_eq!(unescape("\u{fdd0}6"), None);
+        assert_eq!(unescape("\u{fdd0}xy"), None);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn watson_keys() {
+        use serde::{Deserialize, Serialize};
+        use watson_rs::Value::*;
+
+        use crate::de::Deserializer;
+
+        #[crate::watson_keys]
+        #[derive(PartialEq, Serialize, Deserialize, Debug)]
+        struct S {
+            #[watson(key = b"\x00\xff")]
+            raw: i32,
+            #[watson(rename = "other-name")]
+            renamed: bool,
+            plain: (),
+        }
+
+        #[crate::watson_keys]
+        #[derive(PartialEq, Serialize, Deserialize, Debug)]
+        enum E {
+            #[watson(rename = "a")]
+            A {
+                #[watson(key = b"\xfe")]
+                x: i32,
+            },
+        }
+
+        let s = S {
+            raw: 1,
+            renamed: true,
+            plain: (),
+        };
+        let v = crate::from_str(&crate::to_string(&s).unwrap()).unwrap();
+        assert_eq!(
+            v,
+            Object(
+                [
+                    (b"\x00\xff".to_vec(), Int(1)),
+                    (b"other-name".to_vec(), Bool(true)),
+                    (b"plain".to_vec(), Nil),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(S::deserialize(&Deserializer::new(&v)).unwrap(), s);
+        assert_eq!(S::deserialize(Deserializer::from_owned(v)).unwrap(), s);
+
+        let e = E::A { x: 2 };
+        let v = crate::from_str(&crate::to_string(&e).unwrap()).unwrap();
+        assert_eq!(
+            v,
+            watson_rs::object![a: Object([(b"\xfe".to_vec(), Int(2))].into_iter().collect())]
+        );
+        assert_eq!(E::deserialize(&Deserializer::new(&v)).unwrap(), e);
+    }
+}
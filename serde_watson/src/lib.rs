@@ -1,7 +1,38 @@
+//! Serde integration for WATSON.
+//!
+//! Without the default `std` feature, this crate itself only uses `alloc` and the entry points that read from
+//! `io::Read` or write to `io::Write` (e.g. `from_reader` and `to_writer`) are unavailable. The `Value`-based
+//! `Serializer` and `Deserializer` are available in either case.
+//! Note that `watson_rs` still depends on `std` (e.g. `std::io`), so this crate can't be built for targets without
+//! `std` yet.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "reqwest")]
+pub mod client;
 pub mod de;
 pub mod error;
+pub mod key;
+pub mod raw;
+pub mod repr;
 pub mod ser;
 pub mod value;
+pub mod with;
 
-pub use de::{from_reader, from_str};
+#[cfg(feature = "tokio")]
+pub use async_io::{from_reader_async, from_reader_async_with_options, to_writer_async};
+#[cfg(feature = "reqwest")]
+pub use client::{from_response, from_response_with_options};
+#[cfg(feature = "std")]
+pub use de::{from_reader, from_reader_with_options};
+pub use de::{from_str, from_str_with_options};
 pub use error::{Error, ErrorKind, Result};
+pub use raw::RawValue;
+pub use repr::{EnumRepr, NumericKeyRepr, StructRepr};
+#[cfg(feature = "std")]
+pub use ser::{to_string, to_vec, to_writer, to_writer_iter};
+#[cfg(feature = "derive")]
+pub use watson_derive::watson_keys;
@@ -0,0 +1,118 @@
+use serde::de;
+use serde::ser;
+
+use crate::de::Deserializer;
+use crate::error::Result;
+use crate::value::{Value, ValueRef};
+
+/// RawValue holds a part of a document as it is, deferring its deserialization.
+///
+/// It can be deserialized into a specific type later by `deserialize_into`, or serialized again without being
+/// converted into any other type.
+#[derive(PartialEq, Clone, Debug)]
+pub struct RawValue {
+    value: watson_rs::Value,
+}
+
+impl RawValue {
+    /// Returns a new `RawValue`.
+    pub fn new(v: watson_rs::Value) -> Self {
+        RawValue { value: v }
+    }
+
+    /// Returns the underlying `watson::Value`.
+    pub fn get(&self) -> &watson_rs::Value {
+        &self.value
+    }
+
+    /// Returns the underlying `watson::Value`.
+    pub fn into_watson(self) -> watson_rs::Value {
+        self.value
+    }
+
+    /// Deserializes the value into `T`.
+    pub fn deserialize_into<'de, T>(&'de self) -> Result<T>
+    where
+        T: de::Deserialize<'de>,
+    {
+        T::deserialize(&Deserializer::new(&self.value))
+    }
+}
+
+impl From<watson_rs::Value> for RawValue {
+    fn from(v: watson_rs::Value) -> Self {
+        RawValue::new(v)
+    }
+}
+
+impl ser::Serialize for RawValue {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        ValueRef::new(&self.value).serialize(serializer)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for RawValue {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let v = Value::deserialize(deserializer)?;
+        Ok(RawValue::new(v.into_watson()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use watson_rs::Value::*;
+    use watson_rs::{array, object};
+
+    use super::*;
+
+    #[derive(Deserialize, Serialize, Debug)]
+    struct Envelope {
+        kind: std::string::String,
+        payload: RawValue,
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct Payload {
+        id: i32,
+        tags: Vec<std::string::String>,
+    }
+
+    #[test]
+    fn raw_value_defers_deserialization() -> Result<()> {
+        let doc = object![
+            kind: String(b"payload".to_vec()),
+            payload: object![id: Int(1), tags: array![String(b"a".to_vec())], extra: Nil],
+        ];
+        let env: Envelope = de::Deserialize::deserialize(&Deserializer::new(&doc))?;
+        assert_eq!(env.kind, "payload");
+        assert_eq!(
+            env.payload.deserialize_into::<Payload>()?,
+            Payload {
+                id: 1,
+                tags: vec!["a".to_owned()],
+            }
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn raw_value_is_forwarded_verbatim() -> Result<()> {
+        let payload = object![a: array![Uint(1), Float(1.5), Nil], b: Bool(true)];
+        let env = Envelope {
+            kind: "x".to_owned(),
+            payload: RawValue::new(payload.clone()),
+        };
+        let decoded = crate::from_str(&crate::to_string(&env)?)?;
+        let decoded: Envelope = de::Deserialize::deserialize(&Deserializer::new(&decoded))?;
+        assert_eq!(decoded.payload.get(), &payload);
+        Ok(())
+    }
+}
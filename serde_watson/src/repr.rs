@@ -0,0 +1,58 @@
+//! Representations of Rust data types in WATSON.
+
+/// `EnumRepr` specifies how enum variants are represented.
+///
+/// The examples below show how `E::V(S { x: 1 })` is represented, where `V` is a variant of an enum `E`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum EnumRepr {
+    /// `{"V": {"x": 1}}`, and a unit variant is represented as `"V"`.
+    #[default]
+    External,
+
+    /// `{tag: "V", "x": 1}`.
+    /// Only unit variants, struct variants and newtype variants that contain objects can be represented.
+    /// The tag is written before the fields, and a field with the same key as the tag results in
+    /// `ErrorKind::TagCollision`.
+    Internal { tag: &'static str },
+
+    /// `{tag: "V", content: {"x": 1}}`, and `content` is omitted for a unit variant.
+    Adjacent {
+        tag: &'static str,
+        content: &'static str,
+    },
+
+    /// `{"x": 1}`, and a unit variant is represented as nil.
+    /// Since the variant can't be determined from the value, enums can't be deserialized with this representation.
+    /// Use `#[serde(untagged)]` instead if you need to deserialize them.
+    Untagged,
+}
+
+/// `StructRepr` specifies how structs and struct variants are represented.
+///
+/// The examples below show how `S { x: 1, y: 2 }` is represented.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum StructRepr {
+    /// `{"x": 1, "y": 2}`.
+    #[default]
+    Object,
+
+    /// `[1, 2]`, which is smaller but depends on the order of the fields.
+    /// Fields that are skipped with `#[serde(skip_serializing_if = ...)]` shift the following fields.
+    /// Internally tagged struct variants are still represented as objects since they have to hold the tag.
+    Array,
+}
+
+/// `NumericKeyRepr` specifies how numbers in keys of maps are represented.
+///
+/// The examples below show how the key `258u16` is represented.
+/// Numbers in sequences and tuples in keys are always represented as `Binary`.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum NumericKeyRepr {
+    /// `"\x01\x02"`, the big-endian bytes of the number.
+    /// Floats (including `f32`) are represented as the 8 big-endian bytes of the bit pattern of `f64`.
+    #[default]
+    Binary,
+
+    /// `"258"`, the number in ASCII decimal, which is easier to read and to exchange with other implementations.
+    Decimal,
+}
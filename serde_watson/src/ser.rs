@@ -1,24 +1,181 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::io;
 
 use serde::ser;
+use watson_rs::language::Mode;
 use watson_rs::serializer;
 use watson_rs::serializer::WriteInsn;
 use watson_rs::unlexer;
-use watson_rs::{Insn, Value};
+use watson_rs::{Bytes, Insn, Value};
 
 use crate::error::{Error, Result};
+use crate::key::FieldKey;
+use crate::repr::{EnumRepr, NumericKeyRepr, StructRepr};
+
+/// Serializes the given value as WATSON text and writes it to the given writer.
+#[cfg(feature = "std")]
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + ser::Serialize,
+{
+    let mut ser = Serializer::from_writer(writer);
+    value.serialize(&mut ser)?;
+    ser.into_inner().into_inner()?;
+    Ok(())
+}
+
+/// Serializes the items of the given iterator as a WATSON array and writes it to the given writer.
+///
+/// Each item is written as soon as it is produced, so the items don't need to be collected beforehand.
+#[cfg(feature = "std")]
+pub fn to_writer_iter<W, I>(writer: W, iter: I) -> Result<()>
+where
+    W: io::Write,
+    I: IntoIterator,
+    I::Item: ser::Serialize,
+{
+    let mut ser = Serializer::from_writer(writer);
+    ser.serialize_iter(iter)?;
+    ser.into_inner().into_inner()?;
+    Ok(())
+}
+
+/// Serializes the given value as WATSON text.
+#[cfg(feature = "std")]
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + ser::Serialize,
+{
+    let mut buf = Vec::new();
+    to_writer(&mut buf, value)?;
+    Ok(buf)
+}
+
+/// Serializes the given value as WATSON text.
+#[cfg(feature = "std")]
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + ser::Serialize,
+{
+    let buf = to_vec(value)?;
+    Ok(String::from_utf8(buf).expect("WATSON text must be ASCII"))
+}
+
+/// Config configures a `Serializer`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Initial mode of the output (defaults to `A`).
+    /// This only applies to a `Serializer` that writes text (see `Serializer::with_config`).
+    pub initial_mode: Mode,
+
+    /// The output is broken into lines of `chars_per_line` characters, or never broken if set to zero
+    /// (defaults to the one of `unlexer::Config`).
+    /// This only applies to a `Serializer` that writes text (see `Serializer::with_config`).
+    pub chars_per_line: usize,
+
+    /// If set to true, entries of maps are emitted in the bytewise order of their keys, so that the output doesn't
+    /// depend on the iteration order of maps such as `HashMap` (defaults to false).
+    /// Fields of structs are always emitted in the order of their declarations.
+    pub sort_keys: bool,
+
+    /// The representation of enum variants (defaults to `EnumRepr::External`).
+    pub enum_repr: EnumRepr,
+
+    /// The representation of structs (defaults to `StructRepr::Object`).
+    pub struct_repr: StructRepr,
+
+    /// If set to true, fields of structs whose values are nil (e.g. `None` or `()`) are omitted (defaults to false).
+    /// This doesn't apply to structs represented as arrays, whose fields are identified by their positions.
+    pub skip_nil_fields: bool,
+
+    /// The value that `is_human_readable` returns (defaults to true).
+    /// If set to false, types such as `IpAddr` and `SocketAddr` choose compact representations instead of text.
+    /// Keys of maps are always serialized as if it were true.
+    pub human_readable: bool,
+
+    /// The representation of numbers in keys of maps (defaults to `NumericKeyRepr::Binary`).
+    pub numeric_keys: NumericKeyRepr,
+
+    /// How NaN and infinities are serialized (defaults to `NonFiniteFloats::Emit`).
+    pub non_finite_floats: NonFiniteFloats,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            initial_mode: Mode::A,
+            chars_per_line: unlexer::Config::default().chars_per_line,
+            sort_keys: false,
+            enum_repr: EnumRepr::default(),
+            struct_repr: StructRepr::default(),
+            skip_nil_fields: false,
+            human_readable: true,
+            numeric_keys: NumericKeyRepr::default(),
+            non_finite_floats: NonFiniteFloats::default(),
+        }
+    }
+}
+
+/// `NonFiniteFloats` specifies how NaN and infinities are serialized.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum NonFiniteFloats {
+    /// Emits them as they are.
+    #[default]
+    Emit,
+
+    /// Fails with `ErrorKind::NonFiniteFloat`, which is useful if the output is converted into formats such as
+    /// JSON that can't represent them.
+    Error,
+
+    /// Replaces them with nil.
+    Nil,
+}
+
+impl Config {
+    /// Returns a new `Serializer` that writes instructions to the given writer.
+    pub fn build<W>(self, writer: W) -> Serializer<W> {
+        let inner = serializer::Config {
+            sort_keys: self.sort_keys,
+            ..Default::default()
+        }
+        .build(writer);
+        Serializer {
+            inner,
+            config: self,
+        }
+    }
+}
 
 /// Serializer implements serde::ser::Serializer for WATSON encoding.
 pub struct Serializer<W> {
     inner: serializer::Serializer<W>,
+    config: Config,
 }
 
 impl<W> Serializer<W> {
     /// Returns a new `Serializer` that writes to the given writer.
     pub fn new(writer: W) -> Self {
-        Serializer {
-            inner: serializer::Serializer::new(writer),
-        }
+        Config::default().build(writer)
+    }
+
+    /// Sets the representation of enum variants.
+    pub fn with_enum_repr(mut self, repr: EnumRepr) -> Self {
+        self.config.enum_repr = repr;
+        self
+    }
+
+    /// Sets the representation of structs.
+    pub fn with_struct_repr(mut self, repr: StructRepr) -> Self {
+        self.config.struct_repr = repr;
+        self
+    }
+
+    /// Returns the configuration of this `Serializer`.
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
     /// Unwraps the inner value from this `Serializer`.
@@ -27,15 +184,96 @@ impl<W> Serializer<W> {
     }
 }
 
+impl<W> Serializer<W>
+where
+    W: WriteInsn,
+{
+    /// Serializes the items of the given iterator as an array, whose length doesn't need to be known in advance.
+    ///
+    /// Items are written to the underlying writer in small batches as the iterator produces them, so only a bounded
+    /// number of instructions is buffered.
+    /// The same applies to `serialize_seq`, so sequences of any size can also be serialized with `collect_seq`.
+    pub fn serialize_iter<I>(&mut self, iter: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: ser::Serialize,
+    {
+        ser::Serializer::collect_seq(self, iter)
+    }
+
+    /// Writes the entry that holds the name of the variant.
+    fn write_tag(&mut self, tag: &str, variant: &str) -> Result<()> {
+        ser::Serializer::serialize_str(&mut *self, tag)?;
+        ser::Serializer::serialize_str(&mut *self, variant)?;
+        self.inner.write(Insn::Oadd)?;
+        Ok(())
+    }
+
+    /// Serializes the given value into instructions with the same configuration as this `Serializer`.
+    fn to_insns<T>(&self, value: &T) -> Result<Vec<Insn>>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let mut insns = Vec::new();
+        value.serialize(&mut self.config.clone().build(&mut insns))?;
+        Ok(insns)
+    }
+
+    /// Serializes the given key of a map into bytes.
+    fn key_bytes<T>(&self, key: &T) -> Result<Bytes>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let mut bytes = Vec::new();
+        key.serialize(MapKeySerializer {
+            key: &mut bytes,
+            numeric_keys: self.config.numeric_keys,
+        })?;
+        Ok(bytes)
+    }
+
+    /// Returns true if the given value is serialized into nil, which is told by its outermost type alone.
+    fn is_nil<T>(&self, value: &T) -> Result<bool>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(NilDetector {
+            config: &self.config,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
 impl<W> Serializer<unlexer::Unlexer<W>>
 where
     W: io::Write,
 {
     /// Returns a new `Serializer` that writes to the given `io::Write`.
     pub fn from_writer(writer: W) -> Self {
-        Serializer {
-            inner: serializer::Serializer::new(unlexer::Unlexer::new(writer)),
+        Serializer::with_config(writer, Config::default())
+    }
+
+    /// Returns a new `Serializer` that writes text to the given `io::Write` with the given configuration.
+    pub fn with_config(writer: W, config: Config) -> Self {
+        let unlexer = unlexer::Config {
+            initial_mode: config.initial_mode,
+            chars_per_line: config.chars_per_line,
+            ..Default::default()
         }
+        .build(writer);
+        config.build(unlexer)
+    }
+
+    /// Returns a new `Serializer` that writes text to the given `io::Write` with the given configurations of itself
+    /// and the underlying `Unlexer`. `initial_mode` and `chars_per_line` of `config` are replaced with the ones of
+    /// `unlexer`.
+    pub fn from_writer_with_config(writer: W, config: Config, unlexer: unlexer::Config) -> Self {
+        let config = Config {
+            initial_mode: unlexer.initial_mode,
+            chars_per_line: unlexer.chars_per_line,
+            ..config
+        };
+        config.build(unlexer.build(writer))
     }
 }
 
@@ -99,12 +337,16 @@ where
     }
 
     fn serialize_f32(self, v: f32) -> Result<()> {
-        self.inner.serialize(&Value::Float(v as f64))?;
-        Ok(())
+        self.serialize_f64(v as f64)
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
-        self.inner.serialize(&Value::Float(v))?;
+        let v = match self.config.non_finite_floats {
+            NonFiniteFloats::Error if !v.is_finite() => return Err(Error::non_finite_float()),
+            NonFiniteFloats::Nil if !v.is_finite() => Value::Nil,
+            _ => Value::Float(v),
+        };
+        self.inner.serialize(&v)?;
         Ok(())
     }
 
@@ -118,7 +360,7 @@ where
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        self.inner.serialize(&Value::String(v.to_vec()))?;
+        self.inner.string(v)?;
         Ok(())
     }
 
@@ -150,17 +392,27 @@ where
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<()> {
-        self.serialize_str(variant)
+        match self.config.enum_repr {
+            EnumRepr::External => self.serialize_str(variant),
+            EnumRepr::Internal { tag } | EnumRepr::Adjacent { tag, .. } => {
+                self.inner.begin_object()?;
+                self.write_tag(tag, variant)?;
+                self.inner.end_object()?;
+                Ok(())
+            }
+            EnumRepr::Untagged => self.serialize_unit(),
+        }
     }
 
     fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        self.inner.write(Insn::Onew)?;
+        self.inner.begin_object()?;
         self.serialize_str(name)?;
         value.serialize(&mut *self)?;
         self.inner.write(Insn::Oadd)?;
+        self.inner.end_object()?;
         Ok(())
     }
 
@@ -174,16 +426,38 @@ where
     where
         T: ?Sized + ser::Serialize,
     {
-        self.inner.write(Insn::Onew)?;
-        self.serialize_str(variant)?;
+        match self.config.enum_repr {
+            EnumRepr::External => {
+                self.inner.begin_object()?;
+                self.serialize_str(variant)?;
+            }
+            EnumRepr::Internal { tag } => {
+                // The tag is written first into the object that the value is serialized into.
+                return value.serialize(InternallyTagged {
+                    ser: self,
+                    tag,
+                    variant,
+                });
+            }
+            EnumRepr::Adjacent { tag, content } => {
+                self.inner.begin_object()?;
+                self.write_tag(tag, variant)?;
+                self.serialize_str(content)?;
+            }
+            EnumRepr::Untagged => return value.serialize(self),
+        }
         value.serialize(&mut *self)?;
         self.inner.write(Insn::Oadd)?;
+        self.inner.end_object()?;
         Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.inner.write(Insn::Anew)?;
-        Ok(SerializeSeq { ser: self })
+        self.inner.begin_array()?;
+        Ok(SerializeSeq {
+            ser: self,
+            in_variant: false,
+        })
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
@@ -205,18 +479,40 @@ where
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.inner.write(Insn::Onew)?;
-        self.serialize_str(variant)?;
-        self.serialize_seq(None)
+        match self.config.enum_repr {
+            EnumRepr::External => {
+                self.inner.begin_object()?;
+                self.serialize_str(variant)?;
+            }
+            EnumRepr::Internal { .. } => return Err(Error::unrepresentable_variant()),
+            EnumRepr::Adjacent { tag, content } => {
+                self.inner.begin_object()?;
+                self.write_tag(tag, variant)?;
+                self.serialize_str(content)?;
+            }
+            EnumRepr::Untagged => return self.serialize_seq(None),
+        }
+        self.inner.begin_array()?;
+        Ok(SerializeSeq {
+            ser: self,
+            in_variant: true,
+        })
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.inner.write(Insn::Onew)?;
-        Ok(SerializeMap { ser: self })
+        self.inner.begin_object()?;
+        let entries = self.config.sort_keys.then(Vec::new);
+        Ok(SerializeMap {
+            ser: self,
+            in_variant: false,
+            as_array: false,
+            entries,
+            tag: None,
+        })
     }
 
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        self.serialize_map(None)
+        SerializeMap::begin_struct(self, false)
     }
 
     fn serialize_struct_variant(
@@ -226,14 +522,42 @@ where
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.inner.write(Insn::Onew)?;
-        self.serialize_str(variant)?;
-        self.serialize_map(None)
+        match self.config.enum_repr {
+            EnumRepr::External => {
+                self.inner.begin_object()?;
+                self.serialize_str(variant)?;
+            }
+            EnumRepr::Internal { tag } => {
+                // Fields are added to the object that holds the tag.
+                self.inner.begin_object()?;
+                self.write_tag(tag, variant)?;
+                return Ok(SerializeMap {
+                    ser: self,
+                    in_variant: false,
+                    as_array: false,
+                    entries: None,
+                    tag: Some(tag),
+                });
+            }
+            EnumRepr::Adjacent { tag, content } => {
+                self.inner.begin_object()?;
+                self.write_tag(tag, variant)?;
+                self.serialize_str(content)?;
+            }
+            EnumRepr::Untagged => return SerializeMap::begin_struct(self, false),
+        }
+        SerializeMap::begin_struct(self, true)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
     }
 }
 
 pub struct SerializeSeq<'a, W> {
     ser: &'a mut Serializer<W>,
+    // Whether the sequence is wrapped in an object that holds the variant.
+    in_variant: bool,
 }
 
 impl<'a, W> ser::SerializeSeq for SerializeSeq<'a, W>
@@ -253,6 +577,7 @@ where
     }
 
     fn end(self) -> Result<()> {
+        self.ser.inner.end_array()?;
         Ok(())
     }
 }
@@ -316,13 +641,81 @@ where
     }
 
     fn end(self) -> Result<()> {
-        self.ser.inner.write(Insn::Oadd)?;
-        ser::SerializeSeq::end(self)
+        let in_variant = self.in_variant;
+        let ser = self.ser;
+        ser.inner.end_array()?;
+        if in_variant {
+            ser.inner.write(Insn::Oadd)?;
+            ser.inner.end_object()?;
+        }
+        Ok(())
     }
 }
 
 pub struct SerializeMap<'a, W> {
     ser: &'a mut Serializer<W>,
+    // Whether the map is wrapped in an object that holds the variant.
+    in_variant: bool,
+    // Whether the fields of a struct are written as elements of an array.
+    as_array: bool,
+    // Keys and values that are written in the order of the keys at the end, or `None` if they are not sorted.
+    entries: Option<Vec<(Bytes, Vec<Insn>)>>,
+    // The tag of the internally tagged variant that the object holds, which no other key may have.
+    tag: Option<&'static str>,
+}
+
+impl<'a, W> SerializeMap<'a, W>
+where
+    W: WriteInsn,
+{
+    fn begin_struct(ser: &'a mut Serializer<W>, in_variant: bool) -> Result<Self> {
+        let as_array = ser.config.struct_repr == StructRepr::Array;
+        if as_array {
+            ser.inner.begin_array()?;
+        } else {
+            ser.inner.begin_object()?;
+        }
+        Ok(SerializeMap {
+            ser,
+            in_variant,
+            as_array,
+            entries: None,
+            tag: None,
+        })
+    }
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        if self.as_array {
+            value.serialize(&mut *self.ser)?;
+            self.ser.inner.write(Insn::Aadd)?;
+            Ok(())
+        } else if self.ser.config.skip_nil_fields && self.ser.is_nil(value)? {
+            Ok(())
+        } else {
+            ser::SerializeMap::serialize_entry(self, &FieldKey(key), value)
+        }
+    }
+
+    /// Writes the sorted entries, if any, and ends the container.
+    fn end_entries(&mut self) -> Result<()> {
+        if let Some(mut entries) = self.entries.take() {
+            entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+            for (k, v) in entries {
+                ser::Serializer::serialize_bytes(&mut *self.ser, &k)?;
+                self.ser.inner.write_all(&v)?;
+                self.ser.inner.write(Insn::Oadd)?;
+            }
+        }
+        if self.as_array {
+            self.ser.inner.end_array()?;
+        } else {
+            self.ser.inner.end_object()?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a, W> ser::SerializeMap for SerializeMap<'a, W>
@@ -336,22 +729,40 @@ where
     where
         T: ?Sized + ser::Serialize,
     {
-        key.serialize(MapKeySerializer {
-            ser: &mut *self.ser,
-        })
+        let key = self.ser.key_bytes(key)?;
+        if self.tag.is_some_and(|tag| tag.as_bytes() == key) {
+            return Err(Error::tag_collision());
+        }
+        match self.entries.as_mut() {
+            Some(entries) => entries.push((key, Vec::new())),
+            None => self.ser.inner.string(&key)?,
+        }
+        Ok(())
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
+        if let Some(entries) = self.entries.as_mut() {
+            let insns = self.ser.to_insns(value)?;
+            match entries.last_mut() {
+                Some((_, v)) => *v = insns,
+                None => {
+                    return Err(ser::Error::custom(
+                        "serialize_value called before serialize_key",
+                    ))
+                }
+            }
+            return Ok(());
+        }
         value.serialize(&mut *self.ser)?;
         self.ser.inner.write(Insn::Oadd)?;
         Ok(())
     }
 
-    fn end(self) -> Result<()> {
-        Ok(())
+    fn end(mut self) -> Result<()> {
+        self.end_entries()
     }
 }
 
@@ -368,9 +779,7 @@ where
     where
         T: ?Sized + ser::Serialize,
     {
-        ser::SerializeMap::serialize_key(&mut *self, key)?;
-        ser::SerializeMap::serialize_value(&mut *self, value)?;
-        Ok(())
+        SerializeMap::serialize_field(self, key, value)
     }
 
     fn end(self) -> Result<()> {
@@ -391,127 +800,150 @@ where
     where
         T: ?Sized + ser::Serialize,
     {
-        ser::SerializeMap::serialize_entry(self, key, value)
+        SerializeMap::serialize_field(self, key, value)
     }
 
-    fn end(self) -> Result<()> {
-        self.ser.inner.write(Insn::Oadd)?;
-        ser::SerializeMap::end(self)
+    fn end(mut self) -> Result<()> {
+        self.end_entries()?;
+        if self.in_variant {
+            self.ser.inner.write(Insn::Oadd)?;
+            self.ser.inner.end_object()?;
+        }
+        Ok(())
     }
 }
 
-struct MapKeySerializer<'a, W> {
+/// Serializes the content of an internally tagged newtype variant into an object that holds the tag first.
+/// The content must be serialized into an object, such as a struct or a map.
+struct InternallyTagged<'a, W> {
     ser: &'a mut Serializer<W>,
+    tag: &'static str,
+    variant: &'static str,
+}
+
+impl<'a, W> InternallyTagged<'a, W>
+where
+    W: WriteInsn,
+{
+    fn begin_object(self, entries: Option<Vec<(Bytes, Vec<Insn>)>>) -> Result<SerializeMap<'a, W>> {
+        self.ser.inner.begin_object()?;
+        self.ser.write_tag(self.tag, self.variant)?;
+        Ok(SerializeMap {
+            ser: self.ser,
+            in_variant: false,
+            as_array: false,
+            entries,
+            tag: Some(self.tag),
+        })
+    }
 }
 
-impl<'a, W> ser::Serializer for MapKeySerializer<'a, W>
+impl<'a, W> ser::Serializer for InternallyTagged<'a, W>
 where
     W: WriteInsn,
 {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = SerializeMapKeySeq<'a, W>;
+    type SerializeSeq = ser::Impossible<(), Error>;
     type SerializeTuple = ser::Impossible<(), Error>;
     type SerializeTupleStruct = ser::Impossible<(), Error>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
-    type SerializeMap = ser::Impossible<(), Error>;
-    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeMap = SerializeMap<'a, W>;
+    type SerializeStruct = SerializeMap<'a, W>;
     type SerializeStructVariant = ser::Impossible<(), Error>;
 
-    fn serialize_bool(self, v: bool) -> Result<()> {
-        if v {
-            self.ser.serialize_bytes(&[1])
-        } else {
-            self.ser.serialize_bytes(&[0])
-        }
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_i8(self, v: i8) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_i16(self, v: i16) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_i32(self, v: i32) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_i64(self, v: i64) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_u8(self, v: u8) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_u16(self, v: u16) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_u32(self, v: u32) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_u64(self, v: u64) -> Result<()> {
-        self.ser.serialize_bytes(&v.to_be_bytes())
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_f32(self, _v: f32) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_f64(self, _v: f64) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_char(self, v: char) -> Result<()> {
-        let mut buf = [0; 4];
-        self.ser.serialize_str(v.encode_utf8(&mut buf))
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_str(self, v: &str) -> Result<()> {
-        self.ser.serialize_str(v)
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        self.ser.serialize_bytes(v)
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_none(self) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
-    fn serialize_some<T>(self, _value: &T) -> Result<()>
+    fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        Err(Error::key_must_be_bytes())
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
+    // Variants of the content would be internally tagged with the same tag.
     fn serialize_unit_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
+        _variant: &'static str,
     ) -> Result<()> {
-        self.ser.serialize_str(variant)
+        Err(Error::tag_collision())
     }
 
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        value.serialize(self)
+        let mut map = self.begin_object(None)?;
+        ser::SerializeMap::serialize_entry(&mut map, name, value)?;
+        ser::SerializeMap::end(map)
     }
 
     fn serialize_newtype_variant<T>(
@@ -524,18 +956,15 @@ where
     where
         T: ?Sized + ser::Serialize,
     {
-        Err(Error::key_must_be_bytes())
+        Err(Error::tag_collision())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.ser.inner.write(Insn::Snew)?;
-        Ok(SerializeMapKeySeq {
-            ser: &mut *self.ser,
-        })
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_tuple_struct(
@@ -543,7 +972,7 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_tuple_variant(
@@ -553,15 +982,16 @@ where
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::unrepresentable_variant())
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Err(Error::key_must_be_bytes())
+        let entries = self.ser.config.sort_keys.then(Vec::new);
+        self.begin_object(entries)
     }
 
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        Err(Error::key_must_be_bytes())
+        self.begin_object(None)
     }
 
     fn serialize_struct_variant(
@@ -571,103 +1001,319 @@ where
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        Err(Error::key_must_be_bytes())
+        Err(Error::tag_collision())
     }
-}
 
-struct SerializeMapKeySeq<'a, W> {
-    ser: &'a mut Serializer<W>,
+    fn is_human_readable(&self) -> bool {
+        self.ser.config.human_readable
+    }
 }
 
-impl<'a, W> ser::SerializeSeq for SerializeMapKeySeq<'a, W>
-where
-    W: WriteInsn,
-{
-    type Ok = ();
-    type Error = Error;
-
-    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
-    where
-        T: ?Sized + ser::Serialize,
-    {
-        value.serialize(MapKeyBytesSerializer {
-            ser: &mut *self.ser,
-        })
-    }
+/// Serializes a key of a map into bytes.
+struct MapKeySerializer<'a> {
+    key: &'a mut Bytes,
+    numeric_keys: NumericKeyRepr,
+}
 
-    fn end(self) -> Result<()> {
+impl MapKeySerializer<'_> {
+    fn number<T: core::fmt::Display>(self, be_bytes: &[u8], v: T) -> Result<()> {
+        match self.numeric_keys {
+            NumericKeyRepr::Binary => self.key.extend_from_slice(be_bytes),
+            NumericKeyRepr::Decimal => self.key.extend_from_slice(v.to_string().as_bytes()),
+        }
         Ok(())
     }
 }
 
-struct MapKeyBytesSerializer<'a, W> {
-    ser: &'a mut Serializer<W>,
-}
-
-impl<'a, W> ser::Serializer for MapKeyBytesSerializer<'a, W>
-where
-    W: WriteInsn,
-{
+impl<'a> ser::Serializer for MapKeySerializer<'a> {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = ser::Impossible<(), Error>;
-    type SerializeTuple = ser::Impossible<(), Error>;
-    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeSeq = SerializeMapKeySeq<'a>;
+    type SerializeTuple = SerializeMapKeySeq<'a>;
+    type SerializeTupleStruct = SerializeMapKeySeq<'a>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
     type SerializeMap = ser::Impossible<(), Error>;
     type SerializeStruct = ser::Impossible<(), Error>;
     type SerializeStructVariant = ser::Impossible<(), Error>;
 
-    fn serialize_bool(self, _v: bool) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.key.push(v as u8);
+        Ok(())
     }
 
-    fn serialize_i8(self, _v: i8) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
     }
 
-    fn serialize_i16(self, _v: i16) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
     }
 
-    fn serialize_i32(self, _v: i32) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
     }
 
-    fn serialize_i64(self, _v: i64) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        self.ser.serialize_i8(v as i8)?;
-        self.ser.inner.write(Insn::Sadd)?;
-        Ok(())
+        self.number(&v.to_be_bytes(), v)
     }
 
-    fn serialize_u16(self, _v: u16) -> Result<()> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
     }
 
-    fn serialize_u32(self, _v: u32) -> Result<()> {
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.number(&v.to_be_bytes(), v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.number(&(v as f64).to_bits().to_be_bytes(), v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.number(&v.to_bits().to_be_bytes(), v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.key.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
         Err(Error::key_must_be_bytes())
     }
 
-    fn serialize_u64(self, _v: u64) -> Result<()> {
+    fn serialize_some<T>(self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
         Err(Error::key_must_be_bytes())
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<()> {
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
         Err(Error::key_must_be_bytes())
     }
 
-    fn serialize_f64(self, _v: f64) -> Result<()> {
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeMapKeySeq { key: self.key })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
         Err(Error::key_must_be_bytes())
     }
 
-    fn serialize_char(self, _v: char) -> Result<()> {
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
         Err(Error::key_must_be_bytes())
     }
 
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::key_must_be_bytes())
+    }
+}
+
+struct SerializeMapKeySeq<'a> {
+    key: &'a mut Bytes,
+}
+
+impl ser::SerializeSeq for SerializeMapKeySeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(MapKeyBytesSerializer {
+            key: &mut *self.key,
+        })
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for SerializeMapKeySeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeMapKeySeq<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Appends an element of a sequence to a key of a map.
+struct MapKeyBytesSerializer<'a> {
+    key: &'a mut Bytes,
+}
+
+impl MapKeyBytesSerializer<'_> {
+    fn append(self, bytes: &[u8]) -> Result<()> {
+        self.key.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl ser::Serializer for MapKeyBytesSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.append(&[v as u8])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.append(&[v])
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.append(&v.to_be_bytes())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.append(&(v as f64).to_bits().to_be_bytes())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.append(&v.to_bits().to_be_bytes())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        let mut buf = [0; 4];
+        self.append(v.encode_utf8(&mut buf).as_bytes())
+    }
+
     fn serialize_str(self, _v: &str) -> Result<()> {
         Err(Error::key_must_be_bytes())
     }
@@ -704,68 +1350,358 @@ where
         Err(Error::key_must_be_bytes())
     }
 
-    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<()>
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::key_must_be_bytes())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::key_must_be_bytes())
+    }
+}
+
+/// Tells whether a value is serialized into nil without serializing its contents.
+struct NilDetector<'a> {
+    config: &'a Config,
+}
+
+impl<'a> ser::Serializer for NilDetector<'a> {
+    type Ok = bool;
+    type Error = Error;
+    type SerializeSeq = NotNil;
+    type SerializeTuple = NotNil;
+    type SerializeTupleStruct = NotNil;
+    type SerializeTupleVariant = NotNil;
+    type SerializeMap = NotNil;
+    type SerializeStruct = NotNil;
+    type SerializeStructVariant = NotNil;
+
+    fn serialize_bool(self, _v: bool) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<bool> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<bool> {
+        Ok(!v.is_finite() && self.config.non_finite_floats == NonFiniteFloats::Nil)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_none(self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<bool>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<bool> {
+        Ok(self.config.enum_repr == EnumRepr::Untagged)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<bool>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(false)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<bool>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        match self.config.enum_repr {
+            EnumRepr::Untagged => value.serialize(self),
+            _ => Ok(false),
+        }
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<NotNil> {
+        Ok(NotNil)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
+    }
+}
+
+/// A compound value seen by `NilDetector`, whose elements are ignored.
+struct NotNil;
+
+impl ser::SerializeSeq for NotNil {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl ser::SerializeTuple for NotNil {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl ser::SerializeTupleStruct for NotNil {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl ser::SerializeTupleVariant for NotNil {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
+    }
+
+    fn end(self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+impl ser::SerializeMap for NotNil {
+    type Ok = bool;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, _key: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        Err(Error::key_must_be_bytes())
+        Ok(())
     }
 
-    fn serialize_newtype_variant<T>(
-        self,
-        _name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
-    ) -> Result<()>
+    fn serialize_value<T>(&mut self, _value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        Err(Error::key_must_be_bytes())
+        Ok(())
     }
 
-    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Err(Error::key_must_be_bytes())
+    fn end(self) -> Result<bool> {
+        Ok(false)
     }
+}
 
-    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        Err(Error::key_must_be_bytes())
-    }
+impl ser::SerializeStruct for NotNil {
+    type Ok = bool;
+    type Error = Error;
 
-    fn serialize_tuple_struct(
-        self,
-        _name: &'static str,
-        _len: usize,
-    ) -> Result<Self::SerializeTupleStruct> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_field<T>(&mut self, _key: &'static str, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
     }
 
-    fn serialize_tuple_variant(
-        self,
-        _name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
-    ) -> Result<Self::SerializeTupleVariant> {
-        Err(Error::key_must_be_bytes())
+    fn end(self) -> Result<bool> {
+        Ok(false)
     }
+}
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Err(Error::key_must_be_bytes())
-    }
+impl ser::SerializeStructVariant for NotNil {
+    type Ok = bool;
+    type Error = Error;
 
-    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        Err(Error::key_must_be_bytes())
+    fn serialize_field<T>(&mut self, _key: &'static str, _value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(())
     }
 
-    fn serialize_struct_variant(
-        self,
-        _name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
-    ) -> Result<Self::SerializeStructVariant> {
-        Err(Error::key_must_be_bytes())
+    fn end(self) -> Result<bool> {
+        Ok(false)
     }
 }
 
@@ -781,6 +1717,29 @@ mod test {
 
     use super::*;
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_to_string() -> Result<()> {
+        #[derive(Serialize)]
+        struct S {
+            f1: i32,
+            f2: Vec<bool>,
+        }
+
+        let s = S {
+            f1: 123,
+            f2: vec![true, false],
+        };
+        let expected = object![f1: Int(123), f2: array![Bool(true), Bool(false)]];
+        assert_eq!(crate::from_str(&to_string(&s)?)?, expected);
+        assert_eq!(to_vec(&s)?, to_string(&s)?.into_bytes());
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "foo")?;
+        assert_eq!(crate::from_reader(&buf[..])?, String(b"foo".to_vec()));
+        Ok(())
+    }
+
     #[test]
     fn serialize_bool() {
         assert_encodes(true, Bool(true));
@@ -1207,6 +2166,95 @@ mod test {
         );
     }
 
+    #[test]
+    fn serialize_map_key_tuple() {
+        #[derive(Eq, PartialEq, Hash, Debug, Serialize)]
+        struct Key(u8, char);
+
+        type HM<K, T> = std::collections::HashMap<K, T>;
+
+        assert_encodes(
+            [((1_u8, -2_i16, true), 1), ((0xff, 0x100, false), 2)]
+                .into_iter()
+                .collect::<HM<_, i32>>(),
+            object![
+                [b"\x01\xff\xfe\x01"]: Int(1),
+                [b"\xff\x01\x00\x00"]: Int(2),
+            ],
+        );
+        assert_encodes(
+            [(Key(b'a', '参'), 1)].into_iter().collect::<HM<_, i32>>(),
+            object![[b"a\xe5\x8f\x82"]: Int(1)],
+        );
+        assert_encodes(
+            [(vec![0x1234_u16, 0x5678], 1)]
+                .into_iter()
+                .collect::<HM<_, i32>>(),
+            object![[b"\x12\x34\x56\x78"]: Int(1)],
+        );
+    }
+
+    #[test]
+    fn serialize_map_key_unit() {
+        #[derive(Eq, PartialEq, Hash, Debug, Serialize)]
+        struct Unit;
+
+        type HM<K, T> = std::collections::HashMap<K, T>;
+
+        assert_encodes(
+            [((), 1)].into_iter().collect::<HM<_, i32>>(),
+            object![[b""]: Int(1)],
+        );
+        assert_encodes(
+            [(Unit, 1)].into_iter().collect::<HM<_, i32>>(),
+            object![[b""]: Int(1)],
+        );
+    }
+
+    #[test]
+    fn serialize_map_key_float() {
+        #[derive(PartialEq, Serialize, Debug)]
+        struct K<F>(F);
+        impl<F: PartialEq> Eq for K<F> {}
+        impl<F: Copy + Into<f64>> std::hash::Hash for K<F> {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.into().to_bits().hash(state)
+            }
+        }
+        type HM<F> = std::collections::HashMap<K<F>, i32>;
+
+        assert_encodes(
+            [(K(1.5_f64), 1), (K(-0.0), 2)]
+                .into_iter()
+                .collect::<HM<_>>(),
+            object![
+                [b"\x3f\xf8\x00\x00\x00\x00\x00\x00"]: Int(1),
+                [b"\x80\x00\x00\x00\x00\x00\x00\x00"]: Int(2),
+            ],
+        );
+        // `f32` is widened into `f64`.
+        assert_encodes(
+            [(K(1.5_f32), 1)].into_iter().collect::<HM<_>>(),
+            object![[b"\x3f\xf8\x00\x00\x00\x00\x00\x00"]: Int(1)],
+        );
+        assert_encodes(
+            [((K(1.5_f64), 0_u8), 1)]
+                .into_iter()
+                .collect::<std::collections::HashMap<_, i32>>(),
+            object![[b"\x3f\xf8\x00\x00\x00\x00\x00\x00\x00"]: Int(1)],
+        );
+    }
+
+    #[test]
+    fn serialize_map_key_unsupported() {
+        type HM<K, T> = std::collections::HashMap<K, T>;
+
+        let err = encode([(Some(1_u8), 1)].into_iter().collect::<HM<_, i32>>()).unwrap_err();
+        assert_eq!(err.kind(), &crate::ErrorKind::KeyMustBeBytes);
+        let err = encode([(vec!["a"], 1)].into_iter().collect::<HM<_, i32>>()).unwrap_err();
+        assert_eq!(err.kind(), &crate::ErrorKind::KeyMustBeBytes);
+    }
+
     #[test]
     fn serialize_map_key_unit_variant() {
         #[derive(Eq, PartialEq, Hash, Debug, Serialize)]
@@ -1257,6 +2305,36 @@ mod test {
         )
     }
 
+    #[test]
+    fn serialize_flatten() {
+        #[derive(Debug, Serialize)]
+        struct Inner {
+            f2: &'static str,
+            f3: Option<bool>,
+        }
+
+        #[derive(Debug, Serialize)]
+        struct S {
+            f1: i32,
+            #[serde(flatten)]
+            inner: Inner,
+            #[serde(flatten)]
+            rest: std::collections::HashMap<&'static str, i32>,
+        }
+
+        assert_encodes(
+            S {
+                f1: 123,
+                inner: Inner {
+                    f2: "abc",
+                    f3: None,
+                },
+                rest: [("f4", 4)].into_iter().collect(),
+            },
+            object![f1: Int(123), f2: String(b"abc".to_vec()), f3: Nil, f4: Int(4)],
+        )
+    }
+
     #[test]
     fn serialize_struct_variant() {
         #[derive(Debug, Serialize)]
@@ -1270,6 +2348,361 @@ mod test {
         );
     }
 
+    #[test]
+    fn serializer_serialize_iter() -> Result<()> {
+        // The iterator has no size hint.
+        let iter = (0..100).filter(|n| n % 3 == 0).map(|n| (n, n.to_string()));
+        let expected = encode(iter.clone().collect::<Vec<_>>())?;
+
+        let mut insns = vec![];
+        Serializer::new(&mut insns).serialize_iter(iter.clone())?;
+        assert_eq!(insns, expected);
+
+        #[cfg(feature = "std")]
+        {
+            let mut buf = vec![];
+            to_writer_iter(&mut buf, iter.clone())?;
+            assert_eq!(buf, to_vec(&iter.clone().collect::<Vec<_>>())?);
+        }
+
+        let mut insns = vec![];
+        Serializer::new(&mut insns).serialize_iter(iter.take(0))?;
+        assert_eq!(insns, vec![Insn::Anew]);
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_human_readable() {
+        use std::net::Ipv4Addr;
+
+        let addr = Ipv4Addr::new(127, 0, 0, 1);
+        assert_encodes(addr, String(b"127.0.0.1".to_vec()));
+
+        let mut buf = vec![];
+        let mut ser = Config {
+            human_readable: false,
+            ..Default::default()
+        }
+        .build(&mut buf);
+        assert!(!ser::Serializer::is_human_readable(&&mut ser));
+        addr.serialize(&mut ser).expect("serialization error");
+        assert_eq!(
+            decode(&mut buf.into_iter()),
+            array![Uint(127), Uint(0), Uint(0), Uint(1)]
+        );
+    }
+
+    #[test]
+    fn serialize_numeric_keys_decimal() {
+        fn encode_decimal<T: ser::Serialize>(x: T) -> watson_rs::Value {
+            let mut buf = vec![];
+            let mut ser = Config {
+                numeric_keys: NumericKeyRepr::Decimal,
+                ..Default::default()
+            }
+            .build(&mut buf);
+            x.serialize(&mut ser).expect("serialization error");
+            decode(&mut buf.into_iter())
+        }
+
+        type HM<K> = std::collections::HashMap<K, i32>;
+        assert_eq!(
+            encode_decimal([(258i16, 1), (-7, 2)].into_iter().collect::<HM<_>>()),
+            object![[b"258"]: Int(1), [b"-7"]: Int(2)]
+        );
+        // Elements of tuples are still binary.
+        assert_eq!(
+            encode_decimal([((10u8, true), 3)].into_iter().collect::<HM<_>>()),
+            object![[b"\x0a\x01"]: Int(3)]
+        );
+    }
+
+    #[test]
+    fn serialize_non_finite_floats() {
+        fn encode_with(policy: NonFiniteFloats, f: f64) -> Result<watson_rs::Value> {
+            let mut buf = vec![];
+            let mut ser = Config {
+                non_finite_floats: policy,
+                ..Default::default()
+            }
+            .build(&mut buf);
+            f.serialize(&mut ser)?;
+            Ok(decode(&mut buf.into_iter()))
+        }
+
+        for f in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                encode_with(NonFiniteFloats::Emit, f),
+                Ok(Float(g)) if g.to_bits() == f.to_bits()
+            ));
+            assert_eq!(
+                encode_with(NonFiniteFloats::Error, f).unwrap_err().kind(),
+                &crate::ErrorKind::NonFiniteFloat
+            );
+            assert_eq!(encode_with(NonFiniteFloats::Nil, f).unwrap(), Nil);
+        }
+        for policy in [NonFiniteFloats::Error, NonFiniteFloats::Nil] {
+            assert_eq!(encode_with(policy, 1.5).unwrap(), Float(1.5));
+        }
+        assert!(encode([1.5f32, f32::NAN]).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serializer_with_config() -> Result<()> {
+        let mut buf = vec![];
+        let config = Config {
+            initial_mode: Mode::S,
+            chars_per_line: 0,
+            ..Default::default()
+        };
+        let mut ser = Serializer::with_config(&mut buf, config);
+        ().serialize(&mut ser)?;
+        ser.into_inner().into_inner()?;
+        assert_eq!(buf, b"y");
+
+        let mut buf = vec![];
+        let unlexer = unlexer::Config {
+            initial_mode: Mode::S,
+            chars_per_line: 2,
+            trailing_newline: false,
+            ..Default::default()
+        };
+        let mut ser = Serializer::from_writer_with_config(&mut buf, Config::default(), unlexer);
+        assert_eq!(ser.config().initial_mode, Mode::S);
+        (1, "a").serialize(&mut ser)?;
+        ser.into_inner().into_inner()?;
+        let text = std::str::from_utf8(&buf).unwrap();
+        assert!(text.lines().all(|l| l.len() <= 2));
+        let lexer = watson_rs::lexer::Config {
+            initial_mode: Mode::S,
+            ..Default::default()
+        }
+        .build_slice(&buf);
+        let mut vm = watson_rs::VM::new();
+        vm.execute_all(lexer)?;
+        assert_eq!(vm.into_top(), Some(array![Int(1), String(b"a".to_vec())]));
+
+        let keys = ["d", "a", "c", "b", "e", "aa"];
+        let map: std::collections::HashMap<_, _> = keys.iter().map(|&k| (k, 1)).collect();
+        let sorted: std::collections::BTreeMap<_, _> = keys.iter().map(|&k| (k, 1)).collect();
+        let mut buf = vec![];
+        map.serialize(
+            &mut Config {
+                sort_keys: true,
+                ..Default::default()
+            }
+            .build(&mut buf),
+        )?;
+        assert_eq!(buf, encode(sorted)?);
+        fn encode_sorted<T: Serialize>(map: T) -> Result<watson_rs::Value> {
+            let mut buf = vec![];
+            map.serialize(
+                &mut Config {
+                    sort_keys: true,
+                    ..Default::default()
+                }
+                .build(&mut buf),
+            )?;
+            Ok(decode(&mut buf.into_iter()))
+        }
+        let map: std::collections::HashMap<_, _> = [((1_u8, 2_u8), 1), ((0, 5), 2)].into();
+        assert_eq!(
+            encode_sorted(&map)?,
+            object![[b"\x00\x05"]: Int(2), [b"\x01\x02"]: Int(1)]
+        );
+        let map: std::collections::HashMap<_, _> = [(Some(1), 1)].into();
+        assert_eq!(
+            encode_sorted(&map).unwrap_err().kind(),
+            &crate::ErrorKind::KeyMustBeBytes
+        );
+
+        #[derive(Debug, Serialize)]
+        struct Unit;
+
+        #[derive(Debug, Serialize)]
+        struct S {
+            f1: Option<i32>,
+            f2: (),
+            f3: i32,
+            f4: Option<Option<()>>,
+            f5: f64,
+            f6: Option<Vec<i32>>,
+            f7: Unit,
+            f8: Option<Unit>,
+        }
+        let mut buf = vec![];
+        let x = S {
+            f1: None,
+            f2: (),
+            f3: 1,
+            f4: Some(None),
+            f5: f64::NAN,
+            f6: Some(vec![]),
+            f7: Unit,
+            f8: Some(Unit),
+        };
+        x.serialize(
+            &mut Config {
+                skip_nil_fields: true,
+                non_finite_floats: NonFiniteFloats::Nil,
+                ..Default::default()
+            }
+            .build(&mut buf),
+        )?;
+        assert_eq!(
+            decode(&mut buf.into_iter()),
+            object![f3: Int(1), f6: array![]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_struct_as_array() {
+        #[derive(Debug, Serialize)]
+        struct S {
+            f1: u32,
+            f2: Option<Box<S>>,
+        }
+
+        #[derive(Debug, Serialize)]
+        enum E {
+            V { f1: u32 },
+        }
+
+        assert_encodes_as_array(
+            S {
+                f1: 1,
+                f2: Some(Box::new(S { f1: 2, f2: None })),
+            },
+            array![Uint(1), array![Uint(2), Nil]],
+        );
+        assert_encodes_as_array(E::V { f1: 1 }, object![V: array![Uint(1)]]);
+    }
+
+    #[test]
+    fn serialize_enum_repr() {
+        #[derive(Debug, Serialize)]
+        struct S {
+            x: i32,
+        }
+
+        #[derive(Debug, Serialize)]
+        enum E {
+            U,
+            N(S),
+            I(i32),
+            T(i32, i32),
+            St { x: i32 },
+        }
+
+        let name = |s: &str| String(s.as_bytes().to_vec());
+        let internal = EnumRepr::Internal { tag: "t" };
+        assert_encodes_with(internal, E::U, object![t: name("U")]);
+        assert_encodes_with(internal, E::N(S { x: 1 }), object![t: name("N"), x: Int(1)]);
+        assert_encodes_with(internal, E::St { x: 1 }, object![t: name("St"), x: Int(1)]);
+        // The content of a newtype variant must be an object.
+        for x in [E::I(1), E::T(1, 2)] {
+            let err = x.serialize(&mut Serializer::new(&mut vec![]).with_enum_repr(internal));
+            assert_eq!(
+                err.unwrap_err().kind(),
+                &crate::ErrorKind::UnrepresentableVariant
+            );
+        }
+
+        // The tag comes first, followed by the fields in the same order as a struct.
+        #[derive(Debug, Serialize)]
+        enum Content {
+            S(Xy),
+            M(std::collections::BTreeMap<&'static str, i32>),
+            N(Wrapper),
+            C(C),
+            E(E),
+            St { t: i32 },
+        }
+
+        #[derive(Debug, Serialize)]
+        struct Xy {
+            x: i32,
+            y: i32,
+        }
+
+        #[derive(Debug, Serialize)]
+        struct Wrapper(i32);
+
+        #[derive(Debug, Serialize)]
+        struct C {
+            t: i32,
+        }
+
+        #[derive(Debug, Serialize)]
+        struct Tagged {
+            t: &'static str,
+            x: i32,
+            y: i32,
+        }
+
+        let encode_internal = |x: Content| {
+            let mut insns = vec![];
+            x.serialize(&mut Serializer::new(&mut insns).with_enum_repr(internal))
+                .map(|()| insns)
+        };
+        let tagged = |t| Tagged { t, x: 1, y: 2 };
+        assert_eq!(
+            encode_internal(Content::S(Xy { x: 1, y: 2 })).unwrap(),
+            encode(tagged("S")).unwrap()
+        );
+        assert_eq!(
+            encode_internal(Content::M([("y", 2), ("x", 1)].into())).unwrap(),
+            encode(tagged("M")).unwrap()
+        );
+        assert_eq!(
+            decode(&mut encode_internal(Content::N(Wrapper(1))).unwrap().into_iter()),
+            object![t: name("N"), Wrapper: Int(1)]
+        );
+        // Fields must not collide with the tag.
+        for x in [
+            Content::M([("t", 1)].into()),
+            Content::C(C { t: 1 }),
+            Content::E(E::U),
+            Content::E(E::N(S { x: 1 })),
+            Content::E(E::St { x: 1 }),
+            Content::St { t: 1 },
+        ] {
+            assert_eq!(
+                encode_internal(x).unwrap_err().kind(),
+                &crate::ErrorKind::TagCollision
+            );
+        }
+
+        let adjacent = EnumRepr::Adjacent {
+            tag: "t",
+            content: "c",
+        };
+        assert_encodes_with(adjacent, E::U, object![t: name("U")]);
+        assert_encodes_with(
+            adjacent,
+            E::N(S { x: 1 }),
+            object![t: name("N"), c: object![x: Int(1)]],
+        );
+        assert_encodes_with(
+            adjacent,
+            E::T(1, 2),
+            object![t: name("T"), c: array![Int(1), Int(2)]],
+        );
+        assert_encodes_with(
+            adjacent,
+            E::St { x: 1 },
+            object![t: name("St"), c: object![x: Int(1)]],
+        );
+
+        let untagged = EnumRepr::Untagged;
+        assert_encodes_with(untagged, E::U, Nil);
+        assert_encodes_with(untagged, E::N(S { x: 1 }), object![x: Int(1)]);
+        assert_encodes_with(untagged, E::T(1, 2), array![Int(1), Int(2)]);
+        assert_encodes_with(untagged, E::St { x: 1 }, object![x: Int(1)]);
+    }
+
     /*
      * Helper functions
      */
@@ -1282,6 +2715,26 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    fn assert_encodes_with<T>(repr: EnumRepr, x: T, expected: watson_rs::Value)
+    where
+        T: fmt::Debug + ser::Serialize,
+    {
+        let mut buf = vec![];
+        x.serialize(&mut Serializer::new(&mut buf).with_enum_repr(repr))
+            .expect("serialization error");
+        assert_eq!(decode(&mut buf.into_iter()), expected);
+    }
+
+    fn assert_encodes_as_array<T>(x: T, expected: watson_rs::Value)
+    where
+        T: fmt::Debug + ser::Serialize,
+    {
+        let mut buf = vec![];
+        x.serialize(&mut Serializer::new(&mut buf).with_struct_repr(StructRepr::Array))
+            .expect("serialization error");
+        assert_eq!(decode(&mut buf.into_iter()), expected);
+    }
+
     fn assert_encodes_to_float_satisfying<T, F>(x: T, pred: F)
     where
         T: fmt::Debug + ser::Serialize,
@@ -1304,6 +2757,16 @@ mod test {
         assert_eq!(decode(&mut buf.into_iter()), expected);
     }
 
+    fn encode<T>(x: T) -> Result<Vec<Insn>>
+    where
+        T: ser::Serialize,
+    {
+        let mut buf = vec![];
+        let mut ser = Serializer::new(&mut buf);
+        x.serialize(&mut ser)?;
+        Ok(buf)
+    }
+
     fn encode_then_decode<T>(x: T) -> watson_rs::Value
     where
         T: ser::Serialize,
@@ -1,5 +1,7 @@
-use std::fmt;
-use std::ops;
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops;
 
 use serde::de;
 use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
@@ -22,6 +24,12 @@ impl Value {
     pub fn into_watson(self) -> watson_rs::Value {
         self.value
     }
+
+    /// Returns a reference to underlying `watson::Value`.
+    /// With the `preserve-order` feature, entries of objects are kept in the order in which they were visited.
+    pub fn as_watson(&self) -> &watson_rs::Value {
+        &self.value
+    }
 }
 
 impl From<watson_rs::Value> for Value {
@@ -85,7 +93,7 @@ impl<'de> Visitor<'de> for ValueVisitor {
         self.visit_bytes(v.as_bytes())
     }
 
-    fn visit_string<E>(self, v: std::string::String) -> Result<Self::Value, E>
+    fn visit_string<E>(self, v: alloc::string::String) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
@@ -125,7 +133,7 @@ impl<'de> Visitor<'de> for ValueVisitor {
         while let Some(elem) = access.next_element::<Value>()? {
             arr.push(elem.into_watson());
         }
-        Ok(Array(arr).into())
+        Ok(Array(arr.into()).into())
     }
 
     fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
@@ -141,6 +149,13 @@ impl<'de> Visitor<'de> for ValueVisitor {
     {
         Ok(Nil.into())
     }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Nil.into())
+    }
 }
 
 pub struct ValueRef<'a> {
@@ -154,6 +169,12 @@ impl<'a> ValueRef<'a> {
     }
 }
 
+impl AsRef<watson_rs::Value> for Value {
+    fn as_ref(&self) -> &watson_rs::Value {
+        &self.value
+    }
+}
+
 impl<'a> AsRef<watson_rs::Value> for ValueRef<'a> {
     fn as_ref(&self) -> &watson_rs::Value {
         self.value
@@ -242,7 +263,7 @@ impl<'de> Visitor<'de> for BytesVisitor {
         self.visit_bytes(v.as_bytes())
     }
 
-    fn visit_string<E>(self, v: std::string::String) -> Result<Self::Value, E>
+    fn visit_string<E>(self, v: alloc::string::String) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
@@ -325,18 +346,43 @@ mod test {
         );
     }
 
+    #[cfg(feature = "preserve-order")]
+    #[test]
+    fn ser_de_object_preserves_order() {
+        let v = Value::new(Object(
+            vec![(b"b".to_vec(), Int(1)), (b"a".to_vec(), Int(2))]
+                .into_iter()
+                .collect(),
+        ));
+        assert_tokens(
+            &v,
+            &[
+                Token::Map { len: Some(2) },
+                Token::Bytes(b"b"),
+                Token::I64(1),
+                Token::Bytes(b"a"),
+                Token::I64(2),
+                Token::MapEnd,
+            ],
+        );
+        match v.as_watson() {
+            Object(map) => assert_eq!(map.keys().collect::<Vec<_>>(), [b"b", b"a"]),
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn ser_de_array() {
         assert_tokens(
-            &Value::new(Array(vec![])),
+            &Value::new(Array(vec![].into())),
             &[Token::Seq { len: Some(0) }, Token::SeqEnd],
         );
         assert_tokens(
-            &Value::new(Array(vec![Int(123)])),
+            &Value::new(Array(vec![Int(123)].into())),
             &[Token::Seq { len: Some(1) }, Token::I64(123), Token::SeqEnd],
         );
         assert_tokens(
-            &Value::new(Array(vec![Int(123), String(b"hello".to_vec())])),
+            &Value::new(Array(vec![Int(123), String(b"hello".to_vec())].into())),
             &[
                 Token::Seq { len: Some(2) },
                 Token::I64(123),
@@ -0,0 +1,7 @@
+//! Helper modules for `#[serde(with = "...")]`.
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod timestamp;
+
+#[cfg(feature = "uuid")]
+pub mod uuid;
@@ -0,0 +1,253 @@
+//! Canonical representations of datetimes.
+//!
+//! - `chrono` and `time` represent a datetime as the number of nanoseconds since the Unix epoch (`Value::Int`).
+//!   This is the canonical representation, which is compact and preserves the order of datetimes.
+//!   It covers datetimes between 1677 and 2262.
+//! - `chrono_rfc3339` and `time_rfc3339` represent a datetime as an RFC 3339 string in UTC.
+//!
+//! Datetimes are always deserialized in UTC.
+
+#[cfg(feature = "chrono")]
+pub mod chrono {
+    //! `chrono::DateTime` as nanoseconds since the Unix epoch.
+
+    use ::chrono::{DateTime, TimeZone, Utc};
+    use serde::{ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<Tz, S>(dt: &DateTime<Tz>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        Tz: TimeZone,
+        S: Serializer,
+    {
+        let nanos = dt
+            .timestamp_nanos_opt()
+            .ok_or_else(|| ser::Error::custom("datetime out of range of nanosecond timestamps"))?;
+        serializer.serialize_i64(nanos)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        i64::deserialize(deserializer).map(DateTime::from_timestamp_nanos)
+    }
+}
+
+#[cfg(feature = "chrono")]
+pub mod chrono_rfc3339 {
+    //! `chrono::DateTime` as an RFC 3339 string.
+
+    use ::chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+    use alloc::string::String;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<Tz, S>(dt: &DateTime<Tz>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        Tz: TimeZone,
+        S: Serializer,
+    {
+        let s = dt
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "time")]
+pub mod time {
+    //! `time::OffsetDateTime` as nanoseconds since the Unix epoch.
+
+    use ::time::OffsetDateTime;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(dt: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let nanos = i64::try_from(dt.unix_timestamp_nanos())
+            .map_err(|_| ser::Error::custom("datetime out of range of nanosecond timestamps"))?;
+        serializer.serialize_i64(nanos)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let nanos = i64::deserialize(deserializer)?;
+        OffsetDateTime::from_unix_timestamp_nanos(nanos.into()).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "time")]
+pub mod time_rfc3339 {
+    //! `time::OffsetDateTime` as an RFC 3339 string.
+
+    use ::time::format_description::well_known::Rfc3339;
+    use ::time::{OffsetDateTime, UtcOffset};
+    use alloc::string::String;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(dt: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = dt
+            .to_offset(UtcOffset::UTC)
+            .format(&Rfc3339)
+            .map_err(ser::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        OffsetDateTime::parse(&s, &Rfc3339)
+            .map(|dt| dt.to_offset(UtcOffset::UTC))
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{de, ser, Deserialize, Serialize};
+    use watson_rs::Value::*;
+
+    use crate::de::Deserializer;
+    use crate::Result;
+
+    // 1969-07-20T20:17:40.123456789Z
+    const NANOS: i64 = -14_182_939_876_543_211;
+    const TEXT: &[u8] = b"1969-07-20T20:17:40.123456789Z";
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn timestamp_chrono() -> Result<()> {
+        use ::chrono::{DateTime, FixedOffset, Utc};
+
+        #[derive(PartialEq, Serialize, Deserialize, Debug)]
+        struct S {
+            #[serde(with = "super::chrono")]
+            nanos: DateTime<Utc>,
+            #[serde(with = "super::chrono_rfc3339")]
+            text: DateTime<Utc>,
+        }
+
+        #[derive(Serialize)]
+        struct Local {
+            #[serde(with = "super::chrono")]
+            nanos: DateTime<FixedOffset>,
+            #[serde(with = "super::chrono_rfc3339")]
+            text: DateTime<FixedOffset>,
+        }
+
+        let dt = DateTime::from_timestamp_nanos(NANOS);
+        let v = to_value(&S {
+            nanos: dt,
+            text: dt,
+        })?;
+        assert_eq!(
+            v,
+            watson_rs::object![nanos: Int(NANOS), text: String(TEXT.to_vec())]
+        );
+        assert_eq!(
+            from_value::<S>(&v)?,
+            S {
+                nanos: dt,
+                text: dt
+            }
+        );
+
+        // Datetimes with offsets are converted into UTC.
+        let jst = dt.with_timezone(&FixedOffset::east_opt(9 * 3600).unwrap());
+        assert_eq!(
+            to_value(&Local {
+                nanos: jst,
+                text: jst
+            })?,
+            v
+        );
+
+        let too_late = DateTime::parse_from_rfc3339("2263-01-01T00:00:00Z").unwrap();
+        assert!(crate::to_vec(&Local {
+            nanos: too_late,
+            text: too_late
+        })
+        .is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn timestamp_time() -> Result<()> {
+        use ::time::format_description::well_known::Rfc3339;
+        use ::time::{OffsetDateTime, UtcOffset};
+
+        #[derive(PartialEq, Serialize, Deserialize, Debug)]
+        struct S {
+            #[serde(with = "super::time")]
+            nanos: OffsetDateTime,
+            #[serde(with = "super::time_rfc3339")]
+            text: OffsetDateTime,
+        }
+
+        let dt = OffsetDateTime::from_unix_timestamp_nanos(NANOS.into()).unwrap();
+        let v = to_value(&S {
+            nanos: dt,
+            text: dt,
+        })?;
+        assert_eq!(
+            v,
+            watson_rs::object![nanos: Int(NANOS), text: String(TEXT.to_vec())]
+        );
+        assert_eq!(
+            from_value::<S>(&v)?,
+            S {
+                nanos: dt,
+                text: dt
+            }
+        );
+
+        // Datetimes with offsets are converted into UTC.
+        let jst = dt.to_offset(UtcOffset::from_hms(9, 0, 0).unwrap());
+        assert_eq!(
+            to_value(&S {
+                nanos: jst,
+                text: jst
+            })?,
+            v
+        );
+        assert_eq!(from_value::<S>(&v)?.text.offset(), UtcOffset::UTC);
+
+        let too_late = OffsetDateTime::parse("2263-01-01T00:00:00Z", &Rfc3339).unwrap();
+        assert!(crate::to_vec(&S {
+            nanos: too_late,
+            text: too_late
+        })
+        .is_err());
+        Ok(())
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn to_value<T: ser::Serialize>(x: &T) -> Result<watson_rs::Value> {
+        crate::from_str(&crate::to_string(x)?)
+    }
+
+    fn from_value<'de, T: de::Deserialize<'de>>(v: &'de watson_rs::Value) -> Result<T> {
+        T::deserialize(&Deserializer::new(v))
+    }
+}
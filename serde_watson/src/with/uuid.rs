@@ -0,0 +1,132 @@
+//! `uuid::Uuid` as a 16-byte string.
+//!
+//! `Uuid` implements `Serialize` by itself if the `serde` feature of `uuid` is enabled, but it is represented as
+//! hyphenated text unless `ser::Config::human_readable` is disabled. This module always represents it as its 16 bytes.
+//! Use `text` if you prefer the hyphenated form.
+//!
+//! Both modules accept either form on deserialization.
+
+use core::fmt;
+
+use ::uuid::Uuid;
+use serde::{de, Deserializer, Serializer};
+
+pub fn serialize<S>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(uuid.as_bytes())
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(UuidVisitor)
+}
+
+pub mod text {
+    //! `uuid::Uuid` as hyphenated text.
+
+    use ::uuid::Uuid;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(uuid.hyphenated().encode_lower(&mut Uuid::encode_buffer()))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(super::UuidVisitor)
+    }
+}
+
+struct UuidVisitor;
+
+impl<'de> de::Visitor<'de> for UuidVisitor {
+    type Value = Uuid;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a UUID as 16 bytes or text")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Uuid, E>
+    where
+        E: de::Error,
+    {
+        if v.len() == 16 {
+            Uuid::from_slice(v).map_err(E::custom)
+        } else {
+            Uuid::try_parse_ascii(v).map_err(E::custom)
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Uuid, E>
+    where
+        E: de::Error,
+    {
+        Uuid::parse_str(v).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ::uuid::Uuid;
+    use serde::{Deserialize, Serialize};
+    use watson_rs::Value::*;
+
+    use crate::de::Deserializer;
+    use crate::Result;
+
+    const TEXT: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    #[derive(PartialEq, Serialize, Deserialize, Debug)]
+    struct S {
+        #[serde(with = "super")]
+        bytes: Uuid,
+        #[serde(with = "super::text")]
+        text: Uuid,
+    }
+
+    #[test]
+    fn uuid_roundtrip() -> Result<()> {
+        let uuid = Uuid::parse_str(TEXT).unwrap();
+        let x = S {
+            bytes: uuid,
+            text: uuid,
+        };
+        let v = crate::from_str(&crate::to_string(&x)?)?;
+        assert_eq!(
+            v,
+            watson_rs::object![
+                bytes: String(uuid.as_bytes().to_vec()),
+                text: String(TEXT.as_bytes().to_vec()),
+            ]
+        );
+        assert_eq!(S::deserialize(&Deserializer::new(&v))?, x);
+        Ok(())
+    }
+
+    #[test]
+    fn uuid_accepts_either_form() -> Result<()> {
+        let uuid = Uuid::parse_str(TEXT).unwrap();
+        let v = watson_rs::object![
+            bytes: String(TEXT.as_bytes().to_vec()),
+            text: String(uuid.as_bytes().to_vec()),
+        ];
+        let x = S::deserialize(&Deserializer::new(&v))?;
+        assert_eq!((x.bytes, x.text), (uuid, uuid));
+
+        let v = watson_rs::object![
+            bytes: String(b"too short".to_vec()),
+            text: String(TEXT.as_bytes().to_vec()),
+        ];
+        assert!(S::deserialize(&Deserializer::new(&v)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+/* C API for WATSON. See the documentation of the watson_capi crate for the ownership rules. */
+#ifndef WATSON_H
+#define WATSON_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct WatsonValue WatsonValue;
+typedef struct WatsonObjectIter WatsonObjectIter;
+
+typedef enum WatsonStatus {
+    WATSON_OK = 0,
+    WATSON_NULL_POINTER = 1,
+    WATSON_DECODE_ERROR = 2,
+    WATSON_ENCODE_ERROR = 3,
+    WATSON_TYPE_MISMATCH = 4,
+} WatsonStatus;
+
+typedef enum WatsonKind {
+    WATSON_INT = 0,
+    WATSON_UINT = 1,
+    WATSON_FLOAT = 2,
+    WATSON_STRING = 3,
+    WATSON_OBJECT = 4,
+    WATSON_ARRAY = 5,
+    WATSON_BOOL = 6,
+    WATSON_NIL = 7,
+} WatsonKind;
+
+const char *watson_last_error(void);
+
+WatsonStatus watson_decode(const uint8_t *text, size_t len, WatsonValue **out);
+void watson_value_free(WatsonValue *v);
+WatsonStatus watson_encode(const WatsonValue *v, char **out);
+void watson_string_free(char *s);
+
+/*
+ * The functions below accept a null value and treat it as missing: watson_value_kind returns WATSON_NIL,
+ * watson_value_len returns 0, watson_array_get, watson_object_get and watson_object_iter_new return NULL,
+ * watson_object_iter_next returns false, and the functions returning WatsonStatus fail with WATSON_NULL_POINTER.
+ */
+WatsonKind watson_value_kind(const WatsonValue *v);
+WatsonStatus watson_value_as_int(const WatsonValue *v, int64_t *out);
+WatsonStatus watson_value_as_uint(const WatsonValue *v, uint64_t *out);
+WatsonStatus watson_value_as_float(const WatsonValue *v, double *out);
+WatsonStatus watson_value_as_bool(const WatsonValue *v, bool *out);
+WatsonStatus watson_value_as_string(const WatsonValue *v, const uint8_t **bytes, size_t *len);
+size_t watson_value_len(const WatsonValue *v);
+
+const WatsonValue *watson_array_get(const WatsonValue *v, size_t index);
+const WatsonValue *watson_object_get(const WatsonValue *v, const uint8_t *key, size_t len);
+
+WatsonObjectIter *watson_object_iter_new(const WatsonValue *v);
+bool watson_object_iter_next(WatsonObjectIter *iter, const uint8_t **key, size_t *len, const WatsonValue **value);
+void watson_object_iter_free(WatsonObjectIter *iter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WATSON_H */
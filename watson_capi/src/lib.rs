@@ -0,0 +1,493 @@
+//! C API for WATSON.
+//!
+//! # Ownership
+//!
+//! * `watson_decode` returns a value owned by the caller, which must be released with `watson_value_free`.
+//! * Values returned by `watson_array_get`, `watson_object_get` and `watson_object_iter_next` are borrowed from the
+//!   value they were taken from, and are valid until it is released.
+//! * `watson_encode` returns a string owned by the caller, which must be released with `watson_string_free`.
+//!
+//! # Errors
+//!
+//! Functions that can fail return `WatsonStatus`. When they fail, `watson_last_error` returns a message describing
+//! the error until another function fails on the same thread.
+//!
+//! # Null pointers
+//!
+//! Functions that query values accept null in place of a value and treat it as missing: `watson_value_kind` returns
+//! `Nil`, `watson_value_len` returns 0, `watson_array_get`, `watson_object_get` and `watson_object_iter_new` return
+//! null, and `watson_object_iter_next` returns false. Functions that return `WatsonStatus` fail with `NullPointer`.
+//!
+//! See `include/watson.h` for the declarations.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::{ptr, slice};
+
+use watson_rs::{Bytes, Value};
+
+/// A WATSON value.
+#[repr(transparent)]
+pub struct WatsonValue(Value);
+
+impl WatsonValue {
+    fn from_ref(v: &Value) -> *const WatsonValue {
+        v as *const Value as *const WatsonValue
+    }
+}
+
+/// The result of a function.
+#[repr(C)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum WatsonStatus {
+    Ok = 0,
+    /// A required pointer is null.
+    NullPointer = 1,
+    /// The text is not a valid WATSON document.
+    DecodeError = 2,
+    /// The value can't be encoded.
+    EncodeError = 3,
+    /// The value is not of the requested type.
+    TypeMismatch = 4,
+}
+
+/// The type of a value.
+#[repr(C)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum WatsonKind {
+    Int = 0,
+    Uint = 1,
+    Float = 2,
+    String = 3,
+    Object = 4,
+    Array = 5,
+    Bool = 6,
+    Nil = 7,
+}
+
+/// An iterator over the entries of an object.
+pub struct WatsonObjectIter {
+    entries: Vec<(*const Bytes, *const Value)>,
+    pos: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: WatsonStatus, message: impl ToString) -> WatsonStatus {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+    status
+}
+
+/// Returns the message of the last error on this thread, or null if nothing has failed.
+/// The message is valid until another function fails on the same thread.
+#[no_mangle]
+pub extern "C" fn watson_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Decodes `len` bytes of WATSON text and stores the value in `*out`.
+///
+/// # Safety
+/// `text` must point to `len` readable bytes, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn watson_decode(
+    text: *const u8,
+    len: usize,
+    out: *mut *mut WatsonValue,
+) -> WatsonStatus {
+    if (text.is_null() && len != 0) || out.is_null() {
+        return fail(WatsonStatus::NullPointer, "null pointer");
+    }
+    let text = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(text, len)
+    };
+    match watson_rs::from_reader(text) {
+        Ok(v) => {
+            *out = Box::into_raw(Box::new(WatsonValue(v)));
+            WatsonStatus::Ok
+        }
+        Err(e) => fail(WatsonStatus::DecodeError, e),
+    }
+}
+
+/// Releases a value returned by `watson_decode`. It does nothing if `v` is null.
+///
+/// # Safety
+/// `v` must be null or a value returned by `watson_decode` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn watson_value_free(v: *mut WatsonValue) {
+    if !v.is_null() {
+        drop(Box::from_raw(v));
+    }
+}
+
+/// Encodes a value into NUL-terminated WATSON text and stores it in `*out`.
+///
+/// # Safety
+/// `v` must be a valid value, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn watson_encode(
+    v: *const WatsonValue,
+    out: *mut *mut c_char,
+) -> WatsonStatus {
+    if v.is_null() || out.is_null() {
+        return fail(WatsonStatus::NullPointer, "null pointer");
+    }
+    match watson_rs::to_vec(&(*v).0).map(CString::new) {
+        Ok(Ok(s)) => {
+            *out = s.into_raw();
+            WatsonStatus::Ok
+        }
+        Ok(Err(e)) => fail(WatsonStatus::EncodeError, e),
+        Err(e) => fail(WatsonStatus::EncodeError, e),
+    }
+}
+
+/// Releases a string returned by `watson_encode`. It does nothing if `s` is null.
+///
+/// # Safety
+/// `s` must be null or a string returned by `watson_encode` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn watson_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Returns the type of a value, or `Nil` if `v` is null.
+///
+/// # Safety
+/// `v` must be null or a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn watson_value_kind(v: *const WatsonValue) -> WatsonKind {
+    if v.is_null() {
+        return WatsonKind::Nil;
+    }
+    match (*v).0 {
+        Value::Int(_) => WatsonKind::Int,
+        Value::Uint(_) => WatsonKind::Uint,
+        Value::Float(_) => WatsonKind::Float,
+        Value::String(_) => WatsonKind::String,
+        Value::Object(_) => WatsonKind::Object,
+        Value::Array(_) => WatsonKind::Array,
+        Value::Bool(_) => WatsonKind::Bool,
+        Value::Nil => WatsonKind::Nil,
+    }
+}
+
+macro_rules! define_getter {
+    ( $(#[$attr:meta])* $name:ident, $variant:ident, $ty:ty ) => {
+        $(#[$attr])*
+        ///
+        /// # Safety
+        /// `v` must be a valid value, and `out` must be a valid pointer.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(v: *const WatsonValue, out: *mut $ty) -> WatsonStatus {
+            if v.is_null() || out.is_null() {
+                return fail(WatsonStatus::NullPointer, "null pointer");
+            }
+            match (*v).0 {
+                Value::$variant(x) => {
+                    *out = x;
+                    WatsonStatus::Ok
+                }
+                ref v => fail(
+                    WatsonStatus::TypeMismatch,
+                    format!("expected {}, got {}", stringify!($variant), v.kind()),
+                ),
+            }
+        }
+    };
+}
+
+define_getter!(
+    /// Stores the value of an `Int` in `*out`.
+    watson_value_as_int, Int, i64
+);
+define_getter!(
+    /// Stores the value of a `Uint` in `*out`.
+    watson_value_as_uint, Uint, u64
+);
+define_getter!(
+    /// Stores the value of a `Float` in `*out`.
+    watson_value_as_float, Float, f64
+);
+define_getter!(
+    /// Stores the value of a `Bool` in `*out`.
+    watson_value_as_bool, Bool, bool
+);
+
+/// Stores the bytes of a `String` in `*bytes` and `*len`. The bytes are not NUL-terminated and are borrowed from `v`.
+///
+/// # Safety
+/// `v` must be a valid value, and `bytes` and `len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn watson_value_as_string(
+    v: *const WatsonValue,
+    bytes: *mut *const u8,
+    len: *mut usize,
+) -> WatsonStatus {
+    if v.is_null() || bytes.is_null() || len.is_null() {
+        return fail(WatsonStatus::NullPointer, "null pointer");
+    }
+    match (*v).0 {
+        Value::String(ref s) => {
+            *bytes = s.as_ptr();
+            *len = s.len();
+            WatsonStatus::Ok
+        }
+        ref v => fail(
+            WatsonStatus::TypeMismatch,
+            format!("expected String, got {}", v.kind()),
+        ),
+    }
+}
+
+/// Returns the number of elements of an `Array` or entries of an `Object`, or 0 for other values and null.
+///
+/// # Safety
+/// `v` must be null or a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn watson_value_len(v: *const WatsonValue) -> usize {
+    if v.is_null() {
+        return 0;
+    }
+    match (*v).0 {
+        Value::Array(ref arr) => arr.len(),
+        Value::Object(ref map) => map.len(),
+        _ => 0,
+    }
+}
+
+/// Returns the element of an `Array` at `index`, or null if `v` is not an array or `index` is out of range.
+///
+/// # Safety
+/// `v` must be null or a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn watson_array_get(
+    v: *const WatsonValue,
+    index: usize,
+) -> *const WatsonValue {
+    if v.is_null() {
+        return ptr::null();
+    }
+    match (*v).0 {
+        Value::Array(ref arr) => arr.get(index).map_or(ptr::null(), WatsonValue::from_ref),
+        _ => ptr::null(),
+    }
+}
+
+/// Returns the value of an `Object` associated with the key of `len` bytes, or null if `v` is not an object or
+/// doesn't have the key.
+///
+/// # Safety
+/// `v` must be null or a valid value, and `key` must be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn watson_object_get(
+    v: *const WatsonValue,
+    key: *const u8,
+    len: usize,
+) -> *const WatsonValue {
+    if v.is_null() || (key.is_null() && len != 0) {
+        return ptr::null();
+    }
+    let key = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(key, len)
+    };
+    match (*v).0 {
+        Value::Object(ref map) => map.get(key).map_or(ptr::null(), WatsonValue::from_ref),
+        _ => ptr::null(),
+    }
+}
+
+/// Returns an iterator over the entries of an `Object`, or null if `v` is not an object.
+/// The iterator must be released with `watson_object_iter_free` before `v` is released.
+///
+/// # Safety
+/// `v` must be null or a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn watson_object_iter_new(v: *const WatsonValue) -> *mut WatsonObjectIter {
+    if v.is_null() {
+        return ptr::null_mut();
+    }
+    match (*v).0 {
+        Value::Object(ref map) => Box::into_raw(Box::new(WatsonObjectIter {
+            entries: map
+                .iter()
+                .map(|(k, v)| (k as *const Bytes, v as *const Value))
+                .collect(),
+            pos: 0,
+        })),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Stores the next entry in `*key`, `*len` and `*value`, and returns false if there are no more entries or any of
+/// the pointers is null.
+///
+/// # Safety
+/// `iter` must be null or a valid iterator whose object is not released yet, and `key`, `len` and `value` must be
+/// null or valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn watson_object_iter_next(
+    iter: *mut WatsonObjectIter,
+    key: *mut *const u8,
+    len: *mut usize,
+    value: *mut *const WatsonValue,
+) -> bool {
+    if iter.is_null() || key.is_null() || len.is_null() || value.is_null() {
+        return false;
+    }
+    let iter = &mut *iter;
+    match iter.entries.get(iter.pos) {
+        Some(&(k, v)) => {
+            iter.pos += 1;
+            *key = (*k).as_ptr();
+            *len = (*k).len();
+            *value = WatsonValue::from_ref(&*v);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Releases an iterator returned by `watson_object_iter_new`. It does nothing if `iter` is null.
+///
+/// # Safety
+/// `iter` must be null or an iterator returned by `watson_object_iter_new` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn watson_object_iter_free(iter: *mut WatsonObjectIter) {
+    if !iter.is_null() {
+        drop(Box::from_raw(iter));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn decode_query_and_encode() {
+        let orig = watson_rs::object![
+            name: Value::String(b"watson".to_vec()),
+            ports: watson_rs::array![Value::Int(80), Value::Uint(443)],
+        ];
+        let text = watson_rs::to_vec(&orig).unwrap();
+        unsafe {
+            let mut v = ptr::null_mut();
+            assert_eq!(
+                watson_decode(text.as_ptr(), text.len(), &mut v),
+                WatsonStatus::Ok
+            );
+            assert_eq!(watson_value_kind(v), WatsonKind::Object);
+            assert_eq!(watson_value_len(v), 2);
+
+            let name = watson_object_get(v, b"name".as_ptr(), 4);
+            let (mut bytes, mut len) = (ptr::null(), 0);
+            assert_eq!(
+                watson_value_as_string(name, &mut bytes, &mut len),
+                WatsonStatus::Ok
+            );
+            assert_eq!(slice::from_raw_parts(bytes, len), b"watson");
+            assert!(watson_object_get(v, b"none".as_ptr(), 4).is_null());
+
+            let ports = watson_object_get(v, b"ports".as_ptr(), 5);
+            let mut n = 0i64;
+            assert_eq!(
+                watson_value_as_int(watson_array_get(ports, 0), &mut n),
+                WatsonStatus::Ok
+            );
+            assert_eq!(n, 80);
+            assert_eq!(
+                watson_value_as_int(watson_array_get(ports, 1), &mut n),
+                WatsonStatus::TypeMismatch
+            );
+            assert_eq!(
+                CStr::from_ptr(watson_last_error()).to_str().unwrap(),
+                "expected Int, got Uint"
+            );
+            assert!(watson_array_get(ports, 2).is_null());
+
+            let iter = watson_object_iter_new(v);
+            let mut keys = Vec::new();
+            let mut value = ptr::null();
+            while watson_object_iter_next(iter, &mut bytes, &mut len, &mut value) {
+                keys.push(slice::from_raw_parts(bytes, len).to_vec());
+                assert!(!value.is_null());
+            }
+            watson_object_iter_free(iter);
+            keys.sort();
+            assert_eq!(keys, [b"name".to_vec(), b"ports".to_vec()]);
+
+            let mut encoded = ptr::null_mut();
+            assert_eq!(watson_encode(v, &mut encoded), WatsonStatus::Ok);
+            assert_eq!(
+                watson_rs::from_reader(CStr::from_ptr(encoded).to_bytes()).unwrap(),
+                orig
+            );
+            watson_string_free(encoded);
+            watson_value_free(v);
+        }
+    }
+
+    #[test]
+    fn decode_error() {
+        unsafe {
+            let mut v = ptr::null_mut();
+            assert_eq!(
+                watson_decode(b"E".as_ptr(), 1, &mut v),
+                WatsonStatus::DecodeError
+            );
+            assert!(v.is_null());
+            assert!(!watson_last_error().is_null());
+            assert_eq!(
+                watson_decode(ptr::null(), 1, &mut v),
+                WatsonStatus::NullPointer
+            );
+        }
+    }
+
+    #[test]
+    fn null_values() {
+        unsafe {
+            assert_eq!(watson_value_kind(ptr::null()), WatsonKind::Nil);
+            assert_eq!(watson_value_len(ptr::null()), 0);
+            assert!(watson_array_get(ptr::null(), 0).is_null());
+            assert!(watson_object_get(ptr::null(), b"k".as_ptr(), 1).is_null());
+            assert!(watson_object_iter_new(ptr::null()).is_null());
+            let (mut bytes, mut len, mut value) = (ptr::null(), 0, ptr::null());
+            assert!(!watson_object_iter_next(
+                ptr::null_mut(),
+                &mut bytes,
+                &mut len,
+                &mut value
+            ));
+
+            let text = watson_rs::to_vec(&watson_rs::object![k: Value::Nil]).unwrap();
+            let mut v = ptr::null_mut();
+            assert_eq!(
+                watson_decode(text.as_ptr(), text.len(), &mut v),
+                WatsonStatus::Ok
+            );
+            assert!(watson_object_get(v, ptr::null(), 1).is_null());
+            let iter = watson_object_iter_new(v);
+            assert!(!watson_object_iter_next(
+                iter,
+                ptr::null_mut(),
+                &mut len,
+                &mut value
+            ));
+            watson_object_iter_free(iter);
+            watson_value_free(v);
+        }
+    }
+}
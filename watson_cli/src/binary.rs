@@ -0,0 +1,66 @@
+//! Helpers for binary formats.
+
+use anyhow::{bail, Result};
+use watson_rs::Value;
+
+/// A cursor over an input of a binary format.
+pub struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Returns a new `Reader` that reads `input`.
+    pub fn new(input: &'a [u8]) -> Self {
+        Reader { input }
+    }
+
+    /// Returns the next byte without consuming it.
+    pub fn peek(&self) -> Option<u8> {
+        self.input.first().copied()
+    }
+
+    /// Reads a single byte.
+    pub fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads exactly `N` bytes.
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// Reads exactly `len` bytes.
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.input.len() < len {
+            bail!("unexpected end of input");
+        }
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(taken)
+    }
+
+    /// Returns the capacity to reserve for `len` elements, which is bounded by the rest of the input.
+    pub fn capacity(&self, len: usize) -> usize {
+        len.min(self.input.len())
+    }
+
+    /// Returns an error if there are remaining bytes.
+    pub fn finish(&self) -> Result<()> {
+        if !self.input.is_empty() {
+            bail!("trailing bytes after the value");
+        }
+        Ok(())
+    }
+}
+
+/// Converts a key of a map into the key of an object.
+/// Integers and booleans are converted into their textual representations.
+pub fn key(k: Value) -> Result<Vec<u8>> {
+    Ok(match k {
+        Value::String(s) => s,
+        Value::Int(n) => n.to_string().into_bytes(),
+        Value::Uint(n) => n.to_string().into_bytes(),
+        Value::Bool(b) => b.to_string().into_bytes(),
+        ref k => bail!("map key must be a string: {k:?}"),
+    })
+}
@@ -0,0 +1,84 @@
+//! The convention for byte strings in formats that only have Unicode strings.
+//!
+//! A WATSON string that is valid UTF-8 is converted into a string. Otherwise it is converted into an object that
+//! has a single entry `{"$base64": "<the bytes in the standard Base64 alphabet with padding>"}`, and such objects are
+//! converted back into byte strings. Keys of objects must be valid UTF-8.
+
+use anyhow::{anyhow, bail, Result};
+
+/// The key of objects that represent byte strings.
+pub const BASE64_KEY: &str = "$base64";
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the key of an object as `str`.
+pub fn key_to_str(key: &[u8]) -> Result<&str> {
+    std::str::from_utf8(key).map_err(|_| anyhow!("object key is not valid UTF-8: {key:?}"))
+}
+
+/// Encodes `bytes` in Base64.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Decodes Base64 text with padding.
+pub fn decode_base64(s: &str) -> Result<Vec<u8>> {
+    if !s.len().is_multiple_of(4) {
+        bail!("invalid Base64 length: {s:?}");
+    }
+    let mut bytes = Vec::with_capacity(s.len() / 4 * 3);
+    for chunk in s.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            bail!("invalid Base64 padding: {s:?}");
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let d = ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .ok_or_else(|| anyhow!("invalid Base64 character: {:?}", c as char))?;
+            n |= (d as u32) << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64_roundtrip() -> Result<()> {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\xff\xfe", "//4="),
+        ] {
+            assert_eq!(encode_base64(bytes), encoded);
+            assert_eq!(decode_base64(encoded)?, bytes);
+        }
+        assert!(decode_base64("Zg=").is_err());
+        assert!(decode_base64("Z===").is_err());
+        assert!(decode_base64("Zm9*").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+//! Conversion between WATSON and CBOR.
+//!
+//! CBOR doesn't distinguish signed and unsigned integers, so `Uint` that fits in `i64` is wrapped in the tag
+//! `UINT_TAG`. Such tagged integers are read as `Uint`, and the other integers are read as `Int` unless they only fit
+//! in `u64`. Strings that are valid UTF-8 are written as text strings and the others as byte strings, and both are
+//! read as strings. Floats are always written in double precision. Other tags are ignored, and `undefined` is read as
+//! `Nil`. Arrays and maps nested deeper than `format::MAX_DEPTH` are rejected.
+
+use anyhow::{anyhow, bail, Result};
+use watson_rs::Value;
+
+use crate::binary::{self, Reader};
+use crate::format::check_depth;
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+const FALSE: u8 = 20;
+const TRUE: u8 = 21;
+const NULL: u8 = 22;
+const UNDEFINED: u8 = 23;
+const FLOAT16: u8 = 25;
+const FLOAT32: u8 = 26;
+const FLOAT64: u8 = 27;
+
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+/// The tag of unsigned integers that would otherwise be read as `Int` ("WAT" in ASCII).
+pub const UINT_TAG: u64 = 0x574154;
+
+/// Writes a WATSON value in CBOR.
+pub fn to_cbor(v: &Value, out: &mut Vec<u8>) -> Result<()> {
+    write(v, 0, out)
+}
+
+/// Reads a single CBOR data item as a WATSON value.
+pub fn from_cbor(input: &[u8]) -> Result<Value> {
+    let mut r = Reader::new(input);
+    let v = read(&mut r, 0)?;
+    r.finish()?;
+    Ok(v)
+}
+
+fn write(v: &Value, depth: usize, out: &mut Vec<u8>) -> Result<()> {
+    match *v {
+        Value::Int(n) if n >= 0 => write_head(UNSIGNED, n as u64, out),
+        Value::Int(n) => write_head(NEGATIVE, !n as u64, out),
+        Value::Uint(n) => {
+            if n <= i64::MAX as u64 {
+                write_head(TAG, UINT_TAG, out);
+            }
+            write_head(UNSIGNED, n, out);
+        }
+        Value::Float(f) => {
+            out.push(SIMPLE << 5 | FLOAT64);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        Value::String(ref s) => write_string(s, out),
+        Value::Object(ref map) => {
+            check_depth(depth)?;
+            write_head(MAP, map.len() as u64, out);
+            for (k, v) in map {
+                write_string(k, out);
+                write(v, depth + 1, out)?;
+            }
+        }
+        Value::Array(ref arr) => {
+            check_depth(depth)?;
+            write_head(ARRAY, arr.len() as u64, out);
+            for v in arr {
+                write(v, depth + 1, out)?;
+            }
+        }
+        Value::Bool(b) => out.push(SIMPLE << 5 | if b { TRUE } else { FALSE }),
+        Value::Nil => out.push(SIMPLE << 5 | NULL),
+    }
+    Ok(())
+}
+
+fn write_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if let Ok(arg) = u8::try_from(arg) {
+        out.extend_from_slice(&[major | 24, arg]);
+    } else if let Ok(arg) = u16::try_from(arg) {
+        out.push(major | 25);
+        out.extend_from_slice(&arg.to_be_bytes());
+    } else if let Ok(arg) = u32::try_from(arg) {
+        out.push(major | 26);
+        out.extend_from_slice(&arg.to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn write_string(s: &[u8], out: &mut Vec<u8>) {
+    let major = if std::str::from_utf8(s).is_ok() {
+        TEXT
+    } else {
+        BYTES
+    };
+    write_head(major, s.len() as u64, out);
+    out.extend_from_slice(s);
+}
+
+/// Reads the initial byte and the argument of a data item.
+/// The argument is `None` if the item has an indefinite length.
+fn read_head(r: &mut Reader) -> Result<(u8, u8, Option<u64>)> {
+    let b = r.byte()?;
+    let (major, info) = (b >> 5, b & 0x1f);
+    let arg = match info {
+        0..=23 => Some(info as u64),
+        24 => Some(r.byte()? as u64),
+        25 => Some(u16::from_be_bytes(r.array()?) as u64),
+        26 => Some(u32::from_be_bytes(r.array()?) as u64),
+        27 => Some(u64::from_be_bytes(r.array()?)),
+        INDEFINITE if matches!(major, BYTES | TEXT | ARRAY | MAP) => None,
+        _ => bail!("invalid CBOR initial byte: {b:#04x}"),
+    };
+    Ok((major, info, arg))
+}
+
+/// Reads the head of a data item, skipping the tags other than `UINT_TAG`.
+fn read_untagged_head(r: &mut Reader) -> Result<(u8, u8, Option<u64>)> {
+    loop {
+        match read_head(r)? {
+            (TAG, _, Some(tag)) if tag != UINT_TAG => continue,
+            head => return Ok(head),
+        }
+    }
+}
+
+fn read(r: &mut Reader, depth: usize) -> Result<Value> {
+    let (major, info, arg) = read_untagged_head(r)?;
+    Ok(match (major, arg) {
+        (UNSIGNED, Some(n)) => match i64::try_from(n) {
+            Ok(n) => Value::Int(n),
+            Err(_) => Value::Uint(n),
+        },
+        (NEGATIVE, Some(n)) => i64::try_from(n)
+            .map(|n| Value::Int(!n))
+            .map_err(|_| anyhow!("integer out of range: -1 - {n}"))?,
+        (BYTES | TEXT, Some(len)) => Value::String(r.take(to_usize(len)?)?.to_vec()),
+        (BYTES | TEXT, None) => {
+            let mut s = Vec::new();
+            while !read_break(r)? {
+                match read_head(r)? {
+                    (m, _, Some(len)) if m == major => s.extend_from_slice(r.take(to_usize(len)?)?),
+                    _ => bail!("invalid chunk of an indefinite-length string"),
+                }
+            }
+            Value::String(s)
+        }
+        (ARRAY, len) => {
+            check_depth(depth)?;
+            let mut arr = Vec::new();
+            for_each_item(r, len, |r| {
+                arr.push(read(r, depth + 1)?);
+                Ok(())
+            })?;
+            Value::Array(arr.into())
+        }
+        (MAP, len) => {
+            check_depth(depth)?;
+            let mut map = watson_rs::Map::new();
+            for_each_item(r, len, |r| {
+                let k = binary::key(read(r, depth + 1)?)?;
+                map.insert(k, read(r, depth + 1)?);
+                Ok(())
+            })?;
+            Value::Object(map)
+        }
+        (TAG, _) => match read_untagged_head(r)? {
+            (UNSIGNED, _, Some(n)) => Value::Uint(n),
+            _ => bail!("tag {UINT_TAG} must be followed by an unsigned integer"),
+        },
+        (SIMPLE, Some(n)) => match info {
+            FALSE => Value::Bool(false),
+            TRUE => Value::Bool(true),
+            NULL | UNDEFINED => Value::Nil,
+            FLOAT16 => Value::Float(f16_to_f64(n as u16)),
+            FLOAT32 => Value::Float(f32::from_bits(n as u32) as f64),
+            FLOAT64 => Value::Float(f64::from_bits(n)),
+            _ => bail!("unsupported CBOR simple value: {n}"),
+        },
+        _ => unreachable!(),
+    })
+}
+
+/// Calls `f` for each item of an array or a map, which may have an indefinite length.
+fn for_each_item(
+    r: &mut Reader,
+    len: Option<u64>,
+    mut f: impl FnMut(&mut Reader) -> Result<()>,
+) -> Result<()> {
+    match len {
+        Some(len) => {
+            for _ in 0..len {
+                f(r)?;
+            }
+        }
+        None => {
+            while !read_break(r)? {
+                f(r)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Consumes the "break" stop code if it is the next byte.
+fn read_break(r: &mut Reader) -> Result<bool> {
+    if r.peek() == Some(BREAK) {
+        r.byte()?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn to_usize(len: u64) -> Result<usize> {
+    Ok(usize::try_from(len)?)
+}
+
+fn f16_to_f64(h: u16) -> f64 {
+    let exp = (h >> 10) & 0x1f;
+    let frac = (h & 0x3ff) as f64;
+    let v = match exp {
+        0 => frac * 2f64.powi(-24),
+        31 if frac == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + frac / 1024.0) * 2f64.powi(exp as i32 - 15),
+    };
+    if h & 0x8000 != 0 {
+        -v
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn cbor_roundtrip() -> Result<()> {
+        let v = object![
+            int: array![Int(0), Int(-1), Int(200), Int(i64::MIN), Int(i64::MAX)],
+            uint: array![Uint(0), Uint(200), Uint(u64::MAX)],
+            float: Float(1.5),
+            string: String(b"hello".to_vec()),
+            bytes: String(b"\xff\x00".to_vec()),
+            nested: array![Bool(true), Nil, object![]],
+        ];
+        let mut buf = Vec::new();
+        to_cbor(&v, &mut buf)?;
+        assert_eq!(from_cbor(&buf)?, v);
+        Ok(())
+    }
+
+    #[test]
+    fn cbor_rejects_deeply_nested_values() -> Result<()> {
+        let max = crate::format::MAX_DEPTH;
+        let mut v = Nil;
+        for _ in 0..max {
+            v = array![v];
+        }
+        let mut buf = Vec::new();
+        to_cbor(&v, &mut buf)?;
+        assert_eq!(from_cbor(&buf)?, v);
+        assert!(to_cbor(&array![v], &mut Vec::new()).is_err());
+
+        let mut input = vec![0x81; max + 1];
+        input.push(0x00);
+        assert!(from_cbor(&input).is_err());
+        // Deeper items must not overflow the stack.
+        let mut input = vec![0x81; 500_000];
+        input.push(0x00);
+        assert!(from_cbor(&input).is_err());
+        let mut input = vec![0xc1; 500_000];
+        input.push(0x00);
+        assert_eq!(from_cbor(&input)?, Int(0));
+        Ok(())
+    }
+
+    #[test]
+    fn cbor_decode() -> Result<()> {
+        // Examples from RFC 8949 Appendix A.
+        assert_eq!(from_cbor(&[0x18, 0x64])?, Int(100));
+        assert_eq!(from_cbor(&[0x38, 0x63])?, Int(-100));
+        assert_eq!(
+            from_cbor(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])?,
+            Uint(u64::MAX)
+        );
+        assert_eq!(from_cbor(&[0xf9, 0x3c, 0x00])?, Float(1.0));
+        assert_eq!(from_cbor(&[0xf9, 0x7c, 0x00])?, Float(f64::INFINITY));
+        assert_eq!(
+            from_cbor(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0])?,
+            Int(1363896240)
+        );
+        assert_eq!(
+            from_cbor(&[0x5f, 0x42, 0x01, 0x02, 0x43, 0x03, 0x04, 0x05, 0xff])?,
+            String(vec![1, 2, 3, 4, 5])
+        );
+        assert_eq!(
+            from_cbor(&[0xbf, 0x61, 0x61, 0x01, 0x61, 0x62, 0x9f, 0x02, 0x03, 0xff, 0xff])?,
+            object![a: Int(1), b: array![Int(2), Int(3)]]
+        );
+        assert_eq!(from_cbor(&[0xda, 0x00, 0x57, 0x41, 0x54, 0x01])?, Uint(1));
+        assert!(from_cbor(&[0xda, 0x00, 0x57, 0x41, 0x54, 0x20]).is_err());
+        assert!(from_cbor(&[0x3b, 0x80, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(from_cbor(&[0x1c]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Human-readable differences between documents.
+
+use std::fmt::Write;
+
+use watson_rs::diff::Change;
+
+use crate::show::show;
+
+/// Returns the changes one per line: `+` for added values, `-` for removed ones and `~` for replaced ones.
+pub fn to_text(changes: &[Change]) -> String {
+    let mut s = String::new();
+    for change in changes {
+        let (sign, value) = match change {
+            Change::Add { value, .. } => ('+', Some(value)),
+            Change::Remove { .. } => ('-', None),
+            Change::Replace { value, .. } => ('~', Some(value)),
+        };
+        write!(s, "{sign} ").unwrap();
+        if change.path().is_empty() {
+            s.push('.');
+        }
+        for elem in change.path() {
+            write!(s, "{elem}").unwrap();
+        }
+        if let Some(value) = value {
+            s.push_str(": ");
+            show(value, &mut s);
+        }
+        s.push('\n');
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::diff::diff;
+    use watson_rs::{array, object, Value};
+    use Value::*;
+
+    #[test]
+    fn to_text_shows_each_change() {
+        let from = object![name: String(b"a".to_vec()), ports: array![Int(80), Int(443)], old: Nil];
+        let to = object![name: String(b"b".to_vec()), ports: array![Int(80)], new: Bool(true)];
+        assert_eq!(
+            to_text(&diff(&from, &to)),
+            "~ .name: \"b\"\n+ .new: true\n- .old\n- .ports[1]\n"
+        );
+        assert_eq!(to_text(&diff(&Int(1), &Uint(1))), "~ .: 1u\n");
+        assert_eq!(to_text(&diff(&Nil, &Nil)), "");
+    }
+}
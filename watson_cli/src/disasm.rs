@@ -0,0 +1,82 @@
+//! Listings of instructions.
+
+use std::io::Write;
+
+use anyhow::Result;
+use serde_json::json;
+use watson_rs::disasm::{Disassembler, Entry};
+use watson_rs::lexer;
+
+/// Writes the annotated listing of `input`, one line per instruction.
+/// If `json` is set, each line is a JSON object (i.e. the output is in the JSON Lines format).
+pub fn write_listing(
+    input: &[u8],
+    lexer_config: lexer::Config,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    for entry in Disassembler::new(lexer_config.build_slice(input)) {
+        let entry = entry?;
+        if json {
+            serde_json::to_writer(&mut *out, &entry_to_json(&entry))?;
+            writeln!(out)?;
+        } else {
+            writeln!(out, "{entry}")?;
+        }
+    }
+    Ok(())
+}
+
+fn entry_to_json(entry: &Entry) -> serde_json::Value {
+    let loc = &entry.token.location;
+    json!({
+        "path": loc.path.as_ref().map(|p| p.to_string_lossy()),
+        "line": loc.line,
+        "column": loc.column,
+        "mode": format!("{:?}", entry.token.mode),
+        "byte": (loc.byte as char).to_string(),
+        "insn": format!("{:?}", entry.token.insn),
+        "pops": entry.pops,
+        "pushes": entry.pushes,
+        "depth": entry.depth,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn listing_text_and_json() -> Result<()> {
+        let mut out = Vec::new();
+        write_listing(b"B#", lexer::Config::default(), false, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "-:1:1\tA\t'B'\tInew\t-0 +1\tdepth=1\n-:1:2\tA\t'#'\tGpop\t-1 +0\tdepth=0\n"
+        );
+
+        let mut out = Vec::new();
+        let config = lexer::Config::default().with_label("a.watson");
+        write_listing(b"?S", config, true, &mut out)?;
+        let lines = out
+            .split(|&b| b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<serde_json::Result<Vec<serde_json::Value>>>()?;
+        assert_eq!(
+            lines[1],
+            json!({
+                "path": "a.watson",
+                "line": 1,
+                "column": 2,
+                "mode": "S",
+                "byte": "S",
+                "insn": "Inew",
+                "pops": 0,
+                "pushes": 1,
+                "depth": 2,
+            })
+        );
+        Ok(())
+    }
+}
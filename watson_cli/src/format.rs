@@ -0,0 +1,129 @@
+//! Formats that can be converted from and to WATSON.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use watson_rs::Value;
+
+use crate::{cbor, json, msgpack, toml, yaml};
+
+/// The maximum depth of nested arrays and objects that are converted from or into other formats.
+/// Conversions and the libraries of other formats handle values recursively, so deeper values would overflow the
+/// stack. This is the deepest nesting the JSON parser accepts, so that any JSON written by the CLI can be read back.
+pub const MAX_DEPTH: usize = 127;
+
+/// Fails if an array or an object at `depth`, where the outermost value is at zero, is nested too deeply.
+pub fn check_depth(depth: usize) -> Result<()> {
+    if MAX_DEPTH <= depth {
+        bail!("arrays and objects are nested deeper than {MAX_DEPTH} levels");
+    }
+    Ok(())
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    Watson,
+    Json,
+    Yaml,
+    Toml,
+    Msgpack,
+    Cbor,
+}
+
+impl Format {
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "watson" => Some(Format::Watson),
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            "msgpack" | "mpk" => Some(Format::Msgpack),
+            "cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Reads a single value in this format.
+    pub fn read(self, input: &[u8]) -> Result<Value> {
+        match self {
+            Format::Watson => Ok(watson_rs::from_reader(input)?),
+            Format::Json => json::from_json(serde_json::from_slice(input)?),
+            Format::Yaml => yaml::from_yaml(serde_yaml::from_slice(input)?),
+            Format::Toml => toml::from_toml(::toml::from_str(std::str::from_utf8(input)?)?),
+            Format::Msgpack => msgpack::from_msgpack(input),
+            Format::Cbor => cbor::from_cbor(input),
+        }
+    }
+
+    /// Writes a value in this format.
+    pub fn write(self, v: &Value, out: &mut dyn Write) -> Result<()> {
+        match self {
+            Format::Watson => {
+                watson_rs::to_writer(&mut *out, v)?;
+                writeln!(out)?;
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, &json::to_json(v)?)?;
+                writeln!(out)?;
+            }
+            Format::Yaml => serde_yaml::to_writer(&mut *out, &yaml::to_yaml(v)?)?,
+            Format::Toml => out.write_all(::toml::to_string(&toml::to_toml(v)?)?.as_bytes())?,
+            Format::Msgpack => {
+                let mut buf = Vec::new();
+                msgpack::to_msgpack(v, &mut buf)?;
+                out.write_all(&buf)?;
+            }
+            Format::Cbor => {
+                let mut buf = Vec::new();
+                cbor::to_cbor(v, &mut buf)?;
+                out.write_all(&buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn format_roundtrip() -> Result<()> {
+        let v = object![a: array![Int(1), String(b"x".to_vec())], b: Bool(false)];
+        for format in [
+            Format::Watson,
+            Format::Json,
+            Format::Yaml,
+            Format::Toml,
+            Format::Msgpack,
+            Format::Cbor,
+        ] {
+            let mut buf = Vec::new();
+            format.write(&v, &mut buf)?;
+            assert_eq!(format.read(&buf)?, v, "{format:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(Format::from_path(Path::new("a/b.json")), Some(Format::Json));
+        assert_eq!(
+            Format::from_path(Path::new("b.watson")),
+            Some(Format::Watson)
+        );
+        assert_eq!(Format::from_path(Path::new("b.yml")), Some(Format::Yaml));
+        assert_eq!(
+            Format::from_path(Path::new("Cargo.toml")),
+            Some(Format::Toml)
+        );
+        assert_eq!(Format::from_path(Path::new("b.cbor")), Some(Format::Cbor));
+        assert_eq!(Format::from_path(Path::new("b.txt")), None);
+        assert_eq!(Format::from_path(Path::new("b")), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Conversion between WATSON and JSON.
+//!
+//! Integers and floats are converted into JSON numbers. Since JSON can't represent NaN and infinities, they are
+//! rejected. JSON numbers are converted into `Int` if they fit in `i64`, into `Uint` if they only fit in `u64`,
+//! and into `Float` otherwise. Byte strings follow the convention of `crate::bytes`. Values nested deeper than
+//! `format::MAX_DEPTH` are rejected.
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Number};
+use watson_rs::Value;
+
+use crate::bytes;
+use crate::format::check_depth;
+
+/// Converts a WATSON value into a JSON value.
+pub fn to_json(v: &Value) -> Result<serde_json::Value> {
+    value_to_json(v, 0)
+}
+
+/// Converts a JSON value into a WATSON value.
+pub fn from_json(v: serde_json::Value) -> Result<Value> {
+    value_from_json(v, 0)
+}
+
+fn value_to_json(v: &Value, depth: usize) -> Result<serde_json::Value> {
+    Ok(match *v {
+        Value::Int(n) => n.into(),
+        Value::Uint(n) => n.into(),
+        Value::Float(f) => Number::from_f64(f)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| anyhow!("JSON can't represent {f}"))?,
+        Value::String(ref s) => string_to_json(s),
+        Value::Object(ref map) => {
+            check_depth(depth)?;
+            let mut obj = Map::with_capacity(map.len());
+            for (k, v) in map {
+                obj.insert(
+                    bytes::key_to_str(k)?.to_owned(),
+                    value_to_json(v, depth + 1)?,
+                );
+            }
+            serde_json::Value::Object(obj)
+        }
+        Value::Array(ref arr) => {
+            check_depth(depth)?;
+            let arr = arr.iter().map(|v| value_to_json(v, depth + 1));
+            serde_json::Value::Array(arr.collect::<Result<_>>()?)
+        }
+        Value::Bool(b) => b.into(),
+        Value::Nil => serde_json::Value::Null,
+    })
+}
+
+fn value_from_json(v: serde_json::Value, depth: usize) -> Result<Value> {
+    Ok(match v {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Int(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::Uint(u)
+            } else {
+                Value::Float(n.as_f64().unwrap())
+            }
+        }
+        serde_json::Value::String(s) => Value::String(s.into_bytes()),
+        serde_json::Value::Array(arr) => {
+            check_depth(depth)?;
+            let arr = arr.into_iter().map(|v| value_from_json(v, depth + 1));
+            Value::Array(arr.collect::<Result<_>>()?)
+        }
+        serde_json::Value::Object(obj) => {
+            if let Some(b) = json_to_bytes(&obj)? {
+                return Ok(Value::String(b));
+            }
+            check_depth(depth)?;
+            let mut map = watson_rs::Map::with_capacity(obj.len());
+            for (k, v) in obj {
+                map.insert(k.into_bytes(), value_from_json(v, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+    })
+}
+
+fn string_to_json(s: &[u8]) -> serde_json::Value {
+    match std::str::from_utf8(s) {
+        Ok(s) => s.into(),
+        Err(_) => {
+            let mut obj = Map::new();
+            obj.insert(bytes::BASE64_KEY.to_owned(), bytes::encode_base64(s).into());
+            serde_json::Value::Object(obj)
+        }
+    }
+}
+
+fn json_to_bytes(obj: &Map<String, serde_json::Value>) -> Result<Option<Vec<u8>>> {
+    match obj.get(bytes::BASE64_KEY) {
+        Some(serde_json::Value::String(s)) if obj.len() == 1 => bytes::decode_base64(s).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn json_roundtrip() -> Result<()> {
+        let v = object![
+            int: Int(-1),
+            uint: Uint(u64::MAX),
+            float: Float(1.5),
+            string: String(b"hello".to_vec()),
+            bytes: String(b"\xff\x00".to_vec()),
+            array: array![Bool(true), Nil],
+        ];
+        let json = to_json(&v)?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "int": -1,
+                "uint": u64::MAX,
+                "float": 1.5,
+                "string": "hello",
+                "bytes": {"$base64": "/wA="},
+                "array": [true, null],
+            })
+        );
+        assert_eq!(from_json(json)?, v);
+        Ok(())
+    }
+
+    #[test]
+    fn json_rejects_unrepresentable_values() {
+        assert!(to_json(&Float(f64::NAN)).is_err());
+        let mut map = watson_rs::Map::new();
+        map.insert(b"\xff".to_vec(), Nil);
+        assert!(to_json(&Object(map)).is_err());
+    }
+
+    #[test]
+    fn json_rejects_deeply_nested_values() -> Result<()> {
+        let nest = |depth| {
+            let mut v = Nil;
+            for _ in 0..depth {
+                v = array![v];
+            }
+            v
+        };
+        let v = nest(crate::format::MAX_DEPTH);
+        assert_eq!(from_json(to_json(&v)?)?, v);
+        assert!(to_json(&nest(crate::format::MAX_DEPTH + 1)).is_err());
+        // Deeper values must not overflow the stack.
+        assert!(to_json(&nest(200_000)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! Checks of WATSON documents.
+
+use std::fmt;
+
+use watson_rs::language::{Insn, Location, Value};
+use watson_rs::lexer::Stats;
+use watson_rs::vm::{DuplicateKeys, ReadToken};
+use watson_rs::{DecodeOptions, Error, ErrorKind};
+
+/// Options of `lint`.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Limits on the lexer and the VM. `duplicate_keys` is ignored because duplicate keys are always reported.
+    pub decode: DecodeOptions,
+
+    /// The maximum ratio of bytes that are not instructions (defaults to unlimited).
+    pub max_noise_ratio: Option<f64>,
+}
+
+/// A problem found by `lint`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Problem {
+    /// The line and the column where the problem was found, or `None` if it is about the whole document.
+    pub position: Option<(usize, usize)>,
+
+    /// The description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, column)) = self.position {
+            write!(f, "{line}:{column}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The result of `lint`.
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub stats: Stats,
+}
+
+/// Runs the lexer and the VM over `input` and reports problems.
+///
+/// Duplicate keys are reported without stopping. Errors from the lexer and the VM (including type errors and
+/// exceeded limits) stop the check, as well as a document that leaves no value on the stack or discards a whole value
+/// and continues. A document that leaves more than one value on the stack is also reported.
+pub fn lint(input: &[u8], opts: &Options) -> Report {
+    let mut lexer = opts.decode.lexer_config().build_slice(input);
+    let mut vm = watson_rs::vm::Config {
+        duplicate_keys: DuplicateKeys::LastWins,
+        ..opts.decode.vm_config()
+    }
+    .build();
+    let mut problems = Vec::new();
+    let mut last = Location::unknown();
+    let mut started = false;
+    let mut executed = 0;
+    loop {
+        let t = match lexer.read() {
+            Ok(Some(t)) => t,
+            Ok(None) => break,
+            Err(e) => {
+                problems.push(from_error(&e));
+                break;
+            }
+        };
+        executed += 1;
+        if opts.decode.max_insns.is_some_and(|max| executed > max) {
+            problems.push(problem(
+                &t.location,
+                ErrorKind::InputLimitExceeded.to_string(),
+            ));
+            break;
+        }
+        if t.insn == Insn::Oadd {
+            let mut stack = vm.borrow_stack().iter().rev().skip(1);
+            if let (Some(Value::String(k)), Some(Value::Object(map))) = (stack.next(), stack.next())
+            {
+                if map.contains_key(k) {
+                    problems.push(problem(
+                        &t.location,
+                        format!("Duplicate key \"{}\"", k.escape_ascii()),
+                    ));
+                }
+            }
+        }
+        last = t.location.clone();
+        if let Err(e) = vm.execute(t) {
+            problems.push(from_error(&e));
+            break;
+        }
+        let empty = vm.borrow_stack().is_empty();
+        if started && empty {
+            problems.push(problem(
+                &last,
+                "The document is complete but it is followed by more instructions".to_owned(),
+            ));
+            break;
+        }
+        started |= !empty;
+    }
+    let depth = vm.borrow_stack().len();
+    if problems.is_empty() && depth != 1 {
+        let message = if depth == 0 {
+            "The document has no value".to_owned()
+        } else {
+            format!("The document leaves {depth} values on the stack")
+        };
+        problems.push(problem(&last, message));
+    }
+    let stats = lexer.stats();
+    if let Some(max) = opts.max_noise_ratio {
+        if max < stats.noise_ratio() {
+            problems.push(Problem {
+                position: None,
+                message: format!(
+                    "{:.1}% of bytes are not instructions (max: {:.1}%)",
+                    stats.noise_ratio() * 100.0,
+                    max * 100.0
+                ),
+            });
+        }
+    }
+    Report { problems, stats }
+}
+
+fn problem(location: &Location, message: String) -> Problem {
+    Problem {
+        position: (location.line != 0).then_some((location.line, location.column)),
+        message,
+    }
+}
+
+fn from_error(e: &Error) -> Problem {
+    let mut message = e.kind.to_string();
+    if let Some(detail) = e.type_mismatch() {
+        message = format!("{message}: {detail}");
+    }
+    problem(&e.location, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::language::Insn::*;
+    use watson_rs::serializer::WriteInsn;
+    use watson_rs::unlexer::Unlexer;
+
+    #[test]
+    fn lint_valid_document() {
+        let report = lint(b"B u", &Options::default());
+        assert_eq!(report.problems, []);
+        assert_eq!(report.stats.skipped_bytes, 1);
+    }
+
+    #[test]
+    fn lint_reports_problems() {
+        let opts = Options::default();
+        assert_eq!(messages(b"", &opts), ["The document has no value"]);
+        assert_eq!(
+            messages(b"BB", &opts),
+            ["1:2: The document leaves 2 values on the stack"]
+        );
+        assert_eq!(
+            messages(b"Bo", &opts),
+            ["1:2: Type mismatch: Bneg expects (Bool) but got (Int)"]
+        );
+        assert_eq!(
+            messages(b"B#B", &opts),
+            ["1:2: The document is complete but it is followed by more instructions"]
+        );
+        let opts = Options {
+            decode: DecodeOptions {
+                max_insns: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(messages(b"Bu", &opts).is_empty());
+        assert_eq!(messages(b"Bua", &opts), ["1:3: Input limit exceeded"]);
+        let opts = Options {
+            max_noise_ratio: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(
+            messages(b"B  ", &opts),
+            ["66.7% of bytes are not instructions (max: 50.0%)"]
+        );
+    }
+
+    #[test]
+    fn lint_reports_duplicate_keys() {
+        let key = [Snew, Inew, Sadd];
+        let mut insns = vec![Onew];
+        for _ in 0..2 {
+            insns.extend(key);
+            insns.extend([Nnew, Oadd]);
+        }
+        let report = lint(&encode(&insns), &Options::default());
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].message.starts_with("Duplicate key"));
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn messages(input: &[u8], opts: &Options) -> Vec<String> {
+        lint(input, opts)
+            .problems
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    fn encode(insns: &[Insn]) -> Vec<u8> {
+        let mut unlexer = Unlexer::new(Vec::new());
+        for &insn in insns {
+            unlexer.write(insn).unwrap();
+        }
+        unlexer.into_inner().unwrap()
+    }
+}
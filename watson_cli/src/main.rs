@@ -0,0 +1,558 @@
+//! `watson`, a command line tool for WATSON.
+
+mod binary;
+mod bytes;
+mod cbor;
+mod diff;
+mod disasm;
+mod format;
+mod json;
+mod lint;
+mod msgpack;
+mod query;
+mod reformat;
+mod repl;
+mod show;
+mod stats;
+mod toml;
+mod yaml;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use watson_rs::language::Mode;
+use watson_rs::{unlexer, DecodeOptions};
+
+use crate::format::Format;
+
+#[derive(Parser, Debug)]
+#[command(name = "watson", version, about = "A command line tool for WATSON")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Converts a WATSON document into another format (JSON by default).
+    Decode {
+        #[command(flatten)]
+        io: InputOutput,
+
+        #[command(flatten)]
+        formats: Formats,
+    },
+
+    /// Converts a document in another format into WATSON.
+    ///
+    /// The format of the input is guessed from the extension of the input file, or JSON by default.
+    Encode {
+        #[command(flatten)]
+        io: InputOutput,
+
+        #[command(flatten)]
+        formats: Formats,
+    },
+    /// Checks WATSON documents and exits with a non-zero status if any problems are found.
+    Lint {
+        /// The files to check (defaults to the standard input).
+        files: Vec<PathBuf>,
+
+        #[command(flatten)]
+        limits: Limits,
+
+        /// The maximum ratio of bytes that are not instructions (e.g. 0.5).
+        #[arg(long)]
+        max_noise_ratio: Option<f64>,
+    },
+
+    /// Reformats WATSON documents in place.
+    Fmt {
+        /// The files to reformat (defaults to the standard input, which is written to the standard output).
+        files: Vec<PathBuf>,
+
+        /// Decodes the documents and encodes them again with keys of objects sorted.
+        /// Without this, the sequence of instructions is kept as is.
+        #[arg(long)]
+        canonical: bool,
+
+        /// The number of characters per line (0 disables line wrapping).
+        #[arg(long)]
+        width: Option<usize>,
+
+        /// The initial mode of the output.
+        #[arg(long, value_enum, default_value_t = ModeArg::A)]
+        mode: ModeArg,
+
+        /// The initial mode of the input (defaults to the one of the output).
+        #[arg(long, value_enum)]
+        input_mode: Option<ModeArg>,
+
+        /// Writes nothing, and exits with a non-zero status if any documents are not formatted.
+        #[arg(long)]
+        check: bool,
+
+        /// Writes the results to the standard output instead of the files.
+        #[arg(long)]
+        stdout: bool,
+    },
+
+    /// Prints the instructions of a WATSON document with their effects on the stack.
+    Disasm {
+        #[command(flatten)]
+        io: InputOutput,
+
+        /// The initial mode of the input.
+        #[arg(long, value_enum, default_value_t = ModeArg::A)]
+        mode: ModeArg,
+
+        /// Prints each instruction as a JSON object per line.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Executes instructions interactively.
+    Repl,
+
+    /// Prints statistics of a WATSON document.
+    Stats {
+        #[command(flatten)]
+        io: InputOutput,
+
+        /// The initial mode of the input.
+        #[arg(long, value_enum, default_value_t = ModeArg::A)]
+        mode: ModeArg,
+
+        /// Prints the statistics as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints the differences between two documents and exits with a non-zero status if they differ.
+    ///
+    /// The formats of the documents are guessed from the extensions of the files, or WATSON by default.
+    Diff {
+        /// The original document.
+        from: PathBuf,
+
+        /// The modified document.
+        to: PathBuf,
+
+        /// Prints the differences as a WATSON Patch document instead.
+        #[arg(long)]
+        patch: bool,
+
+        /// The output file (defaults to the standard output).
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints a value nested in a document.
+    ///
+    /// The format of the document is guessed from the extension of the file, or WATSON by default.
+    Get {
+        /// The document.
+        input: PathBuf,
+
+        /// The path to the value, e.g. `servers[0].host`, or a JSON Pointer, e.g. `/servers/0/host`.
+        query: String,
+
+        /// The format of the output.
+        #[arg(short, long, value_enum, default_value_t = Format::Watson)]
+        to: Format,
+
+        /// The output file (defaults to the standard output).
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// The initial mode of the lexer or the unlexer.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ModeArg {
+    A,
+    S,
+}
+
+impl From<ModeArg> for Mode {
+    fn from(m: ModeArg) -> Mode {
+        match m {
+            ModeArg::A => Mode::A,
+            ModeArg::S => Mode::S,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct Formats {
+    /// The format of the input.
+    #[arg(long, value_enum)]
+    from: Option<Format>,
+
+    /// The format of the output.
+    #[arg(short, long, value_enum)]
+    to: Option<Format>,
+}
+
+#[derive(Args, Debug)]
+struct Limits {
+    /// The maximum number of values on the stack.
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// The maximum number of bytes in a single string.
+    #[arg(long)]
+    max_string_bytes: Option<usize>,
+
+    /// The maximum number of elements in a single array.
+    #[arg(long)]
+    max_array_len: Option<usize>,
+
+    /// The maximum number of entries in a single object.
+    #[arg(long)]
+    max_object_len: Option<usize>,
+
+    /// The maximum number of instructions.
+    #[arg(long)]
+    max_insns: Option<usize>,
+}
+
+impl Limits {
+    fn decode_options(&self) -> DecodeOptions {
+        DecodeOptions {
+            max_depth: self.max_depth,
+            max_string_bytes: self.max_string_bytes,
+            max_array_len: self.max_array_len,
+            max_object_len: self.max_object_len,
+            max_insns: self.max_insns,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct InputOutput {
+    /// The input file (defaults to the standard input).
+    input: Option<PathBuf>,
+
+    /// The output file (defaults to the standard output).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl InputOutput {
+    fn stdin() -> Self {
+        InputOutput {
+            input: None,
+            output: None,
+        }
+    }
+
+    fn file(path: PathBuf) -> Self {
+        InputOutput {
+            input: Some(path),
+            output: None,
+        }
+    }
+
+    /// Returns the name of the input to display.
+    fn name(&self) -> String {
+        match self.input {
+            Some(ref path) => path.display().to_string(),
+            None => "<stdin>".to_owned(),
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        match self.input {
+            Some(ref path) => {
+                fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+            }
+            None => {
+                let mut buf = Vec::new();
+                io::stdin().read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+
+    /// Writes the output produced by `f`. The output file is not created (or truncated) if `f` fails.
+    fn write(&self, f: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+        match self.output {
+            Some(ref path) => {
+                let mut buf = Vec::new();
+                f(&mut buf)?;
+                fs::write(path, buf)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                f(&mut stdout)?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn input_format(&self) -> Option<Format> {
+        self.input.as_deref().and_then(Format::from_path)
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    match cli.command {
+        Command::Decode { io, formats } => {
+            let from = formats.from.unwrap_or(Format::Watson);
+            let to = formats.to.unwrap_or(Format::Json);
+            convert(&io, from, to)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Encode { io, formats } => {
+            let from = formats
+                .from
+                .or_else(|| io.input_format())
+                .unwrap_or(Format::Json);
+            let to = formats.to.unwrap_or(Format::Watson);
+            convert(&io, from, to)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Lint {
+            files,
+            limits,
+            max_noise_ratio,
+        } => {
+            let opts = lint::Options {
+                decode: limits.decode_options(),
+                max_noise_ratio,
+            };
+            let inputs = if files.is_empty() {
+                vec![InputOutput::stdin()]
+            } else {
+                files.into_iter().map(InputOutput::file).collect()
+            };
+            let mut ok = true;
+            for input in inputs {
+                let name = input.name();
+                let report = lint::lint(&input.read()?, &opts);
+                for problem in &report.problems {
+                    println!("{name}:{problem}");
+                }
+                eprintln!(
+                    "{name}: {} problem(s), {:.1}% of bytes are not instructions",
+                    report.problems.len(),
+                    report.stats.noise_ratio() * 100.0
+                );
+                ok &= report.problems.is_empty();
+            }
+            Ok(exit_code(ok))
+        }
+        Command::Fmt {
+            files,
+            canonical,
+            width,
+            mode,
+            input_mode,
+            check,
+            stdout,
+        } => {
+            let mut unlexer = unlexer::Config {
+                initial_mode: mode.into(),
+                ..Default::default()
+            };
+            if let Some(width) = width {
+                unlexer.chars_per_line = width;
+            }
+            let opts = reformat::Options {
+                canonical,
+                input_mode: input_mode.unwrap_or(mode).into(),
+                unlexer,
+            };
+            let to_stdout = stdout || files.is_empty();
+            let inputs = if files.is_empty() {
+                vec![InputOutput::stdin()]
+            } else {
+                files.into_iter().map(InputOutput::file).collect()
+            };
+            let mut ok = true;
+            for input in inputs {
+                let text = input.read()?;
+                let formatted = reformat::reformat(&text, &opts)
+                    .with_context(|| format!("failed to format {}", input.name()))?;
+                if check {
+                    if formatted != text {
+                        println!("{}: not formatted", input.name());
+                        ok = false;
+                    }
+                } else if to_stdout {
+                    io::stdout().write_all(&formatted)?;
+                } else if formatted != text {
+                    // `files` is not empty here, so the input is a file.
+                    replace_file(input.input.as_deref().unwrap(), &formatted)?;
+                }
+            }
+            Ok(exit_code(ok))
+        }
+        Command::Disasm { io, mode, json } => {
+            let lexer_config = watson_rs::lexer::Config {
+                initial_mode: mode.into(),
+                ..Default::default()
+            }
+            .with_label(&io.name());
+            let input = io.read()?;
+            io.write(|out| disasm::write_listing(&input, lexer_config, json, out))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Repl => {
+            repl::run()?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Stats { io, mode, json } => {
+            let lexer_config = watson_rs::lexer::Config {
+                initial_mode: mode.into(),
+                ..Default::default()
+            };
+            let stats = stats::Stats::collect(&io.read()?, lexer_config)?;
+            io.write(|out| {
+                if json {
+                    serde_json::to_writer_pretty(&mut *out, &stats.to_json())?;
+                    writeln!(out)?;
+                } else {
+                    out.write_all(stats.to_text().as_bytes())?;
+                }
+                Ok(())
+            })?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Diff {
+            from,
+            to,
+            patch,
+            output,
+        } => {
+            let read = |path: PathBuf| {
+                let format = Format::from_path(&path).unwrap_or(Format::Watson);
+                format.read(&InputOutput::file(path).read()?)
+            };
+            let changes = watson_rs::diff::diff(&read(from)?, &read(to)?);
+            let io = InputOutput {
+                input: None,
+                output,
+            };
+            io.write(|out| {
+                if patch {
+                    Format::Watson.write(&watson_rs::diff::to_patch(&changes), out)
+                } else {
+                    Ok(out.write_all(diff::to_text(&changes).as_bytes())?)
+                }
+            })?;
+            Ok(exit_code(changes.is_empty()))
+        }
+        Command::Get {
+            input,
+            query,
+            to,
+            output,
+        } => {
+            let path = query::parse(&query)?;
+            let io = InputOutput {
+                input: Some(input),
+                output,
+            };
+            let v = io
+                .input_format()
+                .unwrap_or(Format::Watson)
+                .read(&io.read()?)?;
+            let Some(selected) = query::select(&v, &path) else {
+                bail!("{}: no value at {query}", io.name());
+            };
+            io.write(|out| to.write(selected, out))?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn exit_code(ok: bool) -> ExitCode {
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn convert(io: &InputOutput, from: Format, to: Format) -> Result<()> {
+    let v = from.read(&io.read()?)?;
+    io.write(|out| to.write(&v, out))
+}
+
+/// Replaces the content of a file by writing a temporary file next to it and renaming it,
+/// so that the file is left intact if writing fails.
+fn replace_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    let result = fs::write(&tmp, content)
+        .and_then(|()| fs::set_permissions(&tmp, fs::metadata(path)?.permissions()))
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("watson: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("watson_cli-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn write_creates_no_file_on_failure() {
+        let path = temp_path("failed-output");
+        let io = InputOutput {
+            input: None,
+            output: Some(path.clone()),
+        };
+        assert!(io.write(|_| bail!("failed")).is_err());
+        assert!(!path.exists());
+
+        io.write(|out| Ok(out.write_all(b"ok")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"ok");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replace_file_renames_temporary_file() {
+        let path = temp_path("replaced");
+        fs::write(&path, b"old").unwrap();
+        replace_file(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        let tmp = path.with_file_name(format!(
+            ".{}.tmp",
+            path.file_name().unwrap().to_str().unwrap()
+        ));
+        assert!(!tmp.exists());
+        fs::remove_file(path).unwrap();
+
+        // The original file is required.
+        assert!(replace_file(&temp_path("missing"), b"new").is_err());
+        assert!(!temp_path("missing").exists());
+    }
+}
@@ -0,0 +1,228 @@
+//! Conversion between WATSON and MessagePack.
+//!
+//! `Int` is written in the signed integer formats (or fixint) and `Uint` in the unsigned ones, so that the distinction
+//! survives a round trip. Integers are read in the same way: the signed formats and fixint are read as `Int` and the
+//! unsigned ones as `Uint`. Strings that are valid UTF-8 are written as str and the others as bin, and both are read
+//! as strings. Extension types are not supported. Arrays and maps nested deeper than `format::MAX_DEPTH` are
+//! rejected.
+
+use anyhow::{bail, Result};
+use rmp::encode;
+use rmp::Marker;
+use watson_rs::Value;
+
+use crate::binary::{self, Reader};
+use crate::format::check_depth;
+
+/// Writes a WATSON value in MessagePack.
+pub fn to_msgpack(v: &Value, out: &mut Vec<u8>) -> Result<()> {
+    write(v, 0, out)
+}
+
+/// Reads a single MessagePack value as a WATSON value.
+pub fn from_msgpack(input: &[u8]) -> Result<Value> {
+    let mut r = Reader::new(input);
+    let v = read(&mut r, 0)?;
+    r.finish()?;
+    Ok(v)
+}
+
+fn write(v: &Value, depth: usize, out: &mut Vec<u8>) -> Result<()> {
+    match *v {
+        Value::Int(n) => write_int(n, out)?,
+        Value::Uint(n) => write_uint(n, out)?,
+        Value::Float(f) => encode::write_f64(out, f)?,
+        Value::String(ref s) => write_string(s, out)?,
+        Value::Object(ref map) => {
+            check_depth(depth)?;
+            encode::write_map_len(out, u32::try_from(map.len())?)?;
+            for (k, v) in map {
+                write_string(k, out)?;
+                write(v, depth + 1, out)?;
+            }
+        }
+        Value::Array(ref arr) => {
+            check_depth(depth)?;
+            encode::write_array_len(out, u32::try_from(arr.len())?)?;
+            for v in arr {
+                write(v, depth + 1, out)?;
+            }
+        }
+        Value::Bool(b) => encode::write_bool(out, b)?,
+        Value::Nil => encode::write_nil(out)?,
+    }
+    Ok(())
+}
+
+fn write_int(n: i64, out: &mut Vec<u8>) -> Result<()> {
+    if (-32..128).contains(&n) {
+        encode::write_sint(out, n)?;
+    } else if let Ok(n) = i8::try_from(n) {
+        encode::write_i8(out, n)?;
+    } else if let Ok(n) = i16::try_from(n) {
+        encode::write_i16(out, n)?;
+    } else if let Ok(n) = i32::try_from(n) {
+        encode::write_i32(out, n)?;
+    } else {
+        encode::write_i64(out, n)?;
+    }
+    Ok(())
+}
+
+fn write_uint(n: u64, out: &mut Vec<u8>) -> Result<()> {
+    if let Ok(n) = u8::try_from(n) {
+        encode::write_u8(out, n)?;
+    } else if let Ok(n) = u16::try_from(n) {
+        encode::write_u16(out, n)?;
+    } else if let Ok(n) = u32::try_from(n) {
+        encode::write_u32(out, n)?;
+    } else {
+        encode::write_u64(out, n)?;
+    }
+    Ok(())
+}
+
+fn write_string(s: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    match std::str::from_utf8(s) {
+        Ok(s) => encode::write_str(out, s)?,
+        Err(_) => encode::write_bin(out, s)?,
+    }
+    Ok(())
+}
+
+fn read(r: &mut Reader, depth: usize) -> Result<Value> {
+    Ok(match Marker::from_u8(r.byte()?) {
+        Marker::FixPos(n) => Value::Int(n as i64),
+        Marker::FixNeg(n) => Value::Int(n as i64),
+        Marker::I8 => Value::Int(i8::from_be_bytes(r.array()?) as i64),
+        Marker::I16 => Value::Int(i16::from_be_bytes(r.array()?) as i64),
+        Marker::I32 => Value::Int(i32::from_be_bytes(r.array()?) as i64),
+        Marker::I64 => Value::Int(i64::from_be_bytes(r.array()?)),
+        Marker::U8 => Value::Uint(r.byte()? as u64),
+        Marker::U16 => Value::Uint(u16::from_be_bytes(r.array()?) as u64),
+        Marker::U32 => Value::Uint(u32::from_be_bytes(r.array()?) as u64),
+        Marker::U64 => Value::Uint(u64::from_be_bytes(r.array()?)),
+        Marker::F32 => Value::Float(f32::from_be_bytes(r.array()?) as f64),
+        Marker::F64 => Value::Float(f64::from_be_bytes(r.array()?)),
+        Marker::FixStr(len) => read_string(r, len as usize)?,
+        Marker::Str8 | Marker::Bin8 => {
+            let len = r.byte()? as usize;
+            read_string(r, len)?
+        }
+        Marker::Str16 | Marker::Bin16 => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            read_string(r, len)?
+        }
+        Marker::Str32 | Marker::Bin32 => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            read_string(r, len)?
+        }
+        Marker::FixArray(len) => read_array(r, len as usize, depth)?,
+        Marker::Array16 => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            read_array(r, len, depth)?
+        }
+        Marker::Array32 => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            read_array(r, len, depth)?
+        }
+        Marker::FixMap(len) => read_map(r, len as usize, depth)?,
+        Marker::Map16 => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            read_map(r, len, depth)?
+        }
+        Marker::Map32 => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            read_map(r, len, depth)?
+        }
+        Marker::True => Value::Bool(true),
+        Marker::False => Value::Bool(false),
+        Marker::Null => Value::Nil,
+        m => bail!("unsupported MessagePack type: {m:?}"),
+    })
+}
+
+fn read_string(r: &mut Reader, len: usize) -> Result<Value> {
+    Ok(Value::String(r.take(len)?.to_vec()))
+}
+
+fn read_array(r: &mut Reader, len: usize, depth: usize) -> Result<Value> {
+    check_depth(depth)?;
+    let mut arr = Vec::with_capacity(r.capacity(len));
+    for _ in 0..len {
+        arr.push(read(r, depth + 1)?);
+    }
+    Ok(Value::Array(arr.into()))
+}
+
+fn read_map(r: &mut Reader, len: usize, depth: usize) -> Result<Value> {
+    check_depth(depth)?;
+    let mut map = watson_rs::Map::with_capacity(r.capacity(len));
+    for _ in 0..len {
+        let k = binary::key(read(r, depth + 1)?)?;
+        map.insert(k, read(r, depth + 1)?);
+    }
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn msgpack_roundtrip() -> Result<()> {
+        let v = object![
+            int: array![Int(0), Int(-1), Int(200), Int(i64::MIN)],
+            uint: array![Uint(0), Uint(200), Uint(u64::MAX)],
+            float: Float(1.5),
+            string: String(b"hello".to_vec()),
+            bytes: String(b"\xff\x00".to_vec()),
+            nested: array![Bool(true), Nil, object![]],
+        ];
+        let mut buf = Vec::new();
+        to_msgpack(&v, &mut buf)?;
+        assert_eq!(from_msgpack(&buf)?, v);
+        Ok(())
+    }
+
+    #[test]
+    fn msgpack_integer_formats() -> Result<()> {
+        let encode = |v| -> Result<Vec<u8>> {
+            let mut buf = Vec::new();
+            to_msgpack(&v, &mut buf)?;
+            Ok(buf)
+        };
+        assert_eq!(encode(Int(1))?, [0x01]);
+        assert_eq!(encode(Int(200))?, [0xd1, 0x00, 0xc8]);
+        assert_eq!(encode(Uint(1))?, [0xcc, 0x01]);
+        assert_eq!(from_msgpack(&[0xcd, 0x01, 0x00])?, Uint(256));
+        assert_eq!(from_msgpack(&[0xd0, 0xff])?, Int(-1));
+        assert!(from_msgpack(&[0x01, 0x02]).is_err());
+        assert!(from_msgpack(&[0xd4, 0x00, 0x00]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn msgpack_rejects_deeply_nested_values() -> Result<()> {
+        let max = crate::format::MAX_DEPTH;
+        let mut v = Nil;
+        for _ in 0..max {
+            v = array![v];
+        }
+        let mut buf = Vec::new();
+        to_msgpack(&v, &mut buf)?;
+        assert_eq!(from_msgpack(&buf)?, v);
+        assert!(to_msgpack(&array![v], &mut Vec::new()).is_err());
+
+        let mut input = vec![0x91; max + 1];
+        input.push(0xc0);
+        assert!(from_msgpack(&input).is_err());
+        // Deeper values must not overflow the stack.
+        let mut input = vec![0x91; 500_000];
+        input.push(0xc0);
+        assert!(from_msgpack(&input).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Path expressions that select a value nested in arrays and objects.
+//!
+//! Two syntaxes are accepted:
+//!
+//! * Paths such as `servers[0].host` or `.labels["app.kubernetes.io/name"]`. `.` alone selects the root.
+//! * JSON Pointers (RFC 6901) such as `/servers/0/host`. The empty string selects the root.
+
+use anyhow::{anyhow, bail, Result};
+use watson_rs::language::PathElem;
+use watson_rs::Value;
+
+/// Parses a path expression.
+///
+/// Each segment of a JSON Pointer becomes `PathElem::Key`; `select` treats it as an index when it is applied to an
+/// array.
+pub fn parse(query: &str) -> Result<Vec<PathElem>> {
+    if query.is_empty() || query.starts_with('/') {
+        return Ok(parse_pointer(query));
+    }
+    let mut path = Vec::new();
+    let mut rest = query.strip_prefix('.').unwrap_or(query);
+    if rest.is_empty() {
+        return Ok(path);
+    }
+    let mut expect_key = !rest.starts_with('[');
+    while !rest.is_empty() || expect_key {
+        if expect_key {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end == 0 {
+                bail!("missing key in {query:?}");
+            }
+            path.push(PathElem::Key(rest.as_bytes()[..end].to_vec()));
+            rest = &rest[end..];
+            expect_key = false;
+        } else if let Some(r) = rest.strip_prefix('.') {
+            rest = r;
+            expect_key = true;
+        } else if let Some(r) = rest.strip_prefix("[\"") {
+            let (key, r) =
+                parse_quoted(r).ok_or_else(|| anyhow!("unterminated key in {query:?}"))?;
+            path.push(PathElem::Key(key));
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let Some((index, r)) = r.split_once(']') else {
+                bail!("missing ']' in {query:?}");
+            };
+            match index.parse() {
+                Ok(i) => path.push(PathElem::Index(i)),
+                Err(_) => bail!("invalid index {index:?} in {query:?}"),
+            }
+            rest = r;
+        } else {
+            bail!("unexpected {rest:?} in {query:?}");
+        }
+    }
+    Ok(path)
+}
+
+fn parse_pointer(query: &str) -> Vec<PathElem> {
+    query
+        .split('/')
+        .skip(1)
+        .map(|seg| PathElem::Key(seg.replace("~1", "/").replace("~0", "~").into_bytes()))
+        .collect()
+}
+
+// Parses the rest of `"key"]` where `\` escapes the next character.
+fn parse_quoted(s: &str) -> Option<(Vec<u8>, &str)> {
+    let mut key = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => key.push(chars.next()?.1),
+            '"' => return Some((key.into_bytes(), s[i + 1..].strip_prefix(']')?)),
+            c => key.push(c),
+        }
+    }
+    None
+}
+
+/// Returns the value that the path points at.
+pub fn select<'a>(v: &'a Value, path: &[PathElem]) -> Option<&'a Value> {
+    path.iter().try_fold(v, |v, elem| match (v, elem) {
+        (Value::Array(a), PathElem::Index(i)) => a.get(*i),
+        (Value::Array(a), PathElem::Key(k)) => {
+            a.get(std::str::from_utf8(k).ok()?.parse::<usize>().ok()?)
+        }
+        (Value::Object(o), PathElem::Key(k)) => o.get(k),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn parse_paths() -> Result<()> {
+        let key = |k: &str| PathElem::Key(k.as_bytes().to_vec());
+        assert_eq!(
+            parse("servers[0].host")?,
+            [key("servers"), PathElem::Index(0), key("host")]
+        );
+        assert_eq!(
+            parse(".a[1][2]")?,
+            [key("a"), PathElem::Index(1), PathElem::Index(2)]
+        );
+        assert_eq!(parse("[3]")?, [PathElem::Index(3)]);
+        assert_eq!(
+            parse(r#".a["b.c\"d"].e"#)?,
+            [key("a"), key("b.c\"d"), key("e")]
+        );
+        assert_eq!(parse(".")?, []);
+        assert_eq!(parse("")?, []);
+        assert_eq!(
+            parse("/servers/0/a~1b~0c")?,
+            [key("servers"), key("0"), key("a/b~c")]
+        );
+        for bad in ["a..b", "a[x]", "a[0", "a.", r#"a["b"#, "a[0]b"] {
+            assert!(parse(bad).is_err(), "{bad}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn select_values() -> Result<()> {
+        let v = object![servers: array![object![host: String(b"example.com".to_vec())]]];
+        let host = Some(&String(b"example.com".to_vec()));
+        assert_eq!(select(&v, &parse("servers[0].host")?), host);
+        assert_eq!(select(&v, &parse("/servers/0/host")?), host);
+        assert_eq!(select(&v, &parse(".")?), Some(&v));
+        assert_eq!(select(&v, &parse("servers[1]")?), None);
+        assert_eq!(select(&v, &parse("servers.host")?), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+//! Reformatting of WATSON text.
+
+use anyhow::Result;
+use watson_rs::language::Mode;
+use watson_rs::text::TextSerializer;
+use watson_rs::{lexer, unlexer, DecodeOptions};
+
+/// Options of `reformat`.
+#[derive(Clone)]
+pub struct Options {
+    /// If set to true, the document is decoded and serialized again with keys of objects sorted, so that equal values
+    /// always result in the same text. Otherwise the sequence of instructions is kept as is.
+    pub canonical: bool,
+
+    /// The mode in which the input starts.
+    pub input_mode: Mode,
+
+    /// The configuration of the output.
+    pub unlexer: unlexer::Config,
+}
+
+/// Re-encodes WATSON text. The output always ends with a newline unless it is empty.
+pub fn reformat(input: &[u8], opts: &Options) -> Result<Vec<u8>> {
+    let lexer_config = lexer::Config {
+        initial_mode: opts.input_mode,
+        ..Default::default()
+    };
+    let mut out = if opts.canonical {
+        let v = DecodeOptions::default().from_tokens(lexer_config.build_slice(input))?;
+        TextSerializer::builder()
+            .unlexer_config(opts.unlexer.clone())
+            .sort_keys(true)
+            .build()
+            .to_vec(&v)?
+    } else {
+        let mut unlexer = opts.unlexer.clone().build(Vec::new());
+        unlexer::transcode(lexer_config.build_slice(input), &mut unlexer)?;
+        unlexer.into_inner()?
+    };
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object, Value::*};
+
+    #[test]
+    fn reformat_keeps_instructions() -> Result<()> {
+        let opts = Options {
+            canonical: false,
+            input_mode: Mode::A,
+            unlexer: unlexer::Config {
+                chars_per_line: 2,
+                ..Default::default()
+            },
+        };
+        assert_eq!(reformat(b"B u\nu#", &opts)?, b"Bu\nu#\n");
+        assert_eq!(reformat(b"", &opts)?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn reformat_canonical() -> Result<()> {
+        let value = object![b: Int(1), a: array![Nil]];
+        let input = watson_rs::text::TextSerializer::builder()
+            .initial_mode(Mode::S)
+            .build()
+            .to_vec(&value)?;
+        let opts = Options {
+            canonical: true,
+            input_mode: Mode::S,
+            unlexer: unlexer::Config::default(),
+        };
+        let out = reformat(&input, &opts)?;
+        assert_eq!(watson_rs::from_reader(&out[..])?, value);
+        assert_eq!(
+            out,
+            [
+                TextSerializer::builder()
+                    .sort_keys(true)
+                    .build()
+                    .to_vec(&value)?,
+                b"\n".to_vec()
+            ]
+            .concat()
+        );
+        assert!(reformat(b"BB", &opts).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! An interactive environment to execute instructions.
+
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use rustyline::error::ReadlineError;
+use watson_rs::language::{Insn, Location, Mode, Token};
+use watson_rs::vm::{self, ReadToken, VM};
+use watson_rs::{asm, lexer};
+
+use crate::show::show;
+
+const HELP: &str = "\
+Type instruction characters (e.g. `Bubba`) or mnemonics (e.g. `Inew Iinc Ishl*2`) to execute them.
+Commands:
+  :dump       prints each value on the stack as WATSON text
+  :undo [N]   reverts the last N instructions (defaults to 1)
+  :reset      clears the stack
+  :help       prints this message
+  :quit       exits";
+
+/// The state of a REPL session.
+pub struct Repl {
+    vm: VM,
+    mode: Mode,
+}
+
+impl Repl {
+    /// Returns a new session with an empty stack.
+    pub fn new() -> Self {
+        Repl {
+            vm: vm::Config {
+                journal: true,
+                ..Default::default()
+            }
+            .build(),
+            mode: Mode::A,
+        }
+    }
+
+    /// Returns the prompt, which shows the current mode.
+    pub fn prompt(&self) -> String {
+        format!("{:?}> ", self.mode)
+    }
+
+    /// Evaluates a line and returns the text to print, or `None` if the session should end.
+    ///
+    /// A line is read as mnemonics if it can be assembled, and as instruction characters otherwise.
+    /// If an instruction fails, it is reverted and the instructions before it stay executed.
+    pub fn eval(&mut self, line: &str) -> Result<Option<String>> {
+        let line = line.trim();
+        if let Some(cmd) = line.strip_prefix(':') {
+            let mut words = cmd.split_whitespace();
+            match words.next() {
+                Some("dump") => return Ok(Some(self.dump()?)),
+                Some("undo") => {
+                    let n = words.next().map(str::parse).transpose()?.unwrap_or(1);
+                    for _ in 0..n {
+                        match self.vm.undo() {
+                            Some(t) => self.mode = t.mode,
+                            None => break,
+                        }
+                    }
+                }
+                Some("reset") => *self = Repl::new(),
+                Some("help") => return Ok(Some(HELP.to_owned())),
+                Some("quit") => return Ok(None),
+                _ => bail!("unknown command: {line} (type :help for help)"),
+            }
+        } else if let Ok(insns) = asm::assemble(line) {
+            for insn in insns {
+                self.execute(Token {
+                    insn,
+                    mode: self.mode,
+                    location: Location::unknown(),
+                })?;
+            }
+        } else {
+            let mut lexer = lexer::Config {
+                initial_mode: self.mode,
+                ..Default::default()
+            }
+            .build_slice(line.as_bytes());
+            while let Some(t) = lexer.read()? {
+                self.execute(t)?;
+            }
+        }
+        Ok(Some(self.show_stack()))
+    }
+
+    /// Returns the values on the stack from the bottom, one per line.
+    pub fn show_stack(&mut self) -> String {
+        let stack = self.vm.borrow_stack();
+        if stack.is_empty() {
+            return "(empty)".to_owned();
+        }
+        let mut s = String::new();
+        for (i, v) in stack.iter().enumerate() {
+            if 0 < i {
+                s.push('\n');
+            }
+            write!(s, "[{i}] ").unwrap();
+            show(v, &mut s);
+        }
+        s
+    }
+
+    fn execute(&mut self, t: Token) -> Result<()> {
+        let next = if t.insn == Insn::Snew {
+            t.mode.flip()
+        } else {
+            t.mode
+        };
+        if let Err(e) = self.vm.execute(t) {
+            // Restores the operands that the failed instruction popped.
+            self.vm.undo();
+            return Err(e.into());
+        }
+        self.mode = next;
+        Ok(())
+    }
+
+    fn dump(&mut self) -> Result<String> {
+        let mut lines = Vec::new();
+        for (i, v) in self.vm.borrow_stack().iter().enumerate() {
+            lines.push(format!("[{i}] {}", watson_rs::to_string(v)?));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Runs a session on the terminal.
+pub fn run() -> Result<()> {
+    let mut editor = rustyline::DefaultEditor::new()?;
+    let mut repl = Repl::new();
+    println!("Type :help for help.");
+    loop {
+        let line = match editor.readline(&repl.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(&line)?;
+        }
+        match repl.eval(&line) {
+            Ok(Some(out)) => println!("{out}"),
+            Ok(None) => return Ok(()),
+            Err(e) => println!("error: {e}\n{}", repl.show_stack()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repl_executes_characters_and_mnemonics() -> Result<()> {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval("")?.unwrap(), "(empty)");
+        assert_eq!(repl.eval("Bub")?.unwrap(), "[0] 2");
+        assert_eq!(repl.eval("Inew Iinc*3")?.unwrap(), "[0] 2\n[1] 3");
+        assert_eq!(repl.eval("Itou")?.unwrap(), "[0] 2\n[1] 3u");
+        assert_eq!(repl.prompt(), "A> ");
+        assert_eq!(repl.eval("?")?.unwrap(), "[0] 2\n[1] 3u\n[2] \"\"");
+        assert_eq!(repl.prompt(), "S> ");
+        assert_eq!(repl.eval(":dump")?.unwrap(), "[0] Bub\n[1] Bubu'\n[2] ?");
+        Ok(())
+    }
+
+    #[test]
+    fn repl_commands() -> Result<()> {
+        let mut repl = Repl::new();
+        repl.eval("Bu?")?;
+        assert_eq!(repl.prompt(), "S> ");
+        assert_eq!(repl.eval(":undo")?.unwrap(), "[0] 1");
+        assert_eq!(repl.prompt(), "A> ");
+        assert_eq!(repl.eval(":undo 5")?.unwrap(), "(empty)");
+        repl.eval("B")?;
+        assert!(repl.eval("o").is_err());
+        assert_eq!(repl.show_stack(), "[0] 0");
+        assert_eq!(repl.eval(":reset")?.unwrap(), "(empty)");
+        assert!(repl.eval(":foo").is_err());
+        assert_eq!(repl.eval(":quit")?, None);
+        Ok(())
+    }
+}
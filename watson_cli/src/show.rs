@@ -0,0 +1,38 @@
+//! A compact, human-readable representation of values.
+
+use std::fmt::Write;
+
+use watson_rs::Value;
+
+/// Writes a value in a compact, human-readable form.
+pub fn show(v: &Value, s: &mut String) {
+    match *v {
+        Value::Int(n) => write!(s, "{n}").unwrap(),
+        Value::Uint(n) => write!(s, "{n}u").unwrap(),
+        Value::Float(f) => write!(s, "{f:?}").unwrap(),
+        Value::String(ref b) => write!(s, "\"{}\"", b.escape_ascii()).unwrap(),
+        Value::Object(ref map) => {
+            s.push('{');
+            for (i, (k, v)) in map.iter().enumerate() {
+                if 0 < i {
+                    s.push_str(", ");
+                }
+                write!(s, "\"{}\": ", k.escape_ascii()).unwrap();
+                show(v, s);
+            }
+            s.push('}');
+        }
+        Value::Array(ref arr) => {
+            s.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if 0 < i {
+                    s.push_str(", ");
+                }
+                show(v, s);
+            }
+            s.push(']');
+        }
+        Value::Bool(b) => write!(s, "{b}").unwrap(),
+        Value::Nil => s.push_str("nil"),
+    }
+}
@@ -0,0 +1,201 @@
+//! Statistics of WATSON documents.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use serde_json::json;
+use watson_rs::language::{Insn, Value, ValueKind};
+use watson_rs::lexer;
+use watson_rs::vm::{self, ReadToken};
+
+/// Statistics of a single document.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    /// Statistics of the bytes of the document.
+    pub lexer: lexer::Stats,
+
+    /// Statistics of the executed instructions.
+    pub vm: vm::Stats,
+
+    /// The number of values of each type in the decoded value, including nested ones.
+    pub nodes: HashMap<ValueKind, u64>,
+
+    /// The maximum depth of nested arrays and objects (0 if the value is neither an array nor an object).
+    pub max_nesting: usize,
+
+    /// The size of the decoded value: the number of bytes in strings and keys, plus 8 bytes per number and
+    /// 1 byte per boolean or nil.
+    pub payload_bytes: u64,
+
+    /// The size of the value encoded again with the default configuration.
+    pub reencoded_bytes: usize,
+}
+
+impl Stats {
+    /// Decodes a document and collects its statistics.
+    pub fn collect(input: &[u8], lexer_config: lexer::Config) -> Result<Stats> {
+        let mut lexer = lexer_config.build_slice(input);
+        let mut vm = vm::Config {
+            collect_stats: true,
+            ..Default::default()
+        }
+        .build();
+        while let Some(t) = lexer.read()? {
+            vm.execute(t)?;
+        }
+        let vm_stats = vm.stats().unwrap().clone();
+        let mut values = vm.take_values();
+        if values.len() != 1 {
+            bail!("the document has {} values instead of one", values.len());
+        }
+        let value = values.pop().unwrap();
+        let mut stats = Stats {
+            lexer: lexer.stats(),
+            vm: vm_stats,
+            nodes: HashMap::new(),
+            max_nesting: 0,
+            payload_bytes: 0,
+            reencoded_bytes: watson_rs::to_vec(&value)?.len(),
+        };
+        stats.visit(&value, 0);
+        Ok(stats)
+    }
+
+    /// Returns the number of times the mode was switched, i.e. the number of `Snew`.
+    pub fn mode_switches(&self) -> u64 {
+        self.vm.insn_counts.get(&Insn::Snew).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of executed instructions of each kind in descending order.
+    pub fn histogram(&self) -> Vec<(Insn, u64)> {
+        let mut counts = self
+            .vm
+            .insn_counts
+            .iter()
+            .map(|(&insn, &n)| (insn, n))
+            .collect::<Vec<_>>();
+        counts.sort_by_key(|&(insn, n)| (std::cmp::Reverse(n), format!("{insn:?}")));
+        counts
+    }
+
+    /// Returns the statistics in a human-readable form.
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        let l = &self.lexer;
+        writeln!(
+            s,
+            "bytes: {} (instructions: {}, noise: {}, noise ratio: {:.1}%)",
+            l.bytes,
+            l.insn_bytes,
+            l.skipped_bytes,
+            l.noise_ratio() * 100.0
+        )
+        .unwrap();
+        writeln!(s, "instructions: {}", self.vm.total_insns()).unwrap();
+        for (insn, n) in self.histogram() {
+            writeln!(s, "  {:<8}{n}", format!("{insn:?}")).unwrap();
+        }
+        writeln!(s, "mode switches: {}", self.mode_switches()).unwrap();
+        writeln!(s, "max stack depth: {}", self.vm.max_stack_depth).unwrap();
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_key(|&(kind, _)| kind.to_string());
+        let nodes = nodes
+            .iter()
+            .map(|(kind, n)| format!("{kind} {n}"))
+            .collect::<Vec<_>>();
+        writeln!(
+            s,
+            "values: {} ({})",
+            self.nodes.values().sum::<u64>(),
+            nodes.join(", ")
+        )
+        .unwrap();
+        writeln!(s, "max nesting: {}", self.max_nesting).unwrap();
+        writeln!(s, "payload bytes: {}", self.payload_bytes).unwrap();
+        writeln!(s, "re-encoded bytes: {}", self.reencoded_bytes).unwrap();
+        s
+    }
+
+    /// Returns the statistics as a JSON object.
+    pub fn to_json(&self) -> serde_json::Value {
+        let l = &self.lexer;
+        let insns = self
+            .histogram()
+            .into_iter()
+            .map(|(insn, n)| (format!("{insn:?}"), n.into()))
+            .collect::<serde_json::Map<_, _>>();
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(kind, &n)| (kind.to_string(), n.into()))
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "bytes": l.bytes,
+            "insn_bytes": l.insn_bytes,
+            "skipped_bytes": l.skipped_bytes,
+            "noise_ratio": l.noise_ratio(),
+            "insns": insns,
+            "mode_switches": self.mode_switches(),
+            "max_stack_depth": self.vm.max_stack_depth,
+            "nodes": nodes,
+            "max_nesting": self.max_nesting,
+            "payload_bytes": self.payload_bytes,
+            "reencoded_bytes": self.reencoded_bytes,
+        })
+    }
+
+    fn visit(&mut self, v: &Value, depth: usize) {
+        *self.nodes.entry(v.kind()).or_insert(0) += 1;
+        match *v {
+            Value::Int(_) | Value::Uint(_) | Value::Float(_) => self.payload_bytes += 8,
+            Value::String(ref s) => self.payload_bytes += s.len() as u64,
+            Value::Object(ref map) => {
+                self.max_nesting = self.max_nesting.max(depth + 1);
+                for (k, v) in map {
+                    self.payload_bytes += k.len() as u64;
+                    self.visit(v, depth + 1);
+                }
+            }
+            Value::Array(ref arr) => {
+                self.max_nesting = self.max_nesting.max(depth + 1);
+                for v in arr {
+                    self.visit(v, depth + 1);
+                }
+            }
+            Value::Bool(_) | Value::Nil => self.payload_bytes += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn stats_of_document() -> Result<()> {
+        let value = object![ab: array![Int(1), Nil], c: String(b"xyz".to_vec())];
+        let text = watson_rs::text::TextSerializer::builder()
+            .chars_per_line(0)
+            .build()
+            .to_vec(&value)?;
+        let input = [&text[..], b" \n"].concat();
+        let stats = Stats::collect(&input, lexer::Config::default())?;
+        assert_eq!(stats.lexer.skipped_bytes, 2);
+        assert_eq!(stats.mode_switches(), 3);
+        assert_eq!(stats.vm.total_insns(), stats.lexer.insn_bytes as u64);
+        assert_eq!(stats.nodes[&ValueKind::Object], 1);
+        assert_eq!(stats.nodes[&ValueKind::Int], 1);
+        assert_eq!(stats.nodes.values().sum::<u64>(), 5);
+        assert_eq!(stats.max_nesting, 2);
+        assert_eq!(stats.payload_bytes, 2 + 8 + 1 + 1 + 3);
+        assert_eq!(stats.reencoded_bytes, watson_rs::to_vec(&value)?.len());
+        assert_eq!(stats.to_json()["insns"]["Snew"], 3);
+        assert!(stats.to_text().contains("max nesting: 2\n"));
+
+        assert!(Stats::collect(b"BB", lexer::Config::default()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Conversion between WATSON and TOML.
+//!
+//! A TOML document is a table, so only objects can be converted into TOML. Since TOML has no null, entries of tables
+//! whose values are `Nil` are omitted, and `Nil` in arrays is rejected. `Uint` is converted into an integer if it fits
+//! in `i64`. Byte strings follow the convention of `crate::bytes`. Values nested deeper than `format::MAX_DEPTH` are
+//! rejected.
+//!
+//! Datetimes are converted into objects that have a single entry `{"$datetime": "<the datetime in RFC 3339>"}`, and
+//! such objects are converted back into datetimes.
+
+use anyhow::{anyhow, bail, Result};
+use toml::value::{Datetime, Table};
+use watson_rs::Value;
+
+use crate::bytes;
+use crate::format::check_depth;
+
+/// The key of objects that represent datetimes.
+pub const DATETIME_KEY: &str = "$datetime";
+
+/// Converts a WATSON object into a TOML table.
+pub fn to_toml(v: &Value) -> Result<Table> {
+    table_to_toml(v, 0)
+}
+
+/// Converts a TOML table into a WATSON object.
+pub fn from_toml(table: Table) -> Result<Value> {
+    table_from_toml(table, 0)
+}
+
+fn table_to_toml(v: &Value, depth: usize) -> Result<Table> {
+    match *v {
+        Value::Object(ref map) => {
+            check_depth(depth)?;
+            let mut table = Table::new();
+            for (k, v) in map {
+                if *v == Value::Nil {
+                    continue;
+                }
+                table.insert(
+                    bytes::key_to_str(k)?.to_owned(),
+                    value_to_toml(v, depth + 1)?,
+                );
+            }
+            Ok(table)
+        }
+        _ => bail!("TOML document must be a table: {v:?}"),
+    }
+}
+
+fn table_from_toml(table: Table, depth: usize) -> Result<Value> {
+    check_depth(depth)?;
+    let mut map = watson_rs::Map::with_capacity(table.len());
+    for (k, v) in table {
+        map.insert(k.into_bytes(), value_from_toml(v, depth + 1)?);
+    }
+    Ok(Value::Object(map))
+}
+
+fn value_to_toml(v: &Value, depth: usize) -> Result<toml::Value> {
+    Ok(match *v {
+        Value::Int(n) => n.into(),
+        Value::Uint(n) => i64::try_from(n)
+            .map_err(|_| anyhow!("TOML can't represent {n}"))?
+            .into(),
+        Value::Float(f) => f.into(),
+        Value::String(ref s) => string_to_toml(s),
+        Value::Object(_) => match object_to_datetime(v)? {
+            Some(d) => toml::Value::Datetime(d),
+            None => toml::Value::Table(table_to_toml(v, depth)?),
+        },
+        Value::Array(ref arr) => {
+            check_depth(depth)?;
+            let arr = arr.iter().map(|v| value_to_toml(v, depth + 1));
+            toml::Value::Array(arr.collect::<Result<_>>()?)
+        }
+        Value::Bool(b) => b.into(),
+        Value::Nil => bail!("TOML can't represent nil"),
+    })
+}
+
+fn value_from_toml(v: toml::Value, depth: usize) -> Result<Value> {
+    Ok(match v {
+        toml::Value::Integer(n) => Value::Int(n),
+        toml::Value::Float(f) => Value::Float(f),
+        toml::Value::String(s) => Value::String(s.into_bytes()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => {
+            let mut map = watson_rs::Map::new();
+            map.insert(
+                DATETIME_KEY.as_bytes().to_vec(),
+                Value::String(d.to_string().into_bytes()),
+            );
+            Value::Object(map)
+        }
+        toml::Value::Array(arr) => {
+            check_depth(depth)?;
+            let arr = arr.into_iter().map(|v| value_from_toml(v, depth + 1));
+            Value::Array(arr.collect::<Result<_>>()?)
+        }
+        toml::Value::Table(table) => match table_to_bytes(&table)? {
+            Some(b) => Value::String(b),
+            None => table_from_toml(table, depth)?,
+        },
+    })
+}
+
+fn string_to_toml(s: &[u8]) -> toml::Value {
+    match std::str::from_utf8(s) {
+        Ok(s) => s.into(),
+        Err(_) => {
+            let mut table = Table::new();
+            table.insert(bytes::BASE64_KEY.to_owned(), bytes::encode_base64(s).into());
+            toml::Value::Table(table)
+        }
+    }
+}
+
+fn table_to_bytes(table: &Table) -> Result<Option<Vec<u8>>> {
+    match table.get(bytes::BASE64_KEY) {
+        Some(toml::Value::String(s)) if table.len() == 1 => bytes::decode_base64(s).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn object_to_datetime(v: &Value) -> Result<Option<Datetime>> {
+    let map = match *v {
+        Value::Object(ref map) if map.len() == 1 => map,
+        _ => return Ok(None),
+    };
+    match map.get(DATETIME_KEY.as_bytes()) {
+        Some(Value::String(s)) => {
+            let s = std::str::from_utf8(s)?;
+            Ok(Some(
+                s.parse()
+                    .map_err(|e| anyhow!("invalid datetime {s:?}: {e}"))?,
+            ))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn toml_roundtrip() -> Result<()> {
+        let v = object![
+            int: Int(-1),
+            float: Float(1.5),
+            string: String(b"hello".to_vec()),
+            bytes: String(b"\xff\x00".to_vec()),
+            datetime: object![["$datetime"]: String(b"1979-05-27T07:32:00Z".to_vec())],
+            array: array![Bool(true), Int(2)],
+            table: object![a: Int(1)],
+        ];
+        let table = to_toml(&v)?;
+        assert_eq!(
+            table,
+            toml::from_str::<Table>(
+                r#"
+                int = -1
+                float = 1.5
+                string = "hello"
+                bytes = { "$base64" = "/wA=" }
+                datetime = 1979-05-27T07:32:00Z
+                array = [true, 2]
+                table = { a = 1 }
+                "#
+            )?
+        );
+        assert_eq!(from_toml(table)?, v);
+        Ok(())
+    }
+
+    #[test]
+    fn toml_nil_and_uint() -> Result<()> {
+        assert_eq!(
+            to_toml(&object![a: Nil, b: Uint(1)])?,
+            toml::from_str::<Table>("b = 1")?
+        );
+        assert!(to_toml(&object![a: Uint(u64::MAX)]).is_err());
+        assert!(to_toml(&object![a: array![Nil]]).is_err());
+        assert!(to_toml(&Int(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn toml_rejects_deeply_nested_values() -> Result<()> {
+        let nest = |depth| {
+            let mut v = Int(1);
+            for _ in 0..depth {
+                v = array![v];
+            }
+            object![a: v]
+        };
+        let v = nest(crate::format::MAX_DEPTH - 1);
+        assert_eq!(from_toml(to_toml(&v)?)?, v);
+        assert!(to_toml(&nest(crate::format::MAX_DEPTH)).is_err());
+        // Deeper values must not overflow the stack.
+        assert!(to_toml(&nest(200_000)).is_err());
+        Ok(())
+    }
+}
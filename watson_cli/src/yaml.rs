@@ -0,0 +1,180 @@
+//! Conversion between WATSON and YAML.
+//!
+//! Numbers are converted in the same way as `crate::json`, except that NaN and infinities are representable in YAML.
+//! Byte strings follow the convention of `crate::bytes`. Keys of mappings that are numbers or booleans are converted
+//! into their textual representations, and tags are ignored. Values nested deeper than `format::MAX_DEPTH` are
+//! rejected.
+
+use anyhow::{bail, Result};
+use serde_yaml::{Mapping, Number};
+use watson_rs::Value;
+
+use crate::bytes;
+use crate::format::check_depth;
+
+/// Converts a WATSON value into a YAML value.
+pub fn to_yaml(v: &Value) -> Result<serde_yaml::Value> {
+    value_to_yaml(v, 0)
+}
+
+/// Converts a YAML value into a WATSON value.
+pub fn from_yaml(v: serde_yaml::Value) -> Result<Value> {
+    value_from_yaml(v, 0)
+}
+
+fn value_to_yaml(v: &Value, depth: usize) -> Result<serde_yaml::Value> {
+    Ok(match *v {
+        Value::Int(n) => n.into(),
+        Value::Uint(n) => n.into(),
+        Value::Float(f) => f.into(),
+        Value::String(ref s) => string_to_yaml(s),
+        Value::Object(ref map) => {
+            check_depth(depth)?;
+            let mut mapping = Mapping::with_capacity(map.len());
+            for (k, v) in map {
+                mapping.insert(bytes::key_to_str(k)?.into(), value_to_yaml(v, depth + 1)?);
+            }
+            serde_yaml::Value::Mapping(mapping)
+        }
+        Value::Array(ref arr) => {
+            check_depth(depth)?;
+            let seq = arr.iter().map(|v| value_to_yaml(v, depth + 1));
+            serde_yaml::Value::Sequence(seq.collect::<Result<_>>()?)
+        }
+        Value::Bool(b) => b.into(),
+        Value::Nil => serde_yaml::Value::Null,
+    })
+}
+
+fn value_from_yaml(v: serde_yaml::Value, depth: usize) -> Result<Value> {
+    Ok(match v {
+        serde_yaml::Value::Null => Value::Nil,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => number_from_yaml(&n),
+        serde_yaml::Value::String(s) => Value::String(s.into_bytes()),
+        serde_yaml::Value::Sequence(seq) => {
+            check_depth(depth)?;
+            let arr = seq.into_iter().map(|v| value_from_yaml(v, depth + 1));
+            Value::Array(arr.collect::<Result<_>>()?)
+        }
+        serde_yaml::Value::Mapping(mapping) => {
+            if let Some(b) = yaml_to_bytes(&mapping)? {
+                return Ok(Value::String(b));
+            }
+            check_depth(depth)?;
+            let mut map = watson_rs::Map::with_capacity(mapping.len());
+            for (k, v) in mapping {
+                map.insert(key_from_yaml(k)?, value_from_yaml(v, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+        serde_yaml::Value::Tagged(tagged) => value_from_yaml(tagged.value, depth)?,
+    })
+}
+
+fn number_from_yaml(n: &Number) -> Value {
+    if let Some(i) = n.as_i64() {
+        Value::Int(i)
+    } else if let Some(u) = n.as_u64() {
+        Value::Uint(u)
+    } else {
+        Value::Float(n.as_f64().unwrap())
+    }
+}
+
+fn key_from_yaml(k: serde_yaml::Value) -> Result<Vec<u8>> {
+    Ok(match k {
+        serde_yaml::Value::String(s) => s.into_bytes(),
+        serde_yaml::Value::Number(n) => n.to_string().into_bytes(),
+        serde_yaml::Value::Bool(b) => b.to_string().into_bytes(),
+        serde_yaml::Value::Tagged(tagged) => key_from_yaml(tagged.value)?,
+        k => bail!("YAML mapping key must be a scalar: {k:?}"),
+    })
+}
+
+fn string_to_yaml(s: &[u8]) -> serde_yaml::Value {
+    match std::str::from_utf8(s) {
+        Ok(s) => s.into(),
+        Err(_) => {
+            let mut mapping = Mapping::new();
+            mapping.insert(bytes::BASE64_KEY.into(), bytes::encode_base64(s).into());
+            serde_yaml::Value::Mapping(mapping)
+        }
+    }
+}
+
+fn yaml_to_bytes(mapping: &Mapping) -> Result<Option<Vec<u8>>> {
+    match mapping.get(bytes::BASE64_KEY) {
+        Some(serde_yaml::Value::String(s)) if mapping.len() == 1 => {
+            bytes::decode_base64(s).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use watson_rs::{array, object};
+    use Value::*;
+
+    #[test]
+    fn yaml_roundtrip() -> Result<()> {
+        let v = object![
+            int: Int(-1),
+            uint: Uint(u64::MAX),
+            float: Float(f64::INFINITY),
+            string: String(b"hello".to_vec()),
+            bytes: String(b"\xff\x00".to_vec()),
+            array: array![Bool(true), Nil],
+        ];
+        let yaml = to_yaml(&v)?;
+        assert_eq!(
+            yaml,
+            serde_yaml::from_str::<serde_yaml::Value>(
+                r#"
+                int: -1
+                uint: 18446744073709551615
+                float: .inf
+                string: hello
+                bytes: {$base64: /wA=}
+                array: [true, null]
+                "#
+            )?
+        );
+        assert_eq!(from_yaml(yaml)?, v);
+        Ok(())
+    }
+
+    #[test]
+    fn yaml_scalar_keys() -> Result<()> {
+        let yaml = serde_yaml::from_str("1: a\ntrue: b\n!foo c: d\n")?;
+        assert_eq!(
+            from_yaml(yaml)?,
+            object![
+                ["1"]: String(b"a".to_vec()),
+                ["true"]: String(b"b".to_vec()),
+                c: String(b"d".to_vec()),
+            ]
+        );
+        assert!(from_yaml(serde_yaml::from_str("[a]: b")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn yaml_rejects_deeply_nested_values() -> Result<()> {
+        let nest = |depth| {
+            let mut v = Nil;
+            for _ in 0..depth {
+                v = array![v];
+            }
+            v
+        };
+        let v = nest(crate::format::MAX_DEPTH);
+        assert_eq!(from_yaml(to_yaml(&v)?)?, v);
+        assert!(to_yaml(&nest(crate::format::MAX_DEPTH + 1)).is_err());
+        // Deeper values must not overflow the stack.
+        assert!(to_yaml(&nest(200_000)).is_err());
+        Ok(())
+    }
+}
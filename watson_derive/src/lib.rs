@@ -0,0 +1,369 @@
+//! Derive macros that convert Rust types from and to `watson_rs::Value` without going through serde.
+//!
+//! - `#[derive(ToWatson)]` implements `From<T> for watson_rs::Value`.
+//! - `#[derive(FromWatson)]` implements `watson_rs::IsValue`. Since `IsValue` requires `Into<Value>`, types that
+//!   derive `FromWatson` also have to derive (or implement) `ToWatson`.
+//!
+//! Types are mapped as follows:
+//!
+//! - Structs with named fields are objects whose keys are the names of the fields.
+//!   Missing fields are read as `Nil`, so that `Option` fields can be omitted.
+//! - Newtype structs are their inner values.
+//! - Tuple structs are arrays.
+//! - Unit structs are `Nil`.
+//! - Unit variants are strings that hold their names.
+//!   Other variants are objects that have a single entry from their names to their contents.
+//!
+//! The key of a field or variant can be changed with `#[watson(rename = "name")]`, or with
+//! `#[watson(key = b"bytes")]` if it is not valid UTF-8.
+//!
+//! These macros are re-exported from `watson_rs` when its `derive` feature is enabled.
+//! `#[watson_keys]` lets structs that derive serde's traits use the same attributes, and is re-exported from
+//! `serde_watson` when its `derive` feature is enabled.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Fields, GenericParam, Generics,
+    Ident, LitByteStr, LitStr, Path,
+};
+
+// This must agree with `serde_watson::key::ESCAPE`.
+const ESCAPE: char = '\u{fdd0}';
+
+#[proc_macro_derive(ToWatson, attributes(watson))]
+pub fn derive_to_watson(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    to_watson(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromWatson, attributes(watson))]
+pub fn derive_from_watson(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_watson(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Replaces `#[watson(...)]` on fields and variants with `#[serde(rename = "...")]`.
+/// Keys that are not valid UTF-8 are renamed in the escaped form of `serde_watson::key`.
+/// This must be placed above `#[derive(Serialize, Deserialize)]`.
+#[proc_macro_attribute]
+pub fn watson_keys(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(item as DeriveInput);
+    rename_for_serde(&mut input)
+        .map(|()| quote!(#input))
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn rename_for_serde(input: &mut DeriveInput) -> syn::Result<()> {
+    fn rename_fields(fields: &mut Fields) -> syn::Result<()> {
+        for field in fields.iter_mut() {
+            if let Some(ident) = &field.ident {
+                let key = key(ident, &field.attrs)?;
+                set_serde_rename(&mut field.attrs, ident, &key);
+            }
+        }
+        Ok(())
+    }
+
+    match &mut input.data {
+        Data::Struct(s) => rename_fields(&mut s.fields),
+        Data::Enum(e) => {
+            for variant in e.variants.iter_mut() {
+                let key = key(&variant.ident, &variant.attrs)?;
+                if std::str::from_utf8(&key.value()).is_err() {
+                    return Err(syn::Error::new_spanned(
+                        &variant.ident,
+                        "keys of variants must be valid UTF-8",
+                    ));
+                }
+                set_serde_rename(&mut variant.attrs, &variant.ident, &key);
+                rename_fields(&mut variant.fields)?;
+            }
+            Ok(())
+        }
+        Data::Union(u) => Err(syn::Error::new_spanned(
+            u.union_token,
+            "watson_keys cannot be applied to unions",
+        )),
+    }
+}
+
+fn set_serde_rename(attrs: &mut Vec<Attribute>, ident: &Ident, key: &LitByteStr) {
+    let renamed = attrs.iter().any(|attr| attr.path().is_ident("watson"));
+    attrs.retain(|attr| !attr.path().is_ident("watson"));
+    if renamed {
+        let name = LitStr::new(&escape(&key.value()), ident.span());
+        attrs.push(parse_quote!(#[serde(rename = #name)]));
+    }
+}
+
+fn escape(key: &[u8]) -> String {
+    match std::str::from_utf8(key) {
+        Ok(s) => s.to_owned(),
+        Err(_) => std::iter::once(ESCAPE.to_string())
+            .chain(key.iter().map(|b| format!("{b:02x}")))
+            .collect(),
+    }
+}
+
+fn to_watson(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_bounds(
+        &input.generics,
+        quote!(::core::convert::Into<::watson_rs::Value>),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(s) => {
+            let (pat, expr) = fields_to_value(&s.fields)?;
+            quote! {
+                let #name #pat = v;
+                #expr
+            }
+        }
+        Data::Enum(e) => {
+            let arms = e.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let tag = key(ident, &variant.attrs)?;
+                let (pat, expr) = fields_to_value(&variant.fields)?;
+                Ok(match variant.fields {
+                    Fields::Unit => quote! {
+                        #name::#ident => ::watson_rs::Value::String(
+                            ::watson_rs::ToBytes::to_bytes(#tag),
+                        )
+                    },
+                    _ => quote! {
+                        #name::#ident #pat => ::watson_rs::derive::tagged(#tag, #expr)
+                    },
+                })
+            });
+            let arms = arms.collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                match v {
+                    #(#arms,)*
+                }
+            }
+        }
+        Data::Union(u) => {
+            return Err(syn::Error::new_spanned(
+                u.union_token,
+                "ToWatson cannot be derived for unions",
+            ))
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::core::convert::From<#name #ty_generics> for ::watson_rs::Value
+        #where_clause
+        {
+            fn from(v: #name #ty_generics) -> ::watson_rs::Value {
+                #body
+            }
+        }
+    })
+}
+
+fn from_watson(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_bounds(&input.generics, quote!(::watson_rs::IsValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (body, kind) = match &input.data {
+        Data::Struct(s) => {
+            let path: Path = parse_quote!(#name);
+            let value = format_ident!("v");
+            (value_to_fields(&path, &s.fields, &value)?, kind(&s.fields))
+        }
+        Data::Enum(e) if e.variants.is_empty() => (
+            quote! {
+                let _ = v;
+                ::core::option::Option::None
+            },
+            quote!(::core::option::Option::None),
+        ),
+        Data::Enum(e) => {
+            let content = format_ident!("content");
+            let arms = e.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let tag = key(ident, &variant.attrs)?;
+                let path: Path = parse_quote!(#name::#ident);
+                let expr = value_to_fields(&path, &variant.fields, &content)?;
+                Ok(quote! { #tag => { #expr } })
+            });
+            let arms = arms.collect::<syn::Result<Vec<_>>>()?;
+            (
+                quote! {
+                    let (tag, #content) = ::watson_rs::derive::untag(v)?;
+                    match tag.as_slice() {
+                        #(#arms)*
+                        _ => ::core::option::Option::None,
+                    }
+                },
+                quote!(::core::option::Option::None),
+            )
+        }
+        Data::Union(u) => {
+            return Err(syn::Error::new_spanned(
+                u.union_token,
+                "FromWatson cannot be derived for unions",
+            ))
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::watson_rs::IsValue for #name #ty_generics #where_clause {
+            fn from_value(v: ::watson_rs::Value) -> ::core::option::Option<Self> {
+                #body
+            }
+
+            fn kind() -> ::core::option::Option<::watson_rs::ValueKind> {
+                #kind
+            }
+        }
+    })
+}
+
+/// Returns a pattern that binds the fields to `__field0`, `__field1`, ... and an expression that converts them into a `Value`.
+fn fields_to_value(fields: &Fields) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let vars: Vec<Ident> = (0..fields.len())
+        .map(|i| format_ident!("__field{}", i))
+        .collect();
+    Ok(match fields {
+        Fields::Named(named) => {
+            let idents: Vec<&Ident> = named
+                .named
+                .iter()
+                .map(|f| f.ident.as_ref().unwrap())
+                .collect();
+            let keys = named
+                .named
+                .iter()
+                .map(|f| key(f.ident.as_ref().unwrap(), &f.attrs))
+                .collect::<syn::Result<Vec<_>>>()?;
+            (
+                quote!({ #(#idents: #vars),* }),
+                quote!({
+                    let mut map = ::watson_rs::Map::new();
+                    #(
+                        map.insert(
+                            ::watson_rs::ToBytes::to_bytes(#keys),
+                            ::core::convert::Into::<::watson_rs::Value>::into(#vars),
+                        );
+                    )*
+                    ::watson_rs::Value::Object(map)
+                }),
+            )
+        }
+        Fields::Unnamed(_) if fields.len() == 1 => (
+            quote!((__field0)),
+            quote!(::core::convert::Into::<::watson_rs::Value>::into(__field0)),
+        ),
+        Fields::Unnamed(_) => (
+            quote!((#(#vars),*)),
+            quote!(::watson_rs::Value::from(::std::vec![
+                #(::core::convert::Into::<::watson_rs::Value>::into(#vars)),*
+            ])),
+        ),
+        Fields::Unit => (quote!(), quote!(::watson_rs::Value::Nil)),
+    })
+}
+
+/// Returns an expression that converts `value` into `path` with the given fields.
+fn value_to_fields(path: &Path, fields: &Fields, value: &Ident) -> syn::Result<TokenStream2> {
+    Ok(match fields {
+        Fields::Named(named) => {
+            let inits = named.named.iter().map(|f| {
+                let ident = f.ident.as_ref().unwrap();
+                let ty = &f.ty;
+                let key = key(ident, &f.attrs)?;
+                Ok(quote! {
+                    #ident: <#ty as ::watson_rs::IsValue>::from_value(
+                        ::watson_rs::derive::take_field(&mut map, #key),
+                    )?
+                })
+            });
+            let inits = inits.collect::<syn::Result<Vec<_>>>()?;
+            quote! {
+                let mut map = <::watson_rs::Map as ::watson_rs::IsValue>::from_value(#value)?;
+                ::core::option::Option::Some(#path { #(#inits),* })
+            }
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            let ty = &unnamed.unnamed[0].ty;
+            quote! {
+                <#ty as ::watson_rs::IsValue>::from_value(#value).map(#path)
+            }
+        }
+        Fields::Unnamed(unnamed) => {
+            let len = unnamed.unnamed.len();
+            let elems = unnamed.unnamed.iter().map(|f| {
+                let ty = &f.ty;
+                quote!(<#ty as ::watson_rs::IsValue>::from_value(elems.next()?)?)
+            });
+            quote! {
+                let elems = <::std::vec::Vec<::watson_rs::Value> as ::watson_rs::IsValue>::from_value(#value)?;
+                if elems.len() != #len {
+                    return ::core::option::Option::None;
+                }
+                let mut elems = elems.into_iter();
+                ::core::option::Option::Some(#path(#(#elems),*))
+            }
+        }
+        Fields::Unit => quote! {
+            <() as ::watson_rs::IsValue>::from_value(#value).map(|()| #path)
+        },
+    })
+}
+
+fn kind(fields: &Fields) -> TokenStream2 {
+    let kind = match fields {
+        Fields::Named(_) => quote!(Object),
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            let ty = &unnamed.unnamed[0].ty;
+            return quote!(<#ty as ::watson_rs::IsValue>::kind());
+        }
+        Fields::Unnamed(_) => quote!(Array),
+        Fields::Unit => quote!(Nil),
+    };
+    quote!(::core::option::Option::Some(::watson_rs::ValueKind::#kind))
+}
+
+/// Returns the key of a field or variant, which is its name unless `#[watson(rename = "...")]` or
+/// `#[watson(key = b"...")]` is given.
+fn key(ident: &Ident, attrs: &[Attribute]) -> syn::Result<LitByteStr> {
+    let mut key = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("watson")) {
+        attr.parse_nested_meta(|meta| {
+            let value = if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                LitByteStr::new(name.value().as_bytes(), name.span())
+            } else if meta.path.is_ident("key") {
+                meta.value()?.parse()?
+            } else {
+                return Err(meta.error("expected `rename` or `key`"));
+            };
+            if key.replace(value).is_some() {
+                return Err(meta.error("duplicate key"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(key.unwrap_or_else(|| {
+        let name = ident.to_string();
+        let name = name.strip_prefix("r#").unwrap_or(&name);
+        LitByteStr::new(name.as_bytes(), ident.span())
+    }))
+}
+
+fn add_bounds(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.params.iter_mut() {
+        if let GenericParam::Type(ty) = param {
+            ty.bounds.push(parse_quote!(#bound));
+        }
+    }
+    generics
+}
@@ -0,0 +1,17 @@
+use std::io;
+
+use watson_rs::serializer::Serializer;
+use watson_rs::unlexer::Unlexer;
+use watson_rs::{array, object, Value};
+
+fn main() -> watson_rs::Result<()> {
+    let value = object![
+        name: Value::String(b"Oozora Subaru".to_vec()),
+        tags: array![Value::String(b"duck".to_vec()), Value::Bool(true)],
+    ];
+
+    // `Unlexer` is a `WriteInsn`, so `Serializer` can write instructions to it directly.
+    let mut ser = Serializer::new(Unlexer::new(io::stdout()));
+    ser.serialize(&value)?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! Assembler of mnemonic listings.
+//!
+//! A listing consists of mnemonics of instructions (e.g. `Inew`) separated by whitespace.
+//! A mnemonic can be followed by `*N` to repeat it `N` times (e.g. `Ishl*3`),
+//! and `Ext(N)` represents `Insn::Ext(N)`. A `;` starts a comment that continues to the end of the line.
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Insn, Location, MNEMONICS};
+use crate::serializer::WriteInsn;
+use crate::unlexer::Unlexer;
+
+/// Parses a listing into a sequence of instructions.
+pub fn assemble(src: &str) -> Result<Vec<Insn>> {
+    let mut insns = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default();
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            let word = &rest[start..];
+            let end = word.find(char::is_whitespace).unwrap_or(word.len());
+            let column = line.len() - word.len() + 1;
+            let (insn, count) = parse_word(&word[..end]).ok_or_else(|| Error {
+                kind: ErrorKind::InvalidAssembly,
+                location: Location {
+                    byte: word.as_bytes()[0],
+                    path: None,
+                    line: i + 1,
+                    column,
+                },
+                source: None,
+            })?;
+            insns.extend(std::iter::repeat_n(insn, count));
+            rest = &word[end..];
+        }
+    }
+    Ok(insns)
+}
+
+/// Parses a listing and converts it into WATSON text with the default configuration of `Unlexer`.
+/// It fails with `ErrorKind::UnrepresentableInstruction` if the listing has an `Ext(N)` that can't be written as text.
+pub fn assemble_text(src: &str) -> Result<Vec<u8>> {
+    let mut unlexer = Unlexer::new(Vec::new());
+    unlexer.write_all(&assemble(src)?)?;
+    unlexer.into_inner()
+}
+
+/// Parses a mnemonic with an optional repeat count.
+fn parse_word(word: &str) -> Option<(Insn, usize)> {
+    let (name, count) = match word.split_once('*') {
+        Some((name, count)) => (name, count.parse().ok()?),
+        None => (word, 1),
+    };
+    if let Some(byte) = name.strip_prefix("Ext(").and_then(|s| s.strip_suffix(')')) {
+        return Some((Insn::Ext(byte.parse().ok()?), count));
+    }
+    let (_, insn) = MNEMONICS.iter().find(|(mnemonic, _)| *mnemonic == name)?;
+    Some((*insn, count))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Insn::*;
+
+    #[test]
+    fn assemble_parses_mnemonics() -> Result<()> {
+        let src = "
+            ; 4
+            Inew Iinc Ishl*2
+            Snew   ; an empty string
+            Ext(88)
+        ";
+        assert_eq!(assemble(src)?, vec![Inew, Iinc, Ishl, Ishl, Snew, Ext(88)]);
+        assert_eq!(assemble_text("Inew Iinc Ishl*2 Snew Inew")?, b"Bubb?S");
+        assert_eq!(
+            assemble_text("Ext(200)").unwrap_err().kind,
+            ErrorKind::UnrepresentableInstruction
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn assemble_reports_location_of_invalid_mnemonic() {
+        for (src, column) in [("Inew\n  Iinc Inop", 8), ("Inew\nIshl*x", 1)] {
+            let err = assemble(src).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::InvalidAssembly);
+            assert_eq!((err.location.line, err.location.column), (2, column));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Byte-oriented sources and sinks for `Lexer` and `Unlexer`.
+//!
+//! Every `io::Read` is a `ByteRead` and every `io::Write` is a `ByteWrite`, so readers and writers from `std::io` can
+//! be used as they are. Other sources and sinks (e.g. ring buffers or serial ports) can implement these traits
+//! directly instead of `std::io` traits.
+
+use std::io;
+use std::slice;
+
+use crate::error::{Error, Result};
+use crate::language::Location;
+
+/// A source of bytes.
+pub trait ByteRead {
+    /// Reads the next byte, or returns `Ok(None)` at the end of the input.
+    fn read_byte(&mut self) -> Result<Option<u8>>;
+}
+
+/// A sink of bytes.
+pub trait ByteWrite {
+    /// Writes all the given bytes.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Makes sure that all bytes written so far reach their destination.
+    fn flush_bytes(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: io::Read + ?Sized> ByteRead for R {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut byte = 0;
+        loop {
+            match self.read(slice::from_mut(&mut byte)) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::from_io_error(e, Location::unknown())),
+            }
+        }
+    }
+}
+
+impl<W: io::Write + ?Sized> ByteWrite for W {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_all(bytes)
+            .map_err(|e| Error::from_io_error(e, Location::unknown()))
+    }
+
+    fn flush_bytes(&mut self) -> Result<()> {
+        self.flush()
+            .map_err(|e| Error::from_io_error(e, Location::unknown()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::language::Insn;
+    use crate::lexer::Lexer;
+    use crate::serializer::WriteInsn;
+    use crate::unlexer;
+    use crate::vm::ReadToken;
+
+    #[test]
+    fn lexer_reads_from_byte_read() -> Result<()> {
+        let mut lexer = Lexer::new(Ring(b"Bu?".iter().copied().collect()));
+        assert_eq!(lexer.read()?.map(|t| t.insn), Some(Insn::Inew));
+        assert_eq!(lexer.read()?.map(|t| t.insn), Some(Insn::Iinc));
+        assert_eq!(lexer.read()?.map(|t| t.insn), Some(Insn::Snew));
+        assert_eq!(lexer.read()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_reports_errors_of_byte_read_with_location() {
+        let mut lexer = Lexer::new(Broken);
+        let err = lexer.read().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::IOError);
+        assert_eq!(err.location.line, 1);
+    }
+
+    #[test]
+    fn unlexer_writes_to_byte_write() -> Result<()> {
+        let mut unlexer = unlexer::Config {
+            buffer_capacity: 2,
+            ..Default::default()
+        }
+        .build(Ring(VecDeque::new()));
+        for insn in [Insn::Inew, Insn::Iinc, Insn::Snew, Insn::Inew] {
+            unlexer.write(insn)?;
+        }
+        let ring = unlexer.into_inner()?;
+        assert_eq!(ring.0, b"Bu?S");
+        Ok(())
+    }
+
+    /*
+     * Helper functions
+     */
+
+    // Neither `io::Read` nor `io::Write`.
+    struct Ring(VecDeque<u8>);
+
+    impl ByteRead for Ring {
+        fn read_byte(&mut self) -> Result<Option<u8>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    impl ByteWrite for Ring {
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+            self.0.extend(bytes);
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl ByteRead for Broken {
+        fn read_byte(&mut self) -> Result<Option<u8>> {
+            Err(Error::from_io_error(
+                io::Error::other("broken"),
+                Location::unknown(),
+            ))
+        }
+    }
+}
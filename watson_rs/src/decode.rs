@@ -0,0 +1,206 @@
+//! Limits and policies on decoding untrusted input.
+
+use std::io;
+use std::path;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Location, Value};
+use crate::lexer;
+use crate::vm::{self, DuplicateKeys, ReadToken};
+
+/// `DecodeOptions` bundles limits and policies that are enforced while decoding a single value.
+///
+/// Each limit defaults to unlimited. `max_input_bytes` and `max_insns` result in `ErrorKind::InputLimitExceeded`,
+/// and the other limits are enforced by the VM, which returns `ErrorKind::LimitExceeded`.
+#[derive(Eq, PartialEq, Clone, Debug, Default)]
+pub struct DecodeOptions {
+    /// The maximum number of bytes in the input.
+    pub max_input_bytes: Option<usize>,
+
+    /// The maximum number of values on the stack, which bounds the depth of nested arrays and objects.
+    pub max_depth: Option<usize>,
+
+    /// The maximum number of bytes in a single string.
+    pub max_string_bytes: Option<usize>,
+
+    /// The maximum number of elements in a single array.
+    pub max_array_len: Option<usize>,
+
+    /// The maximum number of entries in a single object.
+    pub max_object_len: Option<usize>,
+
+    /// The maximum number of instructions to execute, which applies to `from_tokens` as well.
+    pub max_insns: Option<usize>,
+
+    /// How keys that appear more than once in an object are treated (defaults to `DuplicateKeys::LastWins`).
+    pub duplicate_keys: DuplicateKeys,
+}
+
+impl DecodeOptions {
+    /// Returns a `lexer::Config` that enforces the limits on the lexer.
+    pub fn lexer_config(&self) -> lexer::Config {
+        lexer::Config {
+            max_input_bytes: self.max_input_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Returns a `vm::Config` that enforces the limits on the VM.
+    pub fn vm_config(&self) -> vm::Config {
+        vm::Config {
+            max_stack_depth: self.max_depth,
+            max_string_bytes: self.max_string_bytes,
+            max_array_len: self.max_array_len,
+            max_object_len: self.max_object_len,
+            duplicate_keys: self.duplicate_keys,
+            ..Default::default()
+        }
+    }
+
+    /// Decodes WATSON text into a single value. See `crate::from_str` for details.
+    pub fn from_str(&self, s: &str) -> Result<Value> {
+        self.decode(self.lexer_config().build_slice(s.as_bytes()))
+    }
+
+    /// Decodes WATSON text read from the given reader into a single value. See `crate::from_str` for details.
+    pub fn from_reader<R: io::Read>(&self, reader: R) -> Result<Value> {
+        self.decode(self.lexer_config().build(reader))
+    }
+
+    /// Decodes a WATSON file into a single value. See `crate::from_str` for details.
+    pub fn from_file(&self, path: &path::Path) -> Result<Value> {
+        self.decode(self.lexer_config().open(path)?)
+    }
+
+    /// Decodes a single value from tokens read by the given reader (e.g. a lexer with a custom configuration).
+    /// See `crate::from_str` for details. `max_input_bytes` is not applied to the reader.
+    pub fn from_tokens<R: ReadToken>(&self, reader: R) -> Result<Value> {
+        self.decode(reader)
+    }
+
+    fn decode<R: ReadToken>(&self, mut reader: R) -> Result<Value> {
+        let mut vm = self.vm_config().build();
+        let mut last = Location::unknown();
+        let mut started = false;
+        let mut executed = 0;
+        let invalid = |kind, location| Error {
+            kind,
+            location,
+            source: None,
+        };
+        while let Some(t) = reader.read()? {
+            last = t.location.clone();
+            executed += 1;
+            if self.max_insns.is_some_and(|max| executed > max) {
+                return Err(invalid(ErrorKind::InputLimitExceeded, last));
+            }
+            vm.execute(t)?;
+            let empty = vm.borrow_stack().is_empty();
+            if started && empty {
+                return Err(invalid(ErrorKind::InvalidDocument, last));
+            }
+            started |= !empty;
+        }
+        let mut values = vm.take_values();
+        match values.len() {
+            1 => Ok(values.pop().unwrap()),
+            0 => Err(invalid(ErrorKind::EmptyStack, last)),
+            _ => Err(invalid(ErrorKind::InvalidDocument, last)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{array, object, to_string};
+    use Value::*;
+
+    #[test]
+    fn decode_options_unlimited_by_default() -> Result<()> {
+        let value = object![a: array![Int(1), String(b"xy".to_vec())]];
+        assert_eq!(
+            DecodeOptions::default().from_str(&to_string(&value)?)?,
+            value
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_options_enforce_limits() -> Result<()> {
+        let opts = DecodeOptions {
+            max_depth: Some(4),
+            max_string_bytes: Some(1),
+            max_array_len: Some(1),
+            max_object_len: Some(1),
+            max_insns: Some(64),
+            ..Default::default()
+        };
+        let value = object![a: array![Nil]];
+        assert_eq!(opts.from_str(&to_string(&value)?)?, value);
+        for (value, kind) in [
+            (
+                array![array![array![array![Nil]]]],
+                ErrorKind::LimitExceeded,
+            ),
+            (String(b"xy".to_vec()), ErrorKind::LimitExceeded),
+            (array![Nil, Nil], ErrorKind::LimitExceeded),
+            (object![a: Nil, b: Nil], ErrorKind::LimitExceeded),
+            (Int(0x5555_5555_5555_5555), ErrorKind::InputLimitExceeded),
+        ] {
+            let text = to_string(&value)?;
+            assert_eq!(opts.from_str(&text).unwrap_err().kind, kind, "{value:?}");
+            assert_eq!(opts.from_reader(text.as_bytes()).unwrap_err().kind, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_options_limit_input_bytes() -> Result<()> {
+        let text = to_string(&Int(3))?;
+        let opts = DecodeOptions {
+            max_input_bytes: Some(text.len()),
+            ..Default::default()
+        };
+        assert_eq!(opts.from_str(&text)?, Int(3));
+        for text in [format!("{text} "), format!(" {text}")] {
+            assert_eq!(
+                opts.from_str(&text).unwrap_err().kind,
+                ErrorKind::InputLimitExceeded
+            );
+            assert_eq!(
+                opts.from_reader(text.as_bytes()).unwrap_err().kind,
+                ErrorKind::InputLimitExceeded
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_options_from_tokens() -> Result<()> {
+        let lexer = lexer::Config {
+            initial_mode: crate::language::Mode::S,
+            ..Default::default()
+        };
+        let opts = DecodeOptions::default();
+        assert_eq!(opts.from_tokens(lexer.clone().build_slice(b"Sha"))?, Int(2));
+        assert_eq!(
+            opts.from_tokens(lexer.clone().build_slice(b"SS"))
+                .unwrap_err()
+                .kind,
+            ErrorKind::InvalidDocument
+        );
+        let opts = DecodeOptions {
+            max_insns: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(opts.from_tokens(lexer.clone().build_slice(b"Sh"))?, Int(1));
+        assert_eq!(
+            opts.from_tokens(lexer.build_slice(b"Sha"))
+                .unwrap_err()
+                .kind,
+            ErrorKind::InputLimitExceeded
+        );
+        Ok(())
+    }
+}
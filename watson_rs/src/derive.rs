@@ -0,0 +1,191 @@
+//! Support for `#[derive(ToWatson, FromWatson)]`.
+//!
+//! The functions in this module are used by the generated code and are not part of the public API.
+
+use std::mem;
+
+use crate::language::{Bytes, Map, ToBytes, Value};
+
+#[cfg(feature = "derive")]
+pub use watson_derive::{FromWatson, ToWatson};
+
+/// Takes the value of the given field out of `map`, or returns `Nil` if it is missing.
+pub fn take_field(map: &mut Map, key: &[u8]) -> Value {
+    map.get_mut(key)
+        .map_or(Value::Nil, |v| mem::replace(v, Value::Nil))
+}
+
+/// Wraps the content of a variant in an object that has a single entry from its tag.
+pub fn tagged(tag: &[u8], content: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(tag.to_bytes(), content);
+    Value::Object(map)
+}
+
+/// Splits a variant into its tag and content. Unit variants are represented as their tags and their contents are `Nil`.
+pub fn untag(v: Value) -> Option<(Bytes, Value)> {
+    match v {
+        Value::String(tag) => Some((tag, Value::Nil)),
+        Value::Object(map) if map.len() == 1 => map.into_iter().next(),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod test {
+    use super::*;
+    use crate::{object, IsValue, ValueKind};
+    use Value::*;
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    struct Named {
+        id: u64,
+        name: Bytes,
+        tags: Vec<Value>,
+        parent: Option<i64>,
+    }
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    struct Newtype(i64);
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    struct Tuple(i64, bool);
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    struct Unit;
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    struct Generic<T> {
+        inner: T,
+    }
+
+    #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+    enum Enum {
+        Unit,
+        Newtype(f64),
+        Tuple(i64, u64),
+        Struct { r#type: Bytes },
+    }
+
+    #[test]
+    fn derive_struct() {
+        let named = Named {
+            id: 1,
+            name: b"foo".to_vec(),
+            tags: vec![Bool(true)],
+            parent: None,
+        };
+        let v = object![id: Uint(1), name: String(b"foo".to_vec()), tags: Array(vec![Bool(true)].into()), parent: Nil];
+        assert_eq!(roundtrip(named, v), Some(ValueKind::Object));
+
+        // Missing fields are read as `Nil`, and unknown fields are ignored.
+        assert_eq!(
+            Named::from_value(
+                object![id: Uint(2), name: String(vec![]), tags: Array(vec![].into()), extra: Nil]
+            ),
+            Some(Named {
+                id: 2,
+                name: vec![],
+                tags: vec![],
+                parent: None,
+            })
+        );
+        assert_eq!(Named::from_value(object![id: Uint(2)]), None);
+        assert_eq!(Named::from_value(Int(2)), None);
+
+        assert_eq!(roundtrip(Newtype(3), Int(3)), Some(ValueKind::Int));
+        assert_eq!(
+            roundtrip(Tuple(4, false), Array(vec![Int(4), Bool(false)].into())),
+            Some(ValueKind::Array)
+        );
+        assert_eq!(Tuple::from_value(Array(vec![Int(4)].into())), None);
+        assert_eq!(roundtrip(Unit, Nil), Some(ValueKind::Nil));
+        assert_eq!(
+            roundtrip(Generic { inner: Newtype(5) }, object![inner: Int(5)]),
+            Some(ValueKind::Object)
+        );
+    }
+
+    #[test]
+    fn derive_enum() {
+        assert_eq!(roundtrip(Enum::Unit, String(b"Unit".to_vec())), None);
+        assert_eq!(
+            roundtrip(Enum::Newtype(1.5), object![Newtype: Float(1.5)]),
+            None
+        );
+        assert_eq!(
+            roundtrip(
+                Enum::Tuple(-1, 1),
+                object![Tuple: Array(vec![Int(-1), Uint(1)].into())]
+            ),
+            None
+        );
+        assert_eq!(
+            roundtrip(
+                Enum::Struct {
+                    r#type: b"x".to_vec()
+                },
+                object![Struct: object![type: String(b"x".to_vec())]]
+            ),
+            None
+        );
+        assert_eq!(Enum::from_value(String(b"Other".to_vec())), None);
+        assert_eq!(
+            Enum::from_value(object![Newtype: Float(1.0), Unit: Nil]),
+            None
+        );
+    }
+
+    #[test]
+    fn derive_renamed() {
+        #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+        struct S {
+            #[watson(key = b"\x00\xff")]
+            raw: i64,
+            #[watson(rename = "other-name")]
+            renamed: bool,
+        }
+
+        #[derive(ToWatson, FromWatson, PartialEq, Debug)]
+        enum E {
+            #[watson(rename = "a")]
+            A,
+            #[watson(key = b"\xfe")]
+            B(i64),
+        }
+
+        let v = Object(
+            [
+                (b"\x00\xff".to_vec(), Int(1)),
+                (b"other-name".to_vec(), Bool(true)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            roundtrip(
+                S {
+                    raw: 1,
+                    renamed: true
+                },
+                v
+            ),
+            Some(ValueKind::Object)
+        );
+        assert_eq!(roundtrip(E::A, String(b"a".to_vec())), None);
+        let v = Object([(b"\xfe".to_vec(), Int(2))].into_iter().collect());
+        assert_eq!(roundtrip(E::B(2), v), None);
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn roundtrip<T: IsValue + PartialEq + std::fmt::Debug>(x: T, v: Value) -> Option<ValueKind> {
+        let converted = x.into_value();
+        assert_eq!(converted, v);
+        let back = T::from_value(converted).unwrap();
+        assert_eq!(back.into_value(), v);
+        T::kind()
+    }
+}
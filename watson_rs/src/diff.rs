@@ -0,0 +1,362 @@
+//! Structural differences between values and patches that apply them.
+//!
+//! A patch can be converted into a `Value` (a "WATSON Patch" document) so that it can be stored or sent as WATSON.
+//! The document is an array of objects, each of which has the following entries, similarly to JSON Patch:
+//!
+//! * `op`: one of `add`, `remove` and `replace`.
+//! * `path`: an array of keys (strings) and indices (integers) from the root.
+//! * `value`: the new value (only for `add` and `replace`).
+
+use std::fmt;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Location, Map, PathElem, Value};
+
+/// A single change of a value.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Change {
+    /// Adds an entry to an object, or inserts an element into an array at the index.
+    Add { path: Vec<PathElem>, value: Value },
+
+    /// Removes an entry of an object or an element of an array.
+    Remove { path: Vec<PathElem> },
+
+    /// Replaces the value that the path points at.
+    Replace { path: Vec<PathElem>, value: Value },
+}
+
+impl Change {
+    /// Returns the path that the change applies to.
+    pub fn path(&self) -> &[PathElem] {
+        match self {
+            Change::Add { path, .. } | Change::Remove { path } | Change::Replace { path, .. } => {
+                path
+            }
+        }
+    }
+
+    /// Converts the change into an entry of a WATSON Patch document.
+    pub fn to_value(&self) -> Value {
+        let (op, value) = match self {
+            Change::Add { value, .. } => ("add", Some(value)),
+            Change::Remove { .. } => ("remove", None),
+            Change::Replace { value, .. } => ("replace", Some(value)),
+        };
+        let path = self
+            .path()
+            .iter()
+            .map(|elem| match elem {
+                PathElem::Key(k) => Value::String(k.clone()),
+                PathElem::Index(i) => Value::Uint(*i as u64),
+            })
+            .collect();
+        let mut map = Map::new();
+        map.insert(b"op".to_vec(), Value::String(op.as_bytes().to_vec()));
+        map.insert(b"path".to_vec(), Value::Array(path));
+        if let Some(value) = value {
+            map.insert(b"value".to_vec(), value.clone());
+        }
+        Value::Object(map)
+    }
+
+    /// Converts an entry of a WATSON Patch document into a change.
+    pub fn from_value(v: &Value) -> Result<Change> {
+        let map = match v {
+            Value::Object(map) => map,
+            _ => return Err(error(ErrorKind::InvalidPatch)),
+        };
+        let path = match map.get(&b"path"[..]) {
+            Some(Value::Array(elems)) => elems
+                .iter()
+                .map(|elem| match *elem {
+                    Value::String(ref k) => Ok(PathElem::Key(k.clone())),
+                    Value::Int(i) if 0 <= i => Ok(PathElem::Index(i as usize)),
+                    Value::Uint(i) => Ok(PathElem::Index(i as usize)),
+                    _ => Err(error(ErrorKind::InvalidPatch)),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => return Err(error(ErrorKind::InvalidPatch)),
+        };
+        let value = || {
+            map.get(&b"value"[..])
+                .cloned()
+                .ok_or_else(|| error(ErrorKind::InvalidPatch))
+        };
+        match map.get(&b"op"[..]) {
+            Some(Value::String(op)) if op == b"add" => Ok(Change::Add {
+                path,
+                value: value()?,
+            }),
+            Some(Value::String(op)) if op == b"remove" => Ok(Change::Remove { path }),
+            Some(Value::String(op)) if op == b"replace" => Ok(Change::Replace {
+                path,
+                value: value()?,
+            }),
+            _ => Err(error(ErrorKind::InvalidPatch)),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Change::Add { .. } => "add",
+            Change::Remove { .. } => "remove",
+            Change::Replace { .. } => "replace",
+        };
+        write!(f, "{op} ")?;
+        if self.path().is_empty() {
+            write!(f, ".")?;
+        }
+        for elem in self.path() {
+            write!(f, "{elem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the changes that turn `from` into `to`.
+///
+/// Objects and arrays are compared recursively. Entries of objects are compared by their keys, and elements of
+/// arrays by their indices; elements are only appended to or removed from the end of arrays.
+pub fn diff(from: &Value, to: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(&mut Vec::new(), from, to, &mut changes);
+    changes
+}
+
+fn diff_at(path: &mut Vec<PathElem>, from: &Value, to: &Value, changes: &mut Vec<Change>) {
+    match (from, to) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys = a
+                .keys()
+                .chain(b.keys().filter(|k| !a.contains_key(*k)))
+                .collect::<Vec<_>>();
+            keys.sort();
+            for k in keys {
+                path.push(PathElem::Key(k.clone()));
+                match (a.get(k), b.get(k)) {
+                    (Some(x), Some(y)) => diff_at(path, x, y, changes),
+                    (Some(_), None) => changes.push(Change::Remove { path: path.clone() }),
+                    (None, Some(y)) => changes.push(Change::Add {
+                        path: path.clone(),
+                        value: y.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                path.push(PathElem::Index(i));
+                diff_at(path, x, y, changes);
+                path.pop();
+            }
+            for (i, y) in b.iter().enumerate().skip(a.len()) {
+                path.push(PathElem::Index(i));
+                changes.push(Change::Add {
+                    path: path.clone(),
+                    value: y.clone(),
+                });
+                path.pop();
+            }
+            for i in (b.len()..a.len()).rev() {
+                path.push(PathElem::Index(i));
+                changes.push(Change::Remove { path: path.clone() });
+                path.pop();
+            }
+        }
+        _ if from == to => {}
+        _ => changes.push(Change::Replace {
+            path: path.clone(),
+            value: to.clone(),
+        }),
+    }
+}
+
+/// Applies the changes to the value in order.
+/// It fails with `ErrorKind::PathNotFound` if a path doesn't point at a value (or its parent for `Change::Add`).
+pub fn patch(v: &mut Value, changes: &[Change]) -> Result<()> {
+    for change in changes {
+        apply(v, change)?;
+    }
+    Ok(())
+}
+
+fn apply(v: &mut Value, change: &Change) -> Result<()> {
+    let not_found = || error(ErrorKind::PathNotFound);
+    let (last, parent) = match change.path().split_last() {
+        Some((last, parent)) => (last, parent),
+        None => {
+            return match change {
+                Change::Replace { value, .. } => {
+                    *v = value.clone();
+                    Ok(())
+                }
+                _ => Err(not_found()),
+            }
+        }
+    };
+    let parent = parent
+        .iter()
+        .try_fold(v, |v, elem| match (v, elem) {
+            (Value::Array(a), PathElem::Index(i)) => a.get_mut(*i),
+            (Value::Object(o), PathElem::Key(k)) => o.get_mut(k),
+            _ => None,
+        })
+        .ok_or_else(not_found)?;
+    match (parent, last, change) {
+        (Value::Object(o), PathElem::Key(k), Change::Add { value, .. }) => {
+            o.insert(k.clone(), value.clone());
+        }
+        (Value::Array(a), PathElem::Index(i), Change::Add { value, .. }) if *i <= a.len() => {
+            a.insert(*i, value.clone());
+        }
+        (Value::Object(o), PathElem::Key(k), Change::Remove { .. }) if o.contains_key(k) => {
+            o.retain(|key, _| key != k);
+        }
+        (Value::Array(a), PathElem::Index(i), Change::Remove { .. }) if *i < a.len() => {
+            a.remove(*i);
+        }
+        (Value::Object(o), PathElem::Key(k), Change::Replace { value, .. }) => {
+            *o.get_mut(k).ok_or_else(not_found)? = value.clone();
+        }
+        (Value::Array(a), PathElem::Index(i), Change::Replace { value, .. }) => {
+            *a.get_mut(*i).ok_or_else(not_found)? = value.clone();
+        }
+        _ => return Err(not_found()),
+    }
+    Ok(())
+}
+
+/// Converts changes into a WATSON Patch document.
+pub fn to_patch(changes: &[Change]) -> Value {
+    Value::Array(changes.iter().map(Change::to_value).collect())
+}
+
+/// Converts a WATSON Patch document into changes.
+pub fn from_patch(v: &Value) -> Result<Vec<Change>> {
+    match v {
+        Value::Array(entries) => entries.iter().map(Change::from_value).collect(),
+        _ => Err(error(ErrorKind::InvalidPatch)),
+    }
+}
+
+fn error(kind: ErrorKind) -> Error {
+    Error {
+        kind,
+        location: Location::unknown(),
+        source: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{array, object};
+    use Value::*;
+
+    #[test]
+    fn diff_and_patch() -> Result<()> {
+        let from = object![
+            same: Int(1),
+            changed: array![Int(1), Int(2), Int(3)],
+            removed: Nil,
+            nested: object![a: Bool(true)],
+        ];
+        let to = object![
+            same: Int(1),
+            changed: array![Int(1), Uint(2)],
+            added: String(b"x".to_vec()),
+            nested: object![a: Bool(false)],
+        ];
+        let changes = diff(&from, &to);
+        assert_eq!(
+            changes.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            [
+                "add .added",
+                "replace .changed[1]",
+                "remove .changed[2]",
+                "replace .nested.a",
+                "remove .removed",
+            ]
+        );
+        let mut v = from.clone();
+        patch(&mut v, &changes)?;
+        assert_eq!(v, to);
+
+        let mut v = to.clone();
+        patch(&mut v, &diff(&to, &from))?;
+        assert_eq!(v, from);
+        assert_eq!(diff(&from, &from), []);
+        Ok(())
+    }
+
+    #[test]
+    fn diff_of_different_kinds() -> Result<()> {
+        let changes = diff(&array![Nil], &Int(1));
+        assert_eq!(
+            changes,
+            [Change::Replace {
+                path: vec![],
+                value: Int(1)
+            }]
+        );
+        let mut v = array![Nil];
+        patch(&mut v, &changes)?;
+        assert_eq!(v, Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn patch_document_roundtrip() -> Result<()> {
+        let changes = diff(
+            &object![a: array![Nil], b: Nil],
+            &object![a: array![Nil, Int(1)], c: Nil],
+        );
+        let doc = to_patch(&changes);
+        assert_eq!(
+            doc,
+            array![
+                object![op: String(b"add".to_vec()), path: array![String(b"a".to_vec()), Uint(1)], value: Int(1)],
+                object![op: String(b"remove".to_vec()), path: array![String(b"b".to_vec())]],
+                object![op: String(b"add".to_vec()), path: array![String(b"c".to_vec())], value: Nil],
+            ]
+        );
+        assert_eq!(from_patch(&doc)?, changes);
+        assert_eq!(
+            from_patch(&array![
+                object![op: String(b"move".to_vec()), path: array![]]
+            ])
+            .unwrap_err()
+            .kind,
+            ErrorKind::InvalidPatch
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn patch_fails_on_missing_paths() {
+        let mut v = object![a: array![]];
+        for change in [
+            Change::Remove {
+                path: vec![PathElem::Key(b"b".to_vec())],
+            },
+            Change::Add {
+                path: vec![PathElem::Key(b"a".to_vec()), PathElem::Index(1)],
+                value: Nil,
+            },
+            Change::Replace {
+                path: vec![PathElem::Key(b"a".to_vec()), PathElem::Index(0)],
+                value: Nil,
+            },
+            Change::Remove { path: vec![] },
+        ] {
+            assert_eq!(
+                patch(&mut v, &[change]).unwrap_err().kind,
+                ErrorKind::PathNotFound
+            );
+        }
+    }
+}
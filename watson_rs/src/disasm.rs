@@ -0,0 +1,143 @@
+//! Annotated listings of instructions.
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::language::{Insn, Token};
+use crate::vm::{self, ReadToken};
+
+/// A single line of a listing.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Entry {
+    /// The token that the line describes.
+    pub token: Token,
+
+    /// The number of values that the instruction pops, or `None` if it is unknown.
+    pub pops: Option<usize>,
+
+    /// The number of values that the instruction pushes, or `None` if it is unknown.
+    pub pushes: Option<usize>,
+
+    /// The depth of the stack after the instruction, or `None` if it is unknown.
+    /// It can be negative if the instruction pops more values than the stack has.
+    pub depth: Option<isize>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = &self.token.location;
+        match loc.path.as_ref() {
+            Some(p) => write!(f, "{}", p.to_string_lossy())?,
+            None => write!(f, "-")?,
+        }
+        write!(
+            f,
+            ":{}:{}\t{:?}\t{:?}\t{:?}\t",
+            loc.line, loc.column, self.token.mode, loc.byte as char, self.token.insn
+        )?;
+        match (self.pops, self.pushes) {
+            (Some(pops), Some(pushes)) => write!(f, "-{pops} +{pushes}")?,
+            _ => write!(f, "-? +?")?,
+        }
+        match self.depth {
+            Some(depth) if depth < 0 => write!(f, "\tdepth={depth} (underflow)"),
+            Some(depth) => write!(f, "\tdepth={depth}"),
+            None => write!(f, "\tdepth=?"),
+        }
+    }
+}
+
+/// `Disassembler` reads tokens and annotates each of them with its effect on the stack.
+///
+/// The annotations are computed without executing instructions, so a listing is available even if
+/// the document fails to execute.
+pub struct Disassembler<R> {
+    reader: R,
+    depth: Option<isize>,
+}
+
+impl<R: ReadToken> Disassembler<R> {
+    /// Returns a new `Disassembler` that reads from the given reader.
+    pub fn new(reader: R) -> Self {
+        Disassembler {
+            reader,
+            depth: Some(0),
+        }
+    }
+
+    /// Reads the next token and returns its annotated entry.
+    pub fn read(&mut self) -> Result<Option<Entry>> {
+        let token = match self.reader.read()? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let pops = vm::arity(token.insn);
+        let pushes = pushes(token.insn);
+        self.depth = match (self.depth, pops, pushes) {
+            (Some(d), Some(pops), Some(pushes)) => Some(d - pops as isize + pushes as isize),
+            _ => None,
+        };
+        Ok(Some(Entry {
+            token,
+            pops,
+            pushes,
+            depth: self.depth,
+        }))
+    }
+}
+
+impl<R: ReadToken> Iterator for Disassembler<R> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// Returns the number of values that the instruction pushes, or `None` if it is unknown.
+fn pushes(insn: Insn) -> Option<usize> {
+    match insn {
+        Insn::Gpop => Some(0),
+        Insn::Gdup | Insn::Gswp => Some(2),
+        Insn::Ext(_) => None,
+        _ => Some(1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::{self, SliceLexer};
+
+    #[test]
+    fn disassembler_annotates_tokens() -> Result<()> {
+        let listing = Disassembler::new(SliceLexer::new(b"Bu\n?S!"))
+            .map(|e| e.map(|e| e.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            listing,
+            vec![
+                "-:1:1\tA\t'B'\tInew\t-0 +1\tdepth=1",
+                "-:1:2\tA\t'u'\tIinc\t-1 +1\tdepth=1",
+                "-:2:1\tA\t'?'\tSnew\t-0 +1\tdepth=2",
+                "-:2:2\tS\t'S'\tInew\t-0 +1\tdepth=3",
+                "-:2:3\tS\t'!'\tBneg\t-1 +1\tdepth=3",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn disassembler_reports_underflow_and_unknown_effects() -> Result<()> {
+        let lx = lexer::Config {
+            extension_bytes: vec![b'X'],
+            ..Default::default()
+        }
+        .build_slice(b"#XB");
+        let depths = Disassembler::new(lx)
+            .map(|e| e.map(|e| e.depth))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(depths, vec![Some(-1), None, None]);
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! A lossless representation of WATSON text.
+
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Range;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Bytes, Insn, Location, Mode, PathElem, Value};
+use crate::lexer;
+use crate::serializer::Serializer;
+use crate::vm::{ReadToken, VM};
+
+/// `Document` holds a decoded value together with the exact text it was decoded from.
+///
+/// The text is kept as a sequence of instructions, each of which is preceded by the noise (i.e. bytes that are not
+/// instructions) in front of it, so `to_text` reproduces the input byte-for-byte. `set` replaces a nested value by
+/// rewriting only the instructions that built it, leaving everything else (including comments) untouched.
+#[derive(Debug)]
+pub struct Document {
+    pieces: Vec<Piece>,
+    trailing_noise: Vec<u8>,
+    final_mode: Mode,
+    value: Value,
+    root: Node,
+}
+
+#[derive(Debug)]
+struct Piece {
+    noise: Vec<u8>,
+    byte: u8,
+    mode: Mode,
+}
+
+/// The instructions that built a value on the stack.
+#[derive(Default, Debug)]
+struct Node {
+    // Indices of the pieces that built the value, or `None` if they are not contiguous.
+    span: Option<Range<usize>>,
+    children: Children,
+}
+
+#[derive(Default, Debug)]
+enum Children {
+    // The value is not a container, or its elements can't be tracked.
+    #[default]
+    Unknown,
+    Array(Vec<Node>),
+    Object(HashMap<Bytes, Node>),
+}
+
+impl Drop for Node {
+    // Drops nested nodes without recursion, as `Value` does.
+    fn drop(&mut self) {
+        let mut pending = take_children(&mut self.children);
+        while let Some(mut node) = pending.pop() {
+            pending.extend(take_children(&mut node.children));
+        }
+    }
+}
+
+fn take_children(children: &mut Children) -> Vec<Node> {
+    match mem::take(children) {
+        Children::Unknown => Vec::new(),
+        Children::Array(elems) => elems,
+        Children::Object(entries) => entries.into_values().collect(),
+    }
+}
+
+impl Document {
+    /// Decodes WATSON text that represents a single value.
+    pub fn parse(text: &[u8]) -> Result<Document> {
+        let mut lexer = lexer::Config {
+            capture_noise: true,
+            ..Default::default()
+        }
+        .build_slice(text);
+        let mut vm = VM::new();
+        let mut pieces = Vec::new();
+        let mut nodes = Vec::new();
+        let mut final_mode = Mode::A;
+        let mut last = Location::unknown();
+        while let Some(t) = lexer.read()? {
+            let key = match (t.insn, vm.borrow_stack().iter().nth_back(1)) {
+                (Insn::Oadd, Some(Value::String(k))) => Some(k.clone()),
+                _ => None,
+            };
+            let insn = t.insn;
+            pieces.push(Piece {
+                noise: lexer.noise().to_vec(),
+                byte: t.location.byte,
+                mode: t.mode,
+            });
+            final_mode = if insn == Insn::Snew {
+                t.mode.flip()
+            } else {
+                t.mode
+            };
+            last = t.location.clone();
+            vm.execute(t)?;
+            track(&mut nodes, insn, pieces.len() - 1, key);
+        }
+        let mut values = vm.take_values();
+        if values.len() != 1 {
+            let kind = if values.is_empty() {
+                ErrorKind::EmptyStack
+            } else {
+                ErrorKind::InvalidDocument
+            };
+            return Err(Error {
+                kind,
+                location: last,
+                source: None,
+            });
+        }
+        Ok(Document {
+            pieces,
+            trailing_noise: lexer.noise().to_vec(),
+            final_mode,
+            value: values.pop().unwrap(),
+            root: nodes.pop().unwrap(),
+        })
+    }
+
+    /// Returns the decoded value.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Returns the value that the path points at.
+    pub fn get(&self, path: &[PathElem]) -> Option<&Value> {
+        path.iter()
+            .try_fold(&self.value, |v, elem| match (v, elem) {
+                (Value::Array(a), PathElem::Index(i)) => a.get(*i),
+                (Value::Object(o), PathElem::Key(k)) => o.get(k),
+                _ => None,
+            })
+    }
+
+    /// Returns the text that the document represents.
+    pub fn to_text(&self) -> Vec<u8> {
+        let mut text = Vec::new();
+        write_pieces(&mut text, &self.pieces);
+        text.extend_from_slice(&self.trailing_noise);
+        text
+    }
+
+    /// Replaces the value that the path points at.
+    ///
+    /// Only the instructions that built the old value are rewritten. It fails with `ErrorKind::NotEditable` if
+    /// they can't be identified, which can happen if the value was built with `Gdup` or `Gswp`.
+    pub fn set(&mut self, path: &[PathElem], v: &Value) -> Result<()> {
+        if self.get(path).is_none() {
+            return Err(error(ErrorKind::PathNotFound));
+        }
+        let span = path
+            .iter()
+            .try_fold(&self.root, |node, elem| match (&node.children, elem) {
+                (Children::Array(a), PathElem::Index(i)) => a.get(*i),
+                (Children::Object(o), PathElem::Key(k)) => o.get(k),
+                _ => None,
+            })
+            .and_then(|node| node.span.clone())
+            .ok_or_else(|| error(ErrorKind::NotEditable))?;
+
+        let mut insns = Vec::new();
+        Serializer::new(&mut insns).serialize(v)?;
+        let start_mode = self.pieces[span.start].mode;
+        let end_mode = self
+            .pieces
+            .get(span.end)
+            .map_or(self.final_mode, |p| p.mode);
+        let flips = insns.iter().filter(|&&insn| insn == Insn::Snew).count();
+        if (flips % 2 == 1) != (start_mode != end_mode) {
+            // Keeps the mode of the rest of the text.
+            insns.extend([Insn::Snew, Insn::Gpop]);
+        }
+
+        let mut text = Vec::new();
+        write_pieces(&mut text, &self.pieces[..span.start]);
+        text.extend_from_slice(&self.pieces[span.start].noise);
+        let mut mode = start_mode;
+        for insn in insns {
+            text.push(insn.into_byte(mode));
+            if insn == Insn::Snew {
+                mode = mode.flip();
+            }
+        }
+        write_pieces(&mut text, &self.pieces[span.end..]);
+        text.extend_from_slice(&self.trailing_noise);
+        *self = Document::parse(&text)?;
+        Ok(())
+    }
+}
+
+fn write_pieces(text: &mut Vec<u8>, pieces: &[Piece]) {
+    for p in pieces {
+        text.extend_from_slice(&p.noise);
+        text.push(p.byte);
+    }
+}
+
+fn error(kind: ErrorKind) -> Error {
+    Error {
+        kind,
+        location: Location::unknown(),
+        source: None,
+    }
+}
+
+/// Updates `nodes`, which mirrors the stack, after the `i`-th instruction is executed successfully.
+/// `key` is the key of the entry if the instruction is `Oadd`.
+fn track(nodes: &mut Vec<Node>, insn: Insn, i: usize, key: Option<Bytes>) {
+    use Insn::*;
+
+    let new_node = |span, children| Node { span, children };
+    match insn {
+        Inew | Finf | Fnan | Snew | Bnew | Nnew => {
+            nodes.push(new_node(Some(i..i + 1), Children::Unknown))
+        }
+        Anew => nodes.push(new_node(Some(i..i + 1), Children::Array(Vec::new()))),
+        Onew => nodes.push(new_node(Some(i..i + 1), Children::Object(HashMap::new()))),
+        Iinc | Ishl | Ineg | Itof | Itou | Fneg | Bneg => {
+            let x = nodes.pop().unwrap();
+            nodes.push(new_node(join(&[&x], i), Children::Unknown));
+        }
+        Iadd | Isht | Sadd => {
+            let y = nodes.pop().unwrap();
+            let x = nodes.pop().unwrap();
+            nodes.push(new_node(join(&[&x, &y], i), Children::Unknown));
+        }
+        Aadd => {
+            let v = nodes.pop().unwrap();
+            let mut a = nodes.pop().unwrap();
+            a.span = join(&[&a, &v], i);
+            if let Children::Array(elems) = &mut a.children {
+                elems.push(v);
+            }
+            nodes.push(a);
+        }
+        Oadd => {
+            let v = nodes.pop().unwrap();
+            let k = nodes.pop().unwrap();
+            let mut o = nodes.pop().unwrap();
+            o.span = join(&[&o, &k, &v], i);
+            if let (Children::Object(entries), Some(key)) = (&mut o.children, key) {
+                entries.insert(key, v);
+            }
+            nodes.push(o);
+        }
+        Gdup => {
+            // Rewriting either of the values would change both of them.
+            *nodes.last_mut().unwrap() = Node::default();
+            nodes.push(Node::default());
+        }
+        Gpop => {
+            // A value that is pushed and popped right after another value doesn't change the result,
+            // so the instructions can be regarded as a part of the other value.
+            let x = nodes.pop().unwrap();
+            if let (Some(top), Some(xs)) = (nodes.last_mut(), x.span.clone()) {
+                if let Some(ts) = top.span.as_mut() {
+                    if ts.end == xs.start && xs.end == i {
+                        ts.end = i + 1;
+                    }
+                }
+            }
+        }
+        Gswp => {
+            let n = nodes.len();
+            nodes.swap(n - 1, n - 2);
+        }
+        // `VM` fails to execute extension instructions because no handlers are registered.
+        Ext(_) => unreachable!(),
+    }
+}
+
+/// Returns the span of the value built by the `i`-th instruction from the operands if they are contiguous.
+fn join(operands: &[&Node], i: usize) -> Option<Range<usize>> {
+    let start = operands[0].span.as_ref()?.start;
+    let mut end = start;
+    for op in operands {
+        let span = op.span.as_ref()?;
+        if span.start != end {
+            return None;
+        }
+        end = span.end;
+    }
+    (end == i).then_some(start..i + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{array, object, to_string};
+    use Value::*;
+
+    #[test]
+    fn document_reproduces_input() -> Result<()> {
+        let value = object![a: array![Int(1), String(b"xy".to_vec())], b: Nil];
+        let text = to_string(&value)?;
+        let noisy = format!(";; 1\n{}\n  ;; 2\n", text.replace('\n', " \n "));
+        let doc = Document::parse(noisy.as_bytes())?;
+        assert_eq!(doc.value(), &value);
+        assert_eq!(doc.to_text(), noisy.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn document_set_rewrites_only_the_value() -> Result<()> {
+        let value = object![a: array![Int(1), String(b"xy".to_vec())], b: Nil];
+        let text = format!("(1) {} ;;", to_string(&value)?);
+        let mut doc = Document::parse(text.as_bytes())?;
+        let path = [PathElem::Key(b"a".to_vec()), PathElem::Index(0)];
+        assert_eq!(doc.get(&path), Some(&Int(1)));
+
+        // The new value has an odd number of `Snew`, which flips the mode.
+        doc.set(&path, &String(b"z".to_vec()))?;
+        assert_eq!(
+            doc.value(),
+            &object![a: array![String(b"z".to_vec()), String(b"xy".to_vec())], b: Nil]
+        );
+        let new_text = doc.to_text();
+        assert!(new_text.starts_with(b"(1) "));
+        assert!(new_text.ends_with(b" ;;"));
+
+        // Values can be edited repeatedly.
+        doc.set(&path, &Bool(true))?;
+        doc.set(&[PathElem::Key(b"b".to_vec())], &Int(2))?;
+        doc.set(&[], &array![Nil])?;
+        assert_eq!(doc.value(), &array![Nil]);
+        Ok(())
+    }
+
+    #[test]
+    fn document_set_fails_on_untracked_values() -> Result<()> {
+        // Both elements come from the same instructions duplicated by `Gdup`.
+        let mut doc = Document::parse(b"BuE@%s%s")?;
+        assert_eq!(doc.value(), &array![Int(1), Int(1)]);
+        for path in [&[][..], &[PathElem::Index(0)], &[PathElem::Index(1)]] {
+            let err = doc.set(path, &Nil).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::NotEditable);
+        }
+        let err = doc.set(&[PathElem::Index(2)], &Nil).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::PathNotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn document_drops_deeply_nested_value() -> Result<()> {
+        let text = format!("@{}", "@%s".repeat(200_000));
+        let doc = Document::parse(text.as_bytes())?;
+        assert_eq!(doc.to_text(), text.as_bytes());
+        drop(doc);
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use std::error;
 use std::fmt;
 use std::io;
 
-use crate::language::Location;
+use crate::language::{Bytes, Insn, Location, ValueKind};
 
 /// The error type of the WATSON VM.
 #[derive(Debug)]
@@ -14,7 +14,7 @@ pub struct Error {
     pub location: Location,
 
     /// The internal error that causes this error.
-    pub source: Option<Box<dyn error::Error>>,
+    pub source: Option<Box<dyn error::Error + Send + Sync>>,
 }
 
 /// Details of the `Error`.
@@ -24,14 +24,119 @@ pub enum ErrorKind {
     EmptyStack,
 
     /// The type of the value on the top of stack is different from the one that the instruction wants.
+    /// Its details are available through `Error::type_mismatch`.
     TypeMismatch,
 
+    /// `Isht` was executed with a negative shift amount.
+    InvalidShift,
+
+    /// The VM tried to execute an extension instruction that has no handler.
+    UnknownInstruction,
+
+    /// The execution was aborted by the caller.
+    Aborted,
+
+    /// The input is larger than the limit given to the lexer, or has more instructions than
+    /// `DecodeOptions::max_insns`.
+    InputLimitExceeded,
+
     /// An I/O error happened.
     IOError,
+
+    /// The instruction can't be executed because it manipulates a container whose contents were already streamed.
+    StreamingUnsupported,
+
+    /// A template has a character that represents an instruction outside of placeholders.
+    InvalidTemplate,
+
+    /// A template doesn't have enough placeholders for all instructions.
+    TemplateTooShort,
+
+    /// A document doesn't consist of exactly one value.
+    InvalidDocument,
+
+    /// A packed binary is malformed.
+    InvalidBinary,
+
+    /// A mnemonic listing has an unknown mnemonic or an invalid repeat count.
+    InvalidAssembly,
+
+    /// A value or the stack is larger than the limit given to the VM.
+    LimitExceeded,
+
+    /// A path doesn't point at any value.
+    PathNotFound,
+
+    /// A value can't be replaced because it isn't made of a contiguous sequence of instructions.
+    NotEditable,
+
+    /// `Oadd` tried to add a key that the object already has. The key is available through `Error::duplicate_key`.
+    DuplicateKey,
+
+    /// A patch document is malformed.
+    InvalidPatch,
+
+    /// An extension instruction can't be written as text because its byte is not a printable ASCII character or
+    /// represents another instruction.
+    UnrepresentableInstruction,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Details of an error of `ErrorKind::TypeMismatch`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct TypeMismatch {
+    /// The instruction that failed.
+    pub insn: Insn,
+
+    /// The types of operands that the instruction wants, from the top of the stack.
+    /// `None` means that any type is accepted.
+    pub expected: Vec<Option<ValueKind>>,
+
+    /// The types of operands that were actually on the stack, from the top of the stack.
+    pub actual: Vec<ValueKind>,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} expects (", self.insn)?;
+        for (i, kind) in self.expected.iter().enumerate() {
+            if 0 < i {
+                write!(f, ", ")?;
+            }
+            match kind {
+                Some(kind) => write!(f, "{kind}")?,
+                None => write!(f, "any")?,
+            }
+        }
+        write!(f, ") but got (")?;
+        for (i, kind) in self.actual.iter().enumerate() {
+            if 0 < i {
+                write!(f, ", ")?;
+            }
+            write!(f, "{kind}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl error::Error for TypeMismatch {}
+
+/// Details of an error of `ErrorKind::DuplicateKey`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct DuplicateKey {
+    /// The key that was added more than once.
+    pub key: Bytes,
+}
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key \"{}\" is already defined", self.key.escape_ascii())
+    }
+}
+
+impl error::Error for DuplicateKey {}
+
 impl Error {
     /// Creates a new `Error` caused by the given `io::Error`.
     pub fn from_io_error(e: io::Error, location: Location) -> Self {
@@ -41,11 +146,46 @@ impl Error {
             source: Some(Box::new(e)),
         }
     }
+
+    /// Creates a new `Error` of `ErrorKind::TypeMismatch` with the given details.
+    pub fn from_type_mismatch(detail: TypeMismatch, location: Location) -> Self {
+        Error {
+            kind: ErrorKind::TypeMismatch,
+            location,
+            source: Some(Box::new(detail)),
+        }
+    }
+
+    /// Returns the details of the error if it is caused by a type mismatch.
+    pub fn type_mismatch(&self) -> Option<&TypeMismatch> {
+        self.source.as_ref()?.downcast_ref()
+    }
+
+    /// Creates a new `Error` of `ErrorKind::DuplicateKey` with the given key.
+    pub fn from_duplicate_key(key: Bytes, location: Location) -> Self {
+        Error {
+            kind: ErrorKind::DuplicateKey,
+            location,
+            source: Some(Box::new(DuplicateKey { key })),
+        }
+    }
+
+    /// Returns the details of the error if it is caused by a duplicate key.
+    pub fn duplicate_key(&self) -> Option<&DuplicateKey> {
+        self.source.as_ref()?.downcast_ref()
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} at {}", self.kind, self.location)
+        write!(f, "{} at {}", self.kind, self.location)?;
+        if let Some(detail) = self.type_mismatch() {
+            write!(f, ": {detail}")?;
+        }
+        if let Some(detail) = self.duplicate_key() {
+            write!(f, ": {detail}")?;
+        }
+        Ok(())
     }
 }
 
@@ -54,7 +194,23 @@ impl fmt::Display for ErrorKind {
         let msg = match self {
             ErrorKind::EmptyStack => "Empty stack",
             ErrorKind::TypeMismatch => "Type mismatch",
+            ErrorKind::InvalidShift => "Invalid shift amount",
+            ErrorKind::UnknownInstruction => "Unknown instruction",
+            ErrorKind::Aborted => "Execution aborted",
+            ErrorKind::InputLimitExceeded => "Input limit exceeded",
             ErrorKind::IOError => "I/O error",
+            ErrorKind::StreamingUnsupported => "Operation not supported in streaming mode",
+            ErrorKind::InvalidTemplate => "Instruction character outside placeholders",
+            ErrorKind::TemplateTooShort => "Not enough placeholders in template",
+            ErrorKind::InvalidDocument => "Document is not exactly one value",
+            ErrorKind::InvalidBinary => "Malformed packed binary",
+            ErrorKind::InvalidAssembly => "Invalid mnemonic",
+            ErrorKind::LimitExceeded => "Value limit exceeded",
+            ErrorKind::PathNotFound => "Path not found",
+            ErrorKind::NotEditable => "Value not editable",
+            ErrorKind::DuplicateKey => "Duplicate key",
+            ErrorKind::InvalidPatch => "Malformed patch",
+            ErrorKind::UnrepresentableInstruction => "Instruction not representable as text",
         };
         write!(f, "{msg}")
     }
@@ -62,7 +218,9 @@ impl fmt::Display for ErrorKind {
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        self.source.as_deref()
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn error::Error + 'static))
     }
 }
 
@@ -0,0 +1,494 @@
+use std::collections::HashSet;
+
+use crate::error::{Error, ErrorKind, Result, TypeMismatch};
+use crate::language::{Bytes, Insn, Token, Value, ValueKind};
+use crate::vm::{self, DuplicateKeys, ReadToken, VM};
+
+/// A structural event emitted by `EventVM`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Event<'a> {
+    /// Start of an object.
+    BeginObject,
+    /// A key of an object. The corresponding value follows this event.
+    Key(&'a [u8]),
+    /// End of an object.
+    EndObject,
+    /// Start of an array.
+    BeginArray,
+    /// End of an array.
+    EndArray,
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(&'a [u8]),
+    Bool(bool),
+    Nil,
+}
+
+/// A receiver of `Event`s.
+pub trait Handler {
+    /// Handles a single event.
+    fn handle(&mut self, event: Event<'_>) -> Result<()>;
+}
+
+impl<F> Handler for F
+where
+    F: FnMut(Event<'_>) -> Result<()>,
+{
+    fn handle(&mut self, event: Event<'_>) -> Result<()> {
+        self(event)
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+enum Container {
+    Array,
+    Object,
+}
+
+/// `EventVM` executes instructions like `VM`, but reports values as a sequence of `Event`s instead of building them.
+///
+/// Once a value is appended to the array or object at the bottom of the stack, its events are emitted and the
+/// value itself is discarded, so the memory usage is bounded by the largest element rather than the whole document.
+/// Values remaining on the stack when `finish` is called are reported as separate top-level values from the bottom.
+///
+/// Because the contents of the bottom container are gone once they are streamed, duplicating or swapping it
+/// results in `ErrorKind::StreamingUnsupported`. Popping it ends the container immediately.
+///
+/// For the same reason, a key that is added again to the bottom object can't replace the entry that was already
+/// emitted. Under `DuplicateKeys::LastWins` (the default) it is emitted again, so handlers that collect entries into
+/// a map get the same result as `VM` by overwriting them. Under the other policies, the keys of the bottom object are
+/// remembered so that duplicates are discarded or rejected as `VM` does.
+///
+/// `vm::Config::max_array_len`, `vm::Config::max_object_len` and `vm::Stats` count the elements streamed from the
+/// bottom container as if it still held them. Under `DuplicateKeys::LastWins`, a key emitted again counts as another
+/// entry.
+pub struct EventVM<H> {
+    vm: VM,
+    handler: H,
+    streaming: Option<Container>,
+    duplicate_keys: DuplicateKeys,
+    // The keys emitted for the bottom object, which are recorded unless `duplicate_keys` is `LastWins`.
+    keys: HashSet<Bytes>,
+    // The number of elements emitted for the bottom container.
+    streamed_len: usize,
+}
+
+impl<H: Handler> EventVM<H> {
+    /// Returns a new `EventVM` that sends events to the given handler.
+    pub fn new(handler: H) -> Self {
+        EventVM::with_config(handler, vm::Config::default())
+    }
+
+    /// Returns a new `EventVM` that sends events to the given handler and executes instructions with the given
+    /// configuration.
+    pub fn with_config(handler: H, config: vm::Config) -> Self {
+        EventVM {
+            duplicate_keys: config.duplicate_keys,
+            vm: config.build(),
+            handler,
+            streaming: None,
+            keys: HashSet::new(),
+            streamed_len: 0,
+        }
+    }
+
+    /// Returns the statistics collected so far, or `None` if `vm::Config::collect_stats` is not set.
+    pub fn stats(&self) -> Option<&vm::Stats> {
+        self.vm.stats()
+    }
+
+    /// Executes a single instruction.
+    pub fn execute(&mut self, t: Token) -> Result<()> {
+        let depth = self.vm.borrow_stack().len();
+        match (t.insn, depth) {
+            (Insn::Aadd, 2) => {
+                self.begin_streaming(&t)?;
+                if self.streaming == Some(Container::Array) {
+                    let v = self.vm.borrow_stack_mut().operate_as(t.clone()).pop()?;
+                    self.streamed_len += 1;
+                    self.vm
+                        .record_streamed(t.insn, self.streamed_len, &t.location)?;
+                    return emit_value(&mut self.handler, &v);
+                }
+            }
+            (Insn::Oadd, 3) => {
+                self.begin_streaming(&t)?;
+                if self.streaming == Some(Container::Object) {
+                    let mut ops = self.vm.borrow_stack_mut().operate_as(t.clone());
+                    let v = ops.pop()?;
+                    let k = ops.pop()?;
+                    return match k {
+                        Value::String(k) if !self.record_key(&k) => match self.duplicate_keys {
+                            DuplicateKeys::Error => Err(Error::from_duplicate_key(k, t.location)),
+                            _ => self
+                                .vm
+                                .record_streamed(t.insn, self.streamed_len, &t.location),
+                        },
+                        Value::String(ref k) => {
+                            self.streamed_len += 1;
+                            self.vm
+                                .record_streamed(t.insn, self.streamed_len, &t.location)?;
+                            self.handler.handle(Event::Key(k))?;
+                            emit_value(&mut self.handler, &v)
+                        }
+                        _ => Err(Error::from_type_mismatch(
+                            TypeMismatch {
+                                insn: t.insn,
+                                expected: vec![
+                                    None,
+                                    Some(ValueKind::String),
+                                    Some(ValueKind::Object),
+                                ],
+                                actual: vec![v.kind(), k.kind(), ValueKind::Object],
+                            },
+                            t.location,
+                        )),
+                    };
+                }
+            }
+            (Insn::Gpop, 1) => {
+                if let Some(c) = self.streaming.take() {
+                    self.keys.clear();
+                    self.vm.execute(t)?;
+                    return self.handler.handle(end_event(c));
+                }
+            }
+            (Insn::Gdup, 1) | (Insn::Gswp, 2) if self.streaming.is_some() => {
+                return Err(Error {
+                    kind: ErrorKind::StreamingUnsupported,
+                    location: t.location,
+                    source: None,
+                });
+            }
+            _ => {}
+        }
+        self.vm.execute(t)
+    }
+
+    /// Executes all instructions sequentially from the given reader.
+    pub fn execute_all<R>(&mut self, mut reader: R) -> Result<()>
+    where
+        R: ReadToken,
+    {
+        while let Some(token) = reader.read()? {
+            self.execute(token)?;
+        }
+        Ok(())
+    }
+
+    /// Emits events for all values remaining on the stack and returns the handler.
+    pub fn finish(mut self) -> Result<H> {
+        let mut values = self
+            .vm
+            .borrow_stack_mut()
+            .drain()
+            .collect::<Vec<_>>()
+            .into_iter();
+        if let Some(c) = self.streaming.take() {
+            // The bottom value is the streamed container, whose contents have already been emitted.
+            values.next();
+            self.handler.handle(end_event(c))?;
+        }
+        for v in values {
+            emit_value(&mut self.handler, &v)?;
+        }
+        Ok(self.handler)
+    }
+
+    /// Starts streaming the container at the bottom of the stack if it has not been started yet.
+    /// Elements that the container already has are emitted and removed from it.
+    fn begin_streaming(&mut self, t: &Token) -> Result<()> {
+        if self.streaming.is_some() {
+            return Ok(());
+        }
+        let bottom = match self.vm.borrow_stack_mut().bottom_mut() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let (container, existing) = match bottom {
+            Value::Array(arr) if t.insn == Insn::Aadd => {
+                (Container::Array, Value::Array(std::mem::take(arr)))
+            }
+            Value::Object(map) if t.insn == Insn::Oadd => {
+                (Container::Object, Value::Object(std::mem::take(map)))
+            }
+            _ => return Ok(()),
+        };
+        if let Value::Object(ref map) = existing {
+            for k in map.keys() {
+                self.record_key(k);
+            }
+        }
+        self.streaming = Some(container);
+        self.streamed_len = match existing {
+            Value::Array(ref arr) => arr.len(),
+            Value::Object(ref map) => map.len(),
+            _ => 0,
+        };
+        self.handler.handle(match container {
+            Container::Array => Event::BeginArray,
+            Container::Object => Event::BeginObject,
+        })?;
+        emit_value_contents(&mut self.handler, &existing, false)
+    }
+
+    /// Records a key of the bottom object, and returns false if it has already been recorded.
+    /// Keys are not recorded under `DuplicateKeys::LastWins`, where duplicates are emitted as is.
+    fn record_key(&mut self, k: &Bytes) -> bool {
+        self.duplicate_keys == DuplicateKeys::LastWins || self.keys.insert(k.clone())
+    }
+}
+
+fn end_event(c: Container) -> Event<'static> {
+    match c {
+        Container::Array => Event::EndArray,
+        Container::Object => Event::EndObject,
+    }
+}
+
+/// Emits events that represent the given value.
+pub fn emit_value<H: Handler>(handler: &mut H, v: &Value) -> Result<()> {
+    emit_value_contents(handler, v, true)
+}
+
+enum Work<'a> {
+    Value(&'a Value),
+    Key(&'a [u8]),
+    End(Container),
+}
+
+/// Emits events of `v`. If `v` is a container and `with_delimiters` is false,
+/// only the events of its elements are emitted.
+fn emit_value_contents<H: Handler>(
+    handler: &mut H,
+    v: &Value,
+    with_delimiters: bool,
+) -> Result<()> {
+    use Value::*;
+
+    // Uses an explicit stack so that deeply nested values do not overflow the call stack.
+    let mut work = vec![Work::Value(v)];
+    let mut is_root = true;
+    while let Some(w) = work.pop() {
+        let emit_delimiters = with_delimiters || !is_root;
+        is_root = false;
+        match w {
+            Work::Key(k) => handler.handle(Event::Key(k))?,
+            Work::End(c) => handler.handle(end_event(c))?,
+            Work::Value(v) => match *v {
+                Int(n) => handler.handle(Event::Int(n))?,
+                Uint(n) => handler.handle(Event::Uint(n))?,
+                Float(f) => handler.handle(Event::Float(f))?,
+                String(ref s) => handler.handle(Event::String(s))?,
+                Bool(b) => handler.handle(Event::Bool(b))?,
+                Nil => handler.handle(Event::Nil)?,
+                Array(ref arr) => {
+                    if emit_delimiters {
+                        handler.handle(Event::BeginArray)?;
+                        work.push(Work::End(Container::Array));
+                    }
+                    work.extend(arr.iter().rev().map(Work::Value));
+                }
+                Object(ref map) => {
+                    if emit_delimiters {
+                        handler.handle(Event::BeginObject)?;
+                        work.push(Work::End(Container::Object));
+                    }
+                    for (k, v) in map {
+                        work.push(Work::Value(v));
+                        work.push(Work::Key(k));
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::language::Location;
+    use crate::vm::SliceTokenReader;
+    use Insn::*;
+
+    #[test]
+    fn event_vm_scalar() -> Result<()> {
+        assert_eq!(run(&[Inew, Iinc])?, vec!["Int(1)"]);
+        assert_eq!(run(&[Bnew, Bneg])?, vec!["Bool(true)"]);
+        assert_eq!(run(&[Nnew])?, vec!["Nil"]);
+        assert_eq!(run(&[])?, Vec::<std::string::String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_streams_array_elements() -> Result<()> {
+        let mut events = Vec::new();
+        let mut vm = EventVM::new(|e: Event<'_>| {
+            events.push(format!("{:?}", e));
+            Ok(())
+        });
+        vm.execute_all(SliceTokenReader::new(&[Anew, Inew, Aadd]))?;
+        vm.execute_all(SliceTokenReader::new(&[Anew, Nnew, Aadd, Aadd]))?;
+        // The root array does not hold the streamed elements.
+        assert_eq!(vm.vm.peek_top(), Some(&Value::Array(vec![].into())));
+        let _ = vm.finish()?;
+        assert_eq!(
+            events,
+            vec![
+                "BeginArray",
+                "Int(0)",
+                "BeginArray",
+                "Nil",
+                "EndArray",
+                "EndArray"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_streams_object_entries() -> Result<()> {
+        assert_eq!(
+            run(&[Onew, Snew, Inew, Iinc, Ishl, Ishl, Ishl, Ishl, Ishl, Ishl, Sadd, Bnew, Oadd])?,
+            vec!["BeginObject", "Key([64])", "Bool(false)", "EndObject"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_duplicate_keys() -> Result<()> {
+        // {"": false, "": nil}
+        let insns = [Onew, Snew, Bnew, Oadd, Snew, Nnew, Oadd];
+        assert_eq!(
+            run(&insns)?,
+            vec![
+                "BeginObject",
+                "Key([])",
+                "Bool(false)",
+                "Key([])",
+                "Nil",
+                "EndObject"
+            ]
+        );
+        let run_with = |duplicate_keys| {
+            let mut events = Vec::new();
+            let config = vm::Config {
+                duplicate_keys,
+                ..Default::default()
+            };
+            let mut vm = EventVM::with_config(
+                |e: Event<'_>| {
+                    events.push(format!("{:?}", e));
+                    Ok(())
+                },
+                config,
+            );
+            vm.execute_all(SliceTokenReader::new(&insns))?;
+            let _ = vm.finish()?;
+            Ok::<_, Error>(events)
+        };
+        assert_eq!(
+            run_with(DuplicateKeys::FirstWins)?,
+            vec!["BeginObject", "Key([])", "Bool(false)", "EndObject"]
+        );
+        let err = run_with(DuplicateKeys::Error).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DuplicateKey);
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_limits_and_stats_of_streamed_root() -> Result<()> {
+        let new_vm = |max_array_len, max_object_len| {
+            let config = vm::Config {
+                collect_stats: true,
+                max_array_len,
+                max_object_len,
+                ..Default::default()
+            };
+            EventVM::with_config(|_: Event<'_>| Ok(()), config)
+        };
+
+        // [nil, nil, nil]
+        let insns = [Anew, Nnew, Aadd, Nnew, Aadd, Nnew, Aadd];
+        let mut vm = new_vm(None, None);
+        vm.execute_all(SliceTokenReader::new(&insns))?;
+        let stats = vm.stats().unwrap();
+        assert_eq!(stats.insn_counts[&Aadd], 3);
+        assert_eq!(stats.max_array_len, 3);
+        let mut vm = new_vm(Some(2), None);
+        let err = vm.execute_all(SliceTokenReader::new(&insns)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded);
+
+        // {"": nil, "\x01": nil}
+        let insns = [Onew, Snew, Nnew, Oadd, Snew, Inew, Iinc, Sadd, Nnew, Oadd];
+        let mut vm = new_vm(None, None);
+        vm.execute_all(SliceTokenReader::new(&insns))?;
+        let stats = vm.stats().unwrap();
+        assert_eq!(stats.insn_counts[&Oadd], 2);
+        assert_eq!(stats.max_object_len, 2);
+        let mut vm = new_vm(None, Some(1));
+        let err = vm.execute_all(SliceTokenReader::new(&insns)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded);
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_emits_existing_elements_of_root() -> Result<()> {
+        // [Nil] is swapped into the bottom of the stack before anything is added to it.
+        assert_eq!(
+            run(&[Anew, Anew, Nnew, Aadd, Gswp, Aadd])?,
+            vec!["BeginArray", "Nil", "BeginArray", "EndArray", "EndArray"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_ends_popped_root() -> Result<()> {
+        assert_eq!(
+            run(&[Anew, Inew, Aadd, Gpop, Bnew])?,
+            vec!["BeginArray", "Int(0)", "EndArray", "Bool(false)"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_reports_remaining_values_from_bottom() -> Result<()> {
+        assert_eq!(
+            run(&[Anew, Inew, Aadd, Bnew, Nnew])?,
+            vec!["BeginArray", "Int(0)", "EndArray", "Bool(false)", "Nil"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn event_vm_rejects_duplicating_streamed_root() {
+        let err = run(&[Anew, Inew, Aadd, Gdup]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::StreamingUnsupported);
+        let err = run(&[Anew, Inew, Aadd, Nnew, Gswp]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::StreamingUnsupported);
+    }
+
+    #[test]
+    fn event_vm_reports_type_mismatch() {
+        let err = run(&[Onew, Inew, Inew, Oadd]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TypeMismatch);
+        assert_eq!(err.location, Location::unknown());
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn run(insns: &[Insn]) -> Result<Vec<std::string::String>> {
+        let mut events = Vec::new();
+        let mut vm = EventVM::new(|e: Event<'_>| {
+            events.push(format!("{:?}", e));
+            Ok(())
+        });
+        vm.execute_all(SliceTokenReader::new(insns))?;
+        let _ = vm.finish()?;
+        Ok(events)
+    }
+}
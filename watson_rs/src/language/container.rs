@@ -0,0 +1,253 @@
+use std::fmt;
+use std::mem;
+use std::ops;
+
+use super::{Bytes, Value};
+
+#[cfg(not(feature = "preserve-order"))]
+type MapImpl = std::collections::HashMap<Bytes, Value>;
+
+#[cfg(feature = "preserve-order")]
+type MapImpl = indexmap::IndexMap<Bytes, Value>;
+
+/// A type corresponding to WATSON Array.
+///
+/// It behaves like `Vec<Value>` through `Deref`, except that it is dropped without recursion.
+#[derive(PartialEq, Default)]
+pub struct Array(Vec<Value>);
+
+/// A type corresponding to WATSON Object.
+/// With the `preserve-order` feature, it remembers the order in which keys were inserted.
+///
+/// It behaves like `HashMap<Bytes, Value>` (or `IndexMap<Bytes, Value>`) through `Deref`,
+/// except that it is dropped without recursion.
+#[derive(PartialEq, Default)]
+pub struct Map(MapImpl);
+
+impl Array {
+    /// Returns a new empty array.
+    pub fn new() -> Self {
+        Array(Vec::new())
+    }
+
+    /// Returns a new empty array that can hold at least `capacity` elements without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Array(Vec::with_capacity(capacity))
+    }
+
+    /// Returns the underlying vector.
+    pub fn into_vec(mut self) -> Vec<Value> {
+        mem::take(&mut self.0)
+    }
+}
+
+impl Map {
+    /// Returns a new empty object.
+    pub fn new() -> Self {
+        Map(MapImpl::new())
+    }
+
+    /// Returns a new empty object that can hold at least `capacity` entries without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Map(MapImpl::with_capacity(capacity))
+    }
+
+    /// Returns the underlying map.
+    pub fn into_inner(mut self) -> MapImpl {
+        mem::take(&mut self.0)
+    }
+
+    /// Returns an iterator over the keys of the object.
+    pub fn into_keys(self) -> impl Iterator<Item = Bytes> {
+        self.into_iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values of the object.
+    pub fn into_values(self) -> impl Iterator<Item = Value> {
+        self.into_iter().map(|(_, v)| v)
+    }
+}
+
+impl Drop for Array {
+    fn drop(&mut self) {
+        if self.0.iter().any(is_nested) {
+            drop_nested(mem::take(&mut self.0));
+        }
+    }
+}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        if self.0.values().any(is_nested) {
+            drop_nested(mem::take(&mut self.0).into_values());
+        }
+    }
+}
+
+/// Drops values using an explicit stack instead of recursion, so that deeply nested values can't overflow the stack.
+fn drop_nested<I: IntoIterator<Item = Value>>(values: I) {
+    // Nested containers are moved into `pending` and emptied one by one; other values are dropped in place.
+    let mut pending: Vec<Value> = values.into_iter().filter(is_nested).collect();
+    while let Some(mut v) = pending.pop() {
+        match v {
+            Value::Array(ref mut arr) => {
+                pending.extend(mem::take(&mut arr.0).into_iter().filter(is_nested))
+            }
+            Value::Object(ref mut map) => {
+                pending.extend(mem::take(&mut map.0).into_values().filter(is_nested))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns true if dropping the value may recurse.
+fn is_nested(v: &Value) -> bool {
+    match v {
+        Value::Array(arr) => !arr.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+impl Clone for Array {
+    fn clone(&self) -> Self {
+        Array(self.0.clone())
+    }
+}
+
+impl Clone for Map {
+    fn clone(&self) -> Self {
+        Map(self.0.clone())
+    }
+}
+
+impl fmt::Debug for Array {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ops::Deref for Array {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Vec<Value> {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for Array {
+    fn deref_mut(&mut self) -> &mut Vec<Value> {
+        &mut self.0
+    }
+}
+
+impl ops::Deref for Map {
+    type Target = MapImpl;
+
+    fn deref(&self) -> &MapImpl {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for Map {
+    fn deref_mut(&mut self) -> &mut MapImpl {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Value>> for Array {
+    fn from(v: Vec<Value>) -> Self {
+        Array(v)
+    }
+}
+
+impl From<MapImpl> for Map {
+    fn from(m: MapImpl) -> Self {
+        Map(m)
+    }
+}
+
+impl FromIterator<Value> for Array {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        Array(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<(Bytes, Value)> for Map {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Value)>>(iter: I) -> Self {
+        Map(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Value> for Array {
+    fn extend<I: IntoIterator<Item = Value>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl Extend<(Bytes, Value)> for Map {
+    fn extend<I: IntoIterator<Item = (Bytes, Value)>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl IntoIterator for Array {
+    type Item = Value;
+    type IntoIter = std::vec::IntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Array {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Array {
+    type Item = &'a mut Value;
+    type IntoIter = std::slice::IterMut<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+impl IntoIterator for Map {
+    type Item = (Bytes, Value);
+    type IntoIter = <MapImpl as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_inner().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Map {
+    type Item = (&'a Bytes, &'a Value);
+    type IntoIter = <&'a MapImpl as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Map {
+    type Item = (&'a Bytes, &'a mut Value);
+    type IntoIter = <&'a mut MapImpl as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
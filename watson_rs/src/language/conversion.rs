@@ -61,9 +61,15 @@ impl From<Map> for Value {
     }
 }
 
+impl From<super::Array> for Value {
+    fn from(v: super::Array) -> Value {
+        Value::Array(v)
+    }
+}
+
 impl From<Vec<Value>> for Value {
     fn from(v: Vec<Value>) -> Value {
-        Array(v)
+        Value::Array(v.into())
     }
 }
 
@@ -79,12 +85,23 @@ impl From<()> for Value {
     }
 }
 
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Nil, Into::into)
+    }
+}
+
 /// A type that can be converted directly from and to `Value`.
 /// This is different from From<Value> and Into<Value> in that the values of these these types are "identical" to `Value`.
 pub trait IsValue: Into<Value> {
     /// Converts a `Value` into its expected type.
     fn from_value(v: Value) -> Option<Self>;
 
+    /// Returns the type of `Value`s that can be converted into this type, or `None` if any type is accepted.
+    fn kind() -> Option<ValueKind> {
+        None
+    }
+
     /// Converts self into a `Value`.
     fn into_value(self) -> Value {
         self.into()
@@ -104,6 +121,10 @@ impl IsValue for i64 {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Int)
+    }
 }
 
 impl IsValue for u64 {
@@ -113,6 +134,10 @@ impl IsValue for u64 {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Uint)
+    }
 }
 
 impl IsValue for f64 {
@@ -122,6 +147,10 @@ impl IsValue for f64 {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Float)
+    }
 }
 
 impl IsValue for Bytes {
@@ -131,6 +160,10 @@ impl IsValue for Bytes {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::String)
+    }
 }
 
 impl IsValue for Map {
@@ -140,15 +173,33 @@ impl IsValue for Map {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Object)
+    }
 }
 
-impl IsValue for Vec<Value> {
-    fn from_value(v: Value) -> Option<Vec<Value>> {
+impl IsValue for super::Array {
+    fn from_value(v: Value) -> Option<super::Array> {
         match v {
-            Array(a) => Some(a),
+            Value::Array(a) => Some(a),
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Array)
+    }
+}
+
+impl IsValue for Vec<Value> {
+    fn from_value(v: Value) -> Option<Vec<Value>> {
+        super::Array::from_value(v).map(super::Array::into_vec)
+    }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Array)
+    }
 }
 
 impl IsValue for bool {
@@ -158,6 +209,10 @@ impl IsValue for bool {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Bool)
+    }
 }
 
 impl IsValue for () {
@@ -167,6 +222,20 @@ impl IsValue for () {
             _ => None,
         }
     }
+
+    fn kind() -> Option<ValueKind> {
+        Some(ValueKind::Nil)
+    }
+}
+
+/// `None` is represented as `Nil`.
+impl<T: IsValue> IsValue for Option<T> {
+    fn from_value(v: Value) -> Option<Option<T>> {
+        match v {
+            Nil => Some(None),
+            _ => T::from_value(v).map(Some),
+        }
+    }
 }
 
 /// A type that can be converted to `Bytes`.
@@ -181,7 +250,7 @@ impl ToBytes for Bytes {
     }
 }
 
-impl<'a> ToBytes for &'a Bytes {
+impl ToBytes for &Bytes {
     fn to_bytes(self) -> Bytes {
         self.to_vec()
     }
@@ -201,7 +270,7 @@ impl ToBytes for std::string::String {
     }
 }
 
-impl<'a> ToBytes for &'a str {
+impl ToBytes for &str {
     fn to_bytes(self) -> Bytes {
         self.as_bytes().to_vec()
     }
@@ -213,13 +282,13 @@ impl ToBytes for u8 {
     }
 }
 
-impl<'a> ToBytes for &'a [u8] {
+impl ToBytes for &[u8] {
     fn to_bytes(self) -> Bytes {
         self.to_vec()
     }
 }
 
-impl<'a, const N: usize> ToBytes for &'a [u8; N] {
+impl<const N: usize> ToBytes for &[u8; N] {
     fn to_bytes(self) -> Bytes {
         self.to_vec()
     }
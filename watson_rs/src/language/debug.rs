@@ -0,0 +1,200 @@
+use std::fmt::{self, Write};
+
+use super::{Bytes, Map, Value};
+
+/// Formats `Value` in the same way as `#[derive(Debug)]` would, but without recursion
+/// so that deeply nested values can't overflow the stack.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pretty = f.alternate();
+        Printer { f, pretty }.print(self)
+    }
+}
+
+/// Elements of a container that are not printed yet.
+enum Entries<'a> {
+    Array(std::slice::Iter<'a, Value>),
+    Object(<&'a Map as IntoIterator>::IntoIter),
+}
+
+impl<'a> Entries<'a> {
+    fn next(&mut self) -> Option<(Option<&'a Bytes>, &'a Value)> {
+        match self {
+            Entries::Array(iter) => iter.next().map(|v| (None, v)),
+            Entries::Object(iter) => iter.next().map(|(k, v)| (Some(k), v)),
+        }
+    }
+}
+
+/// A partially printed container.
+struct Frame<'a> {
+    entries: Entries<'a>,
+    depth: usize,
+    first: bool,
+    close: char,
+}
+
+struct Printer<'a, 'f> {
+    f: &'a mut fmt::Formatter<'f>,
+    pretty: bool,
+}
+
+impl<'a, 'f> Printer<'a, 'f> {
+    fn print(&mut self, v: &Value) -> fmt::Result {
+        let mut frames = Vec::new();
+        frames.extend(self.open(v, 0)?);
+        while let Some(top) = frames.last_mut() {
+            match top.entries.next() {
+                Some((key, child)) => {
+                    let depth = top.depth + 2;
+                    if self.pretty {
+                        self.pad(depth)?;
+                    } else if !top.first {
+                        self.f.write_str(", ")?;
+                    }
+                    top.first = false;
+                    if let Some(k) = key {
+                        self.debug(k, depth)?;
+                        self.f.write_str(": ")?;
+                    }
+                    match self.open(child, depth)? {
+                        Some(frame) => frames.push(frame),
+                        None => self.end_element()?,
+                    }
+                }
+                None => {
+                    let frame = frames.pop().expect("frames must not be empty");
+                    self.close(&frame)?;
+                    if !frames.is_empty() {
+                        self.end_element()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a leaf, or prints the beginning of a container and returns a frame to print its elements.
+    fn open<'v>(&mut self, v: &'v Value, depth: usize) -> Result<Option<Frame<'v>>, fmt::Error> {
+        let (name, entries, is_empty, open, close) = match *v {
+            Value::Array(ref arr) => (
+                "Array",
+                Entries::Array(arr.iter()),
+                arr.is_empty(),
+                '[',
+                ']',
+            ),
+            Value::Object(ref map) => (
+                "Object",
+                Entries::Object(map.iter()),
+                map.is_empty(),
+                '{',
+                '}',
+            ),
+            Value::Int(ref n) => return self.leaf("Int", Some(n), depth).map(|_| None),
+            Value::Uint(ref n) => return self.leaf("Uint", Some(n), depth).map(|_| None),
+            Value::Float(ref f) => return self.leaf("Float", Some(f), depth).map(|_| None),
+            Value::String(ref s) => return self.leaf("String", Some(s), depth).map(|_| None),
+            Value::Bool(ref b) => return self.leaf("Bool", Some(b), depth).map(|_| None),
+            Value::Nil => return self.leaf("Nil", None, depth).map(|_| None),
+        };
+        self.f.write_str(name)?;
+        self.f.write_char('(')?;
+        if self.pretty {
+            self.f.write_char('\n')?;
+            self.pad(depth + 1)?;
+        }
+        self.f.write_char(open)?;
+        if self.pretty && !is_empty {
+            self.f.write_char('\n')?;
+        }
+        Ok(Some(Frame {
+            entries,
+            depth,
+            first: true,
+            close,
+        }))
+    }
+
+    fn close(&mut self, frame: &Frame) -> fmt::Result {
+        if self.pretty {
+            if !frame.first {
+                self.pad(frame.depth + 1)?;
+            }
+            self.f.write_char(frame.close)?;
+            self.f.write_str(",\n")?;
+            self.pad(frame.depth)?;
+        } else {
+            self.f.write_char(frame.close)?;
+        }
+        self.f.write_char(')')
+    }
+
+    fn end_element(&mut self) -> fmt::Result {
+        if self.pretty {
+            self.f.write_str(",\n")?;
+        }
+        Ok(())
+    }
+
+    /// Prints a variant that is not a container.
+    fn leaf(&mut self, name: &str, field: Option<&dyn fmt::Debug>, depth: usize) -> fmt::Result {
+        self.f.write_str(name)?;
+        if let Some(field) = field {
+            self.f.write_char('(')?;
+            if self.pretty {
+                self.f.write_char('\n')?;
+                self.pad(depth + 1)?;
+                self.debug(field, depth + 1)?;
+                self.f.write_str(",\n")?;
+                self.pad(depth)?;
+            } else {
+                self.debug(field, depth)?;
+            }
+            self.f.write_char(')')?;
+        }
+        Ok(())
+    }
+
+    fn debug(&mut self, v: &dyn fmt::Debug, depth: usize) -> fmt::Result {
+        if self.pretty {
+            write!(
+                Indented {
+                    f: &mut *self.f,
+                    depth
+                },
+                "{v:#?}"
+            )
+        } else {
+            v.fmt(self.f)
+        }
+    }
+
+    fn pad(&mut self, depth: usize) -> fmt::Result {
+        for _ in 0..depth {
+            self.f.write_str("    ")?;
+        }
+        Ok(())
+    }
+}
+
+/// Indents every line but the first one by `depth` levels.
+struct Indented<'a, 'f> {
+    f: &'a mut fmt::Formatter<'f>,
+    depth: usize,
+}
+
+impl<'a, 'f> fmt::Write for Indented<'a, 'f> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.f.write_char('\n')?;
+                for _ in 0..self.depth {
+                    self.f.write_str("    ")?;
+                }
+            }
+            self.f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
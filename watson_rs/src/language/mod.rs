@@ -1,28 +1,49 @@
 use std::fmt;
 use std::path;
-use std::rc::Rc;
+use std::sync::Arc;
 
+mod container;
 mod conversion;
+mod debug;
+mod table;
 
+pub use self::container::{Array, Map};
 pub use self::conversion::{IsValue, ToBytes};
+pub use self::table::ModeTable;
 
 macro_rules! define_insn {
     ( $( ($name:ident, $achar:expr, $schar:expr) ),* ) => {
         /// An instruction of the WATSON Virtual Machine.
         /// See [the specification](https://github.com/genkami/watson/blob/main/doc/spec.md) for more details.
         #[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
+        #[non_exhaustive]
         pub enum Insn {
+            $( $name, )*
+            /// An extension instruction that is not defined in the specification.
+            /// It is represented by the given byte in both modes, and executed by a `vm::InsnHandler`.
+            Ext(u8),
+        }
+
+        /// Indices of instructions defined in the specification.
+        enum InsnIndex {
             $( $name ),*
         }
 
+        /// The number of instructions defined in the specification.
+        pub(crate) const NUM_INSNS: usize = [$( InsnIndex::$name ),*].len();
+
+        /// Mnemonics of instructions defined in the specification.
+        pub(crate) const MNEMONICS: [(&str, Insn); NUM_INSNS] = [$( (stringify!($name), Insn::$name) ),*];
+
         impl Insn {
-            /// Returns an iterator that iterates over all instructions.
+            /// Returns an iterator that iterates over all instructions defined in the specification.
             pub fn all() -> impl Iterator<Item = Self> {
                 [$( Insn::$name ),* ].into_iter()
             }
 
             /// Converts a byte representation into corresponding `Insn`.
             /// Which byte is converted to which insn depends on `Mode`.
+            /// This never returns `Insn::Ext`.
             pub fn from_byte(mode: Mode, byte: u8) -> Option<Self> {
                 match mode {
                     Mode::A => Insn::from_byte_a(byte),
@@ -39,6 +60,25 @@ macro_rules! define_insn {
                 }
             }
 
+            /// Returns true unless it is an `Insn::Ext` that can't be written as text in the mode, i.e. its byte is
+            /// not a printable ASCII character or represents another instruction.
+            pub fn is_representable(self, mode: Mode) -> bool {
+                match self {
+                    Insn::Ext(byte) => is_ext_byte(byte) && Insn::from_byte(mode, byte).is_none(),
+                    _ => true,
+                }
+            }
+
+            /// Returns a unique index less than `NUM_INSNS`, or `None` if it is `Insn::Ext`.
+            pub(crate) fn index(self) -> Option<usize> {
+                match self {
+                    $(
+                        Insn::$name => Some(InsnIndex::$name as usize),
+                    )*
+                    Insn::Ext(_) => None,
+                }
+            }
+
             fn from_byte_a(byte: u8) -> Option<Self> {
                 match byte {
                     $(
@@ -60,16 +100,18 @@ macro_rules! define_insn {
             fn into_byte_a(self) -> u8 {
                 match self {
                     $(
-                        Insn::$name => $achar
-                    ),*
+                        Insn::$name => $achar,
+                    )*
+                    Insn::Ext(byte) => byte,
                 }
             }
 
             fn into_byte_s(self) -> u8 {
                 match self {
                     $(
-                        Insn::$name => $schar
-                    ),*
+                        Insn::$name => $schar,
+                    )*
+                    Insn::Ext(byte) => byte,
                 }
             }
         }
@@ -79,26 +121,191 @@ macro_rules! define_insn {
     }
 }
 
+/// Returns true if the byte is a printable ASCII character, which `Insn::Ext` must be to be written as text.
+pub(crate) fn is_ext_byte(byte: u8) -> bool {
+    byte.is_ascii_graphic()
+}
+
 /// A byte array.
 pub type Bytes = Vec<u8>;
 
-/// A type corresponding to WATSON Object.
-pub type Map = std::collections::HashMap<Bytes, Value>;
-
 /// A value that is defined in WATSON specification.
 /// See [the specification](https://github.com/genkami/watson/blob/main/doc/spec.md) for more details.
-#[derive(PartialEq, Clone, Debug)]
+///
+/// `Value` is cloned, compared, formatted and dropped without recursion so that deeply nested values can't overflow
+/// the stack. For this to work, `Value::Object` and `Value::Array` hold the `Map` and `Array` newtypes rather than
+/// `HashMap` and `Vec`: Rust doesn't allow moving fields out of a type that implements `Drop`, so `Value` itself
+/// can't have one without breaking every `match` that takes a container by value. Use `.into()` to build them
+/// from a `Vec<Value>` or a map, and `Array::into_vec` or `Map::into_inner` to get them back.
 pub enum Value {
     Int(i64),
     Uint(u64),
     Float(f64),
     String(Bytes),
     Object(Map),
-    Array(Vec<Value>),
+    Array(Array),
     Bool(bool),
     Nil,
 }
 
+/// The type of a `Value`.
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
+pub enum ValueKind {
+    Int,
+    Uint,
+    Float,
+    String,
+    Object,
+    Array,
+    Bool,
+    Nil,
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Value {
+    /// Returns the type of the value.
+    pub fn kind(&self) -> ValueKind {
+        match *self {
+            Value::Int(_) => ValueKind::Int,
+            Value::Uint(_) => ValueKind::Uint,
+            Value::Float(_) => ValueKind::Float,
+            Value::String(_) => ValueKind::String,
+            Value::Object(_) => ValueKind::Object,
+            Value::Array(_) => ValueKind::Array,
+            Value::Bool(_) => ValueKind::Bool,
+            Value::Nil => ValueKind::Nil,
+        }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        match *self {
+            Value::Int(n) => Value::Int(n),
+            Value::Uint(n) => Value::Uint(n),
+            Value::Float(f) => Value::Float(f),
+            Value::String(ref s) => Value::String(s.clone()),
+            Value::Bool(b) => Value::Bool(b),
+            Value::Nil => Value::Nil,
+            Value::Array(_) | Value::Object(_) => clone_nested(self),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        // Pairs of containers are compared element by element using an explicit stack instead of recursion.
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            match pair {
+                (Value::Int(a), Value::Int(b)) if a == b => {}
+                (Value::Uint(a), Value::Uint(b)) if a == b => {}
+                (Value::Float(a), Value::Float(b)) if a == b => {}
+                (Value::String(a), Value::String(b)) if a == b => {}
+                (Value::Bool(a), Value::Bool(b)) if a == b => {}
+                (Value::Nil, Value::Nil) => {}
+                (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+                    pending.extend(a.iter().zip(b.iter()));
+                }
+                (Value::Object(a), Value::Object(b)) if a.len() == b.len() => {
+                    for (k, v) in a.iter() {
+                        match b.get(k) {
+                            Some(w) => pending.push((v, w)),
+                            None => return false,
+                        }
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// A partially cloned container used by `clone_nested`.
+enum CloneFrame<'a> {
+    Array {
+        src: std::slice::Iter<'a, Value>,
+        dst: Vec<Value>,
+    },
+    Object {
+        src: <&'a Map as IntoIterator>::IntoIter,
+        dst: Map,
+        key: Option<&'a Bytes>,
+    },
+}
+
+impl<'a> CloneFrame<'a> {
+    fn new(v: &'a Value) -> Option<Self> {
+        match *v {
+            Value::Array(ref arr) => Some(CloneFrame::Array {
+                src: arr.iter(),
+                dst: Vec::with_capacity(arr.len()),
+            }),
+            Value::Object(ref map) => Some(CloneFrame::Object {
+                src: map.iter(),
+                dst: Map::with_capacity(map.len()),
+                key: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the next child to clone, remembering its key if `self` is an object.
+    fn next_child(&mut self) -> Option<&'a Value> {
+        match self {
+            CloneFrame::Array { src, .. } => src.next(),
+            CloneFrame::Object { src, key, .. } => src.next().map(|(k, v)| {
+                *key = Some(k);
+                v
+            }),
+        }
+    }
+
+    fn add_child(&mut self, v: Value) {
+        match self {
+            CloneFrame::Array { dst, .. } => dst.push(v),
+            CloneFrame::Object { dst, key, .. } => {
+                let k = key.take().expect("key must be set by next_child");
+                dst.insert(k.clone(), v);
+            }
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            CloneFrame::Array { dst, .. } => Value::Array(dst.into()),
+            CloneFrame::Object { dst, .. } => Value::Object(dst),
+        }
+    }
+}
+
+/// Clones an array or an object using an explicit stack instead of recursion.
+fn clone_nested(v: &Value) -> Value {
+    let mut frames = vec![CloneFrame::new(v).expect("v must be a container")];
+    loop {
+        let top = frames.last_mut().expect("frames must not be empty");
+        match top.next_child() {
+            Some(child) => match CloneFrame::new(child) {
+                Some(frame) => frames.push(frame),
+                None => top.add_child(child.clone()),
+            },
+            None => {
+                let done = frames.pop().expect("frames must not be empty").into_value();
+                match frames.last_mut() {
+                    Some(parent) => parent.add_child(done),
+                    None => return done,
+                }
+            }
+        }
+    }
+}
+
 define_insn! {
     (Inew, b'B', b'S'),
     (Iinc, b'u', b'h'),
@@ -131,6 +338,9 @@ pub struct Token {
     /// A VM instruction that the token represents.
     pub insn: Insn,
 
+    /// The mode in which the token was read.
+    pub mode: Mode,
+
     /// Location of the instruction.
     pub location: Location,
 }
@@ -142,7 +352,7 @@ pub struct Location {
     pub byte: u8,
 
     /// Optional file path.
-    pub path: Option<Rc<path::Path>>,
+    pub path: Option<Arc<path::Path>>,
 
     /// Line number.
     pub line: usize,
@@ -200,20 +410,34 @@ impl Mode {
     }
 }
 
+/// An element of a path that points at a value nested in arrays and objects.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub enum PathElem {
+    /// A key of an object.
+    Key(Bytes),
+    /// An index of an array.
+    Index(usize),
+}
+
+impl fmt::Display for PathElem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathElem::Key(k) => write!(f, ".{}", String::from_utf8_lossy(k)),
+            PathElem::Index(i) => write!(f, "[{i}]"),
+        }
+    }
+}
+
 /// Creates an array `Value` consisting of the arguments.
 #[macro_export]
 macro_rules! array {
     // To suppress unused_mut.
     () => {
-        $crate::language::Value::Array(std::vec::Vec::new())
+        $crate::language::Value::Array($crate::language::Array::new())
     };
-    ( $( $elem:expr ),* $(,)? ) => {{
-        let mut vec = std::vec::Vec::new();
-        $(
-            vec.push($elem);
-        )*
-        $crate::language::Value::Array(vec)
-    }}
+    ( $( $elem:expr ),* $(,)? ) => {
+        $crate::language::Value::Array(std::vec![ $( $elem ),* ].into())
+    }
 }
 
 #[macro_export]
@@ -260,14 +484,16 @@ mod test {
 
             let mut insns = Insn::all().collect::<HashSet<_>>();
             for c in ASCII_CHARS {
-                Insn::from_byte(mode, c).map(|insn| insns.remove(&insn));
-            }
-            for insn in insns {
-                panic!(
-                    "mode={:?}: instruction {:?} does not have matching byte characters",
-                    mode, insn
-                );
+                if let Some(insn) = Insn::from_byte(mode, c) {
+                    insns.remove(&insn);
+                }
             }
+            assert!(
+                insns.is_empty(),
+                "mode={:?}: instructions {:?} do not have matching byte characters",
+                mode,
+                insns
+            );
         }
 
         assert_surjective(Mode::A);
@@ -281,17 +507,19 @@ mod test {
 
             let mut reversed = HashMap::new();
             for c in ASCII_CHARS {
-                Insn::from_byte(mode, c).map(|insn| match reversed.get(&insn) {
-                    None => {
-                        reversed.insert(insn, c);
+                if let Some(insn) = Insn::from_byte(mode, c) {
+                    match reversed.get(&insn) {
+                        None => {
+                            reversed.insert(insn, c);
+                        }
+                        Some(d) => {
+                            panic!(
+                                "mode={:?}: both {:?} and {:?} are converted into {:?}",
+                                mode, c, d, insn
+                            );
+                        }
                     }
-                    Some(d) => {
-                        panic!(
-                            "mode={:?}: both {:?} and {:?} are converted into {:?}",
-                            mode, c, d, insn
-                        );
-                    }
-                });
+                }
             }
         }
 
@@ -325,13 +553,152 @@ mod test {
         assert_injective(Mode::S);
     }
 
+    #[test]
+    fn insn_ext_into_byte() {
+        assert_eq!(Insn::Ext(b'0').into_byte(Mode::A), b'0');
+        assert_eq!(Insn::Ext(b'0').into_byte(Mode::S), b'0');
+        assert_eq!(Insn::all().find(|i| matches!(i, Insn::Ext(_))), None);
+    }
+
+    #[test]
+    fn insn_is_representable() {
+        assert!(Insn::Inew.is_representable(Mode::A));
+        assert!(Insn::Ext(b'0').is_representable(Mode::A));
+        assert!(Insn::Ext(b'0').is_representable(Mode::S));
+        assert!(!Insn::Ext(b'B').is_representable(Mode::A));
+        assert!(Insn::Ext(b'B').is_representable(Mode::S));
+        assert!(!Insn::Ext(b'\n').is_representable(Mode::A));
+        assert!(!Insn::Ext(b' ').is_representable(Mode::A));
+        assert!(!Insn::Ext(0xff).is_representable(Mode::A));
+    }
+
+    #[test]
+    fn value_clone() {
+        let v = array![
+            Int(1),
+            object![a: array![Nil, Bool(true)], b: object![], c: String(b"x".to_vec())],
+            array![],
+            Float(1.5),
+        ];
+        assert_eq!(v.clone(), v);
+        assert_eq!(Uint(3).clone(), Uint(3));
+    }
+
+    #[test]
+    fn deeply_nested_value_can_be_cloned_and_dropped() {
+        const DEPTH: usize = 200_000;
+
+        let mut v = Nil;
+        for i in 0..DEPTH {
+            v = if i % 2 == 0 {
+                array![v]
+            } else {
+                object![key: v]
+            };
+        }
+        let cloned = v.clone();
+
+        // Checks the depth without recursion.
+        let mut depth = 0;
+        let mut cur = &cloned;
+        loop {
+            cur = match cur {
+                Array(arr) => &arr[0],
+                Object(map) => &map[&b"key".to_vec()],
+                _ => break,
+            };
+            depth += 1;
+        }
+        assert_eq!(depth, DEPTH);
+
+        assert_eq!(v, cloned);
+        assert_ne!(v, array![]);
+        assert!(format!("{v:?}").starts_with("Object({[107, 101, 121]: Array([Object({"));
+
+        drop(v);
+        drop(cloned);
+    }
+
+    #[test]
+    fn value_eq() {
+        let v = object![a: array![Int(1), Nil], b: object![c: Float(1.5)]];
+        assert_eq!(
+            v,
+            object![b: object![c: Float(1.5)], a: array![Int(1), Nil]]
+        );
+        assert_ne!(v, object![a: array![Int(1), Nil]]);
+        assert_ne!(
+            v,
+            object![a: array![Int(1), Nil], b: object![c: Float(2.5)]]
+        );
+        assert_ne!(
+            v,
+            object![a: array![Int(1), Nil], c: object![c: Float(1.5)]]
+        );
+        assert_ne!(array![Int(1)], array![Uint(1)]);
+        assert_ne!(array![Int(1)], array![Int(1), Int(1)]);
+        assert_ne!(Float(f64::NAN), Float(f64::NAN));
+    }
+
+    #[test]
+    fn value_debug_is_the_same_as_derived() {
+        // The same shape as `Value` with a derived `Debug`.
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        enum Derived {
+            Int(i64),
+            Uint(u64),
+            Float(f64),
+            String(Bytes),
+            Object(std::collections::HashMap<Bytes, Derived>),
+            Array(Vec<Derived>),
+            Bool(bool),
+            Nil,
+        }
+
+        fn derived(v: &Value) -> Derived {
+            match v {
+                Int(n) => Derived::Int(*n),
+                Uint(n) => Derived::Uint(*n),
+                Float(f) => Derived::Float(*f),
+                String(s) => Derived::String(s.clone()),
+                Object(map) => {
+                    Derived::Object(map.iter().map(|(k, v)| (k.clone(), derived(v))).collect())
+                }
+                Array(arr) => Derived::Array(arr.iter().map(derived).collect()),
+                Bool(b) => Derived::Bool(*b),
+                Nil => Derived::Nil,
+            }
+        }
+
+        // Objects have at most one key so that both of them are printed in the same order.
+        let values = [
+            Nil,
+            Int(-1),
+            Float(1.0),
+            String(b"ab".to_vec()),
+            array![],
+            object![],
+            array![Uint(1), Bool(true), array![], object![]],
+            object![k: array![object![], array![Nil, String(vec![])], object![x: Int(2)]]],
+            array![
+                array![array![Nil]],
+                object![y: object![z: array![Float(-0.5)]]]
+            ],
+        ];
+        for v in values.iter() {
+            assert_eq!(format!("{v:?}"), format!("{:?}", derived(v)));
+            assert_eq!(format!("{v:#?}"), format!("{:#?}", derived(v)));
+        }
+    }
+
     #[test]
     fn array_macro() {
-        assert_eq!(array![], Array(vec![]));
-        assert_eq!(array![Int(123)], Array(vec![Int(123)]));
+        assert_eq!(array![], Array(vec![].into()));
+        assert_eq!(array![Int(123)], Array(vec![Int(123)].into()));
         assert_eq!(
             array![Int(123), Bool(false), array![Uint(456)]],
-            Array(vec![Int(123), Bool(false), Array(vec![Uint(456)])])
+            Array(vec![Int(123), Bool(false), Array(vec![Uint(456)].into())].into())
         );
         assert_eq!(
             array![
@@ -339,7 +706,7 @@ mod test {
                 Bool(false),
                 array![Uint(456)], // trailing comma
             ],
-            Array(vec![Int(123), Bool(false), Array(vec![Uint(456)])])
+            Array(vec![Int(123), Bool(false), Array(vec![Uint(456)].into())].into())
         )
     }
 
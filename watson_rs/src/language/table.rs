@@ -0,0 +1,144 @@
+use super::{is_ext_byte, Insn, Mode, NUM_INSNS};
+
+/// `ModeTable` defines which byte represents which instruction in each `Mode`.
+///
+/// `Lexer` and `Unlexer` use the table defined in the specification by default, but they can be configured to
+/// use alternative alphabets (e.g. to fit in a constrained character set). Only the byte representation changes;
+/// the instructions and the mode transitions by `Snew` have the same semantics as the specification.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct ModeTable {
+    a: Table,
+    s: Table,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+struct Table {
+    from_byte: [Option<Insn>; 256],
+    into_byte: [u8; NUM_INSNS],
+}
+
+impl ModeTable {
+    /// Returns the table defined in the specification.
+    pub fn standard() -> Self {
+        ModeTable::new(|mode, insn| insn.into_byte(mode)).expect("the standard table must be valid")
+    }
+
+    /// Returns a new table where `f(mode, insn)` is the byte representation of `insn` in `mode`.
+    /// It returns `None` if `f` maps two different instructions to the same byte in the same mode.
+    pub fn new<F>(mut f: F) -> Option<Self>
+    where
+        F: FnMut(Mode, Insn) -> u8,
+    {
+        Some(ModeTable {
+            a: Table::new(|insn| f(Mode::A, insn))?,
+            s: Table::new(|insn| f(Mode::S, insn))?,
+        })
+    }
+
+    /// Converts a byte representation into corresponding `Insn`.
+    /// Like `Insn::from_byte`, this never returns `Insn::Ext`.
+    pub fn from_byte(&self, mode: Mode, byte: u8) -> Option<Insn> {
+        self.table(mode).from_byte[byte as usize]
+    }
+
+    /// Converts the given instruction into its byte representation.
+    /// `Insn::Ext` is converted in the same way as `Insn::into_byte`; use `is_representable` to check that the byte
+    /// is read back as the same instruction.
+    pub fn into_byte(&self, mode: Mode, insn: Insn) -> u8 {
+        match insn.index() {
+            Some(i) => self.table(mode).into_byte[i],
+            None => insn.into_byte(mode),
+        }
+    }
+
+    /// Like `Insn::is_representable`, returns true unless it is an `Insn::Ext` whose byte is not a printable ASCII
+    /// character or represents another instruction in this table.
+    pub fn is_representable(&self, mode: Mode, insn: Insn) -> bool {
+        match insn {
+            Insn::Ext(byte) => is_ext_byte(byte) && self.from_byte(mode, byte).is_none(),
+            _ => true,
+        }
+    }
+
+    fn table(&self, mode: Mode) -> &Table {
+        match mode {
+            Mode::A => &self.a,
+            Mode::S => &self.s,
+        }
+    }
+}
+
+impl Default for ModeTable {
+    fn default() -> Self {
+        ModeTable::standard()
+    }
+}
+
+impl Table {
+    fn new<F>(mut f: F) -> Option<Self>
+    where
+        F: FnMut(Insn) -> u8,
+    {
+        let mut table = Table {
+            from_byte: [None; 256],
+            into_byte: [0; NUM_INSNS],
+        };
+        for insn in Insn::all() {
+            let byte = f(insn);
+            let entry = &mut table.from_byte[byte as usize];
+            if entry.is_some() {
+                return None;
+            }
+            *entry = Some(insn);
+            table.into_byte[insn.index()?] = byte;
+        }
+        Some(table)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn standard_table_is_consistent_with_insn() {
+        let table = ModeTable::standard();
+        for mode in [Mode::A, Mode::S] {
+            for byte in 0..=255 {
+                assert_eq!(table.from_byte(mode, byte), Insn::from_byte(mode, byte));
+            }
+            for insn in Insn::all() {
+                assert_eq!(table.into_byte(mode, insn), insn.into_byte(mode));
+            }
+            assert_eq!(table.into_byte(mode, Insn::Ext(b'0')), b'0');
+            for byte in 0..=255 {
+                assert_eq!(
+                    table.is_representable(mode, Insn::Ext(byte)),
+                    Insn::Ext(byte).is_representable(mode)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn custom_table() {
+        // Swaps the alphabets of the two modes.
+        let table = ModeTable::new(|mode, insn| insn.into_byte(mode.flip())).unwrap();
+        assert_eq!(table.from_byte(Mode::A, b'S'), Some(Insn::Inew));
+        assert_eq!(table.from_byte(Mode::S, b'B'), Some(Insn::Inew));
+        assert_eq!(table.into_byte(Mode::A, Insn::Inew), b'S');
+        assert_eq!(table.into_byte(Mode::S, Insn::Inew), b'B');
+    }
+
+    #[test]
+    fn table_must_be_injective() {
+        assert_eq!(ModeTable::new(|_, _| b'x'), None);
+        assert_eq!(
+            ModeTable::new(|mode, insn| match mode {
+                Mode::A => insn.into_byte(mode),
+                Mode::S => b'x',
+            }),
+            None
+        );
+    }
+}
@@ -1,52 +1,159 @@
+use std::collections::VecDeque;
 use std::fs;
 use std::io;
 use std::path;
-use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::error::{Error, Result};
-use crate::language::{Insn, Location, Mode, Token};
+use crate::byteio::ByteRead;
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Insn, Location, Mode, ModeTable, Token};
 use crate::vm::ReadToken;
 
 /// A lexer of the WATSON language.
 pub struct Lexer<R> {
-    bytes: io::Bytes<R>,
+    reader: R,
+    decoder: Decoder,
+}
 
-    mode: Mode,
+/// A lexer of the WATSON language that reads from an in-memory byte slice.
+/// This is faster than `Lexer` because it does not need to handle I/O errors.
+pub struct SliceLexer<'a> {
+    slice: &'a [u8],
+    pos: usize,
+    decoder: Decoder,
+}
 
-    last_read_byte: u8,
-    file_path: Option<Rc<path::Path>>,
-    line: usize,
-    column: usize,
+/// Statistics of bytes read by a lexer.
+#[derive(Eq, PartialEq, Clone, Copy, Default, Debug)]
+pub struct Stats {
+    /// The total number of bytes read.
+    pub bytes: usize,
+
+    /// The number of bytes that represent instructions.
+    pub insn_bytes: usize,
+
+    /// The number of bytes skipped as noise (including comments).
+    pub skipped_bytes: usize,
+
+    /// The number of newline characters.
+    pub newlines: usize,
+}
+
+impl Stats {
+    /// Returns the ratio of skipped bytes to all bytes, or 0 if nothing has been read.
+    pub fn noise_ratio(&self) -> f64 {
+        if self.bytes == 0 {
+            0.0
+        } else {
+            self.skipped_bytes as f64 / self.bytes as f64
+        }
+    }
+}
+
+/// A syntax of comments that a lexer skips even if they contain bytes that represent instructions.
+/// Delimiters take precedence over instructions.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Comment {
+    /// Everything from `open` to the next `close` is a comment. Comments of this type can't be nested.
+    Block { open: u8, close: u8 },
+
+    /// Everything from the given byte to the end of the line is a comment.
+    Line(u8),
 }
 
 /// Config configures a `Lexer`.
+#[derive(Clone)]
 pub struct Config {
     // Initial mode of a `Lexer` (defaults to `A` by the specificaton).
     pub initial_mode: Mode,
 
+    // Byte representations of instructions (defaults to the one defined by the specification).
+    pub table: ModeTable,
+
     // File path to display (not used to open a file or something).
-    pub file_path: Option<Rc<path::Path>>,
+    pub file_path: Option<Arc<path::Path>>,
+
+    // Bytes that are read as `Insn::Ext` instead of being ignored (defaults to none).
+    // Bytes that represent instructions in the current mode are not affected.
+    pub extension_bytes: Vec<u8>,
+
+    // If set to true, the lexer keeps bytes that do not represent instructions so that they can be retrieved
+    // by `noise` (defaults to false).
+    pub capture_noise: bool,
+
+    // Syntaxes of comments (defaults to none).
+    // Comments are treated as noise.
+    pub comments: Vec<Comment>,
+
+    // The maximum number of bytes to read (defaults to unlimited).
+    // The lexer returns `ErrorKind::InputLimitExceeded` if the input is longer than this.
+    pub max_input_bytes: Option<usize>,
+
+    // The maximum number of tokens to read (defaults to unlimited).
+    // The lexer returns `ErrorKind::InputLimitExceeded` if the input has more tokens than this.
+    pub max_tokens: Option<usize>,
+
+    // If set to true, columns are counted per UTF-8 character instead of per byte (defaults to false).
+    pub utf8_columns: bool,
+
+    // The width of a tab character used to count columns (defaults to 1).
+    // A tab advances the column to the next tab stop.
+    pub tab_width: usize,
+
+    // If set to true, a `ChainedLexer` starts reading each source in the mode where the previous source ended
+    // instead of `initial_mode` (defaults to true).
+    pub carry_mode_across_sources: bool,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             initial_mode: Mode::A,
+            table: ModeTable::standard(),
             file_path: None,
+            extension_bytes: Vec::new(),
+            capture_noise: false,
+            comments: Vec::new(),
+            max_input_bytes: None,
+            max_tokens: None,
+            utf8_columns: false,
+            tab_width: 1,
+            carry_mode_across_sources: true,
         }
     }
 }
 
 impl Config {
+    /// Sets `file_path` to the given label so that locations of tokens from non-file readers are identifiable.
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.file_path = Some(path::Path::new(label).into());
+        self
+    }
+
     /// Returns a new `Lexer` that reads from the given reader.
-    pub fn build<R: io::Read>(self, reader: R) -> Lexer<R> {
+    pub fn build<R: ByteRead>(self, reader: R) -> Lexer<R> {
         Lexer {
-            bytes: reader.bytes(),
-            mode: self.initial_mode,
-            last_read_byte: 0,
-            file_path: self.file_path,
-            line: 1,
-            column: 0,
+            reader,
+            decoder: self.into_decoder(),
+        }
+    }
+
+    /// Returns a new `SliceLexer` that reads from the given slice.
+    pub fn build_slice(self, slice: &[u8]) -> SliceLexer<'_> {
+        SliceLexer {
+            slice,
+            pos: 0,
+            decoder: self.into_decoder(),
+        }
+    }
+
+    /// Returns a new `ChainedLexer` without any sources.
+    pub fn build_chained(self) -> ChainedLexer {
+        ChainedLexer {
+            config: self,
+            sources: VecDeque::new(),
+            current: None,
+            mode: None,
         }
     }
 
@@ -58,6 +165,31 @@ impl Config {
         }
         Ok(self.build(file))
     }
+
+    fn into_decoder(self) -> Decoder {
+        Decoder {
+            mode: self.initial_mode,
+            table: self.table,
+            extension_bytes: self.extension_bytes,
+            noise: Vec::new(),
+            capture_noise: self.capture_noise,
+            emitted: false,
+            comments: self.comments,
+            comment_end: None,
+            max_input_bytes: self.max_input_bytes,
+            max_tokens: self.max_tokens,
+            bytes_read: 0,
+            tokens_read: 0,
+            newlines: 0,
+            last_read_byte: 0,
+            file_path: self.file_path,
+            line: 1,
+            column: 0,
+            column_advance: 1,
+            utf8_columns: self.utf8_columns,
+            tab_width: self.tab_width.max(1),
+        }
+    }
 }
 
 impl Lexer<fs::File> {
@@ -67,77 +199,338 @@ impl Lexer<fs::File> {
     }
 }
 
-impl<R: io::Read> Lexer<R> {
+impl Lexer<io::StdinLock<'static>> {
+    /// Returns a new `Lexer` that reads from the standard input with the default configuration.
+    /// Locations of its tokens are labeled `<stdin>`.
+    pub fn from_stdin() -> Self {
+        Config::default()
+            .with_label("<stdin>")
+            .build(io::stdin().lock())
+    }
+}
+
+impl<R: ByteRead> Lexer<R> {
     /// Returns a new `Lexer` with the default configuration.
     pub fn new(reader: R) -> Self {
         Config::default().build(reader)
     }
 
-    /// Returns the next byte.
-    /// EOF is mapped to `Ok(None)`.
-    fn next_byte(&mut self) -> Result<Option<u8>> {
-        match self.bytes.next() {
-            None => Ok(None),
-            Some(byte) => {
-                let byte = byte.map_err(|e| Error::from_io_error(e, self.current_location()))?;
-                self.last_read_byte = byte;
-                if byte == b'\n' {
-                    self.line += 1;
-                    self.column = 0;
-                } else {
-                    self.column += 1;
+    /// Returns the bytes that were skipped right before the last token returned by `read`.
+    /// After `read` returns `Ok(None)`, this returns the bytes after the last token instead.
+    /// This is always empty unless `Config::capture_noise` is set.
+    pub fn noise(&self) -> &[u8] {
+        &self.decoder.noise
+    }
+
+    /// Returns the statistics of bytes read so far.
+    pub fn stats(&self) -> Stats {
+        self.decoder.stats()
+    }
+}
+
+impl<R: ByteRead> ReadToken for Lexer<R> {
+    /// Returns a next token if exists.
+    fn read(&mut self) -> Result<Option<Token>> {
+        while let Some(byte) = self.reader.read_byte().map_err(|mut e| {
+            e.location = self.decoder.current_location();
+            e
+        })? {
+            if let Some(token) = self.decoder.feed(byte)? {
+                return Ok(Some(token));
+            }
+        }
+        self.decoder.finish();
+        Ok(None)
+    }
+}
+
+impl<'a> SliceLexer<'a> {
+    /// Returns a new `SliceLexer` with the default configuration.
+    pub fn new(slice: &'a [u8]) -> Self {
+        Config::default().build_slice(slice)
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Same as `Lexer::noise`.
+    pub fn noise(&self) -> &[u8] {
+        &self.decoder.noise
+    }
+
+    /// Same as `Lexer::stats`.
+    pub fn stats(&self) -> Stats {
+        self.decoder.stats()
+    }
+}
+
+impl ReadToken for SliceLexer<'_> {
+    /// Returns a next token if exists.
+    fn read(&mut self) -> Result<Option<Token>> {
+        while let Some(&byte) = self.slice.get(self.pos) {
+            self.pos += 1;
+            if let Some(token) = self.decoder.feed(byte)? {
+                return Ok(Some(token));
+            }
+        }
+        self.decoder.finish();
+        Ok(None)
+    }
+}
+
+fn exceeds(n: usize, limit: Option<usize>) -> bool {
+    limit.is_some_and(|limit| limit < n)
+}
+
+/// A lexer that reads from multiple sources one after another as if they were a single source.
+/// Each token has the location in the source where it comes from.
+///
+/// Each source is read by a `Lexer` configured by the `Config` given to `Config::build_chained`,
+/// so limits like `Config::max_input_bytes` are applied per source.
+pub struct ChainedLexer {
+    config: Config,
+    sources: VecDeque<Source>,
+    current: Option<Lexer<Box<dyn io::Read + Send>>>,
+    // The mode where the last source ended.
+    mode: Option<Mode>,
+}
+
+enum Source {
+    Reader(Option<Arc<path::Path>>, Box<dyn io::Read + Send>),
+    File(Arc<path::Path>),
+}
+
+impl ChainedLexer {
+    /// Returns a new `ChainedLexer` with the default configuration.
+    pub fn new() -> Self {
+        Config::default().build_chained()
+    }
+
+    /// Appends a reader to the sources. `file_path` is used only to display locations.
+    pub fn push_reader<R>(&mut self, file_path: Option<&path::Path>, reader: R)
+    where
+        R: io::Read + Send + 'static,
+    {
+        self.sources
+            .push_back(Source::Reader(file_path.map(Into::into), Box::new(reader)));
+    }
+
+    /// Appends a file to the sources. The file is opened when the lexer reaches it.
+    pub fn push_file(&mut self, path: &path::Path) {
+        self.sources.push_back(Source::File(path.into()));
+    }
+
+    /// Starts reading the next source. Returns false if there are no more sources.
+    fn next_source(&mut self) -> Result<bool> {
+        let (file_path, reader) = match self.sources.pop_front() {
+            None => return Ok(false),
+            Some(Source::Reader(file_path, reader)) => (file_path, reader),
+            Some(Source::File(path)) => {
+                let file = fs::File::open(&path).map_err(|e| {
+                    Error::from_io_error(
+                        e,
+                        Location {
+                            path: Some(Arc::clone(&path)),
+                            ..Location::unknown()
+                        },
+                    )
+                })?;
+                (Some(path), Box::new(file) as Box<dyn io::Read + Send>)
+            }
+        };
+        let mut config = self.config.clone();
+        config.file_path = file_path;
+        if let (true, Some(mode)) = (self.config.carry_mode_across_sources, self.mode) {
+            config.initial_mode = mode;
+        }
+        self.current = Some(config.build(reader));
+        Ok(true)
+    }
+}
+
+impl Default for ChainedLexer {
+    fn default() -> Self {
+        ChainedLexer::new()
+    }
+}
+
+impl ReadToken for ChainedLexer {
+    /// Returns a next token if exists.
+    fn read(&mut self) -> Result<Option<Token>> {
+        loop {
+            if let Some(lexer) = self.current.as_mut() {
+                if let Some(token) = lexer.read()? {
+                    return Ok(Some(token));
+                }
+                self.mode = Some(lexer.decoder.mode);
+                self.current = None;
+            }
+            if !self.next_source()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// `Decoder` converts bytes into tokens one by one.
+/// This is the common part of `Lexer` and `SliceLexer`.
+struct Decoder {
+    mode: Mode,
+    table: ModeTable,
+    extension_bytes: Vec<u8>,
+
+    noise: Vec<u8>,
+    capture_noise: bool,
+    // True if the last byte fed was an instruction.
+    emitted: bool,
+
+    comments: Vec<Comment>,
+    // The byte that ends the current comment, if in a comment.
+    comment_end: Option<u8>,
+
+    max_input_bytes: Option<usize>,
+    max_tokens: Option<usize>,
+    bytes_read: usize,
+    tokens_read: usize,
+    newlines: usize,
+
+    last_read_byte: u8,
+    file_path: Option<Arc<path::Path>>,
+    line: usize,
+    column: usize,
+    // How many columns the next character is ahead of the last one.
+    column_advance: usize,
+    utf8_columns: bool,
+    tab_width: usize,
+}
+
+impl Decoder {
+    /// Consumes the next byte and returns a token if the byte represents an instruction.
+    fn feed(&mut self, byte: u8) -> Result<Option<Token>> {
+        self.last_read_byte = byte;
+        self.advance_position(byte);
+        self.bytes_read += 1;
+        if exceeds(self.bytes_read, self.max_input_bytes) {
+            return Err(self.limit_exceeded());
+        }
+        if self.emitted {
+            self.noise.clear();
+            self.emitted = false;
+        }
+        let insn = if self.skip_comment(byte) {
+            self.decode(byte)
+        } else {
+            None
+        };
+        let insn = match insn {
+            Some(insn) => insn,
+            None => {
+                if self.capture_noise {
+                    self.noise.push(byte);
                 }
-                Ok(Some(byte))
+                return Ok(None);
             }
+        };
+        self.tokens_read += 1;
+        if exceeds(self.tokens_read, self.max_tokens) {
+            return Err(self.limit_exceeded());
+        }
+        self.emitted = true;
+        let mode = self.mode;
+        self.advance_state(insn);
+        Ok(Some(Token {
+            insn,
+            mode,
+            location: self.current_location(),
+        }))
+    }
+
+    fn limit_exceeded(&self) -> Error {
+        Error {
+            kind: ErrorKind::InputLimitExceeded,
+            location: self.current_location(),
+            source: None,
+        }
+    }
+
+    fn advance_position(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.newlines += 1;
+            self.line += 1;
+            self.column = 0;
+            self.column_advance = 1;
+        } else if self.utf8_columns && byte & 0xC0 == 0x80 {
+            // A continuation byte of UTF-8 belongs to the same character as the previous one.
+        } else {
+            self.column += self.column_advance;
+            self.column_advance = if byte == b'\t' {
+                // Tab stops are at columns 1, tab_width + 1, 2 * tab_width + 1, ...
+                let next_stop = ((self.column - 1) / self.tab_width + 1) * self.tab_width + 1;
+                next_stop - self.column
+            } else {
+                1
+            };
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        Stats {
+            bytes: self.bytes_read,
+            insn_bytes: self.tokens_read,
+            skipped_bytes: self.bytes_read - self.tokens_read,
+            newlines: self.newlines,
+        }
+    }
+
+    /// Tells that there are no more bytes.
+    fn finish(&mut self) {
+        if self.emitted {
+            self.noise.clear();
+            self.emitted = false;
         }
     }
 
     fn current_location(&self) -> Location {
         Location {
             byte: self.last_read_byte,
-            path: self.file_path.as_ref().map(Rc::clone),
+            path: self.file_path.as_ref().map(Arc::clone),
             line: self.line,
             column: self.column,
         }
     }
 
-    fn advance_state(&mut self, insn: Insn) {
-        // See https://github.com/genkami/watson/blob/main/doc/spec.md#watson-representation.
-        if insn == Insn::Snew {
-            self.mode = self.mode.flip();
+    /// Updates the state of comments and returns false if the byte is a part of a comment.
+    fn skip_comment(&mut self, byte: u8) -> bool {
+        if let Some(end) = self.comment_end {
+            if byte == end {
+                self.comment_end = None;
+            }
+            return false;
         }
+        self.comment_end = self.comments.iter().find_map(|c| match *c {
+            Comment::Block { open, close } if open == byte => Some(close),
+            Comment::Line(marker) if marker == byte => Some(b'\n'),
+            _ => None,
+        });
+        self.comment_end.is_none()
     }
-}
 
-impl<R: io::Read> ReadToken for Lexer<R> {
-    /// Returns a next token if exists.
-    fn read(&mut self) -> Result<Option<Token>> {
-        let token: Token;
-        loop {
-            let byte = self.next_byte()?;
-            match byte {
-                None => {
-                    return Ok(None);
-                }
-                Some(byte) => match Insn::from_byte(self.mode, byte) {
-                    None => {
-                        continue;
-                    }
-                    Some(insn) => {
-                        token = Token {
-                            insn,
-                            location: Location {
-                                byte,
-                                path: self.file_path.clone(),
-                                line: self.line,
-                                column: self.column,
-                            },
-                        };
-                        self.advance_state(token.insn);
-                        return Ok(Some(token));
-                    }
-                },
+    fn decode(&self, byte: u8) -> Option<Insn> {
+        self.table.from_byte(self.mode, byte).or_else(|| {
+            if self.extension_bytes.contains(&byte) {
+                Some(Insn::Ext(byte))
+            } else {
+                None
             }
+        })
+    }
+
+    fn advance_state(&mut self, insn: Insn) {
+        // See https://github.com/genkami/watson/blob/main/doc/spec.md#watson-representation.
+        if insn == Insn::Snew {
+            self.mode = self.mode.flip();
         }
     }
 }
@@ -154,6 +547,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: None,
@@ -167,13 +561,16 @@ mod test {
     #[test]
     fn lexer_new_initial_mode_can_be_overridden() {
         let bytes = b"Shaak".to_vec();
-        let mut conf = Config::default();
-        conf.initial_mode = Mode::S;
+        let conf = Config {
+            initial_mode: Mode::S,
+            ..Default::default()
+        };
         let mut lexer = conf.build(&bytes[..]);
         assert_eq!(
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::S,
                 location: Location {
                     byte: b'S',
                     path: None,
@@ -192,6 +589,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: None,
@@ -206,13 +604,16 @@ mod test {
     fn lexer_file_path_can_be_overridden() {
         let bytes = b"Bubba".to_vec();
         let path = path::Path::new("test.watson");
-        let mut conf = Config::default();
-        conf.file_path = Some(path.to_path_buf().into());
+        let conf = Config {
+            file_path: Some(path.to_path_buf().into()),
+            ..Default::default()
+        };
         let mut lexer = conf.build(&bytes[..]);
         assert_eq!(
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: Some(path.to_path_buf().into()),
@@ -237,6 +638,7 @@ mod test {
             lexer.read()?,
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: Some(path.to_path_buf().into()),
@@ -258,13 +660,16 @@ mod test {
         let path = tempfile.into_temp_path();
         let path_to_display = path::Path::new("anothername.watson");
 
-        let mut conf = Config::default();
-        conf.file_path = Some(path_to_display.to_path_buf().into());
+        let conf = Config {
+            file_path: Some(path_to_display.to_path_buf().into()),
+            ..Default::default()
+        };
         let mut lexer = conf.open(&path)?;
         assert_eq!(
             lexer.read()?,
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: Some(path_to_display.to_path_buf().into()),
@@ -284,6 +689,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: None,
@@ -296,6 +702,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Iinc,
+                mode: Mode::A,
                 location: Location {
                     byte: b'u',
                     path: None,
@@ -308,6 +715,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Ishl,
+                mode: Mode::A,
                 location: Location {
                     byte: b'b',
                     path: None,
@@ -322,6 +730,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Ishl,
+                mode: Mode::A,
                 location: Location {
                     byte: b'b',
                     path: None,
@@ -334,6 +743,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Iadd,
+                mode: Mode::A,
                 location: Location {
                     byte: b'a',
                     path: None,
@@ -355,6 +765,40 @@ mod test {
         assert_eq!(lexer.read().unwrap(), None);
     }
 
+    #[test]
+    fn lexer_reads_extension_bytes() {
+        let bytes = b"0B1".to_vec();
+        let mut lexer = Lexer::new(&bytes[..]);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.read().unwrap(), None);
+
+        let conf = Config {
+            extension_bytes: vec![b'0', b'B'],
+            ..Default::default()
+        };
+        let mut lexer = conf.build(&bytes[..]);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Ext(b'0'));
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.read().unwrap(), None);
+    }
+
+    #[test]
+    fn lexer_uses_custom_table() {
+        // Uses the alphabet of the S mode in the A mode and vice versa.
+        let bytes = b"Sh$Bu".to_vec();
+        let conf = Config {
+            table: ModeTable::new(|mode, insn| insn.into_byte(mode.flip())).unwrap(),
+            ..Default::default()
+        };
+        let mut lexer = conf.build(&bytes[..]);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Iinc);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Snew);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Iinc);
+        assert_eq!(lexer.read().unwrap(), None);
+    }
+
     #[test]
     fn lexer_changes_mode() {
         let bytes = b"Bu?Sh$B".to_vec();
@@ -363,6 +807,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: None,
@@ -375,6 +820,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Iinc,
+                mode: Mode::A,
                 location: Location {
                     byte: b'u',
                     path: None,
@@ -387,6 +833,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Snew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'?',
                     path: None,
@@ -401,6 +848,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::S,
                 location: Location {
                     byte: b'S',
                     path: None,
@@ -413,6 +861,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Iinc,
+                mode: Mode::S,
                 location: Location {
                     byte: b'h',
                     path: None,
@@ -425,6 +874,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Snew,
+                mode: Mode::S,
                 location: Location {
                     byte: b'$',
                     path: None,
@@ -438,6 +888,7 @@ mod test {
             lexer.read().unwrap(),
             Some(Token {
                 insn: Insn::Inew,
+                mode: Mode::A,
                 location: Location {
                     byte: b'B',
                     path: None,
@@ -447,4 +898,249 @@ mod test {
             }),
         );
     }
+
+    #[test]
+    fn slice_lexer_reads_same_tokens_as_lexer() {
+        let bytes = b"Bu?Sh\n$B  ~Bubba".to_vec();
+        let conf = || Config {
+            file_path: Some(path::Path::new("a.watson").into()),
+            ..Default::default()
+        };
+        let mut lexer = conf().build(&bytes[..]);
+        let mut slice_lexer = conf().build_slice(&bytes);
+        loop {
+            let token = slice_lexer.read().unwrap();
+            assert_eq!(token, lexer.read().unwrap());
+            if token.is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn slice_lexer_position() {
+        let mut lexer = SliceLexer::new(b"B  u ");
+        assert_eq!(lexer.position(), 0);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.position(), 1);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Iinc);
+        assert_eq!(lexer.position(), 4);
+        assert_eq!(lexer.read().unwrap(), None);
+        assert_eq!(lexer.position(), 5);
+    }
+
+    #[test]
+    fn lexer_captures_noise() {
+        let bytes = b"0, 1; Bu 23;\n?S$  ".to_vec();
+        let conf = || Config {
+            capture_noise: true,
+            ..Default::default()
+        };
+
+        let mut lexer = conf().build(&bytes[..]);
+        let mut restored = Vec::new();
+        while let Some(token) = lexer.read().unwrap() {
+            restored.extend_from_slice(lexer.noise());
+            restored.push(token.location.byte);
+        }
+        restored.extend_from_slice(lexer.noise());
+        assert_eq!(restored, bytes);
+
+        let mut lexer = conf().build_slice(&bytes);
+        lexer.read().unwrap();
+        assert_eq!(lexer.noise(), b"0, 1; ");
+        lexer.read().unwrap();
+        assert_eq!(lexer.noise(), b"");
+        lexer.read().unwrap();
+        assert_eq!(lexer.noise(), b" 23;\n");
+        lexer.read().unwrap();
+        lexer.read().unwrap();
+        assert_eq!(lexer.read().unwrap(), None);
+        assert_eq!(lexer.noise(), b"  ");
+
+        let mut lexer = Lexer::new(&bytes[..]);
+        lexer.read().unwrap();
+        assert_eq!(lexer.noise(), b"");
+    }
+
+    #[test]
+    fn lexer_skips_comments() {
+        let bytes = b"B(Bubba)u#Bu\nb(a\n".to_vec();
+        let conf = Config {
+            comments: vec![
+                Comment::Block {
+                    open: b'(',
+                    close: b')',
+                },
+                Comment::Line(b'#'),
+            ],
+            capture_noise: true,
+            ..Default::default()
+        };
+        let mut lexer = conf.build_slice(&bytes);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Inew);
+        assert_eq!(lexer.read().unwrap().unwrap().insn, Insn::Iinc);
+        assert_eq!(lexer.noise(), b"(Bubba)");
+        let token = lexer.read().unwrap().unwrap();
+        assert_eq!(token.insn, Insn::Ishl);
+        assert_eq!(token.location.line, 2);
+        assert_eq!(lexer.noise(), b"#Bu\n");
+        // An unterminated comment lasts until EOF.
+        assert_eq!(lexer.read().unwrap(), None);
+        assert_eq!(lexer.noise(), b"(a\n");
+    }
+
+    #[test]
+    fn lexer_limits_input() {
+        let bytes = b"Bu b a".to_vec();
+        let conf = |max_input_bytes, max_tokens| Config {
+            max_input_bytes,
+            max_tokens,
+            ..Default::default()
+        };
+
+        let mut lexer = conf(Some(6), Some(4)).build(&bytes[..]);
+        for _ in 0..4 {
+            assert!(lexer.read().unwrap().is_some());
+        }
+        assert_eq!(lexer.read().unwrap(), None);
+
+        let mut lexer = conf(Some(5), None).build_slice(&bytes);
+        for _ in 0..3 {
+            assert!(lexer.read().unwrap().is_some());
+        }
+        let err = lexer.read().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InputLimitExceeded);
+        assert_eq!(err.location.column, 6);
+
+        let mut lexer = conf(None, Some(3)).build_slice(&bytes);
+        for _ in 0..3 {
+            assert!(lexer.read().unwrap().is_some());
+        }
+        let err = lexer.read().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InputLimitExceeded);
+        assert_eq!(err.location.byte, b'a');
+    }
+
+    #[test]
+    fn lexer_counts_columns_per_character() {
+        let bytes = "ぬ\tB\n\t\tu".as_bytes().to_vec();
+        let columns = |conf: Config| {
+            let mut lexer = conf.build_slice(&bytes);
+            let mut columns = Vec::new();
+            while let Some(token) = lexer.read().unwrap() {
+                columns.push((token.location.line, token.location.column));
+            }
+            columns
+        };
+
+        assert_eq!(columns(Config::default()), vec![(1, 5), (2, 3)]);
+        assert_eq!(
+            columns(Config {
+                utf8_columns: true,
+                tab_width: 4,
+                ..Default::default()
+            }),
+            vec![(1, 5), (2, 9)]
+        );
+        assert_eq!(
+            columns(Config {
+                utf8_columns: true,
+                ..Default::default()
+            }),
+            vec![(1, 3), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn lexer_stats() {
+        let bytes = b"B 0\nu\n".to_vec();
+        let mut lexer = Lexer::new(&bytes[..]);
+        assert_eq!(lexer.stats(), Stats::default());
+        assert_eq!(lexer.stats().noise_ratio(), 0.0);
+
+        while lexer.read().unwrap().is_some() {}
+        let stats = lexer.stats();
+        assert_eq!(
+            stats,
+            Stats {
+                bytes: 6,
+                insn_bytes: 2,
+                skipped_bytes: 4,
+                newlines: 2,
+            }
+        );
+        assert!((stats.noise_ratio() - 4.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn chained_lexer() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("b.watson");
+        fs::write(&path, b"\nS")?;
+
+        let read_all = |conf: Config| -> Result<Vec<(Insn, Mode, Location)>> {
+            let mut lexer = conf.build_chained();
+            lexer.push_reader(Some(path::Path::new("a.watson")), &b"B?"[..]);
+            lexer.push_file(&path);
+            lexer.push_reader(None, &b"B"[..]);
+            let mut tokens = Vec::new();
+            while let Some(t) = lexer.read()? {
+                tokens.push((t.insn, t.mode, t.location));
+            }
+            Ok(tokens)
+        };
+        let loc = |path: Option<&path::Path>, byte, line, column| Location {
+            byte,
+            path: path.map(Into::into),
+            line,
+            column,
+        };
+
+        assert_eq!(
+            read_all(Config::default())?,
+            vec![
+                (
+                    Insn::Inew,
+                    Mode::A,
+                    loc(Some(path::Path::new("a.watson")), b'B', 1, 1)
+                ),
+                (
+                    Insn::Snew,
+                    Mode::A,
+                    loc(Some(path::Path::new("a.watson")), b'?', 1, 2)
+                ),
+                (Insn::Inew, Mode::S, loc(Some(&path), b'S', 2, 1)),
+            ]
+        );
+
+        let tokens = read_all(Config {
+            carry_mode_across_sources: false,
+            ..Default::default()
+        })?;
+        assert_eq!(
+            tokens.iter().map(|t| (t.0, t.1)).collect::<Vec<_>>(),
+            vec![
+                (Insn::Inew, Mode::A),
+                (Insn::Snew, Mode::A),
+                (Insn::Inew, Mode::A),
+            ]
+        );
+
+        let mut lexer = ChainedLexer::new();
+        lexer.push_file(&tempdir.path().join("missing.watson"));
+        let err = lexer.read().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::IOError);
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_with_label() {
+        let mut lexer = Config::default().with_label("<input>").build(&b"B"[..]);
+        let location = lexer.read().unwrap().unwrap().location;
+        assert_eq!(location.path, Some(path::Path::new("<input>").into()));
+        assert!(location
+            .to_string()
+            .starts_with("<input> (line: 1, column: 1)"));
+    }
 }
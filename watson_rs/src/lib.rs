@@ -1,23 +1,51 @@
+// Lets the code generated by `watson_derive` refer to this crate as `::watson_rs` in its own tests.
+#[cfg(test)]
+extern crate self as watson_rs;
+
+use std::io;
+use std::path;
 use std::str::FromStr;
 
+pub mod asm;
+pub mod byteio;
+pub mod decode;
+#[doc(hidden)]
+pub mod derive;
+pub mod diff;
+pub mod disasm;
+pub mod document;
 pub mod error;
+pub mod event;
 pub mod language;
 pub mod lexer;
+pub mod optimizer;
+pub mod pack;
+pub mod provenance;
 pub mod serializer;
+pub mod stream;
+pub mod text;
 pub mod unlexer;
+pub mod vanity;
 pub mod vm;
 
-pub use error::{Error, ErrorKind, Result};
-pub use language::{Bytes, Insn, IsValue, Location, Map, ToBytes, Token, Value};
+pub use byteio::{ByteRead, ByteWrite};
+pub use decode::DecodeOptions;
+#[cfg(feature = "derive")]
+pub use derive::{FromWatson, ToWatson};
+pub use document::Document;
+pub use error::{DuplicateKey, Error, ErrorKind, Result, TypeMismatch};
+pub use language::{
+    Bytes, Insn, IsValue, Location, Map, PathElem, ToBytes, Token, Value, ValueKind,
+};
+pub use stream::Deserializer;
 pub use vm::VM;
 
 impl FromStr for Value {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Value> {
-        let mut bytes = s.as_bytes();
         let mut vm = vm::VM::new();
-        vm.execute_all(lexer::Lexer::new(&mut bytes))?;
+        vm.execute_all(lexer::SliceLexer::new(s.as_bytes()))?;
         vm.into_top().map(Ok).unwrap_or_else(|| {
             Err(Error {
                 kind: ErrorKind::EmptyStack,
@@ -28,6 +56,42 @@ impl FromStr for Value {
     }
 }
 
+/// Decodes WATSON text into a single value.
+///
+/// Unlike `Value::from_str`, which returns the top of the stack and ignores the rest, it is strict about
+/// the structure of the document: it is an error if the text leaves no values or more than one value on the stack,
+/// or if it discards a whole value and continues (e.g. `BuGpopB`), which means that there are trailing instructions
+/// after the document is complete. Errors point at the location of the last token that was read.
+/// Use `DecodeOptions` to limit the size of the input.
+pub fn from_str(s: &str) -> Result<Value> {
+    DecodeOptions::default().from_str(s)
+}
+
+/// Decodes WATSON text read from the given reader into a single value. See `from_str` for details.
+pub fn from_reader<R: io::Read>(reader: R) -> Result<Value> {
+    DecodeOptions::default().from_reader(reader)
+}
+
+/// Decodes a WATSON file into a single value. See `from_str` for details.
+pub fn from_file(path: &path::Path) -> Result<Value> {
+    DecodeOptions::default().from_file(path)
+}
+
+/// Serializes a value to WATSON text and writes it to the given writer.
+pub fn to_writer<W: io::Write>(writer: W, v: &Value) -> Result<()> {
+    text::TextSerializer::default().to_writer(writer, v)
+}
+
+/// Serializes a value to WATSON text.
+pub fn to_vec(v: &Value) -> Result<Vec<u8>> {
+    text::TextSerializer::default().to_vec(v)
+}
+
+/// Serializes a value to WATSON text.
+pub fn to_string(v: &Value) -> Result<String> {
+    text::TextSerializer::default().to_string(v)
+}
+
 #[cfg(test)]
 mod test {
     use crate::*;
@@ -41,4 +105,57 @@ mod test {
         assert_eq!("?SShaaarrk".parse::<Value>()?, Int(8));
         Ok(())
     }
+
+    #[test]
+    fn from_str_returns_single_value() -> Result<()> {
+        assert_eq!(from_str("BBubba")?, Int(4));
+        assert_eq!(from_str("").unwrap_err().kind, ErrorKind::EmptyStack);
+        let err = from_str("B\nBu").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidDocument);
+        assert_eq!((err.location.line, err.location.column), (2, 2));
+        assert_eq!(from_reader(&b"Bu"[..])?, Int(1));
+
+        // `Value::from_str` ignores the values under the top, but `from_str` doesn't.
+        assert_eq!("BBu".parse::<Value>()?, Int(1));
+        assert_eq!(
+            from_str("BBu").unwrap_err().kind,
+            ErrorKind::InvalidDocument
+        );
+        let err = from_str("Bu#B").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidDocument);
+        assert_eq!(err.location.byte, b'#');
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_drops_deeply_nested_value() -> Result<()> {
+        // `Anew` followed by `Anew Gswp Aadd`s (`@` and `@%s` in the A mode).
+        let text = format!("@{}", "@%s".repeat(1_000_000));
+        let value = from_str(&text)?;
+        assert!(matches!(value, Array(ref arr) if arr.len() == 1));
+        drop(value);
+        Ok(())
+    }
+
+    #[test]
+    fn from_file_reads_a_file() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("data.watson");
+        std::fs::write(&path, "Bu")?;
+        assert_eq!(from_file(&path)?, Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn to_string_round_trip() -> Result<()> {
+        let value = object![a: array![Int(1), Nil], b: String(b"x".to_vec())];
+        assert_eq!(to_string(&Int(4))?, "Bubb");
+        assert_eq!(to_string(&value)?.parse::<Value>()?, value);
+        assert_eq!(to_vec(&value)?, to_string(&value)?.into_bytes());
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &value)?;
+        assert_eq!(std::str::from_utf8(&buf).unwrap().parse::<Value>()?, value);
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+//! Peephole optimization of instruction sequences.
+
+use crate::language::Insn;
+use Insn::*;
+
+/// Rewriting rules. Each pattern is replaced with the corresponding replacement.
+const RULES: &[(&[Insn], &[Insn])] = &[
+    // Values that are popped immediately.
+    (&[Gdup, Gpop], &[]),
+    (&[Inew, Gpop], &[]),
+    (&[Finf, Gpop], &[]),
+    (&[Fnan, Gpop], &[]),
+    (&[Snew, Gpop], &[]),
+    (&[Onew, Gpop], &[]),
+    (&[Anew, Gpop], &[]),
+    (&[Bnew, Gpop], &[]),
+    (&[Nnew, Gpop], &[]),
+    // Operations that cancel each other.
+    (&[Gswp, Gswp], &[]),
+    (&[Ineg, Ineg], &[]),
+    (&[Fneg, Fneg], &[]),
+    (&[Bneg, Bneg], &[]),
+    // Arithmetic on zero.
+    (&[Inew, Iadd], &[]),
+    (&[Inew, Gswp, Iadd], &[]),
+    (&[Inew, Isht], &[]),
+    (&[Inew, Ishl], &[Inew]),
+    (&[Inew, Ineg], &[Inew]),
+];
+
+/// Rewrites a sequence of instructions into a shorter one that leaves the same values on the stack.
+///
+/// The result is equivalent to the original only if the original sequence runs without errors;
+/// e.g. `Gdup Gpop` is removed even though it fails on an empty stack.
+pub fn optimize(insns: &[Insn]) -> Vec<Insn> {
+    let mut out: Vec<Insn> = Vec::with_capacity(insns.len());
+    for &insn in insns {
+        out.push(insn);
+        // Replacing a suffix may create another pattern at the end, so repeats until nothing matches.
+        while let Some((pattern, replacement)) = RULES.iter().find(|(p, _)| out.ends_with(p)) {
+            out.truncate(out.len() - pattern.len());
+            out.extend_from_slice(replacement);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::language::Value;
+    use crate::serializer::Serializer;
+    use crate::vm::VM;
+    use crate::{array, object};
+
+    #[test]
+    fn optimize_removes_dead_values() {
+        assert_eq!(optimize(&[Inew, Gdup, Gpop]), vec![Inew]);
+        assert_eq!(optimize(&[Inew, Inew, Nnew, Gpop, Gpop]), vec![Inew]);
+        assert_eq!(
+            optimize(&[Inew, Anew, Inew, Gswp, Gswp, Gpop]),
+            vec![Inew, Anew]
+        );
+    }
+
+    #[test]
+    fn optimize_simplifies_arithmetic() {
+        assert_eq!(
+            optimize(&[Inew, Iinc, Inew, Ishl, Ishl, Iadd]),
+            vec![Inew, Iinc]
+        );
+        assert_eq!(
+            optimize(&[Inew, Iinc, Ineg, Ineg, Inew, Isht]),
+            vec![Inew, Iinc]
+        );
+        assert_eq!(optimize(&[Inew, Iinc, Inew, Gswp, Iadd]), vec![Inew, Iinc]);
+        assert_eq!(optimize(&[Bnew, Bneg, Bneg]), vec![Bnew]);
+    }
+
+    #[test]
+    fn optimize_preserves_serialized_values() {
+        let values = [
+            Value::Int(-1234567890),
+            Value::Float(-0.5),
+            object![a: array![Value::Nil, Value::Bool(true)], b: Value::String(b"b".to_vec())],
+        ];
+        for v in values {
+            let mut insns = Vec::new();
+            Serializer::new(&mut insns).serialize(&v).unwrap();
+            assert_equivalent(&insns);
+        }
+    }
+
+    #[test]
+    fn optimize_preserves_random_programs() {
+        // `Fnan` is excluded because NaN is not equal to itself.
+        let alphabet: Vec<Insn> = Insn::all().filter(|&i| i != Fnan).collect();
+        let mut seed: u64 = 1;
+        for _ in 0..1000 {
+            let insns: Vec<Insn> = (0..16)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    alphabet[(seed >> 33) as usize % alphabet.len()]
+                })
+                .collect();
+            assert_equivalent(&insns);
+        }
+    }
+
+    /*
+     * Helper functions
+     */
+
+    /// Asserts that the optimized program leaves the same values as the original one if the original one succeeds.
+    fn assert_equivalent(insns: &[Insn]) {
+        let run = |insns: &[Insn]| {
+            let mut vm = VM::new();
+            vm.execute_all_insns(insns.iter().copied())
+                .ok()
+                .map(|_| vm.take_values())
+        };
+        if let Some(expected) = run(insns) {
+            let optimized = optimize(insns);
+            assert_eq!(
+                run(&optimized),
+                Some(expected),
+                "{insns:?} => {optimized:?}"
+            );
+        }
+    }
+}
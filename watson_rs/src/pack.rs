@@ -0,0 +1,152 @@
+//! A compact binary encoding of instruction sequences.
+//!
+//! The encoding starts with a header byte, whose lowest bit is the initial mode (`0` for `A`, `1` for `S`)
+//! and the other bits are the version of the encoding (currently zero). Each instruction defined in
+//! the specification follows as a 5-bit code from the most significant bit. `Insn::Ext` is encoded as
+//! `EXT_CODE` followed by its byte. The sequence ends with `END_CODE` and is padded with zeros.
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Insn, Location, Mode, NUM_INSNS};
+
+const CODE_BITS: u32 = 5;
+const EXT_CODE: u8 = 30;
+const END_CODE: u8 = 31;
+
+const _: () = assert!(NUM_INSNS <= EXT_CODE as usize);
+
+/// Encodes a sequence of instructions that is to be read in `initial_mode`.
+pub fn pack(initial_mode: Mode, insns: &[Insn]) -> Vec<u8> {
+    let header = match initial_mode {
+        Mode::A => 0,
+        Mode::S => 1,
+    };
+    let mut w = BitWriter {
+        bytes: vec![header],
+        acc: 0,
+        len: 0,
+    };
+    for &insn in insns {
+        match insn.index() {
+            Some(i) => w.push(i as u32, CODE_BITS),
+            None => {
+                w.push(EXT_CODE as u32, CODE_BITS);
+                w.push(insn.into_byte(initial_mode) as u32, 8);
+            }
+        }
+    }
+    w.push(END_CODE as u32, CODE_BITS);
+    w.finish()
+}
+
+/// Decodes bytes encoded by `pack` and returns the initial mode and the instructions.
+pub fn unpack(bytes: &[u8]) -> Result<(Mode, Vec<Insn>)> {
+    let (&header, body) = bytes.split_first().ok_or_else(invalid)?;
+    let initial_mode = match header {
+        0 => Mode::A,
+        1 => Mode::S,
+        _ => return Err(invalid()),
+    };
+    let all: Vec<Insn> = Insn::all().collect();
+    let mut r = BitReader {
+        bytes: body,
+        pos: 0,
+    };
+    let mut insns = Vec::new();
+    loop {
+        let code = r.pull(CODE_BITS).ok_or_else(invalid)? as u8;
+        match code {
+            END_CODE => break,
+            EXT_CODE => insns.push(Insn::Ext(r.pull(8).ok_or_else(invalid)? as u8)),
+            _ => insns.push(*all.get(code as usize).ok_or_else(invalid)?),
+        }
+    }
+    // Only the padding can follow the end.
+    if body.len() != r.pos.div_ceil(8) {
+        return Err(invalid());
+    }
+    Ok((initial_mode, insns))
+}
+
+fn invalid() -> Error {
+    Error {
+        kind: ErrorKind::InvalidBinary,
+        location: Location::unknown(),
+        source: None,
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: u32,
+}
+
+impl BitWriter {
+    fn push(&mut self, bits: u32, n: u32) {
+        self.acc = self.acc << n | bits;
+        self.len += n;
+        while 8 <= self.len {
+            self.len -= 8;
+            self.bytes.push((self.acc >> self.len) as u8);
+        }
+        self.acc &= (1 << self.len) - 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if 0 < self.len {
+            self.bytes.push((self.acc << (8 - self.len)) as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    // Position in bits.
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn pull(&mut self, n: u32) -> Option<u32> {
+        let mut bits = 0;
+        for _ in 0..n {
+            let byte = *self.bytes.get(self.pos / 8)?;
+            bits = bits << 1 | (byte >> (7 - self.pos % 8) & 1) as u32;
+            self.pos += 1;
+        }
+        Some(bits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Insn::*;
+
+    #[test]
+    fn pack_round_trip() -> Result<()> {
+        let insns = [Inew, Iinc, Snew, Ext(b'x'), Gswp, Sadd, Nnew];
+        let packed = pack(Mode::S, &insns);
+        // 1 byte of header + 7 codes, 1 byte of `Ext` and the end code.
+        assert_eq!(packed.len(), 1 + (8 * 5 + 8_usize).div_ceil(8));
+        assert_eq!(unpack(&packed)?, (Mode::S, insns.to_vec()));
+
+        assert_eq!(unpack(&pack(Mode::A, &[]))?, (Mode::A, vec![]));
+        let all: Vec<Insn> = Insn::all().collect();
+        assert_eq!(unpack(&pack(Mode::A, &all))?, (Mode::A, all));
+        Ok(())
+    }
+
+    #[test]
+    fn unpack_rejects_malformed_input() {
+        let packed = pack(Mode::A, &[Inew, Iinc]);
+        for bytes in [
+            &[][..],
+            &[2, 0][..],
+            &packed[..packed.len() - 1],
+            &[packed.clone(), vec![0]].concat()[..],
+        ] {
+            assert_eq!(unpack(bytes).unwrap_err().kind, ErrorKind::InvalidBinary);
+        }
+    }
+}
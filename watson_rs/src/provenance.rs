@@ -0,0 +1,273 @@
+//! Source locations of values built by the VM.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+
+use crate::language::{Bytes, Insn, Location, PathElem};
+
+/// The instructions that built a value.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Span {
+    /// The location of the first instruction.
+    pub start: Location,
+
+    /// The location of the last instruction.
+    pub end: Location,
+}
+
+/// `Provenance` maps paths of values nested in a value to the `Span`s of the instructions that built them.
+/// The empty path represents the value itself.
+#[derive(Eq, PartialEq, Clone, Debug, Default)]
+pub struct Provenance {
+    spans: HashMap<Vec<PathElem>, Span>,
+}
+
+impl Provenance {
+    /// Returns the span of the value that the path points at, or `None` if it is unknown.
+    pub fn get(&self, path: &[PathElem]) -> Option<&Span> {
+        self.spans.get(path)
+    }
+
+    /// Returns an iterator over all known paths and their spans in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[PathElem], &Span)> {
+        self.spans
+            .iter()
+            .map(|(path, span)| (path.as_slice(), span))
+    }
+}
+
+/// `Tracker` mirrors the stack of a `VM` and records which instructions built each value.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    nodes: Vec<Arc<Node>>,
+    executed: u64,
+}
+
+#[derive(Default)]
+struct Node {
+    // The sequence number of the first instruction and the span, or `None` if unknown.
+    span: Option<(u64, Span)>,
+    children: Children,
+}
+
+#[derive(Clone, Default)]
+enum Children {
+    #[default]
+    None,
+    Array(Vec<Arc<Node>>),
+    Object(HashMap<Bytes, Arc<Node>>),
+}
+
+impl Tracker {
+    /// Records an instruction that was executed successfully.
+    /// `key` is the key of the entry if the instruction is `Oadd`, and `depth` is the depth of the stack after it.
+    pub(crate) fn record(
+        &mut self,
+        insn: Insn,
+        location: &Location,
+        key: Option<Bytes>,
+        depth: usize,
+    ) {
+        use Insn::*;
+
+        let seq = self.executed;
+        self.executed += 1;
+        let nodes = &mut self.nodes;
+        let new_node = |operands: &[&Node], children| {
+            let start = operands
+                .iter()
+                .filter_map(|op| op.span.as_ref())
+                .min_by_key(|(seq, _)| *seq)
+                .map_or((seq, location), |(seq, span)| (*seq, &span.start));
+            let span = Span {
+                start: start.1.clone(),
+                end: location.clone(),
+            };
+            Arc::new(Node {
+                span: Some((start.0, span)),
+                children,
+            })
+        };
+        match insn {
+            Inew | Finf | Fnan | Snew | Bnew | Nnew => nodes.push(new_node(&[], Children::None)),
+            Anew => nodes.push(new_node(&[], Children::Array(Vec::new()))),
+            Onew => nodes.push(new_node(&[], Children::Object(HashMap::new()))),
+            Iinc | Ishl | Ineg | Itof | Itou | Fneg | Bneg => {
+                if let Some(x) = nodes.pop() {
+                    nodes.push(new_node(&[&x], Children::None));
+                }
+            }
+            Iadd | Isht | Sadd => {
+                if let (Some(y), Some(x)) = (nodes.pop(), nodes.pop()) {
+                    nodes.push(new_node(&[&x, &y], Children::None));
+                }
+            }
+            Aadd => {
+                if let (Some(v), Some(mut a)) = (nodes.pop(), nodes.pop()) {
+                    let mut children = detach_children(&mut a);
+                    if let Children::Array(elems) = &mut children {
+                        elems.push(Arc::clone(&v));
+                    }
+                    nodes.push(new_node(&[&a, &v], children));
+                }
+            }
+            Oadd => {
+                if let (Some(v), Some(k), Some(mut o)) = (nodes.pop(), nodes.pop(), nodes.pop()) {
+                    let mut children = detach_children(&mut o);
+                    if let (Children::Object(entries), Some(key)) = (&mut children, key) {
+                        entries.insert(key, Arc::clone(&v));
+                    }
+                    nodes.push(new_node(&[&o, &k, &v], children));
+                }
+            }
+            Gdup => {
+                if let Some(x) = nodes.last() {
+                    nodes.push(Arc::clone(x));
+                }
+            }
+            Gpop => {
+                nodes.pop();
+            }
+            Gswp => {
+                let n = nodes.len();
+                if 2 <= n {
+                    nodes.swap(n - 1, n - 2);
+                }
+            }
+            // The effect of extension instructions is unknown, so it is assumed that they only manipulate
+            // the top of the stack.
+            Ext(_) => {}
+        }
+        self.sync(depth);
+    }
+
+    /// Adjusts the number of values to the depth of the stack, regarding values that are not tracked as unknown.
+    pub(crate) fn sync(&mut self, depth: usize) {
+        self.nodes.truncate(depth);
+        self.nodes.resize_with(depth, Default::default);
+    }
+
+    /// Returns the provenance of the value on the top of the stack.
+    pub(crate) fn provenance(&self) -> Option<Provenance> {
+        let mut spans = HashMap::new();
+        let mut pending = vec![(Vec::new(), self.nodes.last()?)];
+        while let Some((path, node)) = pending.pop() {
+            match &node.children {
+                Children::None => {}
+                Children::Array(elems) => {
+                    for (i, elem) in elems.iter().enumerate() {
+                        pending.push((child_path(&path, PathElem::Index(i)), elem));
+                    }
+                }
+                Children::Object(entries) => {
+                    for (k, v) in entries {
+                        pending.push((child_path(&path, PathElem::Key(k.clone())), v));
+                    }
+                }
+            }
+            if let Some((_, span)) = &node.span {
+                spans.insert(path, span.clone());
+            }
+        }
+        Some(Provenance { spans })
+    }
+}
+
+/// Takes the children out of the node, or copies them if the node is shared by `Gdup`.
+fn detach_children(node: &mut Arc<Node>) -> Children {
+    match Arc::get_mut(node) {
+        Some(node) => mem::take(&mut node.children),
+        None => node.children.clone(),
+    }
+}
+
+fn child_path(path: &[PathElem], elem: PathElem) -> Vec<PathElem> {
+    let mut path = path.to_vec();
+    path.push(elem);
+    path
+}
+
+impl Drop for Node {
+    // Drops nested nodes without recursion, as `Value` does.
+    fn drop(&mut self) {
+        let mut pending = take_children(&mut self.children);
+        while let Some(node) = pending.pop() {
+            if let Ok(mut node) = Arc::try_unwrap(node) {
+                pending.extend(take_children(&mut node.children));
+            }
+        }
+    }
+}
+
+fn take_children(children: &mut Children) -> Vec<Arc<Node>> {
+    match mem::take(children) {
+        Children::None => Vec::new(),
+        Children::Array(elems) => elems,
+        Children::Object(entries) => entries.into_values().collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::SliceLexer;
+    use crate::vm::{self, VM};
+    use crate::{array, object, to_string, Result, Value};
+
+    #[test]
+    fn provenance_of_array() -> Result<()> {
+        let mut vm = new_vm();
+        vm.execute_all(SliceLexer::new(b"@\nBus\nBBuas\n"))?;
+        assert_eq!(vm.peek_top(), Some(&array![Value::Int(1), Value::Int(1)]));
+        let p = vm.provenance().unwrap();
+        assert_eq!(position(p.get(&[]).unwrap()), ((1, 1), (3, 5)));
+        assert_eq!(
+            position(p.get(&[PathElem::Index(0)]).unwrap()),
+            ((2, 1), (2, 2))
+        );
+        assert_eq!(
+            position(p.get(&[PathElem::Index(1)]).unwrap()),
+            ((3, 1), (3, 4))
+        );
+        assert_eq!(p.iter().count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn provenance_of_object() -> Result<()> {
+        let text = to_string(&object![a: Value::Nil])?;
+        let mut vm = new_vm();
+        vm.execute_all(SliceLexer::new(text.as_bytes()))?;
+        let p = vm.provenance().unwrap();
+        // The key flips the mode to S, so the value and `Oadd` are read in mode S.
+        assert_eq!(p.get(&[]).unwrap().end.byte, b'g');
+        let span = p.get(&[PathElem::Key(b"a".to_vec())]).unwrap();
+        assert_eq!((span.start.byte, span.end.byte), (b'y', b'y'));
+        assert_eq!(p.get(&[PathElem::Key(b"b".to_vec())]), None);
+
+        vm.take_values();
+        assert_eq!(vm.provenance(), None);
+        assert_eq!(VM::new().provenance(), None);
+        Ok(())
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn new_vm() -> VM {
+        vm::Config {
+            record_provenance: true,
+            ..Default::default()
+        }
+        .build()
+    }
+
+    fn position(span: &Span) -> ((usize, usize), (usize, usize)) {
+        (
+            (span.start.line, span.start.column),
+            (span.end.line, span.end.column),
+        )
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::Result;
-use crate::language::{Bytes, Insn, Map, Value};
+use crate::language::{Insn, Map, Value};
 use Insn::*;
 use Value::*;
 
@@ -15,27 +15,100 @@ pub trait WriteInsn {
         }
         Ok(())
     }
+
+    /// Marks a logical boundary of the output, such as the end of an array element or an object entry.
+    /// Writers may use it to format their output. It does nothing by default.
+    fn boundary(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns true if `boundary` does something, so that writers that buffer instructions must keep track of
+    /// boundaries. It returns false by default; writers that override `boundary` should override it too.
+    fn uses_boundaries(&self) -> bool {
+        false
+    }
 }
 
-impl<'a> WriteInsn for &'a mut Vec<Insn> {
+impl WriteInsn for &mut Vec<Insn> {
     fn write(&mut self, insn: Insn) -> Result<()> {
         self.push(insn);
         Ok(())
     }
+
+    fn write_all(&mut self, insns: &[Insn]) -> Result<()> {
+        self.extend_from_slice(insns);
+        Ok(())
+    }
 }
 
 /// Serializer converts `Value` into a sequence of `Insn`s.
 pub struct Serializer<W> {
     writer: W,
+    // Instructions that are not written to `writer` yet.
+    // They are written when the buffer is full, or when a public method returns with no containers open.
+    buf: Vec<Insn>,
+    // Positions in `buf` at which `writer.boundary()` is called.
+    boundaries: Vec<usize>,
+    // The number of containers started by `begin_array` or `begin_object` that are not ended yet.
+    depth: usize,
+    sort_keys: bool,
+    shortest_ints: bool,
+    compact_strings: bool,
+}
+
+/// Config configures a `Serializer`.
+#[derive(Clone)]
+pub struct Config {
+    /// If set to true, a `Serializer` emits entries of objects in the bytewise order of their keys,
+    /// so that its output doesn't depend on the internal state of `Map` (defaults to false).
+    pub sort_keys: bool,
+
+    /// If set to true, a `Serializer` chooses the shortest one among equivalent forms to build numbers,
+    /// e.g. negating a positive number instead of building the two's complement representation directly
+    /// (defaults to true).
+    /// If set to false, numbers are always built from their bit patterns, one instruction per bit after the first one.
+    /// Note that neither form is the same as the encoding of the first release of this crate,
+    /// which built each set bit separately and added it.
+    pub shortest_ints: bool,
+
+    /// If set to true, a `Serializer` pushes each byte of strings in a shorter form if possible,
+    /// e.g. `0xff` as -1 (defaults to false).
+    pub compact_strings: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            sort_keys: false,
+            shortest_ints: true,
+            compact_strings: false,
+        }
+    }
+}
+
+impl Config {
+    /// Returns a new `Serializer` that writes to the given writer.
+    pub fn build<W>(self, writer: W) -> Serializer<W> {
+        Serializer {
+            writer,
+            buf: Vec::new(),
+            boundaries: Vec::new(),
+            depth: 0,
+            sort_keys: self.sort_keys,
+            shortest_ints: self.shortest_ints,
+            compact_strings: self.compact_strings,
+        }
+    }
 }
 
 impl<W> Serializer<W> {
-    /// Returns a new `Serializer`.
+    /// Returns a new `Serializer` with the default configuration.
     pub fn new(writer: W) -> Self {
-        Serializer { writer }
+        Config::default().build(writer)
     }
 
     /// Unwraps the inner value from this `Serializer`.
+    /// Instructions of a container that is not ended yet are discarded.
     pub fn into_inner(self) -> W {
         self.writer
     }
@@ -44,104 +117,380 @@ impl<W> Serializer<W> {
 /// Serialize itself can be used as a `WriteInsn`.
 impl<W: WriteInsn> WriteInsn for Serializer<W> {
     fn write(&mut self, insn: Insn) -> Result<()> {
-        self.writer.write(insn)
+        self.emit(insn)?;
+        self.flush_at_top()
+    }
+
+    fn write_all(&mut self, insns: &[Insn]) -> Result<()> {
+        self.emit_all(insns)?;
+        self.flush_at_top()
+    }
+
+    fn boundary(&mut self) -> Result<()> {
+        self.mark_boundary();
+        self.flush_at_top()
+    }
+
+    fn uses_boundaries(&self) -> bool {
+        self.writer.uses_boundaries()
     }
 }
 
 impl<W: WriteInsn> Serializer<W> {
     /// Serializes a single `Value`.
     pub fn serialize(&mut self, v: &Value) -> Result<()> {
-        match *v {
-            Int(n) => self.serialize_int(n),
-            Uint(n) => self.serialize_uint(n),
-            Float(f) => self.serialize_float(f),
-            String(ref s) => self.serialize_string(s),
-            Object(ref map) => self.serialize_object(map),
-            Array(ref arr) => self.serialize_array(arr),
-            Bool(b) => self.serialize_bool(b),
-            Nil => self.serialize_nil(),
+        // Uses an explicit stack so that deeply nested values do not overflow the call stack.
+        let mut work = vec![Work::Value(v)];
+        while let Some(w) = work.pop() {
+            match w {
+                Work::Value(v) => match *v {
+                    Int(n) => self.serialize_int(n)?,
+                    Uint(n) => self.serialize_uint(n)?,
+                    Float(f) => self.serialize_float(f)?,
+                    String(ref s) => self.serialize_string(s)?,
+                    Object(ref map) => self.begin_object_entries(map, &mut work)?,
+                    Array(ref arr) => {
+                        self.emit(Anew)?;
+                        for v in arr.iter().rev() {
+                            work.push(Work::ElementDone);
+                            work.push(Work::Value(v));
+                        }
+                    }
+                    Bool(b) => self.serialize_bool(b)?,
+                    Nil => self.serialize_nil()?,
+                },
+                Work::Key(k) => self.serialize_string(k)?,
+                Work::ElementDone => {
+                    self.emit(Aadd)?;
+                    self.mark_boundary();
+                }
+                Work::ValueDone => {
+                    self.emit(Oadd)?;
+                    self.mark_boundary();
+                }
+            }
         }
+        self.flush_at_top()
+    }
+
+    /// Serializes a string without building a `Value`.
+    pub fn string(&mut self, s: &[u8]) -> Result<()> {
+        self.serialize_string(s)?;
+        self.flush_at_top()
+    }
+
+    /// Starts an array.
+    ///
+    /// This and the following methods serialize a container incrementally, so that values that don't exist as a
+    /// `Value` can be serialized. Each element should be written by either `serialize` or these methods, followed by
+    /// `element_done` (for arrays) or `value_done` (for objects). The caller is responsible for calling them in
+    /// a correct order.
+    ///
+    /// Instructions are buffered until the outermost container is ended, or until the buffer is full.
+    pub fn begin_array(&mut self) -> Result<()> {
+        self.depth += 1;
+        self.emit(Anew)
+    }
+
+    /// Appends the value that was written last to the array.
+    pub fn element_done(&mut self) -> Result<()> {
+        self.write(Aadd)?;
+        self.boundary()
+    }
+
+    /// Ends an array.
+    pub fn end_array(&mut self) -> Result<()> {
+        // The array is already on the top of the stack.
+        self.depth = self.depth.saturating_sub(1);
+        self.flush_at_top()
+    }
+
+    /// Starts an object.
+    pub fn begin_object(&mut self) -> Result<()> {
+        self.depth += 1;
+        self.emit(Onew)
+    }
+
+    /// Writes a key of an object. Its value should be written next.
+    pub fn key(&mut self, k: &[u8]) -> Result<()> {
+        self.serialize_string(k)?;
+        self.flush_at_top()
+    }
+
+    /// Adds the key and the value that were written last to the object.
+    pub fn value_done(&mut self) -> Result<()> {
+        self.write(Oadd)?;
+        self.boundary()
+    }
+
+    /// Ends an object.
+    pub fn end_object(&mut self) -> Result<()> {
+        self.depth = self.depth.saturating_sub(1);
+        self.flush_at_top()
+    }
+
+    /// Writes all buffered instructions to the underlying writer, even if a container is not ended yet.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_buf()
+    }
+
+    /// Appends an instruction to the internal buffer.
+    fn emit(&mut self, insn: Insn) -> Result<()> {
+        self.buf.push(insn);
+        if BUFFER_LEN <= self.buf.len() {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+
+    fn emit_all(&mut self, insns: &[Insn]) -> Result<()> {
+        for &insn in insns {
+            self.emit(insn)?;
+        }
+        Ok(())
+    }
+
+    /// Records a boundary at the end of the buffer if the underlying writer uses it.
+    fn mark_boundary(&mut self) {
+        if self.writer.uses_boundaries() {
+            self.boundaries.push(self.buf.len());
+        }
+    }
+
+    /// Writes all buffered instructions unless a container started by `begin_array` or `begin_object` is open.
+    fn flush_at_top(&mut self) -> Result<()> {
+        if self.depth == 0 {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all buffered instructions and boundaries to the underlying writer.
+    fn flush_buf(&mut self) -> Result<()> {
+        let mut start = 0;
+        for &end in &self.boundaries {
+            if start < end {
+                self.writer.write_all(&self.buf[start..end])?;
+            }
+            self.writer.boundary()?;
+            start = end;
+        }
+        if start < self.buf.len() {
+            self.writer.write_all(&self.buf[start..])?;
+        }
+        self.buf.clear();
+        self.boundaries.clear();
+        Ok(())
     }
 
     fn serialize_int(&mut self, n: i64) -> Result<()> {
-        let mut n = n as u64;
-        self.write(Inew)?;
-        let mut shift: usize = 0;
-        while n != 0 {
-            if n % 2 == 1 {
-                self.write_all(&[Inew, Iinc])?;
-                for _ in 1..=shift {
-                    self.write(Ishl)?;
-                }
-                self.write(Iadd)?;
+        if !self.shortest_ints {
+            return self.write_bits(n as u64);
+        }
+        // Negating a number is sometimes shorter than building its two's complement representation directly,
+        // e.g. -1 is `Inew Iinc Ineg` instead of 64 bits of ones.
+        let direct = n as u64;
+        let negated = n.wrapping_neg() as u64;
+        if pattern_len(negated) + 1 < pattern_len(direct) {
+            self.write_pattern(negated)?;
+            self.emit(Ineg)
+        } else {
+            self.write_pattern(direct)
+        }
+    }
+
+    /// Returns the number of instructions that `serialize_int` emits.
+    fn int_len(&self, n: i64) -> u32 {
+        if !self.shortest_ints {
+            return bits_len(n as u64);
+        }
+        pattern_len(n as u64).min(pattern_len(n.wrapping_neg() as u64) + 1)
+    }
+
+    /// Pushes an integer with the given bit pattern in the shortest form that `pattern_len` considers.
+    fn write_pattern(&mut self, n: u64) -> Result<()> {
+        // Trailing zeros can be made by a single `Isht` instead of `Ishl`s,
+        // e.g. the bit pattern of 1.0 is 0x3ff << 52.
+        if shifted_len(n) < bits_len(n) {
+            let t = n.trailing_zeros();
+            self.write_bits(n >> t)?;
+            self.write_bits(t as u64)?;
+            self.emit(Isht)
+        } else {
+            self.write_bits(n)
+        }
+    }
+
+    /// Pushes an integer with the given bit pattern from the most significant bit.
+    fn write_bits(&mut self, n: u64) -> Result<()> {
+        self.emit(Inew)?;
+        if n == 0 {
+            return Ok(());
+        }
+        self.emit(Iinc)?;
+        let width = u64::BITS - n.leading_zeros();
+        for i in (0..width - 1).rev() {
+            // The lowest bit is always 0 after `Ishl`, so `Iinc` sets it.
+            self.emit(Ishl)?;
+            if n >> i & 1 == 1 {
+                self.emit(Iinc)?;
             }
-            n >>= 1;
-            shift += 1;
         }
         Ok(())
     }
 
     fn serialize_uint(&mut self, n: u64) -> Result<()> {
         self.serialize_int(n as i64)?;
-        self.write(Itou)
+        self.emit(Itou)
     }
 
     fn serialize_float(&mut self, f: f64) -> Result<()> {
         if f.is_nan() {
-            self.write(Fnan)
+            self.emit(Fnan)
         } else if f.is_infinite() {
-            self.write(Finf)?;
+            self.emit(Finf)?;
             if f.is_sign_negative() {
-                self.write(Fneg)?;
+                self.emit(Fneg)?;
             }
             Ok(())
+        } else if self.shortest_ints
+            && f.is_sign_negative()
+            && self.int_len((-f).to_bits() as i64) + 1 < self.int_len(f.to_bits() as i64)
+        {
+            // The sign bit makes the bit pattern of a negative number long.
+            self.serialize_int((-f).to_bits() as i64)?;
+            self.emit_all(&[Itof, Fneg])
         } else {
             self.serialize_int(f.to_bits() as i64)?;
-            self.write(Itof)
+            self.emit(Itof)
         }
     }
 
-    fn serialize_string(&mut self, s: &Bytes) -> Result<()> {
-        self.write(Snew)?;
-        for c in s {
-            self.serialize_int(*c as i64)?;
-            self.write(Sadd)?;
+    fn serialize_string(&mut self, s: &[u8]) -> Result<()> {
+        self.emit(Snew)?;
+        let table = if self.compact_strings {
+            &COMPACT_BYTE_INSNS
+        } else {
+            &BYTE_INSNS
+        };
+        for &c in s {
+            let (insns, len) = &table[c as usize];
+            self.emit_all(&insns[..*len])?;
+            self.emit(Sadd)?;
         }
         Ok(())
     }
 
-    fn serialize_object(&mut self, map: &Map) -> Result<()> {
-        self.write(Onew)?;
-        for (k, v) in map {
-            self.serialize_string(k)?;
-            self.serialize(v)?;
-            self.write(Oadd)?;
+    /// Starts an object and schedules its entries.
+    fn begin_object_entries<'a>(&mut self, map: &'a Map, work: &mut Vec<Work<'a>>) -> Result<()> {
+        self.emit(Onew)?;
+        let mut entries: Vec<_> = map.iter().collect();
+        if self.sort_keys {
+            entries.sort_unstable_by_key(|(k, _)| *k);
         }
-        Ok(())
-    }
-
-    fn serialize_array(&mut self, arr: &Vec<Value>) -> Result<()> {
-        self.write(Anew)?;
-        for i in arr {
-            self.serialize(i)?;
-            self.write(Aadd)?;
+        for (k, v) in entries.into_iter().rev() {
+            work.push(Work::ValueDone);
+            work.push(Work::Value(v));
+            work.push(Work::Key(k));
         }
         Ok(())
     }
 
     fn serialize_bool(&mut self, b: bool) -> Result<()> {
-        self.write(Bnew)?;
+        self.emit(Bnew)?;
         if b {
-            self.write(Bneg)?;
+            self.emit(Bneg)?;
         }
         Ok(())
     }
 
     fn serialize_nil(&mut self) -> Result<()> {
-        self.write(Nnew)
+        self.emit(Nnew)
     }
 }
 
+/// The number of instructions that a `Serializer` buffers before writing them.
+const BUFFER_LEN: usize = 256;
+
+/// A pending step of `Serializer::serialize`.
+enum Work<'a> {
+    Value(&'a Value),
+    Key(&'a [u8]),
+    ElementDone,
+    ValueDone,
+}
+
+/// The maximum number of instructions to push a byte (`0xff`).
+const MAX_BYTE_INSNS: usize = 2 + 7 + 7;
+
+/// Instructions that `Serializer::serialize_int` emits for each byte, and their lengths.
+static BYTE_INSNS: [([Insn; MAX_BYTE_INSNS], usize); 256] = byte_insns_table(false);
+
+/// Same as `BYTE_INSNS`, but uses the fact that `Sadd` only takes the lowest 8 bits,
+/// e.g. `0xff` is pushed as -1.
+static COMPACT_BYTE_INSNS: [([Insn; MAX_BYTE_INSNS], usize); 256] = byte_insns_table(true);
+
+const fn byte_insns_table(compact: bool) -> [([Insn; MAX_BYTE_INSNS], usize); 256] {
+    let mut table = [([Inew; MAX_BYTE_INSNS], 0); 256];
+    let mut b = 0;
+    while b < 256 {
+        table[b] = byte_insns(b);
+        if compact && b != 0 {
+            let (mut insns, len) = byte_insns(256 - b);
+            if len + 1 < table[b].1 {
+                insns[len] = Ineg;
+                table[b] = (insns, len + 1);
+            }
+        }
+        b += 1;
+    }
+    table
+}
+
+/// Same as `Serializer::write_bits`, for numbers up to 0xff.
+const fn byte_insns(b: usize) -> ([Insn; MAX_BYTE_INSNS], usize) {
+    let mut insns = [Inew; MAX_BYTE_INSNS];
+    let mut len = 1;
+    if b != 0 {
+        insns[1] = Iinc;
+        len = 2;
+        let mut i = usize::BITS - b.leading_zeros() - 1;
+        while 0 < i {
+            i -= 1;
+            insns[len] = Ishl;
+            len += 1;
+            if b >> i & 1 == 1 {
+                insns[len] = Iinc;
+                len += 1;
+            }
+        }
+    }
+    (insns, len)
+}
+
+/// Returns the number of instructions that `Serializer::write_bits` emits.
+fn bits_len(n: u64) -> u32 {
+    if n == 0 {
+        return 1;
+    }
+    let width = u64::BITS - n.leading_zeros();
+    2 + (width - 1) + (n.count_ones() - 1)
+}
+
+/// Returns the number of instructions to push `n` by shifting it with `Isht`.
+fn shifted_len(n: u64) -> u32 {
+    if n == 0 {
+        return u32::MAX;
+    }
+    let t = n.trailing_zeros();
+    bits_len(n >> t) + bits_len(t as u64) + 1
+}
+
+/// Returns the number of instructions that `Serializer::write_pattern` emits.
+fn pattern_len(n: u64) -> u32 {
+    bits_len(n).min(shifted_len(n))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -151,22 +500,35 @@ mod test {
     #[test]
     fn serializer_int() {
         assert_eq!(to_insn_vec(&Int(0)), vec![Inew]);
-        assert_eq!(to_insn_vec(&Int(1)), vec![Inew, Inew, Iinc, Iadd]);
-        assert_eq!(to_insn_vec(&Int(2)), vec![Inew, Inew, Iinc, Ishl, Iadd]);
-        assert_eq!(
-            to_insn_vec(&Int(3)),
-            vec![Inew, Inew, Iinc, Iadd, Inew, Iinc, Ishl, Iadd]
-        );
+        assert_eq!(to_insn_vec(&Int(1)), vec![Inew, Iinc]);
+        assert_eq!(to_insn_vec(&Int(2)), vec![Inew, Iinc, Ishl]);
+        assert_eq!(to_insn_vec(&Int(3)), vec![Inew, Iinc, Ishl, Iinc]);
         assert_eq!(
             to_insn_vec(&Int(0b1010101)),
             vec![
-                Inew, // 0b0
-                Inew, Iinc, Iadd, // 0b1
-                Inew, Iinc, Ishl, Ishl, Iadd, // 0b101
-                Inew, Iinc, Ishl, Ishl, Ishl, Ishl, Iadd, // 0b10101
-                Inew, Iinc, Ishl, Ishl, Ishl, Ishl, Ishl, Ishl, Iadd, // 0b1010101
+                Inew, Iinc, // 0b1
+                Ishl, // 0b10
+                Ishl, Iinc, // 0b101
+                Ishl, // 0b1010
+                Ishl, Iinc, // 0b10101
+                Ishl, // 0b101010
+                Ishl, Iinc, // 0b1010101
             ]
         );
+        assert_eq!(to_insn_vec(&Int(-1)), vec![Inew, Iinc, Ineg]);
+        let mut insns = Vec::new();
+        Config {
+            shortest_ints: false,
+            ..Default::default()
+        }
+        .build(&mut insns)
+        .serialize(&Int(-1))
+        .unwrap();
+        assert_eq!(insns.len(), 2 + 63 * 2);
+        assert_eq!(to_insn_vec(&Int(-2)), vec![Inew, Iinc, Ishl, Ineg]);
+        assert_identical(Int(i64::MAX));
+        assert_identical(Int(i64::MIN));
+        assert_identical(Int(i64::MIN + 1));
         assert_identical(Int(1234567890));
         assert_identical(Int(-1234567890));
     }
@@ -185,8 +547,16 @@ mod test {
         assert_eq!(to_insn_vec(&Float(f64::INFINITY)), vec![Finf]);
         assert_eq!(to_insn_vec(&Float(f64::NEG_INFINITY)), vec![Finf, Fneg]);
 
+        assert_eq!(to_insn_vec(&Float(0.0)), vec![Inew, Itof]);
+        assert_eq!(to_insn_vec(&Float(-0.0)), vec![Inew, Itof, Fneg]);
+        assert!(to_insn_vec(&Float(1.0)).len() < 50);
+
         assert_identical(Float(0.0));
         assert_identical(Float(1.0));
+        assert_identical(Float(-1.5));
+        assert_identical(Float(f64::MIN_POSITIVE));
+        assert_identical(Float(f64::MAX));
+        assert_identical(Float(f64::MIN));
         assert_identical(Float(123.45e-67));
         assert_identical(Float(8.9102e34));
     }
@@ -201,6 +571,43 @@ mod test {
         ));
     }
 
+    #[test]
+    fn serializer_string_without_value() -> Result<()> {
+        let mut insns = Vec::new();
+        Serializer::new(&mut insns).string(b"\x00ab\xff")?;
+        assert_eq!(insns, to_insn_vec(&String(b"\x00ab\xff".to_vec())));
+        Ok(())
+    }
+
+    #[test]
+    fn serializer_byte_table() {
+        for b in 0..=255u8 {
+            let (insns, len) = &BYTE_INSNS[b as usize];
+            assert_eq!(&insns[..*len], &to_insn_vec(&Int(b as i64))[..]);
+        }
+    }
+
+    #[test]
+    fn serializer_compact_strings() -> Result<()> {
+        let value = String("日本語".as_bytes().to_vec());
+        let mut insns = Vec::new();
+        Config {
+            compact_strings: true,
+            ..Default::default()
+        }
+        .build(&mut insns)
+        .serialize(&value)?;
+        assert!(insns.len() < to_insn_vec(&value).len());
+
+        let mut vm = vm::VM::new();
+        vm.execute_all(vm::SliceTokenReader::new(&insns))?;
+        assert_eq!(vm.peek_top(), Some(&value));
+
+        let (insns, len) = COMPACT_BYTE_INSNS[0xff];
+        assert_eq!(&insns[..len], &[Inew, Iinc, Ineg]);
+        Ok(())
+    }
+
     #[test]
     fn serializer_object() {
         assert_identical(object![]);
@@ -213,6 +620,28 @@ mod test {
         ]);
     }
 
+    #[test]
+    fn serializer_sort_keys() -> Result<()> {
+        let value = object![b: Int(1), a: Int(2), ab: Int(3), c: Int(4)];
+        let mut insns = Vec::new();
+        Config {
+            sort_keys: true,
+            ..Default::default()
+        }
+        .build(&mut insns)
+        .serialize(&value)?;
+
+        let mut expected = vec![Onew];
+        let mut ser = Serializer::new(&mut expected);
+        for (k, v) in [("a", 2), ("ab", 3), ("b", 1), ("c", 4)] {
+            ser.key(k.as_bytes())?;
+            ser.serialize(&Int(v))?;
+            ser.value_done()?;
+        }
+        assert_eq!(insns, expected);
+        Ok(())
+    }
+
     #[test]
     fn serializer_array() {
         assert_identical(array![]);
@@ -225,6 +654,88 @@ mod test {
         ]);
     }
 
+    #[test]
+    fn serializer_incremental() -> Result<()> {
+        let mut insns = Vec::new();
+        let mut ser = Serializer::new(&mut insns);
+        ser.begin_object()?;
+        ser.key(b"a")?;
+        ser.begin_array()?;
+        for i in 0..3 {
+            ser.serialize(&Int(i))?;
+            ser.element_done()?;
+        }
+        ser.end_array()?;
+        ser.value_done()?;
+        ser.end_object()?;
+
+        let mut vm = vm::VM::new();
+        vm.execute_all(vm::SliceTokenReader::new(&insns))?;
+        assert_eq!(
+            vm.peek_top(),
+            Some(&object![a: array![Int(0), Int(1), Int(2)]])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serializer_deeply_nested_value() -> Result<()> {
+        let depth = 1_000_000;
+        let mut value = Nil;
+        for _ in 0..depth {
+            value = Array(vec![value].into());
+        }
+        let mut counter = 0;
+        Serializer::new(InsnCounter(&mut counter)).serialize(&value)?;
+        assert_eq!(counter, 2 * depth + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn serializer_batches_writes() -> Result<()> {
+        let value = String(vec![b'x'; 1000]);
+        let mut ser = Serializer::new(CallCounter::default());
+        ser.serialize(&value)?;
+        let counter = ser.into_inner();
+        assert_eq!(counter.insns, to_insn_vec(&value).len());
+        assert!(counter.calls <= counter.insns / BUFFER_LEN + 1);
+
+        // Boundaries between elements don't flush the buffer.
+        let value = Array((0..1000).map(Int).collect());
+        let mut ser = Serializer::new(CallCounter::default());
+        ser.serialize(&value)?;
+        let counter = ser.into_inner();
+        assert_eq!(counter.insns, to_insn_vec(&value).len());
+        assert!(counter.calls <= counter.insns / BUFFER_LEN + 1);
+
+        // Neither do instructions written by the incremental API in a container.
+        let mut ser = Serializer::new(CallCounter::default());
+        ser.begin_array()?;
+        for i in 0..1000 {
+            ser.serialize(&Int(i))?;
+            ser.element_done()?;
+        }
+        ser.end_array()?;
+        let counter = ser.into_inner();
+        assert_eq!(counter.insns, to_insn_vec(&value).len());
+        assert!(counter.calls <= counter.insns / BUFFER_LEN + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn serializer_forwards_boundaries() -> Result<()> {
+        let value = array![Int(1), object![a: Nil], Int(2)];
+        let mut ser = Serializer::new(CallCounter {
+            uses_boundaries: true,
+            ..Default::default()
+        });
+        ser.serialize(&value)?;
+        let counter = ser.into_inner();
+        assert_eq!(counter.insns, to_insn_vec(&value).len());
+        assert_eq!(counter.boundaries, 4);
+        Ok(())
+    }
+
     #[test]
     fn serializer_bool() {
         assert_identical(Bool(false));
@@ -236,13 +747,64 @@ mod test {
         assert_identical(Nil);
     }
 
+    #[test]
+    fn serializer_writes_to_unlexer() -> Result<()> {
+        let value = object![a: array![Int(1), String(b"x".to_vec())], b: Float(1.5)];
+        let mut buf = Vec::new();
+        Serializer::new(crate::unlexer::Unlexer::new(&mut buf)).serialize(&value)?;
+
+        let mut vm = vm::VM::new();
+        vm.execute_all(crate::lexer::SliceLexer::new(&buf))?;
+        assert_eq!(vm.peek_top(), Some(&value));
+        Ok(())
+    }
+
     /*
      * Helper functions
      */
 
+    struct InsnCounter<'a>(&'a mut usize);
+
+    impl WriteInsn for InsnCounter<'_> {
+        fn write(&mut self, _insn: Insn) -> Result<()> {
+            *self.0 += 1;
+            Ok(())
+        }
+    }
+
+    /// Counts the number of calls to `write` and `write_all`, and the number of boundaries.
+    #[derive(Default)]
+    struct CallCounter {
+        calls: usize,
+        insns: usize,
+        boundaries: usize,
+        uses_boundaries: bool,
+    }
+
+    impl WriteInsn for CallCounter {
+        fn write(&mut self, insn: Insn) -> Result<()> {
+            self.write_all(&[insn])
+        }
+
+        fn write_all(&mut self, insns: &[Insn]) -> Result<()> {
+            self.calls += 1;
+            self.insns += insns.len();
+            Ok(())
+        }
+
+        fn boundary(&mut self) -> Result<()> {
+            self.boundaries += 1;
+            Ok(())
+        }
+
+        fn uses_boundaries(&self) -> bool {
+            self.uses_boundaries
+        }
+    }
+
     fn to_insn_vec(value: &Value) -> Vec<Insn> {
         let mut insns = Vec::new();
-        Serializer::new(&mut insns).serialize(&value).unwrap();
+        Serializer::new(&mut insns).serialize(value).unwrap();
         insns
     }
 
@@ -0,0 +1,187 @@
+//! Streams of multiple values.
+//!
+//! A stream consists of lines, each of which is a WATSON text that represents a single value.
+//! Every line starts in the mode `A` regardless of the previous lines, so values can be appended to the stream
+//! (e.g. an append-only log file) without knowing what was written before.
+//! Lines that have no instructions are ignored.
+
+use std::io;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Location, Value};
+use crate::lexer::SliceLexer;
+use crate::text::TextSerializer;
+use crate::vm::VM;
+
+/// `StreamWriter` writes values to a stream.
+pub struct StreamWriter<W> {
+    writer: W,
+    serializer: TextSerializer,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> StreamWriter<W> {
+    /// Returns a new `StreamWriter` that writes to the given writer.
+    pub fn new(writer: W) -> Self {
+        StreamWriter {
+            writer,
+            serializer: TextSerializer::builder().chars_per_line(0).build(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Writes a single value as a line.
+    pub fn write(&mut self, v: &Value) -> Result<()> {
+        // Writes the whole line at once so that a line in an append-only file is never interleaved with another.
+        self.buf.clear();
+        self.serializer.to_writer(&mut self.buf, v)?;
+        self.buf.push(b'\n');
+        self.writer.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Unwraps the underlying writer from this `StreamWriter`.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// `StreamReader` reads values from a stream one by one.
+pub struct StreamReader<R> {
+    reader: R,
+    line: Vec<u8>,
+}
+
+impl<R: io::BufRead> StreamReader<R> {
+    /// Returns a new `StreamReader` that reads from the given reader.
+    pub fn new(reader: R) -> Self {
+        StreamReader {
+            reader,
+            line: Vec::new(),
+        }
+    }
+
+    /// Reads the next value. Returns `None` at the end of the stream.
+    pub fn read(&mut self) -> Result<Option<Value>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let mut vm = VM::new();
+            vm.execute_all(SliceLexer::new(&self.line))?;
+            let mut values = vm.take_values();
+            match values.len() {
+                0 => continue,
+                1 => return Ok(values.pop()),
+                _ => {
+                    return Err(Error {
+                        kind: ErrorKind::InvalidDocument,
+                        location: Location::unknown(),
+                        source: None,
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for StreamReader<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// `Deserializer` decodes a stream of multiple values.
+///
+/// Each value must be on its own line, as `StreamWriter` (or `Unlexer` with `chars_per_line` set to zero) writes.
+pub struct Deserializer<R> {
+    reader: R,
+}
+
+impl<R: io::Read> Deserializer<io::BufReader<R>> {
+    /// Returns a new `Deserializer` that reads from the given reader.
+    pub fn from_reader(reader: R) -> Self {
+        Deserializer {
+            reader: io::BufReader::new(reader),
+        }
+    }
+}
+
+impl<'a> Deserializer<&'a [u8]> {
+    /// Returns a new `Deserializer` that reads from the given slice.
+    pub fn from_slice(slice: &'a [u8]) -> Self {
+        Deserializer { reader: slice }
+    }
+}
+
+impl<R: io::BufRead> IntoIterator for Deserializer<R> {
+    type Item = Result<Value>;
+    type IntoIter = StreamReader<R>;
+
+    /// Returns an iterator over the values in the stream.
+    fn into_iter(self) -> StreamReader<R> {
+        StreamReader::new(self.reader)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{array, object};
+    use Value::*;
+
+    #[test]
+    fn stream_round_trip() -> Result<()> {
+        let values = vec![
+            String(b"first".to_vec()),
+            object![a: array![Int(1), Nil]],
+            Float(1.5),
+        ];
+        let mut w = StreamWriter::new(Vec::new());
+        for v in &values {
+            w.write(v)?;
+        }
+        let mut buf = w.into_inner();
+        assert_eq!(buf.iter().filter(|&&b| b == b'\n').count(), 3);
+
+        // Values can be appended later.
+        let mut w = StreamWriter::new(&mut buf);
+        w.write(&Bool(true))?;
+        buf.extend_from_slice(b"\n\n");
+
+        let read = StreamReader::new(&buf[..]).collect::<Result<Vec<_>>>()?;
+        assert_eq!(read[..3], values[..]);
+        assert_eq!(read[3..], [Bool(true)]);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_rejects_line_with_multiple_values() {
+        let mut r = StreamReader::new(&b"B\nBB\n"[..]);
+        assert_eq!(r.read().unwrap(), Some(Int(0)));
+        assert_eq!(r.read().unwrap_err().kind, ErrorKind::InvalidDocument);
+    }
+
+    #[test]
+    fn deserializer_iterates_over_values() -> Result<()> {
+        let values = Deserializer::from_reader(&b"B\nBu\n\nBub\n"[..])
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(values, vec![Int(0), Int(1), Int(2)]);
+
+        let mut iter = Deserializer::from_slice(b"Bu\n?").into_iter();
+        assert_eq!(iter.next().unwrap()?, Int(1));
+        assert_eq!(iter.next().unwrap()?, String(vec![]));
+        assert!(iter.next().is_none());
+        Ok(())
+    }
+}
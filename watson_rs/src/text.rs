@@ -0,0 +1,137 @@
+//! Conversion from `Value` to WATSON text in one call.
+
+use std::io;
+
+use crate::error::Result;
+use crate::language::{Mode, Value};
+use crate::serializer;
+use crate::unlexer;
+
+/// `TextSerializer` converts `Value`s into WATSON text by combining `serializer::Serializer` and `unlexer::Unlexer`.
+#[derive(Clone, Default)]
+pub struct TextSerializer {
+    serializer: serializer::Config,
+    unlexer: unlexer::Config,
+}
+
+/// A builder of `TextSerializer`.
+#[derive(Clone, Default)]
+pub struct Builder {
+    inner: TextSerializer,
+}
+
+impl TextSerializer {
+    /// Returns a builder with the default configuration.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Serializes a value and writes it to the given writer.
+    pub fn to_writer<W: io::Write>(&self, writer: W, v: &Value) -> Result<()> {
+        let unlexer = self.unlexer.clone().build(writer);
+        let mut ser = self.serializer.clone().build(unlexer);
+        ser.serialize(v)?;
+        ser.into_inner().into_inner()?;
+        Ok(())
+    }
+
+    /// Serializes a value.
+    pub fn to_vec(&self, v: &Value) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.to_writer(&mut buf, v)?;
+        Ok(buf)
+    }
+
+    /// Serializes a value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output is not valid UTF-8, which can happen only if `unlexer::Config::table` is customized.
+    pub fn to_string(&self, v: &Value) -> Result<String> {
+        Ok(String::from_utf8(self.to_vec(v)?).expect("WATSON text must be valid UTF-8"))
+    }
+}
+
+impl Builder {
+    /// Sets `unlexer::Config::initial_mode`.
+    pub fn initial_mode(mut self, mode: Mode) -> Self {
+        self.inner.unlexer.initial_mode = mode;
+        self
+    }
+
+    /// Sets `unlexer::Config::chars_per_line`.
+    pub fn chars_per_line(mut self, n: usize) -> Self {
+        self.inner.unlexer.chars_per_line = n;
+        self
+    }
+
+    /// Sets `serializer::Config::sort_keys`.
+    pub fn sort_keys(mut self, b: bool) -> Self {
+        self.inner.serializer.sort_keys = b;
+        self
+    }
+
+    /// Sets `serializer::Config::shortest_ints`.
+    pub fn shortest_ints(mut self, b: bool) -> Self {
+        self.inner.serializer.shortest_ints = b;
+        self
+    }
+
+    /// Sets `serializer::Config::compact_strings`.
+    pub fn compact_strings(mut self, b: bool) -> Self {
+        self.inner.serializer.compact_strings = b;
+        self
+    }
+
+    /// Replaces the whole configuration of the `Unlexer`.
+    pub fn unlexer_config(mut self, conf: unlexer::Config) -> Self {
+        self.inner.unlexer = conf;
+        self
+    }
+
+    /// Returns a new `TextSerializer`.
+    pub fn build(self) -> TextSerializer {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer;
+    use crate::vm::VM;
+    use crate::{array, object};
+    use Value::*;
+
+    #[test]
+    fn text_serializer_default() -> Result<()> {
+        let ser = TextSerializer::builder().build();
+        assert_eq!(ser.to_string(&Int(4))?, "Bubb");
+        assert_eq!(ser.to_vec(&Nil)?, b".".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn text_serializer_builder() -> Result<()> {
+        let value = object![a: array![Int(-1), Bool(true)], b: Nil];
+        let ser = TextSerializer::builder()
+            .initial_mode(Mode::S)
+            .chars_per_line(8)
+            .sort_keys(true)
+            .shortest_ints(false)
+            .build();
+        let text = ser.to_string(&value)?;
+        assert_eq!(text, ser.to_string(&value)?);
+        assert!(text.lines().all(|l| l.len() <= 8));
+
+        let mut vm = VM::new();
+        let lexer = lexer::Config {
+            initial_mode: Mode::S,
+            ..Default::default()
+        }
+        .build_slice(text.as_bytes());
+        vm.execute_all(lexer)?;
+        assert_eq!(vm.into_top(), Some(value));
+        Ok(())
+    }
+}
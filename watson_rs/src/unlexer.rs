@@ -1,50 +1,144 @@
 use std::fs;
-use std::io;
+use std::mem;
 use std::path;
 
-use crate::error::Result;
-use crate::language::{Insn, Mode};
+use crate::byteio::ByteWrite;
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Insn, Location, Mode, ModeTable};
 use crate::serializer::WriteInsn;
+use crate::vm::ReadToken;
 
 const DEFAULT_CHARS_PER_LINE: usize = 80;
+const DEFAULT_MAX_NOISE_LEN: usize = 3;
+const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
 
 /// `Unlexer` converts a sequence of `Insn`s to its ASCII representation.
-pub struct Unlexer<W> {
-    writer: W,
+///
+/// Its output is buffered internally. Buffered bytes are written when the `Unlexer` is dropped,
+/// but errors that happen at that time are ignored, so call `flush` or `into_inner` to handle them.
+pub struct Unlexer<W: ByteWrite> {
+    // Always `Some` except in `into_inner`.
+    writer: Option<W>,
+    buf: Vec<u8>,
+    buffer_capacity: usize,
 
     mode: Mode,
+    table: ModeTable,
     chars_per_line: usize,
+    line_terminator: LineTerminator,
+    trailing_newline: bool,
+    break_at_boundaries: bool,
+    noise: Option<Noise>,
 
     column: usize,
+    break_pending: bool,
+}
+
+/// A sequence of bytes that an `Unlexer` uses to break lines.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum LineTerminator {
+    /// `\n`.
+    Lf,
+    /// `\r\n`.
+    CrLf,
+}
+
+impl LineTerminator {
+    /// Returns its byte representation.
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineTerminator::Lf => b"\n",
+            LineTerminator::CrLf => b"\r\n",
+        }
+    }
 }
 
 /// Config configures an `Unlexer`.
+#[derive(Clone)]
 pub struct Config {
     /// Initial mode of an `Unlexer` (defaults to `A` by the specification).
     pub initial_mode: Mode,
 
+    /// Byte representations of instructions (defaults to the one defined by the specification).
+    /// If the table uses a newline character, `chars_per_line` should be zero.
+    pub table: ModeTable,
+
     /// An `Unlexer` emits a newline character every time it emits `chars_per_line` consecutive characters.
     /// If set to zero, then `Unlexer` does not emit any newline characters.
     pub chars_per_line: usize,
+
+    /// Line terminator to break lines (defaults to `Lf`).
+    pub line_terminator: LineTerminator,
+
+    /// If set to false, an `Unlexer` does not break a line until it emits the next character,
+    /// so that its output never ends with a line terminator (defaults to true).
+    pub trailing_newline: bool,
+
+    /// If set to true, an `Unlexer` also breaks a line at each logical boundary (e.g. the end of an array element
+    /// or an object entry) given by `WriteInsn::boundary` (defaults to false).
+    /// Set `chars_per_line` to zero to break lines only at boundaries.
+    pub break_at_boundaries: bool,
+
+    /// If set, an `Unlexer` inserts random bytes between instructions using a pseudo-random number generator
+    /// seeded with this value (defaults to `None`). Inserted bytes never represent instructions in the current mode,
+    /// so the output still represents the same program. The same seed always yields the same output.
+    pub noise_seed: Option<u64>,
+
+    /// The maximum number of bytes inserted between two instructions if `noise_seed` is set (defaults to 3).
+    pub max_noise_len: usize,
+
+    /// The size of the internal buffer in bytes (defaults to 8 KiB).
+    /// If set to zero, an `Unlexer` writes each character to the underlying writer immediately.
+    pub buffer_capacity: usize,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             initial_mode: Mode::A,
+            table: ModeTable::standard(),
             chars_per_line: DEFAULT_CHARS_PER_LINE,
+            line_terminator: LineTerminator::Lf,
+            trailing_newline: true,
+            break_at_boundaries: false,
+            noise_seed: None,
+            max_noise_len: DEFAULT_MAX_NOISE_LEN,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
         }
     }
 }
 
 impl Config {
     /// Returns a new `Unlexer` that writes to the given writer.
-    pub fn build<W>(self, writer: W) -> Unlexer<W> {
+    pub fn build<W: ByteWrite>(self, writer: W) -> Unlexer<W> {
+        let noise = self.noise_seed.map(|seed| {
+            // Printable characters that don't represent instructions.
+            let candidates = |mode| {
+                (b'!'..=b'~')
+                    .filter(|&b| self.table.from_byte(mode, b).is_none())
+                    .collect::<Vec<_>>()
+            };
+            Noise {
+                rng: SplitMix64(seed),
+                max_len: self.max_noise_len,
+                candidates_a: candidates(Mode::A),
+                candidates_s: candidates(Mode::S),
+                started: false,
+            }
+        });
         Unlexer {
-            writer,
+            writer: Some(writer),
+            buf: Vec::with_capacity(self.buffer_capacity),
+            buffer_capacity: self.buffer_capacity,
             mode: self.initial_mode,
+            table: self.table,
             chars_per_line: self.chars_per_line,
+            line_terminator: self.line_terminator,
+            trailing_newline: self.trailing_newline,
+            break_at_boundaries: self.break_at_boundaries,
+            noise,
             column: 0,
+            break_pending: false,
         }
     }
 
@@ -62,34 +156,208 @@ impl Unlexer<fs::File> {
     }
 }
 
-impl<W> Unlexer<W> {
+impl<W: ByteWrite> Unlexer<W> {
     /// Returns a new `Unlexer` that writes to the given writer with the default configuration.
     pub fn new(writer: W) -> Self {
         Config::default().build(writer)
     }
+
+    /// Returns a reference to the underlying writer.
+    /// It may not contain bytes that are still buffered.
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().unwrap()
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    /// Writing directly to it may break the line wrapping of the `Unlexer`.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().unwrap()
+    }
+
+    /// Writes all buffered bytes and unwraps the underlying writer from this `Unlexer`.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.write_buffered()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    /// Writes all buffered bytes and flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_buffered()?;
+        self.get_mut().flush_bytes()
+    }
+
+    fn write_buffered(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let buf = mem::take(&mut self.buf);
+        let result = self.get_mut().write_bytes(&buf);
+        self.buf = buf;
+        self.buf.clear();
+        result
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.buffer_capacity < self.buf.len() + bytes.len() {
+            self.write_buffered()?;
+        }
+        if self.buffer_capacity < bytes.len() {
+            return self.get_mut().write_bytes(bytes);
+        }
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn is_line_full(&self) -> bool {
+        0 < self.chars_per_line && self.chars_per_line <= self.column
+    }
+
+    fn break_line(&mut self) -> Result<()> {
+        self.column = 0;
+        self.break_pending = false;
+        self.write_bytes(self.line_terminator.as_bytes())
+    }
+
+    /// Writes a single character, breaking lines if necessary.
+    fn put(&mut self, byte: u8) -> Result<()> {
+        if self.break_pending || (!self.trailing_newline && self.is_line_full()) {
+            self.break_line()?;
+        }
+        self.write_bytes(&[byte])?;
+        self.column += 1;
+        if self.trailing_newline && self.is_line_full() {
+            self.break_line()?;
+        }
+        Ok(())
+    }
+
+    fn write_noise(&mut self) -> Result<()> {
+        let noise = match self.noise {
+            Some(ref mut noise) => noise,
+            None => return Ok(()),
+        };
+        let bytes = noise.generate(self.mode);
+        for b in bytes {
+            self.put(b)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: ByteWrite> Drop for Unlexer<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_buffered();
+        }
+    }
 }
 
-impl<W: io::Write> WriteInsn for Unlexer<W> {
+impl<W: ByteWrite> WriteInsn for Unlexer<W> {
     /// Writes a single `Insn` to its underlying writer.
+    /// It fails with `ErrorKind::UnrepresentableInstruction` if the instruction can't be read back from the text.
     fn write(&mut self, insn: Insn) -> Result<()> {
-        let mut buf = [insn.into_byte(self.mode)];
-        self.writer.write_all(&buf)?;
-        self.column += 1;
-        if 0 < self.chars_per_line && self.chars_per_line <= self.column {
-            self.column = 0;
-            buf[0] = b'\n';
-            self.writer.write_all(&buf)?;
+        if !self.table.is_representable(self.mode, insn) {
+            return Err(Error {
+                kind: ErrorKind::UnrepresentableInstruction,
+                location: Location::unknown(),
+                source: None,
+            });
         }
+        self.write_noise()?;
+        self.put(self.table.into_byte(self.mode, insn))?;
         if insn == Insn::Snew {
             self.mode = self.mode.flip();
         }
         Ok(())
     }
+
+    fn boundary(&mut self) -> Result<()> {
+        if !self.break_at_boundaries || self.column == 0 {
+            return Ok(());
+        }
+        if self.trailing_newline {
+            self.break_line()
+        } else {
+            self.break_pending = true;
+            Ok(())
+        }
+    }
+
+    fn uses_boundaries(&self) -> bool {
+        self.break_at_boundaries
+    }
+}
+
+/// Reads all instructions from `reader` and writes them to `writer` as is.
+///
+/// This is useful to reformat WATSON text, e.g. by passing a `Lexer` and an `Unlexer` with different configurations.
+/// Unlike decoding and encoding a `Value`, the sequence of instructions is preserved exactly.
+pub fn transcode<R, W>(mut reader: R, writer: &mut W) -> Result<()>
+where
+    R: ReadToken,
+    W: WriteInsn,
+{
+    while let Some(token) = reader.read()? {
+        writer.write(token.insn)?;
+    }
+    Ok(())
+}
+
+/// Random bytes inserted between instructions.
+struct Noise {
+    rng: SplitMix64,
+    max_len: usize,
+    candidates_a: Vec<u8>,
+    candidates_s: Vec<u8>,
+    // Nothing is inserted before the first instruction.
+    started: bool,
+}
+
+impl Noise {
+    fn generate(&mut self, mode: Mode) -> Vec<u8> {
+        if !self.started {
+            self.started = true;
+            return Vec::new();
+        }
+        let candidates = match mode {
+            Mode::A => &self.candidates_a,
+            Mode::S => &self.candidates_s,
+        };
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+        let len = self.rng.below(self.max_len as u64 + 1) as usize;
+        (0..len)
+            .map(|_| candidates[self.rng.below(candidates.len() as u64) as usize])
+            .collect()
+    }
+}
+
+/// A small pseudo-random number generator (SplitMix64).
+/// Its output is stable across platforms and versions so that seeded outputs are reproducible.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io;
+
     use super::*;
+    use crate::language::Value;
     use Insn::*;
 
     #[test]
@@ -97,17 +365,21 @@ mod test {
         let mut buf = Vec::new();
         let mut unlexer = Unlexer::new(&mut buf);
         unlexer.write(Insn::Inew)?;
+        drop(unlexer);
         assert_eq!(buf, b"B".to_vec());
         Ok(())
     }
 
     #[test]
     fn unlexer_new_initlal_mode_is_configurable() -> Result<()> {
-        let mut conf = Config::default();
-        conf.initial_mode = Mode::S;
+        let conf = Config {
+            initial_mode: Mode::S,
+            ..Default::default()
+        };
         let mut buf = Vec::new();
         let mut unlexer = conf.build(&mut buf);
         unlexer.write(Insn::Inew)?;
+        drop(unlexer);
         assert_eq!(buf, b"S".to_vec());
         Ok(())
     }
@@ -134,17 +406,54 @@ mod test {
     fn unlexer_changes_its_mode() -> Result<()> {
         let mut buf = Vec::new();
         let mut unlexer = Unlexer::new(&mut buf);
-        for insn in vec![Inew, Snew, Inew, Snew, Inew] {
+        for insn in [Inew, Snew, Inew, Snew, Inew] {
             unlexer.write(insn)?;
         }
+        drop(unlexer);
         assert_eq!(buf, b"B?S$B".to_vec());
         Ok(())
     }
 
+    #[test]
+    fn unlexer_uses_custom_table() -> Result<()> {
+        // Uses the alphabet of the S mode in the A mode and vice versa.
+        let conf = Config {
+            table: ModeTable::new(|mode, insn| insn.into_byte(mode.flip())).unwrap(),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let mut unlexer = conf.build(&mut buf);
+        for insn in [Inew, Iinc, Snew, Inew, Iinc] {
+            unlexer.write(insn)?;
+        }
+        drop(unlexer);
+        assert_eq!(buf, b"Sh$Bu".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn unlexer_writes_only_representable_ext() -> Result<()> {
+        let mut buf = Vec::new();
+        let mut unlexer = Unlexer::new(&mut buf);
+        unlexer.write(Ext(b'0'))?;
+        for byte in [b'B', b'\n', b' ', 0xff] {
+            let err = unlexer.write(Ext(byte)).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::UnrepresentableInstruction);
+        }
+        unlexer.write(Snew)?;
+        // `B` doesn't represent any instruction in the S mode.
+        unlexer.write(Ext(b'B'))?;
+        drop(unlexer);
+        assert_eq!(buf, b"0?B".to_vec());
+        Ok(())
+    }
+
     #[test]
     fn unlexer_emits_newline() -> Result<()> {
-        let mut conf = Config::default();
-        conf.chars_per_line = 5;
+        let conf = Config {
+            chars_per_line: 5,
+            ..Default::default()
+        };
         let mut buf = Vec::new();
         let mut unlexer = conf.build(&mut buf);
 
@@ -153,8 +462,173 @@ mod test {
         ] {
             unlexer.write(insn)?;
         }
+        drop(unlexer);
         assert_eq!(buf, b"Bubba\n?Shaa\nk$B".to_vec());
 
         Ok(())
     }
+
+    #[test]
+    fn unlexer_line_terminator_and_trailing_newline() -> Result<()> {
+        let unlex = |line_terminator, trailing_newline| -> Result<Vec<u8>> {
+            let conf = Config {
+                chars_per_line: 5,
+                line_terminator,
+                trailing_newline,
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            let mut unlexer = conf.build(&mut buf);
+            for _ in 0..2 {
+                unlexer.write_all(&[Inew, Iinc, Ishl, Ishl, Iadd])?;
+            }
+            drop(unlexer);
+            Ok(buf)
+        };
+
+        assert_eq!(unlex(LineTerminator::Lf, true)?, b"Bubba\nBubba\n".to_vec());
+        assert_eq!(
+            unlex(LineTerminator::CrLf, true)?,
+            b"Bubba\r\nBubba\r\n".to_vec()
+        );
+        assert_eq!(
+            unlex(LineTerminator::CrLf, false)?,
+            b"Bubba\r\nBubba".to_vec()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unlexer_injects_noise() -> Result<()> {
+        use crate::lexer::SliceLexer;
+        use crate::vm::ReadToken;
+
+        let unlex = |seed| -> Result<Vec<u8>> {
+            let conf = Config {
+                noise_seed: Some(seed),
+                chars_per_line: 0,
+                ..Default::default()
+            };
+            let mut buf = Vec::new();
+            let mut unlexer = conf.build(&mut buf);
+            for _ in 0..10 {
+                unlexer.write_all(&[Inew, Iinc, Snew, Inew, Iinc, Snew])?;
+            }
+            drop(unlexer);
+            Ok(buf)
+        };
+
+        let buf = unlex(42)?;
+        assert_eq!(buf, unlex(42)?);
+        assert_ne!(buf, unlex(43)?);
+        assert!(60 < buf.len());
+
+        let mut lexer = SliceLexer::new(&buf);
+        let mut insns = Vec::new();
+        while let Some(t) = lexer.read()? {
+            insns.push(t.insn);
+        }
+        assert_eq!(insns, [Inew, Iinc, Snew, Inew, Iinc, Snew].repeat(10));
+        Ok(())
+    }
+
+    #[test]
+    fn unlexer_breaks_lines_at_boundaries() -> Result<()> {
+        use crate::array;
+        use crate::serializer::Serializer;
+
+        let unlex = |trailing_newline| -> Result<Vec<u8>> {
+            let conf = Config {
+                chars_per_line: 0,
+                break_at_boundaries: true,
+                trailing_newline,
+                ..Default::default()
+            };
+            let mut ser = Serializer::new(conf.build(Vec::new()));
+            ser.serialize(&array![Value::Nil, Value::Bool(true)])?;
+            ser.into_inner().into_inner()
+        };
+
+        assert_eq!(unlex(true)?, b"@.s\nzos\n".to_vec());
+        assert_eq!(unlex(false)?, b"@.s\nzos".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn unlexer_gives_back_its_writer() -> Result<()> {
+        let mut unlexer = Unlexer::new(Vec::new());
+        unlexer.write_all(&[Inew, Iinc])?;
+        assert!(unlexer.get_ref().is_empty());
+        unlexer.flush()?;
+        assert_eq!(unlexer.get_ref(), b"Bu");
+        unlexer.get_mut().clear();
+        unlexer.write(Ishl)?;
+        assert_eq!(unlexer.into_inner()?, b"b".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn unlexer_batches_writes() -> Result<()> {
+        let count_writes = |buffer_capacity| -> Result<usize> {
+            let conf = Config {
+                chars_per_line: 0,
+                buffer_capacity,
+                ..Default::default()
+            };
+            let mut unlexer = conf.build(CountingWriter(0));
+            unlexer.write_all(&[Inew; 10])?;
+            Ok(unlexer.into_inner()?.0)
+        };
+
+        assert_eq!(count_writes(0)?, 10);
+        assert_eq!(count_writes(4)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn transcode_preserves_instructions() -> Result<()> {
+        use crate::lexer::{self, SliceLexer};
+
+        let src = b"Bubba\n?Shaak$Bu Gswp: %";
+        let conf = Config {
+            initial_mode: Mode::S,
+            chars_per_line: 4,
+            line_terminator: LineTerminator::CrLf,
+            ..Default::default()
+        };
+        let mut unlexer = conf.build(Vec::new());
+        transcode(SliceLexer::new(src), &mut unlexer)?;
+        let out = unlexer.into_inner()?;
+        assert_eq!(out, b"Shaa\r\nk$Bu\r\nbba?\r\nSh?u\r\n:".to_vec());
+
+        let mut insns = Vec::new();
+        transcode(SliceLexer::new(src), &mut &mut insns)?;
+        let mut transcoded = Vec::new();
+        let lexer = lexer::Config {
+            initial_mode: Mode::S,
+            ..Default::default()
+        }
+        .build_slice(&out);
+        transcode(lexer, &mut &mut transcoded)?;
+        assert_eq!(transcoded, insns);
+        Ok(())
+    }
+
+    /*
+     * Helper functions
+     */
+
+    /// Counts the number of calls to `write`.
+    struct CountingWriter(usize);
+
+    impl io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,365 @@
+//! Encoding instructions into human-readable text.
+//!
+//! `VanityWriter` writes instructions by choosing words from a lexicon whose characters lex to
+//! the instructions (ignoring characters that don't represent instructions), so that the output reads like prose
+//! while still representing the same program.
+//!
+//! `TemplateWriter` fills instructions into placeholders of a given carrier text.
+
+use std::collections::VecDeque;
+use std::io;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::language::{Insn, Location, Mode};
+use crate::serializer::WriteInsn;
+
+/// Config configures a `VanityWriter`.
+pub struct Config {
+    /// Words to use (defaults to none).
+    pub lexicon: Vec<String>,
+
+    /// A byte written between words (defaults to a space).
+    /// It must not represent an instruction in either mode.
+    pub separator: u8,
+
+    /// Initial mode of a `VanityWriter` (defaults to `A` by the specification).
+    pub initial_mode: Mode,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            lexicon: Vec::new(),
+            separator: b' ',
+            initial_mode: Mode::A,
+        }
+    }
+}
+
+impl Config {
+    /// Returns a new `VanityWriter` that writes to the given writer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separator` represents an instruction.
+    pub fn build<W>(self, writer: W) -> VanityWriter<W> {
+        assert!(
+            Insn::from_byte(Mode::A, self.separator).is_none()
+                && Insn::from_byte(Mode::S, self.separator).is_none(),
+            "separator must not represent an instruction"
+        );
+        let lexicon: Vec<Vec<u8>> = self
+            .lexicon
+            .into_iter()
+            .map(String::into_bytes)
+            .filter(|w| !w.is_empty())
+            .collect();
+        // A word can't represent more instructions than its length.
+        let lookahead = lexicon.iter().map(Vec::len).max().unwrap_or(1);
+        VanityWriter {
+            writer,
+            lexicon,
+            separator: self.separator,
+            mode: self.initial_mode,
+            pending: VecDeque::new(),
+            lookahead,
+            first_word: true,
+        }
+    }
+}
+
+/// `VanityWriter` converts a sequence of `Insn`s into words.
+///
+/// Since it needs to look ahead to choose words, instructions are buffered until `finish` is called
+/// or enough instructions are written.
+pub struct VanityWriter<W> {
+    writer: W,
+    lexicon: Vec<Vec<u8>>,
+    separator: u8,
+
+    mode: Mode,
+    pending: VecDeque<Insn>,
+    lookahead: usize,
+    first_word: bool,
+}
+
+impl<W: io::Write> VanityWriter<W> {
+    /// Returns a new `VanityWriter` that uses the given lexicon.
+    pub fn new(writer: W, lexicon: Vec<String>) -> Self {
+        Config {
+            lexicon,
+            ..Default::default()
+        }
+        .build(writer)
+    }
+
+    /// Writes all buffered instructions and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        while !self.pending.is_empty() {
+            self.write_word()?;
+        }
+        Ok(self.writer)
+    }
+
+    /// Writes a word that represents one or more instructions at the head of `pending`.
+    /// If there are no such words, the instruction itself is written as a word.
+    fn write_word(&mut self) -> Result<()> {
+        let best = self
+            .lexicon
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| {
+                consumed_insns(w, self.mode, &self.pending).map(|(n, mode)| (n, i, mode))
+            })
+            // Prefers the word that represents the most instructions, and then the first one.
+            .max_by(|(n1, i1, _), (n2, i2, _)| n1.cmp(n2).then(i2.cmp(i1)));
+
+        if self.first_word {
+            self.first_word = false;
+        } else {
+            self.writer.write_all(&[self.separator])?;
+        }
+        match best {
+            Some((n, i, mode)) => {
+                self.writer.write_all(&self.lexicon[i])?;
+                self.pending.drain(..n);
+                self.mode = mode;
+            }
+            None => {
+                let insn = self.pending.pop_front().expect("pending must not be empty");
+                if !insn.is_representable(self.mode) {
+                    return Err(Error {
+                        kind: ErrorKind::UnrepresentableInstruction,
+                        location: Location::unknown(),
+                        source: None,
+                    });
+                }
+                self.writer.write_all(&[insn.into_byte(self.mode)])?;
+                if insn == Insn::Snew {
+                    self.mode = self.mode.flip();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: io::Write> WriteInsn for VanityWriter<W> {
+    fn write(&mut self, insn: Insn) -> Result<()> {
+        self.pending.push_back(insn);
+        while self.lookahead <= self.pending.len() {
+            self.write_word()?;
+        }
+        Ok(())
+    }
+}
+
+/// `TemplateWriter` writes instructions by replacing placeholder characters of a template.
+///
+/// Characters of the template other than placeholders are copied as is, so they must not represent
+/// instructions in the mode at that point. Placeholders that are left when `finish` is called are replaced
+/// with spaces.
+pub struct TemplateWriter<W> {
+    writer: W,
+    template: Vec<u8>,
+    placeholder: u8,
+
+    mode: Mode,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<W: io::Write> TemplateWriter<W> {
+    /// Returns a new `TemplateWriter` that fills instructions into the positions of `placeholder` in `template`.
+    pub fn new(writer: W, template: impl Into<Vec<u8>>, placeholder: u8) -> Self {
+        TemplateWriter {
+            writer,
+            template: template.into(),
+            placeholder,
+            mode: Mode::A,
+            pos: 0,
+            line: 1,
+            column: 0,
+        }
+    }
+
+    /// Writes the rest of the template and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        while self.copy_carrier()? {
+            self.writer.write_all(b" ")?;
+            self.advance();
+        }
+        Ok(self.writer)
+    }
+
+    /// Copies the template up to the next placeholder and returns true if there is a placeholder.
+    fn copy_carrier(&mut self) -> Result<bool> {
+        let start = self.pos;
+        while let Some(&byte) = self.template.get(self.pos) {
+            if byte == self.placeholder {
+                break;
+            }
+            if Insn::from_byte(self.mode, byte).is_some() {
+                self.advance();
+                return Err(Error {
+                    kind: ErrorKind::InvalidTemplate,
+                    location: self.current_location(byte),
+                    source: None,
+                });
+            }
+            self.advance();
+        }
+        self.writer.write_all(&self.template[start..self.pos])?;
+        Ok(self.pos < self.template.len())
+    }
+
+    fn advance(&mut self) {
+        if self.template[self.pos] == b'\n' {
+            self.line += 1;
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+        self.pos += 1;
+    }
+
+    fn current_location(&self, byte: u8) -> Location {
+        Location {
+            byte,
+            path: None,
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl<W: io::Write> WriteInsn for TemplateWriter<W> {
+    fn write(&mut self, insn: Insn) -> Result<()> {
+        if !insn.is_representable(self.mode) {
+            return Err(Error {
+                kind: ErrorKind::UnrepresentableInstruction,
+                location: self.current_location(0),
+                source: None,
+            });
+        }
+        if !self.copy_carrier()? {
+            return Err(Error {
+                kind: ErrorKind::TemplateTooShort,
+                location: self.current_location(0),
+                source: None,
+            });
+        }
+        self.writer.write_all(&[insn.into_byte(self.mode)])?;
+        self.advance();
+        if insn == Insn::Snew {
+            self.mode = self.mode.flip();
+        }
+        Ok(())
+    }
+}
+
+/// Lexes `word` in `mode` and returns the number of instructions it represents and the mode after the word
+/// if they are a non-empty prefix of `insns`.
+fn consumed_insns(word: &[u8], mut mode: Mode, insns: &VecDeque<Insn>) -> Option<(usize, Mode)> {
+    let mut n = 0;
+    for &byte in word {
+        if let Some(insn) = Insn::from_byte(mode, byte) {
+            if insns.get(n) != Some(&insn) {
+                return None;
+            }
+            n += 1;
+            if insn == Insn::Snew {
+                mode = mode.flip();
+            }
+        }
+    }
+    if n == 0 {
+        None
+    } else {
+        Some((n, mode))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::SliceLexer;
+    use crate::vm::ReadToken;
+    use Insn::*;
+
+    #[test]
+    fn vanity_writer_uses_words() -> Result<()> {
+        let mut w = VanityWriter::new(Vec::new(), lexicon(&["Bubba", "Bu", "duck"]));
+        w.write_all(&[Inew, Iinc, Ishl, Ishl, Iadd, Inew, Iinc, Inew])?;
+        assert_eq!(w.finish()?, b"Bubba Bu B".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn vanity_writer_tracks_mode() -> Result<()> {
+        let insns = [Inew, Snew, Inew, Iinc, Snew, Inew, Iinc];
+        let mut w = VanityWriter::new(Vec::new(), lexicon(&["B-?", "Sh$", "but", "Bun"]));
+        w.write_all(&insns)?;
+        let out = w.finish()?;
+        assert_eq!(out, b"B-? Sh$ Bun".to_vec());
+        assert_eq!(lex(&out), insns.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn vanity_writer_round_trip() -> Result<()> {
+        let insns = [
+            Inew, Iinc, Ishl, Snew, Inew, Iinc, Sadd, Snew, Bnew, Bneg, Anew, Gswp, Aadd, Nnew,
+        ];
+        let words = lexicon(&["Bubba", "abu", "eh", "Sakura", "sun", "a!", "zero"]);
+        let mut w = VanityWriter::new(Vec::new(), words);
+        w.write_all(&insns)?;
+        let out = w.finish()?;
+        assert_eq!(lex(&out), insns.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn template_writer_fills_placeholders() -> Result<()> {
+        let mut w = TemplateWriter::new(Vec::new(), "Why _ _\nLynch _ _, _", b'_');
+        w.write_all(&[Inew, Iinc, Snew, Inew])?;
+        let out = w.finish()?;
+        assert_eq!(out, b"Why B u\nLynch ? S,  ".to_vec());
+        assert_eq!(lex(&out), vec![Inew, Iinc, Snew, Inew]);
+        Ok(())
+    }
+
+    #[test]
+    fn template_writer_rejects_instructions_in_carrier() {
+        // `h` represents `Iinc` in the S mode.
+        let mut w = TemplateWriter::new(Vec::new(), "_ Why _", b'_');
+        let err = w.write_all(&[Snew, Inew]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidTemplate);
+        assert_eq!((err.location.byte, err.location.column), (b'h', 4));
+    }
+
+    #[test]
+    fn template_writer_reports_insufficient_capacity() {
+        let mut w = TemplateWriter::new(Vec::new(), "_", b'_');
+        let err = w.write_all(&[Inew, Inew]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TemplateTooShort);
+    }
+
+    /*
+     * Helper functions
+     */
+
+    fn lexicon(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    fn lex(bytes: &[u8]) -> Vec<Insn> {
+        let mut lexer = SliceLexer::new(bytes);
+        let mut insns = Vec::new();
+        while let Some(t) = lexer.read().unwrap() {
+            insns.push(t.insn);
+        }
+        insns
+    }
+}
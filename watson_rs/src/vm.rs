@@ -1,5 +1,12 @@
-use crate::error::{Error, ErrorKind, Result};
-use crate::language::{Bytes, Insn, IsValue, Location, Map, Token, Value};
+use crate::error::{Error, ErrorKind, Result, TypeMismatch};
+use crate::language::{Bytes, Insn, IsValue, Location, Map, Mode, Token, Value, ValueKind};
+use crate::provenance::{Provenance, Tracker};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ops::ControlFlow;
+use std::sync::Arc;
 use Insn::*;
 
 /// A source of tokens.
@@ -7,18 +14,201 @@ pub trait ReadToken {
     /// Reads a single token from an underlying source.
     /// It should return `Ok(None)` if there is no more token.
     fn read(&mut self) -> Result<Option<Token>>;
+
+    /// Returns a reader that reads all tokens from `self` and then reads tokens from `next`.
+    fn chain_tokens<R>(self, next: R) -> Chain<Self, R>
+    where
+        Self: Sized,
+        R: ReadToken,
+    {
+        Chain {
+            first: Some(self),
+            second: next,
+        }
+    }
+
+    /// Returns a reader that reads at most `n` tokens from `self`.
+    fn take_insns(self, n: usize) -> TakeInsns<Self>
+    where
+        Self: Sized,
+    {
+        TakeInsns {
+            reader: self,
+            remaining: n,
+        }
+    }
+
+    /// Returns a reader that calls `f` with each token before passing it on.
+    fn inspect_tokens<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Token),
+    {
+        Inspect { reader: self, f }
+    }
+
+    /// Returns a reader that appends a copy of each token to `out` before passing it on.
+    /// The recorded tokens can be replayed later since `vec::IntoIter<Token>` is also a `ReadToken`.
+    fn tee(self, out: &mut Vec<Token>) -> Tee<'_, Self>
+    where
+        Self: Sized,
+    {
+        Tee { reader: self, out }
+    }
+}
+
+/// Any iterator over tokens can be used as a `ReadToken`.
+impl<I> ReadToken for I
+where
+    I: Iterator<Item = Token>,
+{
+    fn read(&mut self) -> Result<Option<Token>> {
+        Ok(self.next())
+    }
+}
+
+/// A token reader that reads from an iterator over `Insn`s.
+/// Since instructions given by the iterator do not come from any source text, all tokens have `Location::unknown()`.
+/// Their modes are the ones in which they would be read if they were written by `Unlexer` from `Mode::A`.
+pub struct InsnReader<I> {
+    insns: I,
+    mode: Mode,
+}
+
+impl<I> InsnReader<I>
+where
+    I: Iterator<Item = Insn>,
+{
+    /// Returns a new reader that reads tokens from the given instructions.
+    pub fn new<T>(insns: T) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        InsnReader {
+            insns: insns.into_iter(),
+            mode: Mode::A,
+        }
+    }
+}
+
+impl<I> ReadToken for InsnReader<I>
+where
+    I: Iterator<Item = Insn>,
+{
+    fn read(&mut self) -> Result<Option<Token>> {
+        Ok(self
+            .insns
+            .next()
+            .map(|insn| new_unknown_token(insn, &mut self.mode)))
+    }
+}
+
+/// A token reader returned by `ReadToken::chain_tokens`.
+pub struct Chain<R1, R2> {
+    first: Option<R1>,
+    second: R2,
+}
+
+impl<R1, R2> ReadToken for Chain<R1, R2>
+where
+    R1: ReadToken,
+    R2: ReadToken,
+{
+    fn read(&mut self) -> Result<Option<Token>> {
+        if let Some(first) = self.first.as_mut() {
+            match first.read()? {
+                Some(token) => return Ok(Some(token)),
+                None => self.first = None,
+            }
+        }
+        self.second.read()
+    }
+}
+
+/// A token reader returned by `ReadToken::take_insns`.
+pub struct TakeInsns<R> {
+    reader: R,
+    remaining: usize,
+}
+
+impl<R: ReadToken> ReadToken for TakeInsns<R> {
+    fn read(&mut self) -> Result<Option<Token>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let token = self.reader.read()?;
+        if token.is_some() {
+            self.remaining -= 1;
+        }
+        Ok(token)
+    }
+}
+
+/// A token reader returned by `ReadToken::inspect_tokens`.
+pub struct Inspect<R, F> {
+    reader: R,
+    f: F,
+}
+
+impl<R, F> ReadToken for Inspect<R, F>
+where
+    R: ReadToken,
+    F: FnMut(&Token),
+{
+    fn read(&mut self) -> Result<Option<Token>> {
+        let token = self.reader.read()?;
+        if let Some(ref t) = token {
+            (self.f)(t);
+        }
+        Ok(token)
+    }
+}
+
+/// Returns a token that does not come from any source text and updates `mode` as a lexer does.
+fn new_unknown_token(insn: Insn, mode: &mut Mode) -> Token {
+    let token = Token {
+        insn,
+        mode: *mode,
+        location: Location::unknown(),
+    };
+    if insn == Insn::Snew {
+        *mode = mode.flip();
+    }
+    token
+}
+
+/// A token reader returned by `ReadToken::tee`.
+pub struct Tee<'a, R> {
+    reader: R,
+    out: &'a mut Vec<Token>,
+}
+
+impl<R: ReadToken> ReadToken for Tee<'_, R> {
+    fn read(&mut self) -> Result<Option<Token>> {
+        let token = self.reader.read()?;
+        if let Some(ref t) = token {
+            self.out.push(t.clone());
+        }
+        Ok(token)
+    }
 }
 
 /// A token reader that reads from the given slice.
+/// Like `InsnReader`, all tokens have `Location::unknown()`.
 pub struct SliceTokenReader<'a> {
     next: usize,
     slice: &'a [Insn],
+    mode: Mode,
 }
 
 impl<'a> SliceTokenReader<'a> {
     /// Returns a new reader that reads tokens from the given slice.
     pub fn new(slice: &'a [Insn]) -> Self {
-        SliceTokenReader { next: 0, slice }
+        SliceTokenReader {
+            next: 0,
+            slice,
+            mode: Mode::A,
+        }
     }
 }
 
@@ -29,23 +219,35 @@ impl<'a> ReadToken for SliceTokenReader<'a> {
         } else {
             let insn = self.slice[self.next];
             self.next += 1;
-            Ok(Some(Token {
-                insn,
-                location: Location::unknown(),
-            }))
+            Ok(Some(new_unknown_token(insn, &mut self.mode)))
         }
     }
 }
 
 /// A stack of the WATSON VM.
 /// See [the specification](https://github.com/genkami/watson/blob/main/doc/spec.md) for more details.
+///
+/// Values on the stack are reference-counted so that `Gdup` does not copy them.
+/// A shared value is cloned only when an instruction consumes it; discarding it by `Gpop` does not clone it.
 pub struct Stack {
-    vec: Vec<Value>,
+    vec: Vec<Arc<Value>>,
+    popped: Option<Popped>,
+}
+
+/// Values that were on the stack before an instruction and have been popped by it, recorded for `VM::undo`.
+struct Popped {
+    /// The lowest depth of the stack so far.
+    base: usize,
+    /// The popped values from the top.
+    slots: Vec<Arc<Value>>,
 }
 
 impl Stack {
     pub fn new() -> Self {
-        Stack { vec: Vec::new() }
+        Stack {
+            vec: Vec::new(),
+            popped: None,
+        }
     }
 
     /// Returns a StackOps that can manipulate the stack on behalf of the instruction given by the token.
@@ -55,12 +257,52 @@ impl Stack {
 
     /// Returns a value on the top of the stack without consuming it.
     pub fn peek_top(&self) -> Option<&Value> {
-        let len = self.vec.len();
-        if len == 0 {
-            None
-        } else {
-            Some(&self.vec[len - 1])
+        self.vec.last().map(|slot| slot.as_ref())
+    }
+
+    /// Returns an iterator over the values in the stack from the bottom to the top.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Value> + ExactSizeIterator {
+        self.vec.iter().map(|slot| slot.as_ref())
+    }
+
+    /// Returns the number of values in the stack.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// Returns true if the stack has no values.
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Returns a mutable reference to a value on the bottom of the stack.
+    pub(crate) fn bottom_mut(&mut self) -> Option<&mut Value> {
+        self.vec.first_mut().map(Arc::make_mut)
+    }
+
+    /// Removes all values from the stack and returns them in order from the bottom.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = Value> + '_ {
+        self.vec.drain(..).map(Arc::unwrap_or_clone)
+    }
+
+    fn pop(&mut self) -> Option<Value> {
+        self.pop_slot().map(Arc::unwrap_or_clone)
+    }
+
+    /// Pops a value without cloning it, recording it if it is one of the values that `Popped` tracks.
+    fn pop_slot(&mut self) -> Option<Arc<Value>> {
+        let slot = self.vec.pop()?;
+        if let Some(popped) = self.popped.as_mut() {
+            if self.vec.len() < popped.base {
+                popped.base = self.vec.len();
+                popped.slots.push(Arc::clone(&slot));
+            }
         }
+        Some(slot)
+    }
+
+    fn top_mut(&mut self) -> Option<&mut Value> {
+        self.vec.last_mut().map(Arc::make_mut)
     }
 }
 
@@ -79,18 +321,47 @@ pub struct StackOps<'a> {
 impl<'a> StackOps<'a> {
     /// Pushes a value onto the stack.
     pub fn push(&mut self, v: Value) {
-        self.stack.vec.push(v);
+        self.stack.vec.push(Arc::new(v));
     }
 
     /// Pops a value from the stack.
     pub fn pop(&mut self) -> Result<Value> {
-        match self.stack.vec.pop() {
+        match self.stack.pop() {
             Some(x) => Ok(x),
-            None => Err(Error {
-                kind: ErrorKind::EmptyStack,
-                location: self.token.location.clone(),
-                source: None,
-            }),
+            None => Err(self.empty_stack()),
+        }
+    }
+
+    /// Pops a value from the stack and drops it. Unlike `pop`, this does not clone a value shared by `Gdup`.
+    pub fn discard(&mut self) -> Result<()> {
+        match self.stack.pop_slot() {
+            Some(_) => Ok(()),
+            None => Err(self.empty_stack()),
+        }
+    }
+
+    /// Pushes the value on the top of the stack again without copying it.
+    pub fn dup(&mut self) -> Result<()> {
+        match self.stack.vec.last() {
+            Some(v) => {
+                let v = Arc::clone(v);
+                self.stack.vec.push(v);
+                Ok(())
+            }
+            None => Err(self.empty_stack()),
+        }
+    }
+
+    /// Swaps the top two values of the stack.
+    pub fn swap(&mut self) -> Result<()> {
+        match (self.stack.pop_slot(), self.stack.pop_slot()) {
+            (Some(x), Some(y)) => {
+                self.stack.vec.push(x);
+                self.stack.vec.push(y);
+                Ok(())
+            }
+            // The stack is left empty as `Gswp` would leave it.
+            _ => Err(self.empty_stack()),
         }
     }
 
@@ -101,7 +372,8 @@ impl<'a> StackOps<'a> {
         R: IsValue,
         F: FnOnce(T1) -> R,
     {
-        let v1 = self.pop()?;
+        let v1 = self.pop_slot()?;
+        self.check_types(&[T1::kind()], &[&*v1])?;
         let result = f(self.claim(v1)?);
         self.push(result.into_value());
         Ok(())
@@ -116,8 +388,9 @@ impl<'a> StackOps<'a> {
         R: IsValue,
         F: FnOnce(T1, T2) -> R,
     {
-        let v1 = self.pop()?;
-        let v2 = self.pop()?;
+        let v1 = self.pop_slot()?;
+        let v2 = self.pop_slot()?;
+        self.check_types(&[T1::kind(), T2::kind()], &[&*v1, &*v2])?;
         let result = f(self.claim(v1)?, self.claim(v2)?);
         self.push(result.into_value());
         Ok(())
@@ -133,42 +406,496 @@ impl<'a> StackOps<'a> {
         R: IsValue,
         F: FnOnce(T1, T2, T3) -> R,
     {
-        let v1 = self.pop()?;
-        let v2 = self.pop()?;
-        let v3 = self.pop()?;
+        let v1 = self.pop_slot()?;
+        let v2 = self.pop_slot()?;
+        let v3 = self.pop_slot()?;
+        self.check_types(&[T1::kind(), T2::kind(), T3::kind()], &[&*v1, &*v2, &*v3])?;
         let result = f(self.claim(v1)?, self.claim(v2)?, self.claim(v3)?);
         self.push(result.into_value());
         Ok(())
     }
 
-    fn claim<T: IsValue>(&self, v: Value) -> Result<T> {
+    /// Pops a value without cloning it, even if it is shared.
+    fn pop_slot(&mut self) -> Result<Arc<Value>> {
+        match self.stack.pop_slot() {
+            Some(slot) => Ok(slot),
+            None => Err(self.empty_stack()),
+        }
+    }
+
+    fn empty_stack(&self) -> Error {
+        Error {
+            kind: ErrorKind::EmptyStack,
+            location: self.token.location.clone(),
+            source: None,
+        }
+    }
+
+    /// Returns an error of `ErrorKind::TypeMismatch` unless each operand has the corresponding type.
+    fn check_types(&self, expected: &[Option<ValueKind>], operands: &[&Value]) -> Result<()> {
+        let matches = expected
+            .iter()
+            .zip(operands)
+            .all(|(e, v)| e.is_none_or(|e| e == v.kind()));
+        if matches {
+            return Ok(());
+        }
+        Err(Error::from_type_mismatch(
+            TypeMismatch {
+                insn: self.token.insn,
+                expected: expected.to_vec(),
+                actual: operands.iter().map(|v| v.kind()).collect(),
+            },
+            self.token.location.clone(),
+        ))
+    }
+
+    /// Converts a popped value into `T`, cloning it only if it is still shared.
+    fn claim<T: IsValue>(&self, slot: Arc<Value>) -> Result<T> {
+        let v = Arc::unwrap_or_clone(slot);
+        let kind = v.kind();
         match T::from_value(v) {
             Some(x) => Ok(x),
-            None => Err(Error {
-                kind: ErrorKind::TypeMismatch,
-                location: self.token.location.clone(),
-                source: None,
-            }),
+            None => Err(Error::from_type_mismatch(
+                TypeMismatch {
+                    insn: self.token.insn,
+                    expected: vec![T::kind()],
+                    actual: vec![kind],
+                },
+                self.token.location.clone(),
+            )),
         }
     }
 }
 
+/// A handler of extension instructions (`Insn::Ext`).
+/// This can be used to prototype instructions that are not defined in the specification.
+pub trait InsnHandler {
+    /// Executes an extension instruction represented by the given byte.
+    fn execute(&mut self, byte: u8, ops: &mut StackOps<'_>) -> Result<()>;
+}
+
+impl<F> InsnHandler for F
+where
+    F: FnMut(u8, &mut StackOps<'_>) -> Result<()>,
+{
+    fn execute(&mut self, byte: u8, ops: &mut StackOps<'_>) -> Result<()> {
+        self(byte, ops)
+    }
+}
+
+/// A controller of `VM::execute_all_with` that is called periodically during the execution.
+/// This can be used to enforce deadlines or to report progress.
+pub trait Control {
+    /// Returns how many instructions are executed between each call of `check` (defaults to 1024).
+    fn interval(&self) -> u64 {
+        1024
+    }
+
+    /// Receives the number of instructions executed so far.
+    /// Returning `ControlFlow::Break` aborts the execution.
+    fn check(&mut self, executed: u64) -> ControlFlow<()>;
+}
+
+impl<F> Control for F
+where
+    F: FnMut(u64) -> ControlFlow<()>,
+{
+    fn check(&mut self, executed: u64) -> ControlFlow<()> {
+        self(executed)
+    }
+}
+
 /// A WATSON Virturl Machine.
 /// See [the specification](https://github.com/genkami/watson/blob/main/doc/spec.md) for more details.
 pub struct VM {
     stack: Stack,
+    extensions: HashMap<u8, Box<dyn InsnHandler + Send>>,
+    stats: Option<Stats>,
+    journal: Option<VecDeque<JournalEntry>>,
+    overwritten: Option<Value>,
+    trace: Option<Box<dyn io::Write + Send>>,
+    limits: Limits,
+    provenance: Option<Tracker>,
+    duplicate_keys: DuplicateKeys,
 }
 
-impl VM {
+/// Config configures a `VM`.
+#[derive(Default)]
+pub struct Config {
+    /// If set to true, the `VM` collects `Stats` while executing instructions (defaults to false).
+    pub collect_stats: bool,
+
+    /// If set to true, the `VM` records how to revert each instruction so that it can be undone by `VM::undo`
+    /// (defaults to false). This is intended for debuggers; it keeps the values that instructions discard or
+    /// overwrite. Only the last `MAX_JOURNAL_LEN` instructions can be undone.
+    pub journal: bool,
+
+    /// The maximum number of values on the stack (defaults to unlimited).
+    /// The `VM` returns `ErrorKind::LimitExceeded` if an instruction makes the stack deeper than this.
+    pub max_stack_depth: Option<usize>,
+
+    /// The maximum number of bytes in a string built by `Sadd` (defaults to unlimited).
+    pub max_string_bytes: Option<usize>,
+
+    /// The maximum number of elements in an array built by `Aadd` (defaults to unlimited).
+    pub max_array_len: Option<usize>,
+
+    /// The maximum number of entries in an object built by `Oadd` (defaults to unlimited).
+    pub max_object_len: Option<usize>,
+
+    /// If set to true, the `VM` records which instructions built each value so that it can be retrieved by
+    /// `VM::provenance` (defaults to false). Values restored by `VM::undo` lose their provenance.
+    pub record_provenance: bool,
+
+    /// How `Oadd` treats a key that the object already has (defaults to `DuplicateKeys::LastWins`).
+    pub duplicate_keys: DuplicateKeys,
+}
+
+/// `DuplicateKeys` specifies how `Oadd` treats a key that the object already has.
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum DuplicateKeys {
+    /// The new value replaces the old one, as the specification defines.
+    #[default]
+    LastWins,
+
+    /// The old value is kept and the new one is discarded.
+    FirstWins,
+
+    /// The `VM` returns `ErrorKind::DuplicateKey`.
+    Error,
+}
+
+#[derive(Default)]
+struct Limits {
+    stack_depth: Option<usize>,
+    string_bytes: Option<usize>,
+    array_len: Option<usize>,
+    object_len: Option<usize>,
+}
+
+impl Limits {
+    fn check(&self, insn: Insn, stack: &Stack) -> bool {
+        let within = |len: usize, limit: Option<usize>| limit.is_none_or(|max| len <= max);
+        within(stack.len(), self.stack_depth)
+            && match (insn, stack.peek_top()) {
+                (Sadd, Some(Value::String(s))) => within(s.len(), self.string_bytes),
+                (Aadd, Some(Value::Array(a))) => within(a.len(), self.array_len),
+                (Oadd, Some(Value::Object(o))) => within(o.len(), self.object_len),
+                _ => true,
+            }
+    }
+}
+
+impl Config {
     /// Returns a new `VM`.
-    pub fn new() -> Self {
+    pub fn build(self) -> VM {
         VM {
             stack: Stack::new(),
+            extensions: HashMap::new(),
+            stats: if self.collect_stats {
+                Some(Stats::default())
+            } else {
+                None
+            },
+            journal: if self.journal {
+                Some(VecDeque::new())
+            } else {
+                None
+            },
+            overwritten: None,
+            trace: None,
+            limits: Limits {
+                stack_depth: self.max_stack_depth,
+                string_bytes: self.max_string_bytes,
+                array_len: self.max_array_len,
+                object_len: self.max_object_len,
+            },
+            provenance: if self.record_provenance {
+                Some(Tracker::default())
+            } else {
+                None
+            },
+            duplicate_keys: self.duplicate_keys,
+        }
+    }
+}
+
+fn write_trace(
+    w: &mut dyn io::Write,
+    location: &Location,
+    insn: Insn,
+    stack: &Stack,
+    err: Option<&Error>,
+) -> io::Result<()> {
+    match location.path.as_ref() {
+        Some(p) => write!(w, "{}", p.to_string_lossy())?,
+        None => write!(w, "-")?,
+    }
+    write!(
+        w,
+        ":{}:{} {:?} depth={}",
+        location.line,
+        location.column,
+        insn,
+        stack.len()
+    )?;
+    match (err, stack.peek_top()) {
+        (Some(err), _) => writeln!(w, " error={}", err.kind),
+        (None, Some(v)) => writeln!(w, " top={}", Summary(v)),
+        (None, None) => writeln!(w, " top=-"),
+    }
+}
+
+/// Displays a `Value` in a single short line.
+struct Summary<'a>(&'a Value);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MAX_STRING_LEN: usize = 16;
+
+        match self.0 {
+            Value::String(s) if s.len() <= MAX_STRING_LEN => {
+                write!(f, "String(\"{}\")", s.escape_ascii())
+            }
+            Value::String(s) => write!(f, "String(len={})", s.len()),
+            Value::Object(o) => write!(f, "Object(len={})", o.len()),
+            Value::Array(a) => write!(f, "Array(len={})", a.len()),
+            v => write!(f, "{v:?}"),
         }
     }
+}
+
+/// The result of `VM::step`.
+pub struct StepResult<'a> {
+    /// The token that was executed.
+    pub token: Token,
+
+    /// The stack after executing the token.
+    pub stack: &'a Stack,
+}
+
+/// The maximum number of instructions that `VM::undo` can revert.
+pub const MAX_JOURNAL_LEN: usize = 1 << 16;
+
+/// A record of how to revert an instruction.
+struct JournalEntry {
+    token: Token,
+    undo: Undo,
+}
+
+enum Undo {
+    /// Truncates the stack to `base` and pushes back the values that the instruction popped.
+    Restore {
+        base: usize,
+        popped: Vec<Arc<Value>>,
+    },
+
+    /// Removes the last byte of the string on the top and pushes back the integer that `Sadd` consumed.
+    Sadd(i64),
+
+    /// Removes the last element of the array on the top and pushes it back.
+    Aadd,
+
+    /// Removes the entry from the object on the top (or restores the old value) and pushes back the key and
+    /// the value.
+    Oadd { key: Bytes, old: Option<Value> },
+}
+
+/// Returns the number of values that the instruction pops, or `None` if it is unknown.
+pub(crate) fn arity(insn: Insn) -> Option<usize> {
+    let n = match insn {
+        Inew | Finf | Fnan | Snew | Onew | Anew | Bnew | Nnew => 0,
+        Iinc | Ishl | Ineg | Itof | Itou | Fneg | Bneg | Gdup | Gpop => 1,
+        Iadd | Isht | Sadd | Aadd | Gswp => 2,
+        Oadd => 3,
+        Ext(_) => return None,
+    };
+    Some(n)
+}
+
+/// Statistics of instructions executed by a `VM`.
+/// Instructions that resulted in errors are not counted.
+#[derive(Eq, PartialEq, Clone, Default, Debug)]
+pub struct Stats {
+    /// The number of times each instruction was executed.
+    pub insn_counts: HashMap<Insn, u64>,
+
+    /// The maximum number of values on the stack.
+    pub max_stack_depth: usize,
+
+    /// The total number of bytes appended to strings by `Sadd`.
+    pub string_bytes: u64,
+
+    /// The maximum number of entries in an object built by `Oadd`.
+    pub max_object_len: usize,
+
+    /// The maximum number of elements in an array built by `Aadd`.
+    pub max_array_len: usize,
+}
+
+impl Stats {
+    /// Returns the total number of executed instructions.
+    pub fn total_insns(&self) -> u64 {
+        self.insn_counts.values().sum()
+    }
+
+    fn record(&mut self, insn: Insn, stack: &Stack) {
+        *self.insn_counts.entry(insn).or_insert(0) += 1;
+        self.max_stack_depth = self.max_stack_depth.max(stack.len());
+        match (insn, stack.peek_top()) {
+            (Sadd, _) => self.string_bytes += 1,
+            (Oadd, Some(Value::Object(o))) => {
+                self.max_object_len = self.max_object_len.max(o.len())
+            }
+            (Aadd, Some(Value::Array(a))) => self.max_array_len = self.max_array_len.max(a.len()),
+            _ => {}
+        }
+    }
+}
+
+impl VM {
+    /// Returns a new `VM` with the default configuration.
+    pub fn new() -> Self {
+        Config::default().build()
+    }
+
+    /// Returns the statistics collected so far, or `None` if `Config::collect_stats` is not set.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    /// Registers a handler that executes `Insn::Ext(byte)`, replacing the existing one if any.
+    /// Extension instructions without handlers result in `ErrorKind::UnknownInstruction`.
+    pub fn register_extension<H>(&mut self, byte: u8, handler: H)
+    where
+        H: InsnHandler + Send + 'static,
+    {
+        self.extensions.insert(byte, Box::new(handler));
+    }
 
     /// Executes a single instruction.
     pub fn execute(&mut self, t: Token) -> Result<()> {
+        let insn = t.insn;
+        let duplicate = self.duplicate_key(insn);
+        let mut undo = None;
+        if self.journal.is_some() {
+            undo = self.inverse(insn).filter(|_| duplicate.is_none());
+            if undo.is_none() {
+                self.stack.popped = Some(Popped {
+                    base: self.stack.len(),
+                    slots: Vec::new(),
+                });
+            }
+        }
+        let journal_token = self.journal.as_ref().map(|_| t.clone());
+        let key = match (insn, self.provenance.is_some(), &duplicate) {
+            (Oadd, true, None) => match self.stack.iter().nth_back(1) {
+                Some(Value::String(k)) => Some(k.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let location = self.trace.as_ref().map(|_| t.location.clone());
+        let token_location = t.location.clone();
+        let result = match duplicate {
+            None => self.execute_token(t),
+            Some(k) if self.duplicate_keys == DuplicateKeys::Error => {
+                Err(Error::from_duplicate_key(k, token_location.clone()))
+            }
+            Some(_) => {
+                // Discards the key and the value.
+                self.stack.pop_slot();
+                self.stack.pop_slot();
+                Ok(())
+            }
+        };
+        if let Some(token) = journal_token {
+            self.record_undo(token, undo);
+        }
+        self.overwritten = None;
+        let result = result.and_then(|()| {
+            if self.limits.check(insn, &self.stack) {
+                Ok(())
+            } else {
+                Err(Error {
+                    kind: ErrorKind::LimitExceeded,
+                    location: token_location.clone(),
+                    source: None,
+                })
+            }
+        });
+        if let (Some(w), Some(location)) = (self.trace.as_mut(), location) {
+            write_trace(w, &location, insn, &self.stack, result.as_ref().err())
+                .map_err(|e| Error::from_io_error(e, location))?;
+        }
+        result?;
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record(insn, &self.stack);
+        }
+        if let Some(tracker) = self.provenance.as_mut() {
+            tracker.record(insn, &token_location, key, self.stack.len());
+        }
+        Ok(())
+    }
+
+    /// Returns how to revert `insn` without keeping references to its operands, or `None` if it needs to.
+    /// Instructions that modify a string, an array or an object must not keep references to it; otherwise
+    /// they would have to copy it.
+    fn inverse(&self, insn: Insn) -> Option<Undo> {
+        let mut operands = self.stack.iter().rev();
+        match (insn, operands.next(), operands.next(), operands.next()) {
+            (Sadd, Some(Value::Int(x)), Some(Value::String(_)), _) => Some(Undo::Sadd(*x)),
+            (Aadd, Some(_), Some(Value::Array(_)), _) => Some(Undo::Aadd),
+            (Oadd, Some(_), Some(Value::String(k)), Some(Value::Object(_))) => Some(Undo::Oadd {
+                key: k.clone(),
+                old: None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn record_undo(&mut self, token: Token, undo: Option<Undo>) {
+        let undo = match undo {
+            Some(Undo::Oadd { key, .. }) => Undo::Oadd {
+                key,
+                old: self.overwritten.take(),
+            },
+            Some(undo) => undo,
+            None => {
+                let popped = self
+                    .stack
+                    .popped
+                    .take()
+                    .expect("popped values must be recorded");
+                Undo::Restore {
+                    base: popped.base,
+                    popped: popped.slots,
+                }
+            }
+        };
+        let journal = self.journal.as_mut().expect("journal must be enabled");
+        if journal.len() == MAX_JOURNAL_LEN {
+            journal.pop_front();
+        }
+        journal.push_back(JournalEntry { token, undo });
+    }
+
+    /// Returns the key that `insn` would add to an object that already has it, unless duplicates are allowed.
+    fn duplicate_key(&self, insn: Insn) -> Option<Bytes> {
+        if insn != Oadd || self.duplicate_keys == DuplicateKeys::LastWins {
+            return None;
+        }
+        let mut operands = self.stack.iter().rev().skip(1);
+        match (operands.next(), operands.next()) {
+            (Some(Value::String(k)), Some(Value::Object(o))) if o.contains_key(k) => {
+                Some(k.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn execute_token(&mut self, t: Token) -> Result<()> {
         let mut ops = self.stack.operate_as(t.clone());
 
         fn push<T: IsValue>(ops: &mut StackOps, x: T) -> Result<()> {
@@ -179,11 +906,27 @@ impl VM {
         // See https://github.com/genkami/watson/blob/main/doc/spec.md#instructions.
         match t.insn {
             Inew => push(&mut ops, 0_i64),
-            Iinc => ops.apply1(|x: i64| x + 1),
+            // Integers are 64-bit two's complement, so arithmetic wraps around on overflow.
+            Iinc => ops.apply1(|x: i64| x.wrapping_add(1)),
             Ishl => ops.apply1(|x: i64| x << 1),
-            Iadd => ops.apply2(|y: i64, x: i64| x + y),
-            Ineg => ops.apply1(|x: i64| -x),
-            Isht => ops.apply2(|y: i64, x: i64| x << y),
+            Iadd => ops.apply2(|y: i64, x: i64| x.wrapping_add(y)),
+            Ineg => ops.apply1(|x: i64| x.wrapping_neg()),
+            Isht => {
+                let y = ops.pop_slot()?;
+                let x = ops.pop_slot()?;
+                ops.check_types(&[Some(ValueKind::Int), Some(ValueKind::Int)], &[&*y, &*x])?;
+                let (y, x): (i64, i64) = (ops.claim(y)?, ops.claim(x)?);
+                // Shifting by 64 or more bits results in zero, while shifting by a negative amount is an error.
+                match u32::try_from(y) {
+                    Ok(y) => push(&mut ops, x.checked_shl(y).unwrap_or(0)),
+                    Err(_) if 0 < y => push(&mut ops, 0_i64),
+                    Err(_) => Err(Error {
+                        kind: ErrorKind::InvalidShift,
+                        location: t.location,
+                        source: None,
+                    }),
+                }
+            }
             Itof => ops.apply1(|x: i64| f64::from_bits(x as u64)),
             Itou => ops.apply1(|x: i64| x as u64),
             Finf => push(&mut ops, f64::INFINITY),
@@ -195,10 +938,13 @@ impl VM {
                 s
             }),
             Onew => push(&mut ops, Map::new()),
-            Oadd => ops.apply3(|v: Value, k: Bytes, mut o: Map| {
-                o.insert(k, v);
-                o
-            }),
+            Oadd => {
+                let overwritten = &mut self.overwritten;
+                ops.apply3(|v: Value, k: Bytes, mut o: Map| {
+                    *overwritten = o.insert(k, v);
+                    o
+                })
+            }
             Anew => push(&mut ops, Vec::<Value>::new()),
             Aadd => ops.apply2(|v: Value, mut a: Vec<Value>| {
                 a.push(v);
@@ -207,37 +953,162 @@ impl VM {
             Bnew => push(&mut ops, false),
             Bneg => ops.apply1(|b: bool| !b),
             Nnew => push(&mut ops, ()),
-            Gdup => {
-                let v = ops.pop()?;
-                ops.push(v.clone());
-                ops.push(v);
-                Ok(())
+            Gdup => ops.dup(),
+            Gpop => ops.discard(),
+            Gswp => ops.swap(),
+            Ext(byte) => match self.extensions.get_mut(&byte) {
+                Some(handler) => handler.execute(byte, &mut ops),
+                None => Err(Error {
+                    kind: ErrorKind::UnknownInstruction,
+                    location: t.location,
+                    source: None,
+                }),
+            },
+        }
+    }
+
+    /// Executes all instructions sequentially from the given reader.
+    pub fn execute_all<R>(&mut self, mut reader: R) -> Result<()>
+    where
+        R: ReadToken,
+    {
+        while let Some(token) = reader.read()? {
+            self.execute(token)?;
+        }
+        Ok(())
+    }
+
+    /// Executes all instructions sequentially from the given reader, calling `ctl` periodically.
+    /// If `ctl` breaks, this returns an error of `ErrorKind::Aborted` at the last executed instruction.
+    pub fn execute_all_with<R, C>(&mut self, mut reader: R, mut ctl: C) -> Result<()>
+    where
+        R: ReadToken,
+        C: Control,
+    {
+        let interval = ctl.interval().max(1);
+        let mut executed: u64 = 0;
+        while let Some(token) = reader.read()? {
+            let location = token.location.clone();
+            self.execute(token)?;
+            executed += 1;
+            if executed.is_multiple_of(interval) && ctl.check(executed).is_break() {
+                return Err(Error {
+                    kind: ErrorKind::Aborted,
+                    location,
+                    source: None,
+                });
             }
-            Gpop => {
-                ops.pop()?;
-                Ok(())
+        }
+        Ok(())
+    }
+
+    /// Starts writing a line to `w` every time the `VM` executes an instruction.
+    /// Each line consists of the location, the instruction, the depth of the stack, and a summary of the top value
+    /// (or the error if the instruction failed).
+    pub fn trace_to<W>(&mut self, w: W)
+    where
+        W: io::Write + Send + 'static,
+    {
+        self.trace = Some(Box::new(w));
+    }
+
+    /// Stops tracing started by `trace_to`.
+    pub fn stop_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Reverts the last instruction executed (even if it failed) and returns its token.
+    /// This returns `None` if there is no instruction to revert or `Config::journal` is not set.
+    /// `Stats` are not reverted.
+    pub fn undo(&mut self) -> Option<Token> {
+        let entry = self.journal.as_mut()?.pop_back()?;
+        let stack = &mut self.stack;
+        let base = match entry.undo {
+            Undo::Restore { base, popped } => {
+                stack.vec.truncate(base);
+                stack.vec.extend(popped.into_iter().rev());
+                base
             }
-            Gswp => {
-                let v1 = ops.pop()?;
-                let v2 = ops.pop()?;
-                ops.push(v1);
-                ops.push(v2);
-                Ok(())
+            Undo::Sadd(x) => {
+                if let Some(Value::String(s)) = stack.top_mut() {
+                    s.pop();
+                }
+                stack.vec.push(Arc::new(Value::Int(x)));
+                stack.len() - 2
             }
+            Undo::Aadd => {
+                let v = match stack.top_mut() {
+                    Some(Value::Array(a)) => a.pop(),
+                    _ => None,
+                };
+                stack.vec.extend(v.map(Arc::new));
+                stack.len() - 2
+            }
+            Undo::Oadd { key, old } => {
+                let v = match stack.top_mut() {
+                    Some(Value::Object(o)) => match old {
+                        Some(old) => o.get_mut(&key).map(|v| mem::replace(v, old)),
+                        None => remove_entry(o, &key),
+                    },
+                    _ => None,
+                };
+                stack.vec.push(Arc::new(Value::String(key)));
+                stack.vec.extend(v.map(Arc::new));
+                stack.len() - 3
+            }
+        };
+        if let Some(tracker) = self.provenance.as_mut() {
+            tracker.sync(base);
+            tracker.sync(self.stack.len());
         }
+        Some(entry.token)
     }
 
-    /// Executes all instructions sequentially from the given reader.
-    pub fn execute_all<R>(&mut self, mut reader: R) -> Result<()>
+    /// Reads a single token from the reader and executes it.
+    /// This returns `Ok(None)` if the reader has no more tokens.
+    pub fn step<R>(&mut self, reader: &mut R) -> Result<Option<StepResult<'_>>>
+    where
+        R: ReadToken + ?Sized,
+    {
+        let token = match reader.read()? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        self.execute(token.clone())?;
+        Ok(Some(StepResult {
+            token,
+            stack: &self.stack,
+        }))
+    }
+
+    /// Executes a single instruction that does not come from any source text.
+    /// Errors caused by this instruction are reported at `Location::unknown()`.
+    /// The instruction is treated as if it is read in `Mode::A`.
+    pub fn execute_insn(&mut self, insn: Insn) -> Result<()> {
+        self.execute(Token {
+            insn,
+            mode: Mode::A,
+            location: Location::unknown(),
+        })
+    }
+
+    /// Executes all instructions sequentially from the given iterator.
+    pub fn execute_all_insns<I>(&mut self, insns: I) -> Result<()>
     where
-        R: ReadToken,
+        I: IntoIterator<Item = Insn>,
     {
-        while let Some(token) = reader.read()? {
-            self.execute(token)?;
+        for insn in insns {
+            self.execute_insn(insn)?;
         }
         Ok(())
     }
 
+    /// Returns the provenance of the value on the top of the stack, or `None` if the stack is empty or
+    /// `Config::record_provenance` is not set.
+    pub fn provenance(&self) -> Option<Provenance> {
+        self.provenance.as_ref()?.provenance()
+    }
+
     /// Returns a `Value` on the top of the stack.
     pub fn peek_top(&self) -> Option<&Value> {
         self.stack.peek_top()
@@ -245,13 +1116,70 @@ impl VM {
 
     /// Converts itself into a value on the top of its stack.
     pub fn into_top(mut self) -> Option<Value> {
-        self.stack.vec.pop()
+        self.stack.pop()
+    }
+
+    /// Removes all values from its stack and returns them in order from the bottom.
+    /// This is useful for decoding a stream where multiple values are encoded one after another.
+    /// Instructions executed so far can no longer be undone.
+    pub fn take_values(&mut self) -> Vec<Value> {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.clear();
+        }
+        if let Some(tracker) = self.provenance.as_mut() {
+            tracker.sync(0);
+        }
+        self.stack.drain().collect()
+    }
+
+    /// Borrows its stack for debug purpose.
+    pub fn borrow_stack(&self) -> &Stack {
+        &self.stack
     }
 
     /// Borrows its stack mutably for debug purpose.
     pub fn borrow_stack_mut(&mut self) -> &mut Stack {
         &mut self.stack
     }
+
+    /// Applies the limits and the statistics to an `Aadd` or `Oadd` that `EventVM` executed on a container that it
+    /// streams, which has had `len` elements added so far. The container itself no longer holds them.
+    pub(crate) fn record_streamed(
+        &mut self,
+        insn: Insn,
+        len: usize,
+        location: &Location,
+    ) -> Result<()> {
+        let limit = match insn {
+            Aadd => self.limits.array_len,
+            Oadd => self.limits.object_len,
+            _ => None,
+        };
+        if limit.is_some_and(|max| len > max) {
+            return Err(Error {
+                kind: ErrorKind::LimitExceeded,
+                location: location.clone(),
+                source: None,
+            });
+        }
+        if let Some(stats) = self.stats.as_mut() {
+            *stats.insn_counts.entry(insn).or_insert(0) += 1;
+            match insn {
+                Aadd => stats.max_array_len = stats.max_array_len.max(len),
+                Oadd => stats.max_object_len = stats.max_object_len.max(len),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the entry that has the given key, keeping the order of the other entries.
+fn remove_entry(map: &mut Map, key: &[u8]) -> Option<Value> {
+    #[cfg(feature = "preserve-order")]
+    return map.shift_remove(key);
+    #[cfg(not(feature = "preserve-order"))]
+    return map.remove(key);
 }
 
 impl Default for VM {
@@ -494,6 +1422,28 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn vm_execute_int_arithmetic_wraps_around() -> Result<()> {
+        let mut vm = VM::new();
+        for (init, insn, expected) in [
+            (&[i64::MAX][..], Iinc, i64::MIN),
+            (&[i64::MAX, 1][..], Iadd, i64::MIN),
+            (&[i64::MIN, -1][..], Iadd, i64::MAX),
+            (&[i64::MIN][..], Ineg, i64::MIN),
+            (&[i64::MAX][..], Ineg, -i64::MAX),
+            (&[i64::MIN][..], Ishl, 0),
+        ] {
+            let mut ops = vm.borrow_stack_mut().force_operate();
+            for x in init {
+                ops.push(Int(*x));
+            }
+            vm.execute(new_token(insn))?;
+            assert_eq!(vm.peek_top(), Some(&Int(expected)), "{insn:?} {init:?}");
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn vm_execute_isht() -> Result<()> {
         let mut vm = VM::new();
@@ -504,6 +1454,27 @@ mod test {
         vm.execute(new_token(Isht))?;
         assert_eq!(vm.peek_top(), Some(&Int(12)));
 
+        for (x, y, expected) in [
+            (1, 63, i64::MIN),
+            (-1, 1, -2),
+            (1, 64, 0),
+            (-1, 100, 0),
+            (1, i64::MAX, 0),
+        ] {
+            let mut ops = vm.borrow_stack_mut().force_operate();
+            ops.push(Int(x));
+            ops.push(Int(y));
+            vm.execute(new_token(Isht))?;
+            assert_eq!(vm.peek_top(), Some(&Int(expected)));
+        }
+
+        for y in [-1, i64::MIN] {
+            let mut ops = vm.borrow_stack_mut().force_operate();
+            ops.push(Int(1));
+            ops.push(Int(y));
+            assert_error_kind_is(vm.execute(new_token(Isht)), ErrorKind::InvalidShift);
+        }
+
         Ok(())
     }
 
@@ -537,7 +1508,7 @@ mod test {
         let mut vm = VM::new();
 
         vm.execute(new_token(Finf))?;
-        let top = vm.peek_top().clone();
+        let top = vm.peek_top();
         let f = match top {
             Some(Float(f)) => f,
             _ => panic!("not a float: {:?}", top),
@@ -553,7 +1524,7 @@ mod test {
         let mut vm = VM::new();
 
         vm.execute(new_token(Fnan))?;
-        let top = vm.peek_top().clone();
+        let top = vm.peek_top();
         let f = match top {
             Some(Float(f)) => f,
             _ => panic!("not a float: {:?}", top),
@@ -643,6 +1614,26 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn vm_execute_oadd_type_mismatch() -> Result<()> {
+        let mut vm = VM::new();
+        vm.execute_all_insns([Onew, Inew, Nnew])?;
+        let err = vm.execute_insn(Oadd).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TypeMismatch);
+        assert_eq!(
+            err.type_mismatch(),
+            Some(&TypeMismatch {
+                insn: Oadd,
+                expected: vec![None, Some(ValueKind::String), Some(ValueKind::Object)],
+                actual: vec![ValueKind::Nil, ValueKind::Int, ValueKind::Object],
+            })
+        );
+        assert!(err
+            .to_string()
+            .ends_with(": Oadd expects (any, String, Object) but got (Nil, Int, Object)"));
+        Ok(())
+    }
+
     #[test]
     fn vm_execute_anew() -> Result<()> {
         let mut vm = VM::new();
@@ -726,6 +1717,27 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn vm_execute_gdup_shares_value() -> Result<()> {
+        let mut vm = VM::new();
+        vm.execute_all_insns([Anew, Inew, Aadd, Gdup])?;
+        assert!(Arc::ptr_eq(&vm.stack.vec[0], &vm.stack.vec[1]));
+
+        // Discarding the duplicated value does not clone it.
+        vm.execute_insn(Gpop)?;
+        assert_eq!(Arc::strong_count(&vm.stack.vec[0]), 1);
+        vm.execute_insn(Gdup)?;
+
+        // Modifying the duplicated value does not affect the original one.
+        vm.execute_all_insns([Nnew, Aadd])?;
+        let mut ops = vm.borrow_stack_mut().force_operate();
+        assert_eq!(ops.pop()?, array![Int(0), Nil]);
+        assert_eq!(ops.pop()?, array![Int(0)]);
+        assert_error_kind_is(ops.pop(), ErrorKind::EmptyStack);
+
+        Ok(())
+    }
+
     #[test]
     fn vm_execute_gpop() -> Result<()> {
         let mut vm = VM::new();
@@ -742,6 +1754,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn vm_drops_deeply_nested_values() -> Result<()> {
+        const DEPTH: usize = 200_000;
+
+        let nest = |vm: &mut VM| -> Result<()> {
+            vm.execute_insn(Anew)?;
+            for _ in 0..DEPTH {
+                vm.execute_all_insns([Anew, Gswp, Aadd])?;
+            }
+            Ok(())
+        };
+
+        let mut vm = VM::new();
+        // Overwrites a nested value by `Oadd`.
+        vm.execute_all_insns([Onew, Snew])?;
+        nest(&mut vm)?;
+        vm.execute_all_insns([Oadd, Snew, Nnew, Oadd])?;
+        assert_eq!(vm.peek_top(), Some(&object![[b""]: Nil]));
+        // Discards a nested value by `Gpop`.
+        nest(&mut vm)?;
+        vm.execute_insn(Gpop)?;
+        // Drops a nested value left on the stack.
+        nest(&mut vm)?;
+        drop(vm);
+
+        Ok(())
+    }
+
     #[test]
     fn vm_execute_gswp() -> Result<()> {
         let mut vm = VM::new();
@@ -759,16 +1799,518 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn vm_execute_ext() -> Result<()> {
+        let mut vm = VM::new();
+        assert_error_kind_is(
+            vm.execute(new_token(Ext(b'0'))),
+            ErrorKind::UnknownInstruction,
+        );
+
+        // Pushes a signed integer that the byte represents.
+        vm.register_extension(b'0', |byte: u8, ops: &mut StackOps<'_>| {
+            ops.push(Int((byte - b'0') as i64));
+            Ok(())
+        });
+        vm.execute(new_token(Ext(b'0')))?;
+        assert_eq!(vm.peek_top(), Some(&Int(0)));
+        assert_error_kind_is(
+            vm.execute(new_token(Ext(b'1'))),
+            ErrorKind::UnknownInstruction,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_stats() -> Result<()> {
+        let mut vm = VM::new();
+        vm.execute_insn(Inew)?;
+        assert_eq!(vm.stats(), None);
+
+        let mut vm = Config {
+            collect_stats: true,
+            ..Default::default()
+        }
+        .build();
+        assert_eq!(vm.stats(), Some(&Stats::default()));
+        vm.execute_all_insns([Anew, Snew, Inew, Sadd, Inew, Sadd, Aadd])?;
+        vm.execute_all_insns([Onew, Snew, Nnew, Oadd, Aadd])?;
+        assert_error_kind_is(vm.execute_insn(Aadd), ErrorKind::EmptyStack);
+
+        let stats = vm.stats().unwrap();
+        assert_eq!(stats.total_insns(), 12);
+        assert_eq!(stats.insn_counts[&Inew], 2);
+        assert_eq!(stats.insn_counts[&Aadd], 2);
+        assert_eq!(stats.insn_counts.get(&Gpop), None);
+        assert_eq!(stats.max_stack_depth, 4);
+        assert_eq!(stats.string_bytes, 2);
+        assert_eq!(stats.max_object_len, 1);
+        assert_eq!(stats.max_array_len, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_limits() -> Result<()> {
+        let new_vm = || {
+            Config {
+                max_stack_depth: Some(3),
+                max_string_bytes: Some(1),
+                max_array_len: Some(1),
+                max_object_len: Some(1),
+                ..Default::default()
+            }
+            .build()
+        };
+        let mut vm = new_vm();
+        vm.execute_all_insns([Anew, Snew, Inew])?;
+        assert_error_kind_is(vm.execute_insn(Inew), ErrorKind::LimitExceeded);
+
+        for insns in [
+            &[Snew, Inew, Sadd, Inew, Sadd][..],
+            &[Anew, Nnew, Aadd, Nnew, Aadd][..],
+            &[Onew, Snew, Nnew, Oadd, Snew, Inew, Sadd, Nnew, Oadd][..],
+        ] {
+            let mut vm = new_vm();
+            let (last, init) = insns.split_last().unwrap();
+            vm.execute_all_insns(init.iter().copied())?;
+            assert_error_kind_is(vm.execute_insn(*last), ErrorKind::LimitExceeded);
+        }
+
+        // Overwriting an existing key doesn't make the object larger.
+        let mut vm = new_vm();
+        vm.execute_all_insns([Onew, Snew, Nnew, Oadd, Snew, Bnew, Oadd])?;
+        Ok(())
+    }
+
+    #[cfg(feature = "preserve-order")]
+    #[test]
+    fn vm_preserves_object_order() -> Result<()> {
+        // {"\x01": nil, "": nil}
+        let insns = [Onew, Snew, Inew, Iinc, Sadd, Nnew, Oadd, Snew, Nnew, Oadd];
+        let mut vm = VM::new();
+        vm.execute_all_insns(insns)?;
+        match vm.peek_top() {
+            Some(Value::Object(map)) => {
+                assert_eq!(map.keys().collect::<Vec<_>>(), [&b"\x01"[..], b""]);
+            }
+            v => panic!("unexpected value: {v:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn vm_duplicate_keys() -> Result<()> {
+        // {"": nil, "": true, "\x01": 0}
+        let insns = [
+            Onew, Snew, Nnew, Oadd, Snew, Bnew, Oadd, Snew, Inew, Iinc, Sadd, Inew, Oadd,
+        ];
+        let run = |policy| -> Result<Value> {
+            let mut vm = Config {
+                duplicate_keys: policy,
+                ..Default::default()
+            }
+            .build();
+            vm.execute_all_insns(insns)?;
+            Ok(vm.into_top().unwrap())
+        };
+        let obj = |v| {
+            Value::Object(
+                [(b"".to_vec(), v), (b"\x01".to_vec(), Value::Int(0))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        assert_eq!(run(DuplicateKeys::LastWins)?, obj(Value::Bool(false)));
+        assert_eq!(run(DuplicateKeys::FirstWins)?, obj(Value::Nil));
+
+        let err = run(DuplicateKeys::Error).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DuplicateKey);
+        assert_eq!(err.duplicate_key().unwrap().key, b"");
+        assert!(err.to_string().contains("key \"\" is already defined"));
+        Ok(())
+    }
+
+    #[test]
+    fn vm_execute_all_with() -> Result<()> {
+        struct Every2<'a>(&'a mut Vec<u64>);
+
+        impl Control for Every2<'_> {
+            fn interval(&self) -> u64 {
+                2
+            }
+
+            fn check(&mut self, executed: u64) -> ControlFlow<()> {
+                self.0.push(executed);
+                ControlFlow::Continue(())
+            }
+        }
+
+        let mut vm = VM::new();
+        let mut checked = Vec::new();
+        vm.execute_all_with(
+            SliceTokenReader::new(&[Inew, Iinc, Ishl, Ishl, Iinc]),
+            Every2(&mut checked),
+        )?;
+        assert_eq!(vm.peek_top(), Some(&Int(5)));
+        assert_eq!(checked, vec![2, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_execute_all_with_aborts() -> Result<()> {
+        let mut vm = VM::new();
+        let insns = vec![Inew; 3000];
+        let mut checked = Vec::new();
+        let result = vm.execute_all_with(SliceTokenReader::new(&insns), |executed| {
+            checked.push(executed);
+            if executed < 2048 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
+        assert_error_kind_is(result, ErrorKind::Aborted);
+        assert_eq!(checked, vec![1024, 2048]);
+        assert_eq!(vm.borrow_stack().len(), 2048);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_undo() -> Result<()> {
+        let mut vm = VM::new();
+        vm.execute_insn(Inew)?;
+        assert_eq!(vm.undo(), None);
+
+        let mut vm = Config {
+            journal: true,
+            ..Default::default()
+        }
+        .build();
+        let insns = [
+            Anew, Snew, Inew, Sadd, Gdup, Gpop, Aadd, Bnew, Gswp, Gswp, Bneg, Onew, Snew, Nnew,
+            Oadd, Snew, Bnew, Oadd,
+        ];
+        let snapshot = |vm: &VM| vm.stack.iter().cloned().collect::<Vec<_>>();
+        let mut snapshots = Vec::new();
+        for insn in insns {
+            snapshots.push(snapshot(&vm));
+            vm.execute_insn(insn)?;
+        }
+        snapshots.push(snapshot(&vm));
+        assert_error_kind_is(vm.execute_insn(Sadd), ErrorKind::TypeMismatch);
+        assert_eq!(vm.undo().map(|t| t.insn), Some(Sadd));
+        assert_eq!(snapshot(&vm), snapshots.pop().unwrap());
+        for insn in insns.iter().rev() {
+            let expected = snapshots.pop().unwrap();
+            assert_eq!(vm.undo().map(|t| t.insn), Some(*insn));
+            assert_eq!(snapshot(&vm), expected);
+        }
+        assert_eq!(vm.undo(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_journal_does_not_share_values() -> Result<()> {
+        let mut vm = Config {
+            journal: true,
+            ..Default::default()
+        }
+        .build();
+        vm.execute_all_insns([Anew, Onew, Snew, Inew, Sadd, Nnew, Oadd, Aadd])?;
+        assert_eq!(Arc::strong_count(&vm.stack.vec[0]), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn vm_journal_is_bounded() -> Result<()> {
+        let mut vm = Config {
+            journal: true,
+            ..Default::default()
+        }
+        .build();
+        vm.execute_all_insns(std::iter::repeat_n(Nnew, MAX_JOURNAL_LEN + 1))?;
+        let mut undone = 0;
+        while vm.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_JOURNAL_LEN);
+        assert_eq!(vm.stack.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn vm_undo_ext() -> Result<()> {
+        let mut vm = Config {
+            journal: true,
+            ..Default::default()
+        }
+        .build();
+        vm.register_extension(b'0', |_: u8, ops: &mut StackOps<'_>| {
+            ops.pop()?;
+            ops.pop()?;
+            Ok(())
+        });
+        vm.execute_all_insns([Inew, Nnew, Ext(b'0')])?;
+        assert_eq!(vm.peek_top(), None);
+        vm.undo();
+        assert_eq!(vm.peek_top(), Some(&Nil));
+        assert_eq!(vm.stack.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_trace_to() -> Result<()> {
+        use std::sync::Mutex;
+
+        #[derive(Clone)]
+        struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+        let mut vm = VM::new();
+        vm.execute(new_token(Nnew))?;
+        vm.trace_to(buf.clone());
+        vm.execute_all(
+            crate::lexer::Lexer::new(&b"Bu?"[..]).chain_tokens(SliceTokenReader::new(&[
+                Gswp, Sadd, Anew, Gswp, Aadd, Onew, Aadd,
+            ])),
+        )?;
+        assert_error_kind_is(vm.execute(new_token(Iinc)), ErrorKind::TypeMismatch);
+        vm.stop_trace();
+        vm.execute(new_token(Gpop))?;
+
+        assert_eq!(
+            std::string::String::from_utf8(buf.0.lock().unwrap().clone()).unwrap(),
+            [
+                "-:1:1 Inew depth=2 top=Int(0)",
+                "-:1:2 Iinc depth=2 top=Int(1)",
+                "-:1:3 Snew depth=3 top=String(\"\")",
+                "-:0:0 Gswp depth=3 top=Int(1)",
+                "-:0:0 Sadd depth=2 top=String(\"\\x01\")",
+                "-:0:0 Anew depth=3 top=Array(len=0)",
+                "-:0:0 Gswp depth=3 top=String(\"\\x01\")",
+                "-:0:0 Aadd depth=2 top=Array(len=1)",
+                "-:0:0 Onew depth=3 top=Object(len=0)",
+                "-:0:0 Aadd depth=2 top=Array(len=2)",
+                "-:0:0 Iinc depth=1 error=Type mismatch",
+                "",
+            ]
+            .join("\n")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_step() -> Result<()> {
+        let mut vm = VM::new();
+        let mut reader = SliceTokenReader::new(&[Inew, Iinc, Anew, Gswp]);
+
+        let step = vm.step(&mut reader)?.unwrap();
+        assert_eq!(step.token.insn, Inew);
+        assert_eq!(step.stack.iter().collect::<Vec<_>>(), vec![&Int(0)]);
+
+        vm.step(&mut reader)?;
+        vm.step(&mut reader)?;
+        let step = vm.step(&mut reader)?.unwrap();
+        assert_eq!(step.token.insn, Gswp);
+        assert_eq!(
+            step.stack.iter().collect::<Vec<_>>(),
+            vec![&array![], &Int(1)]
+        );
+
+        assert!(vm.step(&mut reader)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn vm_take_values() -> Result<()> {
+        let mut vm = VM::new();
+        assert_eq!(vm.take_values(), vec![]);
+
+        vm.execute_all_insns([Inew, Iinc, Anew, Nnew, Aadd, Bnew, Gdup])?;
+        assert_eq!(
+            vm.take_values(),
+            vec![Int(1), array![Nil], Bool(false), Bool(false)]
+        );
+        assert_eq!(vm.peek_top(), None);
+
+        vm.execute_insn(Nnew)?;
+        assert_eq!(vm.take_values(), vec![Nil]);
+        Ok(())
+    }
+
+    #[test]
+    fn vm_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<VM>();
+        assert_send::<Error>();
+        assert_send::<crate::lexer::Lexer<std::fs::File>>();
+    }
+
+    #[test]
+    fn vm_execute_insn() -> Result<()> {
+        let mut vm = VM::new();
+
+        vm.execute_insn(Inew)?;
+        vm.execute_insn(Iinc)?;
+        assert_eq!(vm.peek_top(), Some(&Int(1)));
+
+        let err = vm.execute_insn(Fneg).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TypeMismatch);
+        assert_eq!(err.location, Location::unknown());
+
+        Ok(())
+    }
+
+    #[test]
+    fn vm_execute_all_insns() -> Result<()> {
+        let mut vm = VM::new();
+
+        vm.execute_all_insns([Inew, Iinc, Ishl, Ishl])?;
+        assert_eq!(vm.peek_top(), Some(&Int(4)));
+
+        vm.execute_all_insns(vec![Gpop, Anew, Nnew, Aadd])?;
+        assert_eq!(vm.peek_top(), Some(&array![Nil]));
+
+        assert_error_kind_is(vm.execute_all_insns([Gpop, Gpop]), ErrorKind::EmptyStack);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_from_token_iterator() -> Result<()> {
+        let mut reader = vec![new_token(Inew), new_token(Iinc)].into_iter();
+        assert_eq!(reader.read()?, Some(new_token(Inew)));
+        assert_eq!(reader.read()?, Some(new_token(Iinc)));
+        assert_eq!(reader.read()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn insn_reader() -> Result<()> {
+        let mut reader = InsnReader::new([Inew, Iinc]);
+        assert_eq!(reader.read()?.map(|t| t.insn), Some(Inew));
+        assert_eq!(reader.read()?.map(|t| t.insn), Some(Iinc));
+        assert_eq!(reader.read()?, None);
+
+        let mut vm = VM::new();
+        vm.execute_all(InsnReader::new(vec![Inew, Iinc, Ishl]))?;
+        assert_eq!(vm.peek_top(), Some(&Int(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn insn_readers_track_mode() -> Result<()> {
+        let insns = [Inew, Snew, Inew, Snew, Inew];
+        let expected = vec![Mode::A, Mode::A, Mode::S, Mode::S, Mode::A];
+
+        let mut modes = Vec::new();
+        let mut reader = InsnReader::new(insns);
+        while let Some(t) = reader.read()? {
+            modes.push(t.mode);
+        }
+        assert_eq!(modes, expected);
+
+        let mut modes = Vec::new();
+        let mut reader = SliceTokenReader::new(&insns);
+        while let Some(t) = reader.read()? {
+            modes.push(t.mode);
+        }
+        assert_eq!(modes, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_chain() -> Result<()> {
+        let mut vm = VM::new();
+        vm.execute_all(
+            InsnReader::new([Anew, Inew])
+                .chain_tokens(SliceTokenReader::new(&[]))
+                .chain_tokens(SliceTokenReader::new(&[Iinc, Aadd])),
+        )?;
+        assert_eq!(vm.peek_top(), Some(&array![Int(1)]));
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_take_insns() -> Result<()> {
+        let mut reader = InsnReader::new([Inew, Iinc, Ishl]).take_insns(2);
+        assert_eq!(reader.read()?.map(|t| t.insn), Some(Inew));
+        assert_eq!(reader.read()?.map(|t| t.insn), Some(Iinc));
+        assert_eq!(reader.read()?, None);
+
+        let mut reader = InsnReader::new([Inew]).take_insns(2);
+        assert_eq!(reader.read()?.map(|t| t.insn), Some(Inew));
+        assert_eq!(reader.read()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_inspect() -> Result<()> {
+        let mut seen = Vec::new();
+        let mut vm = VM::new();
+        vm.execute_all(InsnReader::new([Inew, Iinc]).inspect_tokens(|t| seen.push(t.insn)))?;
+        assert_eq!(seen, vec![Inew, Iinc]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_iterator_adapters_are_not_ambiguous() -> Result<()> {
+        // `Iterator::chain` and `Iterator::inspect` can be called on token iterators with `ReadToken` in scope.
+        let mut count = 0;
+        let it = vec![new_token(Inew)]
+            .into_iter()
+            .chain(vec![new_token(Iinc)])
+            .inspect(|_| count += 1);
+        let mut vm = VM::new();
+        vm.execute_all(it)?;
+        assert_eq!(count, 2);
+        assert_eq!(vm.peek_top(), Some(&Value::Int(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn read_token_tee() -> Result<()> {
+        let mut recorded = Vec::new();
+        let mut vm = VM::new();
+        vm.execute_all(InsnReader::new([Inew, Iinc, Ishl]).tee(&mut recorded))?;
+        assert_eq!(
+            recorded.iter().map(|t| t.insn).collect::<Vec<_>>(),
+            vec![Inew, Iinc, Ishl]
+        );
+
+        let mut replayed = VM::new();
+        replayed.execute_all(recorded.into_iter())?;
+        assert_eq!(replayed.peek_top(), vm.peek_top());
+        Ok(())
+    }
+
     /*
      * Helper functions
      */
 
     trait StackExt {
-        fn force_operate(&mut self) -> StackOps;
+        fn force_operate(&mut self) -> StackOps<'_>;
     }
 
     impl StackExt for Stack {
-        fn force_operate(&mut self) -> StackOps {
+        fn force_operate(&mut self) -> StackOps<'_> {
             self.operate_as(new_meaningless_token())
         }
     }
@@ -784,7 +2326,8 @@ mod test {
 
     fn new_token(insn: Insn) -> Token {
         Token {
-            insn: insn,
+            insn,
+            mode: Mode::A,
             location: Location {
                 byte: b'X',
                 path: None,
@@ -0,0 +1,112 @@
+//! WebAssembly bindings for WATSON.
+//!
+//! Values are converted from and to JavaScript values as follows:
+//!
+//! | WATSON   | JavaScript                                                           |
+//! |----------|----------------------------------------------------------------------|
+//! | `Int`    | `number` if it is a safe integer, `bigint` otherwise                 |
+//! | `Uint`   | `number` if it is a safe integer, `bigint` otherwise                 |
+//! | `Float`  | `number`                                                             |
+//! | `String` | `string` if it is valid UTF-8, `Uint8Array` otherwise                |
+//! | `Object` | `Object` (keys must be valid UTF-8)                                  |
+//! | `Array`  | `Array`                                                              |
+//! | `Bool`   | `boolean`                                                            |
+//! | `Nil`    | `null` (`undefined` is also encoded as `Nil`)                        |
+//!
+//! A `number` is encoded as `Int` if it is a safe integer and as `Float` otherwise. A `bigint` is encoded as `Int` if
+//! it fits in `i64` and as `Uint` otherwise.
+//!
+//! `decode` throws an `Error` rather than dropping data if an object has a key that is not valid UTF-8, because such
+//! keys can't be told apart once they are converted into JavaScript strings.
+
+use js_sys::{Array, BigInt, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use watson_rs::{Map, Value};
+
+/// The maximum integer that a `number` can represent exactly (`Number.MAX_SAFE_INTEGER`).
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Decodes WATSON text into a JavaScript value. It throws an `Error` if the text is not exactly one value
+/// (see `watson_rs::from_str`) or has an object key that is not valid UTF-8.
+#[wasm_bindgen]
+pub fn decode(text: &str) -> Result<JsValue, JsError> {
+    let v = watson_rs::from_str(text)?;
+    to_js(&v)
+}
+
+/// Encodes a JavaScript value into WATSON text. It throws an `Error` if the value can't be represented in WATSON.
+#[wasm_bindgen]
+pub fn encode(value: JsValue) -> Result<String, JsError> {
+    Ok(watson_rs::to_string(&from_js(&value)?)?)
+}
+
+fn to_js(v: &Value) -> Result<JsValue, JsError> {
+    Ok(match *v {
+        Value::Int(n) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n) => {
+            JsValue::from_f64(n as f64)
+        }
+        Value::Int(n) => BigInt::from(n).into(),
+        Value::Uint(n) if n <= MAX_SAFE_INTEGER as u64 => JsValue::from_f64(n as f64),
+        Value::Uint(n) => BigInt::from(n).into(),
+        Value::Float(f) => JsValue::from_f64(f),
+        Value::String(ref s) => match std::str::from_utf8(s) {
+            Ok(s) => JsValue::from_str(s),
+            Err(_) => Uint8Array::from(&s[..]).into(),
+        },
+        Value::Object(ref map) => {
+            let obj = Object::new();
+            for (k, v) in map {
+                let k = std::str::from_utf8(k)
+                    .map_err(|_| JsError::new("object key is not valid UTF-8"))?;
+                Reflect::set(&obj, &JsValue::from_str(k), &to_js(v)?).unwrap_throw();
+            }
+            obj.into()
+        }
+        Value::Array(ref arr) => arr.iter().map(to_js).collect::<Result<Array, _>>()?.into(),
+        Value::Bool(b) => JsValue::from_bool(b),
+        Value::Nil => JsValue::NULL,
+    })
+}
+
+fn from_js(v: &JsValue) -> Result<Value, JsError> {
+    if v.is_null() || v.is_undefined() {
+        Ok(Value::Nil)
+    } else if let Some(b) = v.as_bool() {
+        Ok(Value::Bool(b))
+    } else if let Some(f) = v.as_f64() {
+        if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER as f64 {
+            Ok(Value::Int(f as i64))
+        } else {
+            Ok(Value::Float(f))
+        }
+    } else if v.is_bigint() {
+        if let Ok(n) = i64::try_from(v.clone()) {
+            Ok(Value::Int(n))
+        } else if let Ok(n) = u64::try_from(v.clone()) {
+            Ok(Value::Uint(n))
+        } else {
+            Err(JsError::new("bigint out of range"))
+        }
+    } else if let Some(s) = v.as_string() {
+        Ok(Value::String(s.into_bytes()))
+    } else if let Some(bytes) = v.dyn_ref::<Uint8Array>() {
+        Ok(Value::String(bytes.to_vec()))
+    } else if Array::is_array(v) {
+        Array::from(v)
+            .iter()
+            .map(|elem| from_js(&elem))
+            .collect::<Result<_, _>>()
+            .map(Value::Array)
+    } else if v.is_object() && !v.is_function() {
+        let mut map = Map::new();
+        for entry in Object::entries(v.unchecked_ref()).iter() {
+            let entry = Array::from(&entry);
+            let k = entry.get(0).as_string().unwrap_throw();
+            map.insert(k.into_bytes(), from_js(&entry.get(1))?);
+        }
+        Ok(Value::Object(map))
+    } else {
+        Err(JsError::new("unsupported value"))
+    }
+}
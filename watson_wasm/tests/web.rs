@@ -0,0 +1,44 @@
+//! Tests that run on a JavaScript engine with `wasm-pack test --node`.
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{BigInt, JSON};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+use watson_wasm::{decode, encode};
+
+fn json(v: &JsValue) -> String {
+    JSON::stringify(v).unwrap().as_string().unwrap()
+}
+
+#[wasm_bindgen_test]
+fn roundtrip() {
+    let v =
+        JSON::parse(r#"{"name":"watson","tags":["a","b"],"n":-12,"f":1.5,"ok":true,"none":null}"#)
+            .unwrap();
+    let text = encode(v.clone()).unwrap();
+    assert_eq!(json(&decode(&text).unwrap()), json(&v));
+}
+
+#[wasm_bindgen_test]
+fn large_integers_are_bigints() {
+    let v = JsValue::from(BigInt::from(u64::MAX));
+    let decoded = decode(&encode(v).unwrap()).unwrap();
+    assert!(decoded.is_bigint());
+    assert_eq!(u64::try_from(decoded).unwrap(), u64::MAX);
+}
+
+#[wasm_bindgen_test]
+fn invalid_text_throws() {
+    assert!(decode("E").is_err());
+    // Trailing values are not ignored.
+    assert!(decode("BB").is_err());
+}
+
+#[wasm_bindgen_test]
+fn invalid_utf8_keys_throw() {
+    let mut map = watson_rs::Map::new();
+    map.insert(b"\xff".to_vec(), watson_rs::Value::Nil);
+    map.insert(b"\xfe".to_vec(), watson_rs::Value::Nil);
+    let text = watson_rs::to_string(&watson_rs::Value::Object(map)).unwrap();
+    assert!(decode(&text).is_err());
+}